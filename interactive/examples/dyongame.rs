@@ -6,12 +6,16 @@ extern crate piston;
 extern crate current;
 extern crate dyon_interactive;
 extern crate music;
+extern crate sdl2;
 extern crate image;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use current::CurrentGuard;
 use dyon::{error, load, Lt, Module, Dfn, Runtime, Type};
 use dyon_interactive::{FontNames, ImageNames};
+use dyon_interactive::audio::{AudioBackend, Repeat};
+use dyon_interactive::record::Recorder;
 use image::RgbaImage;
 use piston::input::Event;
 use piston::window::WindowSettings;
@@ -29,9 +33,119 @@ enum Sound {
     Name(Arc<String>),
 }
 
+/// Plays audio with `piston-music`.
+struct MusicAudio {
+    music: Vec<Arc<String>>,
+    sounds: Vec<Arc<String>>,
+    /// Music bound to names by `bind_music__name_file`.
+    music_names: HashMap<Arc<String>, usize>,
+    /// Sounds bound to names by `bind_sound__name_file`.
+    sound_names: HashMap<Arc<String>, usize>,
+    volume: f64,
+}
+
+impl MusicAudio {
+    fn new() -> MusicAudio {
+        MusicAudio {
+            music: vec![],
+            sounds: vec![],
+            music_names: HashMap::new(),
+            sound_names: HashMap::new(),
+            volume: 1.0,
+        }
+    }
+
+    fn music_id(&self, name: &Arc<String>) -> Result<usize, String> {
+        self.music_names.get(name).cloned()
+            .ok_or_else(|| format!("Music `{}` is not bound", name))
+    }
+
+    fn sound_id(&self, name: &Arc<String>) -> Result<usize, String> {
+        self.sound_names.get(name).cloned()
+            .ok_or_else(|| format!("Sound `{}` is not bound", name))
+    }
+
+    /// Plays sound with its own volume.
+    fn play_sound_repeat_volume(
+        &mut self,
+        sound: usize,
+        repeat: Repeat,
+        volume: f64
+    ) -> Result<(), String> {
+        let file = self.sounds.get(sound).ok_or_else(|| "Sound index outside range".to_owned())?;
+        music::play_sound(&Sound::Name(file.clone()), music_repeat(repeat), volume);
+        Ok(())
+    }
+}
+
+fn music_repeat(repeat: Repeat) -> music::Repeat {
+    match repeat {
+        Repeat::Forever => music::Repeat::Forever,
+        Repeat::Times(n) => music::Repeat::Times(n),
+    }
+}
+
+impl AudioBackend for MusicAudio {
+    fn load_music(&mut self, file: &str) -> Result<usize, String> {
+        let file = Arc::new(file.to_owned());
+        music::bind_music_file(Music::Name(file.clone()), &**file);
+        self.music.push(file);
+        Ok(self.music.len() - 1)
+    }
+
+    fn load_sound(&mut self, file: &str) -> Result<usize, String> {
+        let file = Arc::new(file.to_owned());
+        music::bind_sound_file(Sound::Name(file.clone()), &**file);
+        self.sounds.push(file);
+        Ok(self.sounds.len() - 1)
+    }
+
+    fn play_sound(&mut self, sound: usize) -> Result<(), String> {
+        let volume = self.volume;
+        self.play_sound_repeat_volume(sound, Repeat::Times(0), volume)
+    }
+
+    fn play_music(&mut self, id: usize, repeat: Repeat) -> Result<(), String> {
+        let file = self.music.get(id).ok_or_else(|| "Music index outside range".to_owned())?;
+        music::play_music(&Music::Name(file.clone()), music_repeat(repeat));
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f64) {
+        self.volume = volume;
+        music::set_volume(volume);
+    }
+
+    fn stop_music(&mut self) {
+        // `piston-music` plays music with SDL2 mixer, which can halt it.
+        sdl2::mixer::Music::halt();
+    }
+}
+
 fn main() {
-    let file = std::env::args_os().nth(1)
-        .and_then(|s| s.into_string().ok());
+    let mut args = std::env::args_os().skip(1)
+        .filter_map(|s| s.into_string().ok());
+    let file = args.next();
+    // Records events for replay, e.g. to reproduce bugs.
+    // The seed is recorded such that random numbers are the same on replay.
+    let seed = {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() ^ d.as_secs() as u32).unwrap_or(0)
+    };
+    let mut recorder = match (args.next(), args.next()) {
+        (Some(ref flag), Some(ref record)) if flag == "--record" => {
+            match Recorder::create(record, seed) {
+                Ok(x) => Some(x),
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            }
+        }
+        _ => None
+    };
     let file = if let Some(file) = file {
         use std::env::set_current_dir;
         use std::path::PathBuf;
@@ -47,7 +161,7 @@ fn main() {
             file
         }
     } else {
-        println!("dyongame <file.dyon>");
+        println!("dyongame <file.dyon> [--record <events.dyon>]");
         return;
     };
 
@@ -66,6 +180,9 @@ fn main() {
 
     let mut factory = ();
     let mut dyon_runtime = Runtime::new();
+    if recorder.is_some() {
+        dyon_runtime.set_seed(u64::from(seed));
+    }
     let fira_sans = include_bytes!("../assets/FiraSans-Regular.ttf");
     let hack = include_bytes!("../assets/Hack-Regular.ttf");
     let font_texture_settings = TextureSettings::new().filter(Filter::Nearest);
@@ -88,6 +205,9 @@ fn main() {
     let factory_guard: CurrentGuard<()> = CurrentGuard::new(&mut factory);
     let window_guard = CurrentGuard::new(&mut window);
     let event_guard: CurrentGuard<Option<Event>> = CurrentGuard::new(&mut e);
+    let recorder_guard: CurrentGuard<Option<Recorder>> = CurrentGuard::new(&mut recorder);
+    let mut audio = MusicAudio::new();
+    let audio_guard: CurrentGuard<MusicAudio> = CurrentGuard::new(&mut audio);
     let glyphs_guard: CurrentGuard<Vec<GlyphCache>> = CurrentGuard::new(&mut glyphs);
     let font_names_guard: CurrentGuard<FontNames> = CurrentGuard::new(&mut font_names);
     let images_guard: CurrentGuard<Vec<RgbaImage>> = CurrentGuard::new(&mut images);
@@ -109,6 +229,8 @@ fn main() {
     drop(images_guard);
     drop(font_names_guard);
     drop(glyphs_guard);
+    drop(audio_guard);
+    drop(recorder_guard);
     drop(event_guard);
     drop(window_guard);
     drop(factory_guard);
@@ -116,10 +238,12 @@ fn main() {
 
 fn load_module(file: &str) -> Option<Module> {
     use dyon_functions::*;
-    use dyon_interactive::add_functions;
+    use dyon_interactive::{add_functions, add_gui, add_render};
+    use dyon_interactive::audio::add_audio_functions;
 
     let mut module = Module::new();
     add_functions::<Sdl2Window, (), GlyphCache>(&mut module);
+    add_audio_functions::<MusicAudio>(&mut module);
     module.add(Arc::new("render_source".into()), render_source, Dfn {
         lts: vec![],
         tys: vec![],
@@ -193,11 +317,11 @@ fn load_module(file: &str) -> Option<Module> {
         }
     );
 
-    if error(dyon::load_str(
-        "render.dyon",
-        Arc::new(include_str!("../src/render.dyon").into()),
-        &mut module
-    )) {
+    if error(add_render(&mut module)) {
+        return None;
+    }
+
+    if error(add_gui(&mut module)) {
         return None;
     }
 
@@ -215,10 +339,11 @@ mod dyon_functions {
     use opengl_graphics::{GlGraphics, GlyphCache, Texture, TextureSettings};
     use dyon::Runtime;
     use dyon_interactive::{draw_2d, NO_EVENT};
+    use dyon_interactive::record::Recorder;
     use current::Current;
     use std::sync::Arc;
-    use music;
-    use {Music, Sound};
+    use dyon_interactive::audio::{AudioBackend, Repeat};
+    use MusicAudio;
     use image::RgbaImage;
 
     dyon_fn!{fn render_source() -> String {include_str!("../src/render.dyon").into()}}
@@ -280,7 +405,11 @@ mod dyon_functions {
         let window = unsafe { &mut *Current::<Sdl2Window>::new() };
         let events = unsafe { &mut *Current::<Events>::new() };
         let e = unsafe { &mut *Current::<Option<Event>>::new() };
+        let recorder = unsafe { &mut *Current::<Option<Recorder>>::new() };
         if let Some(new_e) = events.next(window) {
+            if let Some(ref mut recorder) = *recorder {
+                recorder.record(&new_e)?;
+            }
             *e = Some(new_e);
             rt.push(true);
         } else {
@@ -290,62 +419,63 @@ mod dyon_functions {
         Ok(())
     }
 
+    // The functions using names are kept for compatibility,
+    // and play audio through the same backend as `play_sound` and `play_music__repeat`.
+
     #[allow(non_snake_case)]
     pub fn bind_sound__name_file(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<MusicAudio>::new() };
         let file: Arc<String> = rt.pop()?;
         let name: Arc<String> = rt.pop()?;
-        music::bind_sound_file(Sound::Name(name), &**file);
+        let id = audio.load_sound(&file)?;
+        audio.sound_names.insert(name, id);
         Ok(())
     }
 
     #[allow(non_snake_case)]
     pub fn bind_music__name_file(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<MusicAudio>::new() };
         let file: Arc<String> = rt.pop()?;
         let name: Arc<String> = rt.pop()?;
-        music::bind_music_file(Music::Name(name), &**file);
+        let id = audio.load_music(&file)?;
+        audio.music_names.insert(name, id);
         Ok(())
     }
 
     #[allow(non_snake_case)]
     pub fn play_sound__name_repeat_volume(rt: &mut Runtime) -> Result<(), String> {
-        use music::Repeat;
-
+        let audio = unsafe { &mut *Current::<MusicAudio>::new() };
         let volume: f64 = rt.pop()?;
         let repeat: f64 = rt.pop()?;
         let name: Arc<String> = rt.pop()?;
-        let repeat = if repeat == -1.0 {Repeat::Forever} else {Repeat::Times(repeat as u16)};
-        music::play_sound(&Sound::Name(name), repeat, volume);
-        Ok(())
+        let id = audio.sound_id(&name)?;
+        audio.play_sound_repeat_volume(id, Repeat::from_f64(repeat), volume)
     }
 
     #[allow(non_snake_case)]
     pub fn play_sound_forever__name_volume(rt: &mut Runtime) -> Result<(), String> {
-        use music::Repeat;
-
+        let audio = unsafe { &mut *Current::<MusicAudio>::new() };
         let volume: f64 = rt.pop()?;
         let name: Arc<String> = rt.pop()?;
-        music::play_sound(&Sound::Name(name), Repeat::Forever, volume);
-        Ok(())
+        let id = audio.sound_id(&name)?;
+        audio.play_sound_repeat_volume(id, Repeat::Forever, volume)
     }
 
     #[allow(non_snake_case)]
     pub fn play_music__name_repeat(rt: &mut Runtime) -> Result<(), String> {
-        use music::Repeat;
-
+        let audio = unsafe { &mut *Current::<MusicAudio>::new() };
         let repeat: f64 = rt.pop()?;
         let name: Arc<String> = rt.pop()?;
-        let repeat = if repeat == -1.0 {Repeat::Forever} else {Repeat::Times(repeat as u16)};
-        music::play_music(&Music::Name(name), repeat);
-        Ok(())
+        let id = audio.music_id(&name)?;
+        audio.play_music(id, Repeat::from_f64(repeat))
     }
 
     #[allow(non_snake_case)]
     pub fn play_music_forever__name(rt: &mut Runtime) -> Result<(), String> {
-        use music::Repeat;
-
+        let audio = unsafe { &mut *Current::<MusicAudio>::new() };
         let name: Arc<String> = rt.pop()?;
-        music::play_music(&Music::Name(name), Repeat::Forever);
-        Ok(())
+        let id = audio.music_id(&name)?;
+        audio.play_music(id, Repeat::Forever)
     }
 
     pub fn set_music_volume(rt: &mut Runtime) -> Result<(), String> {
@@ -0,0 +1,337 @@
+//! Runs a Dyon script without a window, rendering into images.
+//!
+//! Use `draw(image: _, list: _)` to render a draw list into an image,
+//! and `save(image: _, file: _)` to write it to disk.
+//!
+//! With `--replay <events.dyon>`, events recorded by `dyongame --record`
+//! are fed to the script through `next_event`, using the recorded seed,
+//! and `draw(list)` renders into an offscreen frame with the recorded draw size.
+//! The window functions and the other functions of `dyongame` are available,
+//! but the window is not shown and audio is only recorded.
+
+#[macro_use]
+extern crate dyon;
+extern crate current;
+extern crate piston;
+extern crate dyon_interactive;
+extern crate image;
+extern crate texture;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use current::CurrentGuard;
+use dyon::{error, load, Module, Runtime};
+use dyon_interactive::{FontNames, ImageNames};
+use dyon_interactive::audio::RecordingAudio;
+use dyon_interactive::record::Replay;
+use dyon_interactive::software::{GlyphCache, Texture};
+use image::RgbaImage;
+use piston::event_loop::{EventSettings, Events};
+use piston::input::{Event, RenderArgs};
+use piston::window::{NoWindow, WindowSettings};
+use texture::TextureSettings;
+
+/// The frame size of recordings without window size and draw size.
+const FRAME_SIZE: u32 = 512;
+
+/// Sounds and music bound to names by `bind_sound__name_file` and `bind_music__name_file`.
+#[derive(Default)]
+struct AudioNames {
+    music: HashMap<Arc<String>, usize>,
+    sounds: HashMap<Arc<String>, usize>,
+}
+
+fn main() {
+    let mut args = std::env::args_os().skip(1)
+        .filter_map(|s| s.into_string().ok());
+    let file = if let Some(file) = args.next() {
+        file
+    } else {
+        println!("dyonrender <file.dyon> [--replay <events.dyon>]");
+        return;
+    };
+    let mut replay = match (args.next(), args.next()) {
+        (Some(ref flag), Some(ref replay)) if flag == "--replay" => {
+            let render_args = RenderArgs {
+                ext_dt: 0.0,
+                width: FRAME_SIZE,
+                height: FRAME_SIZE,
+                draw_width: FRAME_SIZE,
+                draw_height: FRAME_SIZE,
+            };
+            match Replay::load_file(replay, &render_args) {
+                Ok(x) => Some(x),
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            }
+        }
+        _ => None
+    };
+
+    let dyon_module = match load_module(&file, replay.is_some()) {
+        None => return,
+        Some(m) => Arc::new(m)
+    };
+
+    let mut factory = ();
+    let mut dyon_runtime = Runtime::new();
+    if let Some(seed) = replay.as_ref().and_then(|replay| replay.seed()) {
+        dyon_runtime.set_seed(seed);
+    }
+    let fira_sans = include_bytes!("../assets/FiraSans-Regular.ttf");
+    let hack = include_bytes!("../assets/Hack-Regular.ttf");
+    let mut glyphs = vec![
+        GlyphCache::from_bytes(&fira_sans[..], (), TextureSettings::new()).unwrap(),
+        GlyphCache::from_bytes(&hack[..], (), TextureSettings::new()).unwrap()
+    ];
+    let mut font_names = FontNames(vec![
+        Arc::new("FiraSans-Regular".to_owned()),
+        Arc::new("Hack-Regular".to_owned()),
+    ]);
+    let mut images = vec![];
+    let mut image_names = ImageNames(vec![]);
+    let mut textures: Vec<Texture> = vec![];
+    let mut e: Option<Event> = None;
+    let mut frame = RgbaImage::new(FRAME_SIZE, FRAME_SIZE);
+    let mut audio = RecordingAudio::new();
+    let mut audio_names = AudioNames::default();
+    let mut window = NoWindow::new(&WindowSettings::new("dyonrender", [FRAME_SIZE; 2]));
+    let mut events = Events::new(EventSettings::new());
+
+    let factory_guard: CurrentGuard<()> = CurrentGuard::new(&mut factory);
+    let glyphs_guard: CurrentGuard<Vec<GlyphCache>> = CurrentGuard::new(&mut glyphs);
+    let font_names_guard: CurrentGuard<FontNames> = CurrentGuard::new(&mut font_names);
+    let images_guard: CurrentGuard<Vec<RgbaImage>> = CurrentGuard::new(&mut images);
+    let image_names_guard: CurrentGuard<ImageNames> = CurrentGuard::new(&mut image_names);
+    let textures_guard: CurrentGuard<Vec<Texture>> = CurrentGuard::new(&mut textures);
+    let replay_guard: Option<CurrentGuard<Replay>> = replay.as_mut().map(CurrentGuard::new);
+    let event_guard: CurrentGuard<Option<Event>> = CurrentGuard::new(&mut e);
+    let frame_guard: CurrentGuard<RgbaImage> = CurrentGuard::new(&mut frame);
+    let audio_guard: CurrentGuard<RecordingAudio> = CurrentGuard::new(&mut audio);
+    let audio_names_guard: CurrentGuard<AudioNames> = CurrentGuard::new(&mut audio_names);
+    let window_guard: CurrentGuard<NoWindow> = CurrentGuard::new(&mut window);
+    let events_guard: CurrentGuard<Events> = CurrentGuard::new(&mut events);
+
+    error(dyon_runtime.run(&dyon_module));
+
+    drop(events_guard);
+    drop(window_guard);
+    drop(audio_names_guard);
+    drop(audio_guard);
+    drop(frame_guard);
+    drop(event_guard);
+    drop(replay_guard);
+    drop(textures_guard);
+    drop(image_names_guard);
+    drop(images_guard);
+    drop(font_names_guard);
+    drop(glyphs_guard);
+    drop(factory_guard);
+}
+
+fn load_module(file: &str, replay: bool) -> Option<Module> {
+    use dyon::{Dfn, Lt, Type};
+    use dyon_interactive::{add_event_functions, add_gui, add_render, add_window_functions};
+    use dyon_interactive::audio::add_audio_functions;
+    use dyon_interactive::record::add_replay_functions;
+    use dyon_interactive::software::add_functions;
+
+    let mut module = Module::new();
+    add_functions(&mut module);
+    // Audio is not played, only recorded.
+    add_audio_functions::<RecordingAudio>(&mut module);
+    if replay {
+        use dyongame_functions::*;
+
+        add_window_functions::<NoWindow>(&mut module);
+        add_event_functions(&mut module);
+        add_replay_functions(&mut module);
+        module.add(Arc::new("draw".into()), draw, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::array()],
+            ret: Type::Void
+        });
+        // The same functions as in `dyongame`, such that games can be replayed.
+        module.add(Arc::new("render_source".into()), render_source, Dfn {
+            lts: vec![],
+            tys: vec![],
+            ret: Type::Text
+        });
+        module.add(Arc::new("bind_sound__name_file".into()),
+            bind_sound__name_file, Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Type::Text; 2],
+                ret: Type::Void
+            });
+        module.add(Arc::new("bind_music__name_file".into()),
+            bind_music__name_file, Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Type::Text; 2],
+                ret: Type::Void
+            });
+        module.add(Arc::new("play_sound__name_repeat_volume".into()),
+            play_sound__name_repeat_volume, Dfn {
+                lts: vec![Lt::Default; 3],
+                tys: vec![Type::Text, Type::F64, Type::F64],
+                ret: Type::Void
+            });
+        module.add(Arc::new("play_sound_forever__name_volume".into()),
+            play_sound_forever__name_volume, Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Type::Text, Type::F64],
+                ret: Type::Void
+            });
+        module.add(Arc::new("play_music__name_repeat".into()),
+            play_music__name_repeat, Dfn {
+                lts: vec![Lt::Default; 2],
+                tys: vec![Type::Text, Type::F64],
+                ret: Type::Void
+            });
+        module.add(Arc::new("play_music_forever__name".into()),
+            play_music_forever__name, Dfn {
+                lts: vec![Lt::Default; 1],
+                tys: vec![Type::Text],
+                ret: Type::Void
+            });
+        module.add(Arc::new("set_music_volume".into()),
+            set_music_volume, Dfn {
+                lts: vec![Lt::Default],
+                tys: vec![Type::F64],
+                ret: Type::Void
+            });
+    }
+
+    if error(add_render(&mut module)) {
+        return None;
+    }
+
+    if error(add_gui(&mut module)) {
+        return None;
+    }
+
+    if error(load(file, &mut module)) {
+        None
+    } else {
+        Some(module)
+    }
+}
+
+/// Renders draw list into the offscreen frame.
+///
+/// The frame and window are resized to the sizes of the render event.
+fn draw(rt: &mut Runtime) -> Result<(), String> {
+    use current::Current;
+    use piston::input::RenderEvent;
+    use piston::window::AdvancedWindow;
+    use dyon_interactive::NO_EVENT;
+    use dyon_interactive::software::draw_image_viewport;
+
+    let e = unsafe { &*Current::<Option<Event>>::new() };
+    let window = unsafe { &mut *Current::<NoWindow>::new() };
+    let frame = unsafe { &mut *Current::<RgbaImage>::new() };
+    let glyphs = unsafe { &mut *Current::<Vec<GlyphCache>>::new() };
+    let textures = unsafe { &mut *Current::<Vec<Texture>>::new() };
+    if let &Some(ref e) = e {
+        if let Some(args) = e.render_args() {
+            if frame.dimensions() != (args.draw_width, args.draw_height) {
+                *frame = RgbaImage::new(args.draw_width, args.draw_height);
+            }
+            window.set_size([args.width, args.height]);
+            draw_image_viewport(rt, frame, args.viewport(), glyphs, textures)
+        } else {
+            Ok(())
+        }
+    } else {
+        Err(NO_EVENT.into())
+    }
+}
+
+/// Functions of `dyongame` that are not in the library,
+/// where audio is recorded instead of played.
+mod dyongame_functions {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use current::Current;
+    use dyon::Runtime;
+    use dyon_interactive::audio::{AudioBackend, RecordingAudio, Repeat};
+    use AudioNames;
+
+    dyon_fn!{fn render_source() -> String {include_str!("../src/render.dyon").into()}}
+
+    fn sound_id(names: &HashMap<Arc<String>, usize>, name: &Arc<String>) -> Result<usize, String> {
+        names.get(name).cloned().ok_or_else(|| format!("Sound `{}` is not bound", name))
+    }
+
+    fn music_id(names: &HashMap<Arc<String>, usize>, name: &Arc<String>) -> Result<usize, String> {
+        names.get(name).cloned().ok_or_else(|| format!("Music `{}` is not bound", name))
+    }
+
+    #[allow(non_snake_case)]
+    pub fn bind_sound__name_file(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<RecordingAudio>::new() };
+        let names = unsafe { &mut *Current::<AudioNames>::new() };
+        let file: Arc<String> = rt.pop()?;
+        let name: Arc<String> = rt.pop()?;
+        let id = audio.load_sound(&file)?;
+        names.sounds.insert(name, id);
+        Ok(())
+    }
+
+    #[allow(non_snake_case)]
+    pub fn bind_music__name_file(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<RecordingAudio>::new() };
+        let names = unsafe { &mut *Current::<AudioNames>::new() };
+        let file: Arc<String> = rt.pop()?;
+        let name: Arc<String> = rt.pop()?;
+        let id = audio.load_music(&file)?;
+        names.music.insert(name, id);
+        Ok(())
+    }
+
+    // Repeat and volume of sounds are not recorded.
+
+    #[allow(non_snake_case)]
+    pub fn play_sound__name_repeat_volume(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<RecordingAudio>::new() };
+        let names = unsafe { &*Current::<AudioNames>::new() };
+        let _volume: f64 = rt.pop()?;
+        let _repeat: f64 = rt.pop()?;
+        let name: Arc<String> = rt.pop()?;
+        audio.play_sound(sound_id(&names.sounds, &name)?)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn play_sound_forever__name_volume(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<RecordingAudio>::new() };
+        let names = unsafe { &*Current::<AudioNames>::new() };
+        let _volume: f64 = rt.pop()?;
+        let name: Arc<String> = rt.pop()?;
+        audio.play_sound(sound_id(&names.sounds, &name)?)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn play_music__name_repeat(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<RecordingAudio>::new() };
+        let names = unsafe { &*Current::<AudioNames>::new() };
+        let repeat: f64 = rt.pop()?;
+        let name: Arc<String> = rt.pop()?;
+        audio.play_music(music_id(&names.music, &name)?, Repeat::from_f64(repeat))
+    }
+
+    #[allow(non_snake_case)]
+    pub fn play_music_forever__name(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<RecordingAudio>::new() };
+        let names = unsafe { &*Current::<AudioNames>::new() };
+        let name: Arc<String> = rt.pop()?;
+        audio.play_music(music_id(&names.music, &name)?, Repeat::Forever)
+    }
+
+    pub fn set_music_volume(rt: &mut Runtime) -> Result<(), String> {
+        let audio = unsafe { &mut *Current::<RecordingAudio>::new() };
+        let volume: f64 = rt.pop()?;
+        audio.set_volume(volume);
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! Audio playback with a generic backend.
+//!
+//! The host sets up an `A: AudioBackend` as current object,
+//! and adds the functions with `add_audio_functions::<A>`.
+//!
+//! `RecordingAudio` plays nothing, but stores what was triggered,
+//! which is useful for testing without an audio device.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use current::Current;
+use dyon::{Dfn, Lt, Module, Runtime, Type};
+
+/// How many times to play sound or music.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Repeat {
+    /// Repeats forever.
+    Forever,
+    /// Repeats a number of times after playing once.
+    Times(u16),
+}
+
+impl Repeat {
+    /// Converts from Dyon, where `-1` means forever.
+    pub fn from_f64(repeat: f64) -> Repeat {
+        if repeat == -1.0 {Repeat::Forever} else {Repeat::Times(repeat as u16)}
+    }
+}
+
+/// Implemented by audio backends.
+///
+/// Sounds and music are referenced by the index returned when loading them.
+pub trait AudioBackend {
+    /// Loads music from file.
+    fn load_music(&mut self, file: &str) -> Result<usize, String>;
+    /// Loads sound from file.
+    fn load_sound(&mut self, file: &str) -> Result<usize, String>;
+    /// Plays sound once.
+    fn play_sound(&mut self, sound: usize) -> Result<(), String>;
+    /// Plays music, replacing any music that is playing.
+    fn play_music(&mut self, music: usize, repeat: Repeat) -> Result<(), String>;
+    /// Sets volume between 0 and 1.
+    fn set_volume(&mut self, volume: f64);
+    /// Stops music.
+    fn stop_music(&mut self);
+}
+
+/// Stores what was triggered by audio functions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioEvent {
+    /// Played sound.
+    PlaySound(Arc<String>),
+    /// Played music.
+    PlayMusic(Arc<String>, Repeat),
+    /// Set volume.
+    SetVolume(f64),
+    /// Stopped music.
+    StopMusic,
+}
+
+/// Audio backend that records events instead of playing them.
+#[derive(Default)]
+pub struct RecordingAudio {
+    /// Loaded music files.
+    pub music: Vec<Arc<String>>,
+    /// Loaded sound files.
+    pub sounds: Vec<Arc<String>>,
+    /// Events in the order they were triggered.
+    pub events: Vec<AudioEvent>,
+}
+
+impl RecordingAudio {
+    /// Creates a new recording audio backend.
+    pub fn new() -> RecordingAudio {Default::default()}
+}
+
+impl AudioBackend for RecordingAudio {
+    fn load_music(&mut self, file: &str) -> Result<usize, String> {
+        self.music.push(Arc::new(file.into()));
+        Ok(self.music.len() - 1)
+    }
+
+    fn load_sound(&mut self, file: &str) -> Result<usize, String> {
+        self.sounds.push(Arc::new(file.into()));
+        Ok(self.sounds.len() - 1)
+    }
+
+    fn play_sound(&mut self, sound: usize) -> Result<(), String> {
+        let file = self.sounds.get(sound).ok_or_else(|| "Sound index outside range".to_owned())?;
+        self.events.push(AudioEvent::PlaySound(file.clone()));
+        Ok(())
+    }
+
+    fn play_music(&mut self, music: usize, repeat: Repeat) -> Result<(), String> {
+        let file = self.music.get(music).ok_or_else(|| "Music index outside range".to_owned())?;
+        self.events.push(AudioEvent::PlayMusic(file.clone(), repeat));
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f64) {
+        self.events.push(AudioEvent::SetVolume(volume));
+    }
+
+    fn stop_music(&mut self) {
+        self.events.push(AudioEvent::StopMusic);
+    }
+}
+
+/// Adds audio functions to module, using a generic backend.
+///
+/// `A` is audio backend.
+pub fn add_audio_functions<A: Any + AudioBackend>(module: &mut Module) {
+    module.add(Arc::new("load_music".into()), load_music::<A>, Dfn {
+        lts: vec![Lt::Default],
+        tys: vec![Type::Text],
+        ret: Type::Result(Box::new(Type::F64))
+    });
+    module.add(Arc::new("load_sound".into()), load_sound::<A>, Dfn {
+        lts: vec![Lt::Default],
+        tys: vec![Type::Text],
+        ret: Type::Result(Box::new(Type::F64))
+    });
+    module.add(Arc::new("play_sound".into()), play_sound::<A>, Dfn {
+        lts: vec![Lt::Default],
+        tys: vec![Type::F64],
+        ret: Type::Void
+    });
+    module.add(Arc::new("play_music__repeat".into()), play_music__repeat::<A>, Dfn {
+        lts: vec![Lt::Default; 2],
+        tys: vec![Type::F64; 2],
+        ret: Type::Void
+    });
+    module.add(Arc::new("set_volume".into()), set_volume::<A>, Dfn {
+        lts: vec![Lt::Default],
+        tys: vec![Type::F64],
+        ret: Type::Void
+    });
+    module.add(Arc::new("stop_music".into()), stop_music::<A>, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Void
+    });
+}
+
+pub fn load_music<A: Any + AudioBackend>(rt: &mut Runtime) -> Result<(), String> {
+    let audio = unsafe { &mut *Current::<A>::new() };
+    let file: Arc<String> = rt.pop()?;
+    rt.push(audio.load_music(&file).map_err(Arc::new));
+    Ok(())
+}
+
+pub fn load_sound<A: Any + AudioBackend>(rt: &mut Runtime) -> Result<(), String> {
+    let audio = unsafe { &mut *Current::<A>::new() };
+    let file: Arc<String> = rt.pop()?;
+    rt.push(audio.load_sound(&file).map_err(Arc::new));
+    Ok(())
+}
+
+pub fn play_sound<A: Any + AudioBackend>(rt: &mut Runtime) -> Result<(), String> {
+    let audio = unsafe { &mut *Current::<A>::new() };
+    let sound: usize = rt.pop()?;
+    audio.play_sound(sound)
+}
+
+#[allow(non_snake_case)]
+pub fn play_music__repeat<A: Any + AudioBackend>(rt: &mut Runtime) -> Result<(), String> {
+    let audio = unsafe { &mut *Current::<A>::new() };
+    let repeat: f64 = rt.pop()?;
+    let music: usize = rt.pop()?;
+    audio.play_music(music, Repeat::from_f64(repeat))
+}
+
+pub fn set_volume<A: Any + AudioBackend>(rt: &mut Runtime) -> Result<(), String> {
+    let audio = unsafe { &mut *Current::<A>::new() };
+    let volume: f64 = rt.pop()?;
+    audio.set_volume(volume);
+    Ok(())
+}
+
+pub fn stop_music<A: Any + AudioBackend>(_rt: &mut Runtime) -> Result<(), String> {
+    let audio = unsafe { &mut *Current::<A>::new() };
+    audio.stop_music();
+    Ok(())
+}
@@ -0,0 +1,523 @@
+//! Bulk operations on images.
+//!
+//! These work on whole images or regions at once,
+//! which is much faster than setting pixels one by one from Dyon.
+//! Everything runs on the CPU, so it does not require a window.
+//!
+//! Uses the current `Vec<RgbaImage>`.
+
+use std::sync::Arc;
+
+use current::Current;
+use dyon::{Dfn, Lt, Module, Runtime, Type, Variable};
+use image::{Rgba, RgbaImage};
+
+/// How source pixels are combined with destination pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    /// Replaces destination.
+    Replace,
+    /// Blends using source alpha.
+    Alpha,
+    /// Adds source color.
+    Add,
+    /// Multiplies with source color.
+    Multiply,
+    /// Inverse multiplication of inverse colors, which brightens.
+    Screen,
+}
+
+impl BlendMode {
+    /// Converts from Dyon.
+    pub fn from_str(mode: &str) -> Result<BlendMode, String> {
+        Ok(match mode {
+            "replace" => BlendMode::Replace,
+            "alpha" => BlendMode::Alpha,
+            "add" => BlendMode::Add,
+            "multiply" => BlendMode::Multiply,
+            "screen" => BlendMode::Screen,
+            _ => return Err(format!("Unknown blend mode `{}`, expected \
+                `replace`, `alpha`, `add`, `multiply` or `screen`", mode))
+        })
+    }
+
+    /// Blends source color on top of destination color.
+    ///
+    /// Colors have straight (not premultiplied) alpha.
+    /// The blended color is used where the destination is opaque,
+    /// and composited over the destination using source alpha.
+    pub fn blend(self, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+        let f: fn(f32, f32) -> f32 = match self {
+            BlendMode::Replace => return src,
+            BlendMode::Alpha => |s, _| s,
+            BlendMode::Add => |s, d| (s + d).min(1.0),
+            BlendMode::Multiply => |s, d| s * d,
+            BlendMode::Screen => |s, d| 1.0 - (1.0 - s) * (1.0 - d),
+        };
+        let (sa, da) = (src[3], dst[3]);
+        let a = sa + da * (1.0 - sa);
+        if a <= 0.0 {return [0.0; 4]};
+        let c = |i: usize| {
+            let s = src[i] * (1.0 - da) + f(src[i], dst[i]) * da;
+            (s * sa + dst[i] * da * (1.0 - sa)) / a
+        };
+        [c(0), c(1), c(2), a]
+    }
+}
+
+/// How pixels are sampled when resizing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// Uses the nearest pixel.
+    Nearest,
+    /// Interpolates between the 4 nearest pixels.
+    Bilinear,
+}
+
+impl Filter {
+    /// Converts from Dyon.
+    pub fn from_str(filter: &str) -> Result<Filter, String> {
+        match filter {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            _ => Err(format!("Unknown filter `{}`, expected `nearest` or `bilinear`", filter))
+        }
+    }
+}
+
+/// Converts pixel to color.
+pub fn to_color(pixel: &Rgba<u8>) -> [f32; 4] {
+    [
+        f32::from(pixel.data[0]) / 255.0,
+        f32::from(pixel.data[1]) / 255.0,
+        f32::from(pixel.data[2]) / 255.0,
+        f32::from(pixel.data[3]) / 255.0
+    ]
+}
+
+/// Converts color to pixel.
+pub fn to_pixel(color: [f32; 4]) -> Rgba<u8> {
+    fn to_u8(x: f32) -> u8 {(x.max(0.0).min(1.0) * 255.0 + 0.5) as u8}
+
+    Rgba {data: [to_u8(color[0]), to_u8(color[1]), to_u8(color[2]), to_u8(color[3])]}
+}
+
+/// Clips rectangle `[x, y, w, h]` to image, returning pixel bounds `[x0, y0, x1, y1]`.
+fn clip(image: &RgbaImage, rect: [f64; 4]) -> [u32; 4] {
+    let (w, h) = image.dimensions();
+    let clamp = |x: f64, max: u32| x.max(0.0).min(f64::from(max)) as u32;
+    [
+        clamp(rect[0], w),
+        clamp(rect[1], h),
+        clamp(rect[0] + rect[2], w),
+        clamp(rect[1] + rect[3], h)
+    ]
+}
+
+/// Fills rectangle with color.
+pub fn fill(image: &mut RgbaImage, corner: [f64; 2], size: [f64; 2], color: [f32; 4]) {
+    let [x0, y0, x1, y1] = clip(image, [corner[0], corner[1], size[0], size[1]]);
+    let pixel = to_pixel(color);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            image.put_pixel(x, y, pixel);
+        }
+    }
+}
+
+/// Draws a region of source image at position in destination image.
+///
+/// The region is `[x, y, w, h]` in source pixels.
+/// Parts outside either image are skipped.
+pub fn blit(
+    dst: &mut RgbaImage,
+    pos: [f64; 2],
+    src: &RgbaImage,
+    src_rect: [f64; 4],
+    mode: BlendMode
+) {
+    let [sx0, sy0, sx1, sy1] = clip(src, src_rect);
+    let (dw, dh) = dst.dimensions();
+    // Offset from source to destination.
+    let dx = pos[0].floor() as i64 - src_rect[0].floor() as i64;
+    let dy = pos[1].floor() as i64 - src_rect[1].floor() as i64;
+    for sy in sy0..sy1 {
+        let y = i64::from(sy) + dy;
+        if y < 0 || y >= i64::from(dh) {continue};
+        for sx in sx0..sx1 {
+            let x = i64::from(sx) + dx;
+            if x < 0 || x >= i64::from(dw) {continue};
+            let (x, y) = (x as u32, y as u32);
+            let color = mode.blend(to_color(src.get_pixel(sx, sy)), to_color(dst.get_pixel(x, y)));
+            dst.put_pixel(x, y, to_pixel(color));
+        }
+    }
+}
+
+/// Returns a resized copy of image.
+pub fn resize(image: &RgbaImage, w: u32, h: u32, filter: Filter) -> RgbaImage {
+    let (sw, sh) = image.dimensions();
+    if sw == 0 || sh == 0 {return RgbaImage::new(w, h)};
+    let scale_x = f64::from(sw) / f64::from(w.max(1));
+    let scale_y = f64::from(sh) / f64::from(h.max(1));
+    match filter {
+        Filter::Nearest => RgbaImage::from_fn(w, h, |x, y| {
+            let sx = ((f64::from(x) + 0.5) * scale_x) as u32;
+            let sy = ((f64::from(y) + 0.5) * scale_y) as u32;
+            *image.get_pixel(sx.min(sw - 1), sy.min(sh - 1))
+        }),
+        Filter::Bilinear => RgbaImage::from_fn(w, h, |x, y| {
+            let fx = ((f64::from(x) + 0.5) * scale_x - 0.5).max(0.0);
+            let fy = ((f64::from(y) + 0.5) * scale_y - 0.5).max(0.0);
+            let (x0, y0) = ((fx as u32).min(sw - 1), (fy as u32).min(sh - 1));
+            let (x1, y1) = ((x0 + 1).min(sw - 1), (y0 + 1).min(sh - 1));
+            let (tx, ty) = ((fx - f64::from(x0)) as f32, (fy - f64::from(y0)) as f32);
+            let (a, b) = (to_color(image.get_pixel(x0, y0)), to_color(image.get_pixel(x1, y0)));
+            let (c, d) = (to_color(image.get_pixel(x0, y1)), to_color(image.get_pixel(x1, y1)));
+            let mut color = [0.0; 4];
+            for i in 0..4 {
+                let top = a[i] + (b[i] - a[i]) * tx;
+                let bottom = c[i] + (d[i] - c[i]) * tx;
+                color[i] = top + (bottom - top) * ty;
+            }
+            to_pixel(color)
+        }),
+    }
+}
+
+/// Returns image flipped horizontally.
+pub fn flipped_horizontal(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| *image.get_pixel(w - 1 - x, y))
+}
+
+/// Returns image flipped vertically.
+pub fn flipped_vertical(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| *image.get_pixel(x, h - 1 - y))
+}
+
+/// Returns image rotated 90 degrees clockwise.
+pub fn rotated90(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    RgbaImage::from_fn(h, w, |x, y| *image.get_pixel(y, h - 1 - x))
+}
+
+/// Returns image rotated 180 degrees.
+pub fn rotated180(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| *image.get_pixel(w - 1 - x, h - 1 - y))
+}
+
+/// Returns image rotated 270 degrees clockwise.
+pub fn rotated270(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    RgbaImage::from_fn(h, w, |x, y| *image.get_pixel(w - 1 - y, x))
+}
+
+/// Returns image convolved with kernel.
+///
+/// The kernel is rows of weights with odd width and height,
+/// centered on each pixel. Pixels outside the image are clamped to the edge.
+/// All channels are filtered, including alpha.
+pub fn convolve(image: &RgbaImage, kernel: &[Vec<f64>]) -> Result<RgbaImage, String> {
+    let kh = kernel.len();
+    let kw = kernel.get(0).map(|row| row.len()).unwrap_or(0);
+    if kh % 2 == 0 || kw % 2 == 0 {
+        return Err("Expected kernel to have odd width and height".into());
+    }
+    if kernel.iter().any(|row| row.len() != kw) {
+        return Err("Expected all rows of kernel to have the same length".into());
+    }
+    let (w, h) = image.dimensions();
+    let (rx, ry) = ((kw / 2) as i64, (kh / 2) as i64);
+    Ok(RgbaImage::from_fn(w, h, |x, y| {
+        let mut sum = [0.0; 4];
+        for (j, row) in kernel.iter().enumerate() {
+            let sy = (i64::from(y) + j as i64 - ry).max(0).min(i64::from(h) - 1) as u32;
+            for (i, &weight) in row.iter().enumerate() {
+                let sx = (i64::from(x) + i as i64 - rx).max(0).min(i64::from(w) - 1) as u32;
+                let color = to_color(image.get_pixel(sx, sy));
+                for k in 0..4 {
+                    sum[k] += f64::from(color[k]) * weight;
+                }
+            }
+        }
+        to_pixel([sum[0] as f32, sum[1] as f32, sum[2] as f32, sum[3] as f32])
+    }))
+}
+
+/// Adds functions for bulk image operations to module.
+pub fn add_image_ops_functions(module: &mut Module) {
+    module.add(Arc::new("fill__image_corner_size_color".into()),
+        fill__image_corner_size_color, Dfn {
+            lts: vec![Lt::Default; 4],
+            tys: vec![Type::F64, Type::Vec4, Type::Vec4, Type::Vec4],
+            ret: Type::Void
+        });
+    module.add(Arc::new("blit__image_pos_from".into()),
+        blit__image_pos_from, Dfn {
+            lts: vec![Lt::Default; 3],
+            tys: vec![Type::F64, Type::Vec4, Type::F64],
+            ret: Type::Void
+        });
+    module.add(Arc::new("blit__image_pos_from_mode".into()),
+        blit__image_pos_from_mode, Dfn {
+            lts: vec![Lt::Default; 4],
+            tys: vec![Type::F64, Type::Vec4, Type::F64, Type::Text],
+            ret: Type::Void
+        });
+    module.add(Arc::new("blit__image_pos_from_srccorner_srcsize".into()),
+        blit__image_pos_from_srccorner_srcsize, Dfn {
+            lts: vec![Lt::Default; 5],
+            tys: vec![Type::F64, Type::Vec4, Type::F64, Type::Vec4, Type::Vec4],
+            ret: Type::Void
+        });
+    module.add(Arc::new("blit__image_pos_from_srccorner_srcsize_mode".into()),
+        blit__image_pos_from_srccorner_srcsize_mode, Dfn {
+            lts: vec![Lt::Default; 6],
+            tys: vec![Type::F64, Type::Vec4, Type::F64, Type::Vec4, Type::Vec4, Type::Text],
+            ret: Type::Void
+        });
+    module.add(Arc::new("resize__image_size".into()),
+        resize__image_size, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![Type::F64, Type::Vec4],
+            ret: Type::Void
+        });
+    module.add(Arc::new("resize__image_size_filter".into()),
+        resize__image_size_filter, Dfn {
+            lts: vec![Lt::Default; 3],
+            tys: vec![Type::F64, Type::Vec4, Type::Text],
+            ret: Type::Void
+        });
+    module.add(Arc::new("flip_horizontal".into()),
+        flip_horizontal, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::F64],
+            ret: Type::Void
+        });
+    module.add(Arc::new("flip_vertical".into()),
+        flip_vertical, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::F64],
+            ret: Type::Void
+        });
+    module.add(Arc::new("rotate90".into()),
+        rotate90, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::F64],
+            ret: Type::Void
+        });
+    module.add(Arc::new("rotate180".into()),
+        rotate180, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::F64],
+            ret: Type::Void
+        });
+    module.add(Arc::new("rotate270".into()),
+        rotate270, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::F64],
+            ret: Type::Void
+        });
+    module.add(Arc::new("pixels".into()),
+        pixels, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::F64],
+            ret: Type::Array(Box::new(Type::Vec4))
+        });
+    module.add(Arc::new("set_pixels__image_pixels".into()),
+        set_pixels__image_pixels, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![Type::F64, Type::Array(Box::new(Type::Vec4))],
+            ret: Type::Void
+        });
+    module.add(Arc::new("convolve__image_kernel".into()),
+        convolve__image_kernel, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![Type::F64, Type::Array(Box::new(Type::Array(Box::new(Type::F64))))],
+            ret: Type::Void
+        });
+}
+
+fn image_mut(images: &mut [RgbaImage], id: usize) -> Result<&mut RgbaImage, String> {
+    images.get_mut(id).ok_or_else(|| "Image id is out of bounds".to_owned())
+}
+
+#[allow(non_snake_case)]
+pub fn fill__image_corner_size_color(rt: &mut Runtime) -> Result<(), String> {
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    let color: [f32; 4] = rt.pop_vec4()?;
+    let size: [f64; 2] = rt.pop_vec4()?;
+    let corner: [f64; 2] = rt.pop_vec4()?;
+    let id: usize = rt.pop()?;
+    fill(image_mut(images, id)?, corner, size, color);
+    Ok(())
+}
+
+/// Blits with source region and blend mode, cloning source if it is the destination.
+fn blit_images(
+    dst: usize,
+    pos: [f64; 2],
+    src: usize,
+    src_rect: Option<[f64; 4]>,
+    mode: BlendMode
+) -> Result<(), String> {
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    if src >= images.len() || dst >= images.len() {
+        return Err("Image id is out of bounds".into());
+    }
+    let copy;
+    let (src, dst) = if src == dst {
+        copy = images[src].clone();
+        (&copy, &mut images[dst])
+    } else if src < dst {
+        let (a, b) = images.split_at_mut(dst);
+        (&a[src], &mut b[0])
+    } else {
+        let (a, b) = images.split_at_mut(src);
+        (&b[0], &mut a[dst])
+    };
+    let src_rect = src_rect.unwrap_or_else(|| {
+        let (w, h) = src.dimensions();
+        [0.0, 0.0, f64::from(w), f64::from(h)]
+    });
+    blit(dst, pos, src, src_rect, mode);
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn blit__image_pos_from(rt: &mut Runtime) -> Result<(), String> {
+    let src: usize = rt.pop()?;
+    let pos: [f64; 2] = rt.pop_vec4()?;
+    let dst: usize = rt.pop()?;
+    blit_images(dst, pos, src, None, BlendMode::Replace)
+}
+
+#[allow(non_snake_case)]
+pub fn blit__image_pos_from_mode(rt: &mut Runtime) -> Result<(), String> {
+    let mode: Arc<String> = rt.pop()?;
+    let src: usize = rt.pop()?;
+    let pos: [f64; 2] = rt.pop_vec4()?;
+    let dst: usize = rt.pop()?;
+    blit_images(dst, pos, src, None, BlendMode::from_str(&mode)?)
+}
+
+#[allow(non_snake_case)]
+pub fn blit__image_pos_from_srccorner_srcsize(rt: &mut Runtime) -> Result<(), String> {
+    let src_size: [f64; 2] = rt.pop_vec4()?;
+    let src_corner: [f64; 2] = rt.pop_vec4()?;
+    let src: usize = rt.pop()?;
+    let pos: [f64; 2] = rt.pop_vec4()?;
+    let dst: usize = rt.pop()?;
+    let src_rect = [src_corner[0], src_corner[1], src_size[0], src_size[1]];
+    blit_images(dst, pos, src, Some(src_rect), BlendMode::Replace)
+}
+
+#[allow(non_snake_case)]
+pub fn blit__image_pos_from_srccorner_srcsize_mode(rt: &mut Runtime) -> Result<(), String> {
+    let mode: Arc<String> = rt.pop()?;
+    let src_size: [f64; 2] = rt.pop_vec4()?;
+    let src_corner: [f64; 2] = rt.pop_vec4()?;
+    let src: usize = rt.pop()?;
+    let pos: [f64; 2] = rt.pop_vec4()?;
+    let dst: usize = rt.pop()?;
+    let src_rect = [src_corner[0], src_corner[1], src_size[0], src_size[1]];
+    blit_images(dst, pos, src, Some(src_rect), BlendMode::from_str(&mode)?)
+}
+
+fn resize_image(id: usize, size: [f64; 2], filter: Filter) -> Result<(), String> {
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    if size[0] < 0.0 || size[1] < 0.0 {
+        return Err("Expected image size to be non-negative".into());
+    }
+    let image = image_mut(images, id)?;
+    *image = resize(image, size[0] as u32, size[1] as u32, filter);
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn resize__image_size(rt: &mut Runtime) -> Result<(), String> {
+    let size: [f64; 2] = rt.pop_vec4()?;
+    let id: usize = rt.pop()?;
+    resize_image(id, size, Filter::Bilinear)
+}
+
+#[allow(non_snake_case)]
+pub fn resize__image_size_filter(rt: &mut Runtime) -> Result<(), String> {
+    let filter: Arc<String> = rt.pop()?;
+    let size: [f64; 2] = rt.pop_vec4()?;
+    let id: usize = rt.pop()?;
+    resize_image(id, size, Filter::from_str(&filter)?)
+}
+
+/// Replaces image with a transformed copy.
+fn transform_image(rt: &mut Runtime, f: fn(&RgbaImage) -> RgbaImage) -> Result<(), String> {
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    let id: usize = rt.pop()?;
+    let image = image_mut(images, id)?;
+    *image = f(image);
+    Ok(())
+}
+
+pub fn flip_horizontal(rt: &mut Runtime) -> Result<(), String> {
+    transform_image(rt, flipped_horizontal)
+}
+
+pub fn flip_vertical(rt: &mut Runtime) -> Result<(), String> {
+    transform_image(rt, flipped_vertical)
+}
+
+pub fn rotate90(rt: &mut Runtime) -> Result<(), String> {
+    transform_image(rt, rotated90)
+}
+
+pub fn rotate180(rt: &mut Runtime) -> Result<(), String> {
+    transform_image(rt, rotated180)
+}
+
+pub fn rotate270(rt: &mut Runtime) -> Result<(), String> {
+    transform_image(rt, rotated270)
+}
+
+pub fn pixels(rt: &mut Runtime) -> Result<(), String> {
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    let id: usize = rt.pop()?;
+    let image = image_mut(images, id)?;
+    let pixels: Vec<Variable> = image.pixels().map(|pixel| Variable::Vec4(to_color(pixel))).collect();
+    rt.stack.push(Variable::Array(Arc::new(pixels)));
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn set_pixels__image_pixels(rt: &mut Runtime) -> Result<(), String> {
+    use dyon::TINVOTS;
+
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    let pixels = rt.stack.pop().expect(TINVOTS);
+    let id: usize = rt.pop()?;
+    let image = image_mut(images, id)?;
+    let pixels = match rt.resolve(&pixels) {
+        &Variable::Array(ref arr) => arr.clone(),
+        x => return Err(rt.expected(x, "[vec4]"))
+    };
+    let (w, h) = image.dimensions();
+    if pixels.len() != w as usize * h as usize {
+        return Err(format!("Expected {} pixels, found {}", w as usize * h as usize, pixels.len()));
+    }
+    for (pixel, v) in image.pixels_mut().zip(pixels.iter()) {
+        *pixel = to_pixel(rt.var_vec4(rt.resolve(v))?);
+    }
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn convolve__image_kernel(rt: &mut Runtime) -> Result<(), String> {
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    let kernel: Vec<Vec<f64>> = rt.pop()?;
+    let id: usize = rt.pop()?;
+    let image = image_mut(images, id)?;
+    *image = convolve(image, &kernel)?;
+    Ok(())
+}
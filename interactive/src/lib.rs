@@ -6,6 +6,7 @@ extern crate texture;
 extern crate image;
 
 use std::any::Any;
+use std::collections::HashMap;
 use std::sync::Arc;
 use self::dyon::*;
 use self::current::Current;
@@ -13,10 +14,31 @@ use self::piston::input::*;
 use self::piston::window::*;
 use self::graphics::{Context, Graphics};
 use self::graphics::character::CharacterCache;
+use self::graphics::types::Matrix2d;
 use texture::CreateTexture;
 
+pub mod audio;
+pub mod image_ops;
+pub mod record;
+pub mod software;
+pub mod sprite;
+
 pub const NO_EVENT: &'static str = "No event";
 
+/// Loads `render.dyon` into module, which wraps draw list commands,
+/// e.g. `rectangle(color: #ff0000, corner: (0, 0), size: (10, 10))`.
+pub fn add_render(module: &mut Module) -> Result<(), String> {
+    load_str("render.dyon", Arc::new(include_str!("render.dyon").into()), module)
+}
+
+/// Loads `gui.dyon` into module, which adds immediate-mode widgets,
+/// e.g. `gui_button(id: "ok", text: "Ok")`.
+///
+/// The widgets draw with the functions added by `add_render`.
+pub fn add_gui(module: &mut Module) -> Result<(), String> {
+    load_str("gui.dyon", Arc::new(include_str!("gui.dyon").into()), module)
+}
+
 /// Adds functions to module, using a generic backend.
 ///
 /// `W` is window.
@@ -28,6 +50,18 @@ pub fn add_functions<W, F, C>(module: &mut Module)
           C::Texture: CreateTexture<F>,
           C: Any + CharacterCache,
 {
+    add_window_functions::<W>(module);
+    add_event_functions(module);
+    add_font_functions::<F, C>(module);
+    add_image_functions(module);
+    sprite::add_sprite_functions::<F, C::Texture>(module);
+}
+
+/// Adds functions for the window and event loop settings to module.
+///
+/// `W` is window.
+/// Uses the current `W` and `Events`.
+pub fn add_window_functions<W: Any + AdvancedWindow>(module: &mut Module) {
     module.add(Arc::new("window_size".into()), window_size::<W>, Dfn {
         lts: vec![],
         tys: vec![],
@@ -53,76 +87,6 @@ pub fn add_functions<W, F, C>(module: &mut Module)
         tys: vec![Type::Vec4],
         ret: Type::Void
     });
-    module.add(Arc::new("render".into()), render, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool
-    });
-    module.add(Arc::new("after_render".into()), after_render, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool
-    });
-    module.add(Arc::new("update".into()), update, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool
-    });
-    module.add(Arc::new("idle".into()), idle, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool
-    });
-    module.add(Arc::new("press".into()), press, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool
-    });
-    module.add(Arc::new("release".into()), release, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool
-    });
-    module.add(Arc::new("resize".into()), resize, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool,
-    });
-    module.add(Arc::new("focus".into()), focus, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool,
-    });
-    module.add(Arc::new("cursor".into()), cursor, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool,
-    });
-    module.add(Arc::new("text".into()), text, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool,
-    });
-    module.add(Arc::new("mouse_cursor".into()), mouse_cursor, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Bool
-    });
-    module.add(Arc::new("focus_arg".into()), focus_arg, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Option(Box::new(Type::Bool)),
-    });
-    module.add(Arc::new("cursor_arg".into()), cursor_arg, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Option(Box::new(Type::Bool)),
-    });
-    module.add(Arc::new("mouse_cursor_pos".into()), mouse_cursor_pos, Dfn {
-        lts: vec![],
-        tys: vec![],
-        ret: Type::Option(Box::new(Type::Vec4)),
-    });
     module.add(Arc::new("window_title".into()),
         window_title::<W>, Dfn {
             lts: vec![],
@@ -213,6 +177,83 @@ pub fn add_functions<W, F, C>(module: &mut Module)
             tys: vec![Type::Bool],
             ret: Type::Void
         });
+}
+
+/// Adds functions for reading the current event to module.
+///
+/// These only depend on the current `Option<Event>`,
+/// such that they can be used without a window, e.g. when replaying events.
+pub fn add_event_functions(module: &mut Module) {
+    module.add(Arc::new("render".into()), render, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+    module.add(Arc::new("after_render".into()), after_render, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+    module.add(Arc::new("update".into()), update, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+    module.add(Arc::new("idle".into()), idle, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+    module.add(Arc::new("press".into()), press, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+    module.add(Arc::new("release".into()), release, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+    module.add(Arc::new("resize".into()), resize, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool,
+    });
+    module.add(Arc::new("focus".into()), focus, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool,
+    });
+    module.add(Arc::new("cursor".into()), cursor, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool,
+    });
+    module.add(Arc::new("text".into()), text, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool,
+    });
+    module.add(Arc::new("mouse_cursor".into()), mouse_cursor, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+    module.add(Arc::new("focus_arg".into()), focus_arg, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Option(Box::new(Type::Bool)),
+    });
+    module.add(Arc::new("cursor_arg".into()), cursor_arg, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Option(Box::new(Type::Bool)),
+    });
+    module.add(Arc::new("mouse_cursor_pos".into()), mouse_cursor_pos, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Option(Box::new(Type::Vec4)),
+    });
     module.add(Arc::new("render_ext_dt".into()),
         render_ext_dt, Dfn {
             lts: vec![],
@@ -261,6 +302,17 @@ pub fn add_functions<W, F, C>(module: &mut Module)
             tys: vec![],
             ret: Type::Option(Box::new(Type::Text))
         });
+}
+
+/// Adds functions for fonts to module, using a generic backend.
+///
+/// `F` is factory (to create textures).
+/// `C` is character cache.
+pub fn add_font_functions<F, C>(module: &mut Module)
+    where F: 'static + Clone,
+          C::Texture: CreateTexture<F>,
+          C: Any + CharacterCache,
+{
     module.add(Arc::new("width__font_size_string".into()),
         width__font_size_string::<C>, Dfn {
             lts: vec![Lt::Default; 3],
@@ -281,6 +333,10 @@ pub fn add_functions<W, F, C>(module: &mut Module)
             ret: Type::Result(Box::new(Type::F64))
         }
     );
+}
+
+/// Adds functions for images to module.
+pub fn add_image_functions(module: &mut Module) {
     module.add(Arc::new("image_names".into()),
         image_names, Dfn {
             lts: vec![],
@@ -330,6 +386,7 @@ pub fn add_functions<W, F, C>(module: &mut Module)
             ret: Type::Vec4
         }
     );
+    image_ops::add_image_ops_functions(module);
 }
 
 pub fn window_size<W: Any + Window>(rt: &mut Runtime) -> Result<(), String> {
@@ -844,7 +901,191 @@ pub fn pxl__image_pos(rt: &mut Runtime) -> Result<(), String> {
     Ok(())
 }
 
+/// Draws a custom command.
+///
+/// The arguments are the items following the command name.
+/// The context transform includes the current `transform__rx_ry`.
+pub type DrawFn<G> = fn(
+    rt: &Runtime,
+    args: &[Variable],
+    c: Context,
+    g: &mut G
+) -> Result<(), String>;
+
+/// Checks whether a Rust object has the type of an ad-hoc type.
+pub type IsObject = fn(obj: &Any) -> bool;
+
+fn is_object<T: Any>(obj: &Any) -> bool {obj.is::<T>()}
+
+/// Signatures of draw commands and Rust object types.
+#[derive(Clone)]
+struct Signatures {
+    commands: HashMap<Arc<String>, Vec<Type>>,
+    objects: HashMap<Arc<String>, IsObject>,
+}
+
+impl Signatures {
+    fn built_in() -> Signatures {
+        use dyon::Type::*;
+        use sprite::{
+            sprite_batch_type, spritesheet_type, tilemap_type,
+            SpriteBatch, Spritesheet, Tilemap,
+        };
+
+        let mut commands = HashMap::new();
+        {
+            let mut add = |name: &str, tys: Vec<Type>| {
+                commands.insert(Arc::new(name.into()), tys);
+            };
+            add("clear", vec![Vec4]);
+            add("transform__rx_ry", vec![Vec4, Vec4]);
+            add("line__color_radius_from_to", vec![Vec4, F64, Vec4, Vec4]);
+            add("rectangle__color_corner_size", vec![Vec4, Vec4, Vec4]);
+            add("ellipse__color_corner_size_resolution", vec![Vec4, Vec4, Vec4, F64]);
+            add("ellipse__border_color_corner_size_resolution", vec![F64, Vec4, Vec4, Vec4, F64]);
+            add("text__font_color_size_pos_string", vec![F64, Vec4, F64, Vec4, Text]);
+            add("image__texture_pos_color", vec![F64, Vec4, Vec4]);
+            add("image__texture_pos_color_srccorner_srcsize", vec![F64, Vec4, Vec4, Vec4, Vec4]);
+            add("sprite__sheet_index_pos_color", vec![spritesheet_type(), F64, Vec4, Vec4]);
+            add("tilemap__map_pos_color", vec![tilemap_type(), Vec4, Vec4]);
+            add("sprites__batch", vec![sprite_batch_type()]);
+        }
+        let mut objects: HashMap<Arc<String>, IsObject> = HashMap::new();
+        objects.insert(Arc::new("Spritesheet".into()), is_object::<Spritesheet>);
+        objects.insert(Arc::new("Tilemap".into()), is_object::<Tilemap>);
+        objects.insert(Arc::new("SpriteBatch".into()), is_object::<SpriteBatch>);
+        Signatures {commands, objects}
+    }
+
+    /// Returns `true` if variable has type.
+    ///
+    /// Rust objects must match the registered type of an ad-hoc type.
+    fn is_type(&self, rt: &Runtime, ty: &Type, v: &Variable) -> bool {
+        match (ty, rt.resolve(v)) {
+            (&Type::Any, _) |
+            (&Type::Bool, &Variable::Bool(..)) |
+            (&Type::F64, &Variable::F64(..)) |
+            (&Type::Vec4, &Variable::Vec4(_)) |
+            (&Type::Mat4, &Variable::Mat4(_)) |
+            (&Type::Text, &Variable::Text(_)) |
+            (&Type::Object, &Variable::Object(_)) => true,
+            (&Type::Array(ref ty), &Variable::Array(ref arr)) =>
+                arr.iter().all(|v| self.is_type(rt, ty, v)),
+            (&Type::Option(ref ty), &Variable::Option(ref v)) =>
+                v.as_ref().map(|v| self.is_type(rt, ty, v)).unwrap_or(true),
+            (&Type::AdHoc(ref name, ref ty), v) => match (self.objects.get(name), v) {
+                (Some(is_object), &Variable::RustObject(ref obj)) =>
+                    obj.lock().map(|obj| is_object(&*obj)).unwrap_or(false),
+                (Some(_), _) => false,
+                (None, v) => self.is_type(rt, ty, v),
+            },
+            _ => false,
+        }
+    }
+}
+
+thread_local! {
+    /// Signatures of built-in draw commands, shared by new registries.
+    static BUILT_IN: Arc<Signatures> = Arc::new(Signatures::built_in());
+}
+
+/// Stores signatures of draw commands, used to check draw lists.
+///
+/// Custom commands are drawn by their registered function.
+pub struct DrawCommands<G> {
+    signatures: Arc<Signatures>,
+    draws: HashMap<Arc<String>, DrawFn<G>>,
+}
+
+impl<G: Graphics> Default for DrawCommands<G> {
+    fn default() -> DrawCommands<G> {DrawCommands::new()}
+}
+
+impl<G: Graphics> DrawCommands<G> {
+    /// Creates a new registry with the built-in draw commands.
+    ///
+    /// The built-in commands are shared, so this is cheap to call every frame.
+    pub fn new() -> DrawCommands<G> {
+        DrawCommands {
+            signatures: BUILT_IN.with(|s| s.clone()),
+            draws: HashMap::new(),
+        }
+    }
+
+    /// Registers a custom draw command.
+    ///
+    /// Replaces any existing command with the same name.
+    pub fn register_draw_command(&mut self, name: &str, tys: Vec<Type>, draw: DrawFn<G>) {
+        let name: Arc<String> = Arc::new(name.into());
+        Arc::make_mut(&mut self.signatures).commands.insert(name.clone(), tys);
+        self.draws.insert(name, draw);
+    }
+
+    /// Registers the Rust object type of an ad-hoc type used by draw commands,
+    /// e.g. `Spritesheet`.
+    pub fn register_object_type<T: Any>(&mut self, name: &str) {
+        Arc::make_mut(&mut self.signatures).objects
+            .insert(Arc::new(name.into()), is_object::<T>);
+    }
+
+    /// Returns the signature of a draw command, e.g. `["clear", vec4]`.
+    pub fn signature(&self, name: &str) -> Option<String> {
+        self.signatures.commands.get(&Arc::new(name.into())).map(|tys| {
+            let mut res = format!("[{:?}", name);
+            for ty in tys {
+                res.push_str(", ");
+                res.push_str(&ty.description());
+            }
+            res.push(']');
+            res
+        })
+    }
+
+    /// Checks that a draw list only contains known commands with arguments of right type.
+    pub fn check(&self, rt: &Runtime, draw_list: &Variable) -> Result<(), String> {
+        let arr = if let &Variable::Array(ref arr) = rt.resolve(draw_list) {
+            arr
+        } else {
+            return Err("Expected draw list to be an array".into());
+        };
+        for (i, it) in arr.iter().enumerate() {
+            self.check_item(rt, it).map_err(|err| format!("Draw list item {}: {}", i, err))?;
+        }
+        Ok(())
+    }
+
+    /// Checks a draw list item, returning the command name and arguments.
+    fn check_item<'a>(
+        &self,
+        rt: &'a Runtime,
+        it: &'a Variable
+    ) -> Result<(&'a Arc<String>, &'a [Variable]), String> {
+        let it = if let &Variable::Array(ref it) = rt.resolve(it) {
+            it
+        } else {
+            return Err("Expected array `[name, ..]`".into());
+        };
+        let name = if let Some(&Variable::Text(ref name)) = it.get(0).map(|x| rt.resolve(x)) {
+            name
+        } else {
+            return Err("Expected command name as first item".into());
+        };
+        let tys = if let Some(tys) = self.signatures.commands.get(name) {
+            tys
+        } else {
+            return Err(format!("Unknown draw command `{}`", name));
+        };
+        if it.len() != tys.len() + 1 ||
+           !tys.iter().zip(&it[1..]).all(|(ty, v)| self.signatures.is_type(rt, ty, v)) {
+            return Err(format!("Expected `{}`", self.signature(name).unwrap()));
+        }
+        Ok((name, &it[1..]))
+    }
+}
+
 /// Helper method for drawing 2D in Dyon environment.
+///
+/// Checks the draw list against the built-in draw commands before drawing.
 pub fn draw_2d<C: CharacterCache<Texture = G::Texture>, G: Graphics>(
     rt: &mut Runtime,
     glyphs: &mut Vec<C>,
@@ -852,106 +1093,166 @@ pub fn draw_2d<C: CharacterCache<Texture = G::Texture>, G: Graphics>(
     c: Context,
     g: &mut G
 ) -> Result<(), String> {
-    use self::graphics::*;
-    use self::graphics::types::Matrix2d;
+    draw_2d_with(rt, &DrawCommands::new(), glyphs, textures, c, g)
+}
 
+/// Helper method for drawing 2D in Dyon environment, with custom draw commands.
+///
+/// The whole draw list is checked before drawing, such that an invalid item
+/// draws nothing. Errors name the index of the draw list item that failed.
+pub fn draw_2d_with<C: CharacterCache<Texture = G::Texture>, G: Graphics>(
+    rt: &mut Runtime,
+    commands: &DrawCommands<G>,
+    glyphs: &mut Vec<C>,
+    textures: &mut Vec<G::Texture>,
+    c: Context,
+    g: &mut G
+) -> Result<(), String> {
     let draw_list = rt.stack.pop().expect(TINVOTS);
-    let arr = rt.resolve(&draw_list);
+    let rt: &Runtime = rt;
+    let arr = if let &Variable::Array(ref arr) = rt.resolve(&draw_list) {
+        arr
+    } else {
+        return Err("Expected draw list to be an array".into());
+    };
+    // Check the whole list before drawing anything.
+    commands.check(rt, &draw_list)?;
     let mut transform = c.transform;
-    if let &Variable::Array(ref arr) = arr {
-        for it in &**arr {
-            let it = rt.resolve(it);
-            if let &Variable::Array(ref it) = it {
-                let ty: Arc<String> = rt.var(&it[0])?;
-                match &**ty {
-                    "clear" => {
-                        let color: [f32; 4] = rt.var_vec4(&it[1])?;
-                        clear(color, g);
-                    }
-                    "transform__rx_ry" => {
-                        // Changes transform matrix.
-                        let rx: [f32; 4] = rt.var_vec4(&it[1])?;
-                        let ry: [f32; 4] = rt.var_vec4(&it[2])?;
-                        let t: Matrix2d = [
-                            [rx[0] as f64, rx[1] as f64, rx[2] as f64],
-                            [ry[0] as f64, ry[1] as f64, ry[2] as f64]
-                        ];
-                        transform = math::multiply(c.transform, t);
-                    }
-                    "line__color_radius_from_to" => {
-                        let color: [f32; 4] = rt.var_vec4(&it[1])?;
-                        let radius: f64 = rt.var(&it[2])?;
-                        let from: [f64; 2] = rt.var_vec4(&it[3])?;
-                        let to: [f64; 2] = rt.var_vec4(&it[4])?;
-                        line(color, radius, [from[0], from[1], to[0], to[1]], transform, g);
-                    }
-                    "rectangle__color_corner_size" => {
-                        let color: [f32; 4] = rt.var_vec4(&it[1])?;
-                        let corner: [f64; 2] = rt.var_vec4(&it[2])?;
-                        let size: [f64; 2] = rt.var_vec4(&it[3])?;
-                        rectangle(color, [corner[0], corner[1], size[0], size[1]], transform, g);
-                    }
-                    "ellipse__color_corner_size_resolution" => {
-                        let color: [f32; 4] = rt.var_vec4(&it[1])?;
-                        let corner: [f64; 2] = rt.var_vec4(&it[2])?;
-                        let size: [f64; 2] = rt.var_vec4(&it[3])?;
-                        let resolution: u32 = rt.var(&it[4])?;
-                        Ellipse::new(color)
-                        .resolution(resolution as u32)
-                        .draw([corner[0], corner[1], size[0], size[1]], &c.draw_state, transform, g);
-                    }
-                    "ellipse__border_color_corner_size_resolution" => {
-                        let border: f64 = rt.var(&it[1])?;
-                        let color: [f32; 4] = rt.var_vec4(&it[2])?;
-                        let corner: [f64; 2] = rt.var_vec4(&it[3])?;
-                        let size: [f64; 2] = rt.var_vec4(&it[4])?;
-                        let resolution: u32 = rt.var(&it[5])?;
-                        Ellipse::new_border(color, border)
-                        .resolution(resolution as u32)
-                        .draw([corner[0], corner[1], size[0], size[1]], &c.draw_state, transform, g);
-                    }
-                    "text__font_color_size_pos_string" => {
-                        let font: usize = rt.var(&it[1])?;
-                        let color: [f32; 4] = rt.var_vec4(&it[2])?;
-                        let size: u32 = rt.var(&it[3])?;
-                        let pos: [f64; 2] = rt.var_vec4(&it[4])?;
-                        let text: Arc<String> = rt.var(&it[5])?;
-                        text::Text::new_color(color, size).draw(
-                            &text,
-                            glyphs.get_mut(font)
-                                .ok_or_else(|| "Font index outside range".to_owned())?,
-                            &c.draw_state,
-                            transform.trans(pos[0], pos[1]), g
-                        ).map_err(|_| "Could not get glyph".to_owned())?;
-                    }
-                    "image__texture_pos_color" => {
-                        let id: usize = rt.var(&it[1])?;
-                        let pos: [f64; 2] = rt.var_vec4(&it[2])?;
-                        let color: [f32; 4] = rt.var_vec4(&it[3])?;
-                        Image::new_color(color).draw(
-                            &textures[id],
-                            &c.draw_state,
-                            transform.trans(pos[0], pos[1]), g
-                        );
-                    }
-                    "image__texture_pos_color_srccorner_srcsize" => {
-                        let id: usize = rt.var(&it[1])?;
-                        let pos: [f64; 2] = rt.var_vec4(&it[2])?;
-                        let color: [f32; 4] = rt.var_vec4(&it[3])?;
-                        let srccorner: [f64; 2] = rt.var_vec4(&it[4])?;
-                        let srcsize: [f64; 2] = rt.var_vec4(&it[5])?;
-                        Image::new_color(color)
-                        .src_rect([srccorner[0], srccorner[1], srcsize[0], srcsize[1]])
-                        .draw(
-                            &textures[id],
-                            &c.draw_state,
-                            transform.trans(pos[0], pos[1]), g
-                        );
-                    }
-                    _ => {}
-                }
-            }
+    for (i, it) in arr.iter().enumerate() {
+        draw_item(rt, commands, it, glyphs, textures, c, &mut transform, g)
+            .map_err(|err| format!("Draw list item {}: {}", i, err))?;
+    }
+    Ok(())
+}
+
+/// Draws a single draw list item that has been checked.
+fn draw_item<C: CharacterCache<Texture = G::Texture>, G: Graphics>(
+    rt: &Runtime,
+    commands: &DrawCommands<G>,
+    it: &Variable,
+    glyphs: &mut Vec<C>,
+    textures: &mut Vec<G::Texture>,
+    c: Context,
+    transform: &mut Matrix2d,
+    g: &mut G
+) -> Result<(), String> {
+    use self::graphics::*;
+    use sprite::{draw_sprites, draw_tilemap, with_variable, SpriteBatch, Spritesheet, Tilemap};
+
+    let (name, args) = commands.check_item(rt, it)?;
+    if let Some(draw) = commands.draws.get(name) {
+        return draw(rt, args, Context {transform: *transform, ..c}, g);
+    }
+    match &***name {
+        "clear" => {
+            let color: [f32; 4] = rt.var_vec4(&args[0])?;
+            clear(color, g);
+        }
+        "transform__rx_ry" => {
+            // Changes transform matrix.
+            let rx: [f32; 4] = rt.var_vec4(&args[0])?;
+            let ry: [f32; 4] = rt.var_vec4(&args[1])?;
+            let t: Matrix2d = [
+                [rx[0] as f64, rx[1] as f64, rx[2] as f64],
+                [ry[0] as f64, ry[1] as f64, ry[2] as f64]
+            ];
+            *transform = math::multiply(c.transform, t);
+        }
+        "line__color_radius_from_to" => {
+            let color: [f32; 4] = rt.var_vec4(&args[0])?;
+            let radius: f64 = rt.var(&args[1])?;
+            let from: [f64; 2] = rt.var_vec4(&args[2])?;
+            let to: [f64; 2] = rt.var_vec4(&args[3])?;
+            line(color, radius, [from[0], from[1], to[0], to[1]], *transform, g);
+        }
+        "rectangle__color_corner_size" => {
+            let color: [f32; 4] = rt.var_vec4(&args[0])?;
+            let corner: [f64; 2] = rt.var_vec4(&args[1])?;
+            let size: [f64; 2] = rt.var_vec4(&args[2])?;
+            rectangle(color, [corner[0], corner[1], size[0], size[1]], *transform, g);
+        }
+        "ellipse__color_corner_size_resolution" => {
+            let color: [f32; 4] = rt.var_vec4(&args[0])?;
+            let corner: [f64; 2] = rt.var_vec4(&args[1])?;
+            let size: [f64; 2] = rt.var_vec4(&args[2])?;
+            let resolution: u32 = rt.var(&args[3])?;
+            Ellipse::new(color)
+            .resolution(resolution as u32)
+            .draw([corner[0], corner[1], size[0], size[1]], &c.draw_state, *transform, g);
+        }
+        "ellipse__border_color_corner_size_resolution" => {
+            let border: f64 = rt.var(&args[0])?;
+            let color: [f32; 4] = rt.var_vec4(&args[1])?;
+            let corner: [f64; 2] = rt.var_vec4(&args[2])?;
+            let size: [f64; 2] = rt.var_vec4(&args[3])?;
+            let resolution: u32 = rt.var(&args[4])?;
+            Ellipse::new_border(color, border)
+            .resolution(resolution as u32)
+            .draw([corner[0], corner[1], size[0], size[1]], &c.draw_state, *transform, g);
         }
+        "text__font_color_size_pos_string" => {
+            let font: usize = rt.var(&args[0])?;
+            let color: [f32; 4] = rt.var_vec4(&args[1])?;
+            let size: u32 = rt.var(&args[2])?;
+            let pos: [f64; 2] = rt.var_vec4(&args[3])?;
+            let text: Arc<String> = rt.var(&args[4])?;
+            text::Text::new_color(color, size).draw(
+                &text,
+                glyphs.get_mut(font)
+                    .ok_or_else(|| "Font index outside range".to_owned())?,
+                &c.draw_state,
+                transform.trans(pos[0], pos[1]), g
+            ).map_err(|_| "Could not get glyph".to_owned())?;
+        }
+        "image__texture_pos_color" => {
+            let id: usize = rt.var(&args[0])?;
+            let pos: [f64; 2] = rt.var_vec4(&args[1])?;
+            let color: [f32; 4] = rt.var_vec4(&args[2])?;
+            Image::new_color(color).draw(
+                textures.get(id)
+                    .ok_or_else(|| "Texture index outside range".to_owned())?,
+                &c.draw_state,
+                transform.trans(pos[0], pos[1]), g
+            );
+        }
+        "image__texture_pos_color_srccorner_srcsize" => {
+            let id: usize = rt.var(&args[0])?;
+            let pos: [f64; 2] = rt.var_vec4(&args[1])?;
+            let color: [f32; 4] = rt.var_vec4(&args[2])?;
+            let srccorner: [f64; 2] = rt.var_vec4(&args[3])?;
+            let srcsize: [f64; 2] = rt.var_vec4(&args[4])?;
+            Image::new_color(color)
+            .src_rect([srccorner[0], srccorner[1], srcsize[0], srcsize[1]])
+            .draw(
+                textures.get(id)
+                    .ok_or_else(|| "Texture index outside range".to_owned())?,
+                &c.draw_state,
+                transform.trans(pos[0], pos[1]), g
+            );
+        }
+        "sprite__sheet_index_pos_color" => {
+            let sheet = with_variable(rt, &args[0], "Spritesheet",
+                |x: &mut Spritesheet| *x)?;
+            let index: u32 = rt.var(&args[1])?;
+            let pos: [f64; 2] = rt.var_vec4(&args[2])?;
+            let color: [f32; 4] = rt.var_vec4(&args[3])?;
+            draw_sprites(&sheet, Some((index, pos)), &color,
+                *transform, &c.draw_state, textures, g)?;
+        }
+        "tilemap__map_pos_color" => {
+            let pos: [f64; 2] = rt.var_vec4(&args[1])?;
+            let color: [f32; 4] = rt.var_vec4(&args[2])?;
+            with_variable(rt, &args[0], "Tilemap", |map: &mut Tilemap| {
+                draw_tilemap(map, pos, &color, *transform, &c.draw_state, textures, g)
+            })??;
+        }
+        "sprites__batch" => {
+            with_variable(rt, &args[0], "SpriteBatch", |batch: &mut SpriteBatch| {
+                draw_sprites(&batch.sheet, batch.sprites.iter().cloned(), &batch.color,
+                    *transform, &c.draw_state, textures, g)
+            })??;
+        }
+        _ => {}
     }
-    return Ok(())
+    Ok(())
 }
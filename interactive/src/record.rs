@@ -0,0 +1,325 @@
+//! Recording and replay of events.
+//!
+//! Events are stored in the Dyon data format, as an array of objects.
+//! The first item is the seed of the random number generator:
+//!
+//! ```dyon
+//! [
+//!     {type: "seed", value: 42},
+//!     {type: "update", dt: 0.008333333333333333},
+//!     {type: "press", keyboard: 97},
+//!     {type: "mouse_cursor", x: 10, y: 20},
+//!     {type: "render", ext_dt: 0.001, width: 512, height: 512, draw_width: 1024, draw_height: 1024}
+//! ]
+//! ```
+//!
+//! Supported events are loop events, keyboard keys, mouse buttons,
+//! mouse cursor, text, resize, focus and cursor.
+//! Other events are not recorded.
+//!
+//! Render events store the window size and draw size,
+//! such that frames can be rendered at the recorded size on replay.
+//!
+//! Events are written one per line. A recording that was not closed,
+//! e.g. because the program crashed, is replayed up to the last complete event.
+
+use std::fs::{read_to_string, File};
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use std::vec;
+
+use current::Current;
+use dyon::{Dfn, Module, Object, Runtime, Type, Variable};
+use dyon::schema::Schema;
+use piston::input::*;
+
+/// Writes events to a file as they happen.
+///
+/// The array is closed when the recorder is dropped.
+/// Use the same seed for the runtime with `Runtime::set_seed`.
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Creates a new recorder writing to file, starting with the seed.
+    pub fn create(file: &str, seed: u32) -> Result<Recorder, String> {
+        let f = File::create(file)
+            .map_err(|err| format!("Could not create `{}`:\n{}", file, err))?;
+        let mut file = BufWriter::new(f);
+        write!(file, "[\n    {{type: \"seed\", value: {}}}", seed)
+            .map_err(|err| format!("{}", err))?;
+        Ok(Recorder {file})
+    }
+
+    /// Records event.
+    ///
+    /// The file is flushed after every render event,
+    /// such that most of the recording survives a crash.
+    pub fn record(&mut self, e: &Event) -> Result<(), String> {
+        if let Some(data) = event_to_data(e) {
+            self.file.write_all(b",\n    ").map_err(|err| format!("{}", err))?;
+            self.file.write_all(data.as_bytes()).map_err(|err| format!("{}", err))?;
+            if e.render_args().is_some() {
+                self.file.flush().map_err(|err| format!("{}", err))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.file.write_all(b"\n]\n");
+        let _ = self.file.flush();
+    }
+}
+
+/// Replays recorded events.
+pub struct Replay {
+    seed: Option<u64>,
+    events: vec::IntoIter<Event>,
+}
+
+impl Replay {
+    /// Loads recorded events from file.
+    ///
+    /// Render events that do not store the window size and draw size,
+    /// take them from `render_args`.
+    pub fn load_file(file: &str, render_args: &RenderArgs) -> Result<Replay, String> {
+        let text = read_to_string(file)
+            .map_err(|err| format!("Could not open `{}`:\n{}", file, err))?;
+        Replay::load_data(&text, render_args)
+            .map_err(|err| format!("In `{}`:\n{}", file, err))
+    }
+
+    /// Loads recorded events from text.
+    ///
+    /// When the array is not closed, the events are loaded up to the last complete line.
+    pub fn load_data(text: &str, render_args: &RenderArgs) -> Result<Replay, String> {
+        let data = match Schema::Any.load_data(text) {
+            Ok(data) => data,
+            Err(_) if !text.trim_end().ends_with(']') => load_unclosed(text)?,
+            Err(err) => return Err(err),
+        };
+        Replay::from_data(&data, render_args)
+    }
+
+    /// Creates replay from recorded events.
+    pub fn from_data(data: &Variable, render_args: &RenderArgs) -> Result<Replay, String> {
+        let arr = if let &Variable::Array(ref arr) = data {
+            arr
+        } else {
+            return Err("Expected array of events".into());
+        };
+        let seed = match arr.get(0) {
+            Some(&Variable::Object(ref obj)) if is_seed(obj) => Some(
+                seed_from_data(obj).map_err(|err| format!("Event 0: {}", err))?),
+            _ => None,
+        };
+        let start = if seed.is_some() {1} else {0};
+        let mut events = Vec::with_capacity(arr.len());
+        for (i, v) in arr.iter().enumerate().skip(start) {
+            events.push(event_from_data(v, render_args)
+                .map_err(|err| format!("Event {}: {}", i, err))?);
+        }
+        Ok(Replay {seed, events: events.into_iter()})
+    }
+
+    /// Returns the recorded seed of the random number generator.
+    pub fn seed(&self) -> Option<u64> {self.seed}
+}
+
+/// Closes the array of a recording that was not finished.
+///
+/// Since events are written one per line, an incomplete last line is dropped.
+fn load_unclosed(text: &str) -> Result<Variable, String> {
+    let text = text.trim_end();
+    let close = |text: &str| format!("{}\n]", text.trim_end().trim_end_matches(','));
+    Schema::Any.load_data(&close(text)).or_else(|err| match text.rfind('\n') {
+        Some(i) => Schema::Any.load_data(&close(&text[..i])),
+        None => Err(err),
+    })
+}
+
+fn is_seed(obj: &Object) -> bool {
+    match obj.get(&Arc::new("type".into())) {
+        Some(&Variable::Text(ref ty)) => &**ty == "seed",
+        _ => false,
+    }
+}
+
+fn seed_from_data(obj: &Object) -> Result<u64, String> {
+    let seed = f64_field(obj, "value")?;
+    if seed < 0.0 || seed.fract() != 0.0 || seed > u32::max_value() as f64 {
+        return Err("Expected `value` to be a non-negative integer seed".into());
+    }
+    Ok(seed as u64)
+}
+
+impl Iterator for Replay {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {self.events.next()}
+}
+
+/// Converts event to Dyon data, returning `None` if it is not supported.
+pub fn event_to_data(e: &Event) -> Option<String> {
+    fn button(ty: &str, button: Button) -> Option<String> {
+        match button {
+            Button::Keyboard(key) =>
+                Some(format!("{{type: {:?}, keyboard: {}}}", ty, key as u64)),
+            Button::Mouse(button) =>
+                Some(format!("{{type: {:?}, mouse: {}}}", ty, button as u64)),
+            _ => None
+        }
+    }
+
+    if let Some(args) = e.update_args() {
+        Some(format!("{{type: \"update\", dt: {}}}", args.dt))
+    } else if let Some(args) = e.render_args() {
+        Some(format!("{{type: \"render\", ext_dt: {}, width: {}, height: {}, \
+                      draw_width: {}, draw_height: {}}}",
+            args.ext_dt, args.width, args.height, args.draw_width, args.draw_height))
+    } else if e.after_render_args().is_some() {
+        Some("{type: \"after_render\"}".into())
+    } else if let Some(args) = e.idle_args() {
+        Some(format!("{{type: \"idle\", dt: {}}}", args.dt))
+    } else if let Some(b) = e.press_args() {
+        button("press", b)
+    } else if let Some(b) = e.release_args() {
+        button("release", b)
+    } else if let Some(pos) = e.mouse_cursor_args() {
+        Some(format!("{{type: \"mouse_cursor\", x: {}, y: {}}}", pos[0], pos[1]))
+    } else if let Some(size) = e.resize_args() {
+        Some(format!("{{type: \"resize\", w: {}, h: {}}}", size[0] as f64, size[1] as f64))
+    } else if let Some(val) = e.focus_args() {
+        Some(format!("{{type: \"focus\", value: {}}}", val))
+    } else if let Some(val) = e.cursor_args() {
+        Some(format!("{{type: \"cursor\", value: {}}}", val))
+    } else if let Some(text) = e.text_args() {
+        Some(format!("{{type: \"text\", text: \"{}\"}}", escape(&text)))
+    } else {
+        None
+    }
+}
+
+/// Escapes string for Dyon data.
+fn escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if c.is_control() => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c)
+        }
+    }
+    res
+}
+
+fn field<'a>(obj: &'a Object, name: &str) -> Result<&'a Variable, String> {
+    obj.get(&Arc::new(name.into()))
+        .ok_or_else(|| format!("Expected field `{}`", name))
+}
+
+fn f64_field(obj: &Object, name: &str) -> Result<f64, String> {
+    match field(obj, name)? {
+        &Variable::F64(val, _) => Ok(val),
+        _ => Err(format!("Expected `{}` to be `f64`", name))
+    }
+}
+
+/// Reads size field, using the default when it is missing.
+fn size_field(obj: &Object, name: &str, default: u32) -> Result<u32, String> {
+    if !obj.contains_key(&Arc::new(name.into())) {return Ok(default)};
+    let val = f64_field(obj, name)?;
+    if val < 0.0 || val.fract() != 0.0 || val > u32::max_value() as f64 {
+        return Err(format!("Expected `{}` to be a non-negative integer", name));
+    }
+    Ok(val as u32)
+}
+
+fn bool_field(obj: &Object, name: &str) -> Result<bool, String> {
+    match field(obj, name)? {
+        &Variable::Bool(val, _) => Ok(val),
+        _ => Err(format!("Expected `{}` to be `bool`", name))
+    }
+}
+
+fn button_field(obj: &Object) -> Result<Button, String> {
+    if obj.contains_key(&Arc::new("keyboard".into())) {
+        Ok(Button::Keyboard(Key::from(f64_field(obj, "keyboard")? as u32)))
+    } else {
+        Ok(Button::Mouse(MouseButton::from(f64_field(obj, "mouse")? as u32)))
+    }
+}
+
+/// Converts Dyon data to event.
+///
+/// Render events that do not store the window size and draw size,
+/// take them from `render_args`.
+pub fn event_from_data(data: &Variable, render_args: &RenderArgs) -> Result<Event, String> {
+    let obj = if let &Variable::Object(ref obj) = data {
+        obj
+    } else {
+        return Err("Expected object".into());
+    };
+    let old = Event::from(Input::Focus(false));
+    let ty = match field(obj, "type")? {
+        &Variable::Text(ref ty) => ty.clone(),
+        _ => return Err("Expected `type` to be `str`".into())
+    };
+    let e = match &**ty {
+        "update" => UpdateEvent::from_update_args(&UpdateArgs {dt: f64_field(obj, "dt")?}, &old),
+        "render" => {
+            let args = RenderArgs {
+                ext_dt: f64_field(obj, "ext_dt")?,
+                width: size_field(obj, "width", render_args.width)?,
+                height: size_field(obj, "height", render_args.height)?,
+                draw_width: size_field(obj, "draw_width", render_args.draw_width)?,
+                draw_height: size_field(obj, "draw_height", render_args.draw_height)?,
+            };
+            RenderEvent::from_render_args(&args, &old)
+        }
+        "after_render" => AfterRenderEvent::from_after_render_args(&AfterRenderArgs, &old),
+        "idle" => IdleEvent::from_idle_args(&IdleArgs {dt: f64_field(obj, "dt")?}, &old),
+        "press" => <Event as PressEvent>::from_button(button_field(obj)?, &old),
+        "release" => <Event as ReleaseEvent>::from_button(button_field(obj)?, &old),
+        "mouse_cursor" =>
+            MouseCursorEvent::from_pos([f64_field(obj, "x")?, f64_field(obj, "y")?], &old),
+        "resize" =>
+            ResizeEvent::from_width_height(f64_field(obj, "w")? as _, f64_field(obj, "h")? as _, &old),
+        "focus" => FocusEvent::from_focused(bool_field(obj, "value")?, &old),
+        "cursor" => CursorEvent::from_cursor(bool_field(obj, "value")?, &old),
+        "text" => match field(obj, "text")? {
+            &Variable::Text(ref text) => TextEvent::from_text(text, &old),
+            _ => return Err("Expected `text` to be `str`".into())
+        },
+        _ => return Err(format!("Unknown event type `{}`", ty))
+    };
+    e.ok_or_else(|| format!("Could not create `{}` event", ty))
+}
+
+/// Adds functions for replaying events to module.
+///
+/// Uses the current `Replay` and `Option<Event>`.
+pub fn add_replay_functions(module: &mut Module) {
+    module.add(Arc::new("next_event".into()), next_event, Dfn {
+        lts: vec![],
+        tys: vec![],
+        ret: Type::Bool
+    });
+}
+
+/// Sets the current event to the next replayed event.
+pub fn next_event(rt: &mut Runtime) -> Result<(), String> {
+    let replay = unsafe { &mut *Current::<Replay>::new() };
+    let e = unsafe { &mut *Current::<Option<Event>>::new() };
+    *e = replay.next();
+    rt.push(e.is_some());
+    Ok(())
+}
@@ -0,0 +1,312 @@
+//! Headless software renderer for draw lists.
+//!
+//! Renders the same draw lists as `draw_2d` into images on the CPU,
+//! without a window or a GPU.
+//! This is useful for snapshot testing and for rendering thumbnails.
+//!
+//! The host sets up these current objects:
+//!
+//! - `()` as texture factory
+//! - `Vec<GlyphCache>` and `FontNames`
+//! - `Vec<RgbaImage>` and `ImageNames`
+//! - `Vec<Texture>`
+
+use std::sync::Arc;
+
+use current::Current;
+use dyon::{Dfn, Lt, Module, Runtime, Type};
+use graphics::{Context, DrawState, Graphics, ImageSize, Viewport};
+use graphics::types::Color;
+use image::{Rgba, RgbaImage};
+use texture::{CreateTexture, Format, TextureOp, TextureSettings};
+
+use draw_2d;
+
+/// Glyph cache for software rendering.
+pub type GlyphCache = ::graphics::glyph_cache::rusttype::GlyphCache<'static, (), Texture>;
+
+/// Texture stored in memory.
+pub struct Texture(pub RgbaImage);
+
+impl ImageSize for Texture {
+    fn get_size(&self) -> (u32, u32) {self.0.dimensions()}
+}
+
+impl TextureOp<()> for Texture {
+    type Error = String;
+}
+
+impl CreateTexture<()> for Texture {
+    fn create<S: Into<[u32; 2]>>(
+        _factory: &mut (),
+        _format: Format,
+        memory: &[u8],
+        size: S,
+        _settings: &TextureSettings
+    ) -> Result<Self, String> {
+        let size = size.into();
+        RgbaImage::from_raw(size[0], size[1], memory.to_vec())
+            .map(Texture)
+            .ok_or_else(|| "Texture memory does not match size".into())
+    }
+}
+
+/// Renders triangles into an image.
+pub struct SoftwareGraphics<'a> {
+    /// The image to render into.
+    pub image: &'a mut RgbaImage,
+}
+
+impl<'a> Graphics for SoftwareGraphics<'a> {
+    type Texture = Texture;
+
+    fn clear_color(&mut self, color: Color) {
+        let color = Rgba {data: [to_u8(color[0]), to_u8(color[1]), to_u8(color[2]), to_u8(color[3])]};
+        for pixel in self.image.pixels_mut() {
+            *pixel = color;
+        }
+    }
+
+    fn clear_stencil(&mut self, _value: u8) {}
+
+    fn tri_list<F>(&mut self, _draw_state: &DrawState, color: &[f32; 4], mut f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]]))
+    {
+        let image = &mut *self.image;
+        f(&mut |vertices: &[[f32; 2]]| {
+            for tri in vertices.chunks(3) {
+                if tri.len() < 3 {break};
+                fill_triangle(image, [tri[0], tri[1], tri[2]], |_| *color);
+            }
+        });
+    }
+
+    fn tri_list_uv<F>(
+        &mut self,
+        _draw_state: &DrawState,
+        color: &[f32; 4],
+        texture: &Texture,
+        mut f: F
+    )
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]]))
+    {
+        let image = &mut *self.image;
+        f(&mut |vertices: &[[f32; 2]], uvs: &[[f32; 2]]| {
+            for (tri, uv) in vertices.chunks(3).zip(uvs.chunks(3)) {
+                if tri.len() < 3 || uv.len() < 3 {break};
+                fill_triangle(image, [tri[0], tri[1], tri[2]], |w| {
+                    let u = w[0] * uv[0][0] as f64 + w[1] * uv[1][0] as f64 +
+                            w[2] * uv[2][0] as f64;
+                    let v = w[0] * uv[0][1] as f64 + w[1] * uv[1][1] as f64 +
+                            w[2] * uv[2][1] as f64;
+                    let texel = sample(&texture.0, u, v);
+                    [
+                        texel[0] * color[0],
+                        texel[1] * color[1],
+                        texel[2] * color[2],
+                        texel[3] * color[3]
+                    ]
+                });
+            }
+        });
+    }
+}
+
+fn to_u8(x: f32) -> u8 {
+    (x.max(0.0).min(1.0) * 255.0 + 0.5) as u8
+}
+
+/// Returns twice the signed area of triangle `a, b, p`.
+fn edge(a: [f64; 2], b: [f64; 2], p: [f64; 2]) -> f64 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// Returns `true` if a point is inside the edge from `a` to `b`.
+///
+/// Points exactly on the edge are only inside for one direction,
+/// such that triangles sharing an edge do not draw the same pixel twice.
+fn inside(w: f64, a: [f64; 2], b: [f64; 2]) -> bool {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    w > 0.0 || (w == 0.0 && (dy > 0.0 || (dy == 0.0 && dx < 0.0)))
+}
+
+/// Fills triangle in normalized device coordinates,
+/// using a function of barycentric weights for color.
+fn fill_triangle<F>(image: &mut RgbaImage, tri: [[f32; 2]; 3], mut shade: F)
+    where F: FnMut([f64; 3]) -> [f32; 4]
+{
+    let (w, h) = image.dimensions();
+    let to_pixel = |v: [f32; 2]| [
+        (f64::from(v[0]) + 1.0) * 0.5 * f64::from(w),
+        (1.0 - f64::from(v[1])) * 0.5 * f64::from(h)
+    ];
+    let (a, mut b, mut c) = (to_pixel(tri[0]), to_pixel(tri[1]), to_pixel(tri[2]));
+    let mut area = edge(a, b, c);
+    if area == 0.0 {return};
+    // Index of weight for each corner.
+    let mut ind = [0, 1, 2];
+    if area < 0.0 {
+        ::std::mem::swap(&mut b, &mut c);
+        ind.swap(1, 2);
+        area = -area;
+    }
+    let clamp = |x: f64, max: u32| x.max(0.0).min(f64::from(max)) as u32;
+    let min_x = clamp(a[0].min(b[0]).min(c[0]).floor(), w);
+    let max_x = clamp(a[0].max(b[0]).max(c[0]).ceil(), w);
+    let min_y = clamp(a[1].min(b[1]).min(c[1]).floor(), h);
+    let max_y = clamp(a[1].max(b[1]).max(c[1]).ceil(), h);
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = [f64::from(x) + 0.5, f64::from(y) + 0.5];
+            let (wa, wb, wc) = (edge(b, c, p), edge(c, a, p), edge(a, b, p));
+            if !inside(wa, b, c) || !inside(wb, c, a) || !inside(wc, a, b) {continue};
+            let mut weights = [0.0; 3];
+            weights[ind[0]] = wa / area;
+            weights[ind[1]] = wb / area;
+            weights[ind[2]] = wc / area;
+            blend(image.get_pixel_mut(x, y), shade(weights));
+        }
+    }
+}
+
+/// Blends color on top of pixel.
+fn blend(pixel: &mut Rgba<u8>, color: [f32; 4]) {
+    let a = color[3].max(0.0).min(1.0);
+    for i in 0..3 {
+        let dst = f32::from(pixel.data[i]) / 255.0;
+        pixel.data[i] = to_u8(color[i] * a + dst * (1.0 - a));
+    }
+    let dst = f32::from(pixel.data[3]) / 255.0;
+    pixel.data[3] = to_u8(a + dst * (1.0 - a));
+}
+
+/// Samples nearest texel at texture coordinates.
+fn sample(texture: &RgbaImage, u: f64, v: f64) -> [f32; 4] {
+    let (w, h) = texture.dimensions();
+    if w == 0 || h == 0 {return [0.0; 4]};
+    let x = (u * f64::from(w)).max(0.0).min(f64::from(w - 1)) as u32;
+    let y = (v * f64::from(h)).max(0.0).min(f64::from(h - 1)) as u32;
+    let texel = texture.get_pixel(x, y).data;
+    [
+        f32::from(texel[0]) / 255.0,
+        f32::from(texel[1]) / 255.0,
+        f32::from(texel[2]) / 255.0,
+        f32::from(texel[3]) / 255.0
+    ]
+}
+
+/// Renders draw list on top of image.
+///
+/// The draw list is popped from the stack, see `draw_2d`.
+pub fn draw_image(
+    rt: &mut Runtime,
+    image: &mut RgbaImage,
+    glyphs: &mut Vec<GlyphCache>,
+    textures: &mut Vec<Texture>
+) -> Result<(), String> {
+    let (w, h) = image.dimensions();
+    let c = Context::new_abs(f64::from(w), f64::from(h));
+    let mut g = SoftwareGraphics {image};
+    draw_2d(rt, glyphs, textures, c, &mut g)
+}
+
+/// Renders draw list on top of image, using window coordinates of viewport.
+///
+/// The image should have the draw size of the viewport.
+/// The draw list is popped from the stack, see `draw_2d`.
+pub fn draw_image_viewport(
+    rt: &mut Runtime,
+    image: &mut RgbaImage,
+    viewport: Viewport,
+    glyphs: &mut Vec<GlyphCache>,
+    textures: &mut Vec<Texture>
+) -> Result<(), String> {
+    let c = Context::new_viewport(viewport);
+    let mut g = SoftwareGraphics {image};
+    draw_2d(rt, glyphs, textures, c, &mut g)
+}
+
+/// Adds functions for software rendering to module,
+/// including functions for fonts, images and sprites.
+pub fn add_functions(module: &mut Module) {
+    use {add_font_functions, add_image_functions};
+    use sprite::add_sprite_functions;
+
+    add_font_functions::<(), GlyphCache>(module);
+    add_image_functions(module);
+    add_sprite_functions::<(), Texture>(module);
+    module.add(Arc::new("draw__image_list".into()),
+        draw__image_list, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![Type::F64, Type::array()],
+            ret: Type::Void
+        }
+    );
+    module.add(Arc::new("create_texture".into()),
+        create_texture, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![Type::F64],
+            ret: Type::F64
+        }
+    );
+    module.add(Arc::new("update__texture_image".into()),
+        update__texture_image, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![Type::F64, Type::F64],
+            ret: Type::Void
+        }
+    );
+}
+
+#[allow(non_snake_case)]
+pub fn draw__image_list(rt: &mut Runtime) -> Result<(), String> {
+    use dyon::TINVOTS;
+
+    let images = unsafe { &mut *Current::<Vec<RgbaImage>>::new() };
+    let glyphs = unsafe { &mut *Current::<Vec<GlyphCache>>::new() };
+    let textures = unsafe { &mut *Current::<Vec<Texture>>::new() };
+    let draw_list = rt.stack.pop().expect(TINVOTS);
+    let id: usize = rt.pop()?;
+    let image = if let Some(x) = images.get_mut(id) {
+        x
+    } else {
+        return Err("Image id is out of bounds".into());
+    };
+    rt.stack.push(draw_list);
+    draw_image(rt, image, glyphs, textures)
+}
+
+pub fn create_texture(rt: &mut Runtime) -> Result<(), String> {
+    let images = unsafe { &*Current::<Vec<RgbaImage>>::new() };
+    let textures = unsafe { &mut *Current::<Vec<Texture>>::new() };
+    let id: usize = rt.pop()?;
+    let new_id = textures.len();
+    let image = if let Some(x) = images.get(id) {
+        x
+    } else {
+        return Err("Image id is out of bounds".into());
+    };
+    textures.push(Texture(image.clone()));
+    rt.push(new_id);
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn update__texture_image(rt: &mut Runtime) -> Result<(), String> {
+    let images = unsafe { &*Current::<Vec<RgbaImage>>::new() };
+    let textures = unsafe { &mut *Current::<Vec<Texture>>::new() };
+    let image_id: usize = rt.pop()?;
+    let texture_id: usize = rt.pop()?;
+    let image = if let Some(x) = images.get(image_id) {
+        x
+    } else {
+        return Err("Image id is out of bounds".into());
+    };
+    let texture = if let Some(x) = textures.get_mut(texture_id) {
+        x
+    } else {
+        return Err("Texture id is out of bounds".into());
+    };
+    texture.0 = image.clone();
+    Ok(())
+}
@@ -0,0 +1,307 @@
+//! Spritesheets, tilemaps and sprite batches.
+//!
+//! These are Rust objects that are drawn with a single draw command each.
+//! All sprites of one draw command are sent to the backend in a single batch,
+//! without resolving a Dyon variable per sprite.
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use current::Current;
+use dyon::{Dfn, Lt, Module, Runtime, RustObject, Type, Variable};
+use graphics::{DrawState, Graphics, ImageSize};
+use graphics::types::Matrix2d;
+use image::RgbaImage;
+use texture::CreateTexture;
+
+/// Tiles of same size in a texture, indexed row by row.
+#[derive(Clone, Copy, Debug)]
+pub struct Spritesheet {
+    /// Texture id.
+    pub texture: usize,
+    /// Size of each tile.
+    pub tile_size: [f64; 2],
+}
+
+/// A grid of tiles from a spritesheet.
+#[derive(Clone, Debug)]
+pub struct Tilemap {
+    /// The spritesheet to draw tiles from.
+    pub sheet: Spritesheet,
+    /// Number of tiles per row.
+    pub width: usize,
+    /// Tile indices, row by row. Negative indices are not drawn.
+    pub tiles: Vec<i64>,
+}
+
+/// Sprites from a spritesheet, drawn with the same color.
+#[derive(Clone, Debug)]
+pub struct SpriteBatch {
+    /// The spritesheet to draw sprites from.
+    pub sheet: Spritesheet,
+    /// The color to multiply with.
+    pub color: [f32; 4],
+    /// Tile index and position of each sprite.
+    pub sprites: Vec<(u32, [f64; 2])>,
+}
+
+fn ad_hoc(name: &str) -> Type {
+    Type::AdHoc(Arc::new(name.into()), Box::new(Type::Any))
+}
+
+/// Returns the type of spritesheets.
+pub fn spritesheet_type() -> Type {ad_hoc("Spritesheet")}
+
+/// Returns the type of tilemaps.
+pub fn tilemap_type() -> Type {ad_hoc("Tilemap")}
+
+/// Returns the type of sprite batches.
+pub fn sprite_batch_type() -> Type {ad_hoc("SpriteBatch")}
+
+/// Adds functions for sprites to module.
+///
+/// `F` is factory (to create textures).
+/// `T` is texture.
+pub fn add_sprite_functions<F, T>(module: &mut Module)
+    where F: 'static + Clone, T: 'static + CreateTexture<F>
+{
+    module.add(Arc::new("spritesheet__image_size".into()),
+        spritesheet__image_size::<F, T>, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![Type::Text, Type::Vec4],
+            ret: spritesheet_type()
+        });
+    module.add(Arc::new("spritesheet__texture_size".into()),
+        spritesheet__texture_size, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![Type::F64, Type::Vec4],
+            ret: spritesheet_type()
+        });
+    module.add(Arc::new("tilemap__sheet_tiles".into()),
+        tilemap__sheet_tiles, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![spritesheet_type(),
+                      Type::Array(Box::new(Type::Array(Box::new(Type::F64))))],
+            ret: tilemap_type()
+        });
+    module.add(Arc::new("set_tile__map_pos_index".into()),
+        set_tile__map_pos_index, Dfn {
+            lts: vec![Lt::Default; 3],
+            tys: vec![tilemap_type(), Type::Vec4, Type::F64],
+            ret: Type::Void
+        });
+    module.add(Arc::new("sprite_batch__sheet_color".into()),
+        sprite_batch__sheet_color, Dfn {
+            lts: vec![Lt::Default; 2],
+            tys: vec![spritesheet_type(), Type::Vec4],
+            ret: sprite_batch_type()
+        });
+    module.add(Arc::new("push_sprite__batch_index_pos".into()),
+        push_sprite__batch_index_pos, Dfn {
+            lts: vec![Lt::Default; 3],
+            tys: vec![sprite_batch_type(), Type::F64, Type::Vec4],
+            ret: Type::Void
+        });
+    module.add(Arc::new("clear_sprites".into()),
+        clear_sprites, Dfn {
+            lts: vec![Lt::Default],
+            tys: vec![sprite_batch_type()],
+            ret: Type::Void
+        });
+}
+
+/// Calls closure with Rust object of a specific type.
+pub fn with_object<T: Any, U, F>(obj: &RustObject, name: &str, f: F) -> Result<U, String>
+    where F: FnOnce(&mut T) -> U
+{
+    let mut guard = obj.lock().map_err(|_| format!("`{}` is poisoned", name))?;
+    let x = guard.downcast_mut::<T>().ok_or_else(|| format!("Expected `{}`", name))?;
+    Ok(f(x))
+}
+
+/// Calls closure with Rust object of a specific type from a variable.
+pub fn with_variable<T: Any, U, F>(rt: &Runtime, v: &Variable, name: &str, f: F) -> Result<U, String>
+    where F: FnOnce(&mut T) -> U
+{
+    match rt.resolve(v) {
+        &Variable::RustObject(ref obj) => with_object(obj, name, f),
+        x => Err(rt.expected(x, name))
+    }
+}
+
+fn push_object<T: Any>(rt: &mut Runtime, val: T) {
+    rt.stack.push(Variable::RustObject(Arc::new(Mutex::new(val)) as RustObject));
+}
+
+/// Creates a spritesheet from an image by name, uploading the image to a new texture.
+///
+/// Every call uploads a new texture, so this should be called once per image,
+/// e.g. when loading, with the spritesheet reused for drawing.
+#[allow(non_snake_case)]
+pub fn spritesheet__image_size<F, T>(rt: &mut Runtime) -> Result<(), String>
+    where F: 'static + Clone, T: 'static + CreateTexture<F>
+{
+    use texture::{Format, TextureSettings};
+    use ImageNames;
+
+    let images = unsafe { &*Current::<Vec<RgbaImage>>::new() };
+    let image_names = unsafe { &*Current::<ImageNames>::new() };
+    let textures = unsafe { &mut *Current::<Vec<T>>::new() };
+    let factory = unsafe { &*Current::<F>::new() };
+    let tile_size: [f64; 2] = rt.pop_vec4()?;
+    let name: Arc<String> = rt.pop()?;
+    if tile_size[0] <= 0.0 || tile_size[1] <= 0.0 {
+        return Err("Expected tile size to be positive".into());
+    }
+    let image = image_names.0.iter().rposition(|x| *x == name)
+        .and_then(|id| images.get(id))
+        .ok_or_else(|| format!("Could not find image `{}`", name))?;
+    let (w, h) = image.dimensions();
+    let texture = T::create(&mut factory.clone(), Format::Rgba8, &**image, [w, h],
+        &TextureSettings::new()).map_err(|err| format!("{:?}", err))?;
+    let id = textures.len();
+    textures.push(texture);
+    push_object(rt, Spritesheet {texture: id, tile_size});
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn spritesheet__texture_size(rt: &mut Runtime) -> Result<(), String> {
+    let tile_size: [f64; 2] = rt.pop_vec4()?;
+    let texture: usize = rt.pop()?;
+    if tile_size[0] <= 0.0 || tile_size[1] <= 0.0 {
+        return Err("Expected tile size to be positive".into());
+    }
+    push_object(rt, Spritesheet {texture, tile_size});
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn tilemap__sheet_tiles(rt: &mut Runtime) -> Result<(), String> {
+    let rows: Vec<Vec<f64>> = rt.pop()?;
+    let sheet: RustObject = rt.pop()?;
+    let sheet = with_object(&sheet, "Spritesheet", |x: &mut Spritesheet| *x)?;
+    let width = rows.get(0).map(|row| row.len()).unwrap_or(0);
+    let mut tiles = Vec::with_capacity(width * rows.len());
+    for row in &rows {
+        if row.len() != width {
+            return Err("Expected all rows of tiles to have the same length".into());
+        }
+        tiles.extend(row.iter().map(|&x| x as i64));
+    }
+    push_object(rt, Tilemap {sheet, width, tiles});
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn set_tile__map_pos_index(rt: &mut Runtime) -> Result<(), String> {
+    let index: f64 = rt.pop()?;
+    let pos: [f64; 2] = rt.pop_vec4()?;
+    let map: RustObject = rt.pop()?;
+    with_object(&map, "Tilemap", |map: &mut Tilemap| {
+        let (x, y) = (pos[0] as usize, pos[1] as usize);
+        if pos[0] < 0.0 || pos[1] < 0.0 || x >= map.width || y * map.width + x >= map.tiles.len() {
+            return Err("Tile position is out of bounds".into());
+        }
+        map.tiles[y * map.width + x] = index as i64;
+        Ok(())
+    })?
+}
+
+#[allow(non_snake_case)]
+pub fn sprite_batch__sheet_color(rt: &mut Runtime) -> Result<(), String> {
+    let color: [f32; 4] = rt.pop_vec4()?;
+    let sheet: RustObject = rt.pop()?;
+    let sheet = with_object(&sheet, "Spritesheet", |x: &mut Spritesheet| *x)?;
+    push_object(rt, SpriteBatch {sheet, color, sprites: vec![]});
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub fn push_sprite__batch_index_pos(rt: &mut Runtime) -> Result<(), String> {
+    let pos: [f64; 2] = rt.pop_vec4()?;
+    let index: u32 = rt.pop()?;
+    let batch: RustObject = rt.pop()?;
+    with_object(&batch, "SpriteBatch", |batch: &mut SpriteBatch| {
+        batch.sprites.push((index, pos));
+    })
+}
+
+pub fn clear_sprites(rt: &mut Runtime) -> Result<(), String> {
+    let batch: RustObject = rt.pop()?;
+    with_object(&batch, "SpriteBatch", |batch: &mut SpriteBatch| {
+        batch.sprites.clear();
+    })
+}
+
+/// Draws sprites in a single batch.
+///
+/// Each sprite is a tile index and a position.
+/// Returns an error if a tile index is outside the texture.
+pub fn draw_sprites<G, I>(
+    sheet: &Spritesheet,
+    sprites: I,
+    color: &[f32; 4],
+    transform: Matrix2d,
+    draw_state: &DrawState,
+    textures: &[G::Texture],
+    g: &mut G
+) -> Result<(), String>
+    where G: Graphics, I: IntoIterator<Item = (u32, [f64; 2])>
+{
+    use graphics::triangulation::{rect_tri_list_uv, rect_tri_list_xy};
+    use graphics::BACK_END_MAX_VERTEX_COUNT;
+
+    let texture = textures.get(sheet.texture)
+        .ok_or_else(|| "Texture index outside range".to_owned())?;
+    let [tw, th] = sheet.tile_size;
+    let columns = (f64::from(texture.get_width()) / tw) as u32;
+    let rows = (f64::from(texture.get_height()) / th) as u32;
+    let tiles = u64::from(columns) * u64::from(rows);
+    let mut xy = vec![];
+    let mut uv = vec![];
+    for (index, pos) in sprites {
+        if u64::from(index) >= tiles {
+            return Err(format!("Tile index {} is outside range of spritesheet with {} tiles",
+                index, tiles));
+        }
+        let src = [f64::from(index % columns) * tw, f64::from(index / columns) * th, tw, th];
+        xy.extend_from_slice(&rect_tri_list_xy(transform, [pos[0], pos[1], tw, th]));
+        uv.extend_from_slice(&rect_tri_list_uv(texture, src));
+    }
+    if xy.is_empty() {return Ok(())};
+    // Whole rectangles per chunk.
+    let n = BACK_END_MAX_VERTEX_COUNT / 6 * 6;
+    g.tri_list_uv(draw_state, color, texture, |f| {
+        for (xy, uv) in xy.chunks(n).zip(uv.chunks(n)) {
+            f(xy, uv)
+        }
+    });
+    Ok(())
+}
+
+/// Draws the tiles of tilemap, with upper left corner at position.
+///
+/// Returns an error if a tile index is outside the texture.
+pub fn draw_tilemap<G: Graphics>(
+    map: &Tilemap,
+    pos: [f64; 2],
+    color: &[f32; 4],
+    transform: Matrix2d,
+    draw_state: &DrawState,
+    textures: &[G::Texture],
+    g: &mut G
+) -> Result<(), String> {
+    if let Some(index) = map.tiles.iter().find(|&&x| x > i64::from(u32::max_value())) {
+        return Err(format!("Tile index {} is outside range of spritesheet", index));
+    }
+    let [tw, th] = map.sheet.tile_size;
+    let width = map.width.max(1);
+    let sprites = map.tiles.iter().enumerate()
+        .filter(|&(_, &index)| index >= 0)
+        .map(|(i, &index)| (index as u32, [
+            pos[0] + (i % width) as f64 * tw,
+            pos[1] + (i / width) as f64 * th
+        ]));
+    draw_sprites(&map.sheet, sprites, color, transform, draw_state, textures, g)
+}
@@ -0,0 +1,587 @@
+extern crate current;
+extern crate dyon;
+extern crate dyon_interactive;
+extern crate image;
+extern crate piston;
+extern crate texture;
+
+use std::sync::{Arc, Mutex};
+use current::CurrentGuard;
+use dyon::{load_str, Call, Module, Runtime, RustObject, Variable};
+use dyon::schema::Schema;
+use dyon_interactive::audio::{add_audio_functions, AudioEvent, RecordingAudio, Repeat};
+use dyon_interactive::record::{event_from_data, event_to_data, Replay};
+use dyon_interactive::software::{draw_image, GlyphCache, Texture};
+use dyon_interactive::sprite::{SpriteBatch, Spritesheet, Tilemap};
+use image::{Rgba, RgbaImage};
+use piston::input::*;
+
+fn text(s: &str) -> Variable {Variable::Text(Arc::new(s.into()))}
+
+fn list(items: Vec<Variable>) -> Variable {Variable::Array(Arc::new(items))}
+
+fn rgba(data: [u8; 4]) -> Rgba<u8> {Rgba {data}}
+
+fn object<T: 'static>(val: T) -> Variable {
+    Variable::RustObject(Arc::new(Mutex::new(val)) as RustObject)
+}
+
+/// Renders a draw list on top of a black 4x4 image.
+fn render(draw_list: Vec<Variable>, textures: &mut Vec<Texture>) -> Result<RgbaImage, String> {
+    let mut image = RgbaImage::from_pixel(4, 4, rgba([0, 0, 0, 255]));
+    let mut glyphs: Vec<GlyphCache> = vec![];
+    let mut rt = Runtime::new();
+    rt.stack.push(list(draw_list));
+    draw_image(&mut rt, &mut image, &mut glyphs, textures)?;
+    Ok(image)
+}
+
+#[test]
+fn test_software_clear() {
+    let image = render(vec![
+        list(vec![text("clear"), Variable::Vec4([1.0, 0.0, 0.0, 1.0])]),
+    ], &mut vec![]).unwrap();
+    assert!(image.pixels().all(|p| *p == rgba([255, 0, 0, 255])));
+}
+
+#[test]
+fn test_software_rectangle() {
+    let image = render(vec![
+        list(vec![
+            text("rectangle__color_corner_size"),
+            Variable::Vec4([1.0, 1.0, 1.0, 1.0]),
+            Variable::Vec4([1.0, 1.0, 0.0, 0.0]),
+            Variable::Vec4([2.0, 2.0, 0.0, 0.0]),
+        ]),
+    ], &mut vec![]).unwrap();
+    for (x, y, p) in image.enumerate_pixels() {
+        let inside = x >= 1 && x < 3 && y >= 1 && y < 3;
+        let expected = if inside {rgba([255; 4])} else {rgba([0, 0, 0, 255])};
+        assert_eq!(*p, expected, "At ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn test_software_image() {
+    let mut textures = vec![Texture(RgbaImage::from_pixel(2, 2, rgba([0, 255, 0, 255])))];
+    let image = render(vec![
+        list(vec![
+            text("image__texture_pos_color"),
+            Variable::f64(0.0),
+            Variable::Vec4([2.0, 0.0, 0.0, 0.0]),
+            Variable::Vec4([1.0, 1.0, 1.0, 1.0]),
+        ]),
+    ], &mut textures).unwrap();
+    for (x, y, p) in image.enumerate_pixels() {
+        let inside = x >= 2 && y < 2;
+        let expected = if inside {rgba([0, 255, 0, 255])} else {rgba([0, 0, 0, 255])};
+        assert_eq!(*p, expected, "At ({}, {})", x, y);
+    }
+
+    // Half transparent color is blended with the background.
+    let image = render(vec![
+        list(vec![
+            text("image__texture_pos_color"),
+            Variable::f64(0.0),
+            Variable::Vec4([0.0, 0.0, 0.0, 0.0]),
+            Variable::Vec4([1.0, 1.0, 1.0, 0.5]),
+        ]),
+    ], &mut textures).unwrap();
+    assert_eq!(*image.get_pixel(0, 0), rgba([0, 128, 0, 255]));
+    assert_eq!(*image.get_pixel(3, 3), rgba([0, 0, 0, 255]));
+}
+
+#[test]
+fn test_draw_errors() {
+    let clear = list(vec![text("clear"), Variable::Vec4([0.0; 4])]);
+    let err = render(vec![
+        clear.clone(),
+        list(vec![text("clear"), Variable::f64(0.0)]),
+    ], &mut vec![]).unwrap_err();
+    assert_eq!(err, "Draw list item 1: Expected `[\"clear\", vec4]`");
+
+    let err = render(vec![
+        clear.clone(),
+        clear.clone(),
+        list(vec![
+            text("image__texture_pos_color"),
+            Variable::f64(3.0),
+            Variable::Vec4([0.0; 4]),
+            Variable::Vec4([1.0; 4]),
+        ]),
+    ], &mut vec![]).unwrap_err();
+    assert_eq!(err, "Draw list item 2: Texture index outside range");
+}
+
+#[test]
+fn test_draw_checked_up_front() {
+    let mut image = RgbaImage::from_pixel(4, 4, rgba([0, 0, 0, 255]));
+    let mut glyphs: Vec<GlyphCache> = vec![];
+    let mut rt = Runtime::new();
+    rt.stack.push(list(vec![
+        list(vec![text("clear"), Variable::Vec4([1.0; 4])]),
+        list(vec![text("clear")]),
+    ]));
+    let err = draw_image(&mut rt, &mut image, &mut glyphs, &mut vec![]).unwrap_err();
+    assert_eq!(err, "Draw list item 1: Expected `[\"clear\", vec4]`");
+    // Nothing is drawn when the last item is invalid.
+    assert!(image.pixels().all(|p| *p == rgba([0, 0, 0, 255])));
+}
+
+#[test]
+fn test_draw_object_types() {
+    let sheet = Spritesheet {texture: 0, tile_size: [1.0, 1.0]};
+    let map = Tilemap {sheet, width: 1, tiles: vec![0]};
+    let sprite = |sheet: Variable| list(vec![
+        text("sprite__sheet_index_pos_color"),
+        sheet,
+        Variable::f64(0.0),
+        Variable::Vec4([0.0; 4]),
+        Variable::Vec4([1.0; 4]),
+    ]);
+    let mut textures = vec![Texture(RgbaImage::from_pixel(1, 1, rgba([255; 4])))];
+    assert!(render(vec![sprite(object(sheet))], &mut textures).is_ok());
+    let err = render(vec![sprite(object(map))], &mut textures).unwrap_err();
+    assert!(err.starts_with("Draw list item 0: Expected `[\"sprite__sheet_index_pos_color\", "),
+        "{}", err);
+}
+
+fn render_args() -> RenderArgs {
+    RenderArgs {ext_dt: 0.0, width: 4, height: 4, draw_width: 4, draw_height: 4}
+}
+
+#[test]
+fn test_event_round_trip() {
+    let old = Event::from(Input::Focus(false));
+    let events = vec![
+        UpdateEvent::from_update_args(&UpdateArgs {dt: 0.25}, &old),
+        RenderEvent::from_render_args(&RenderArgs {ext_dt: 0.5, ..render_args()}, &old),
+        AfterRenderEvent::from_after_render_args(&AfterRenderArgs, &old),
+        IdleEvent::from_idle_args(&IdleArgs {dt: 0.125}, &old),
+        <Event as PressEvent>::from_button(Button::Keyboard(Key::A), &old),
+        <Event as ReleaseEvent>::from_button(Button::Mouse(MouseButton::Right), &old),
+        MouseCursorEvent::from_pos([10.0, 20.5], &old),
+        ResizeEvent::from_width_height(320, 240, &old),
+        FocusEvent::from_focused(true, &old),
+        CursorEvent::from_cursor(false, &old),
+        TextEvent::from_text("\"a\\b\n", &old),
+    ];
+    for e in events {
+        let e = e.unwrap();
+        let data = event_to_data(&e).unwrap();
+        let v = Schema::Any.load_data(&data).unwrap();
+        let e2 = event_from_data(&v, &render_args()).unwrap();
+        assert_eq!(event_to_data(&e2), Some(data));
+    }
+}
+
+#[test]
+fn test_replay_render_size() {
+    let size = |data: &str| {
+        let v = Schema::Any.load_data(data).unwrap();
+        let args = event_from_data(&v, &render_args()).unwrap().render_args().unwrap();
+        (args.width, args.height, args.draw_width, args.draw_height)
+    };
+    assert_eq!(size("{type: \"render\", ext_dt: 0, width: 8, height: 6, \
+                    draw_width: 16, draw_height: 12}"), (8, 6, 16, 12));
+    // Recordings without sizes use the given render arguments.
+    assert_eq!(size("{type: \"render\", ext_dt: 0}"), (4, 4, 4, 4));
+}
+
+#[test]
+fn test_replay_unclosed() {
+    let closed = "[\n    {type: \"seed\", value: 7},\n    {type: \"update\", dt: 0.5}\n]\n";
+    let replay = Replay::load_data(closed, &render_args()).unwrap();
+    assert_eq!(replay.seed(), Some(7));
+    assert_eq!(replay.count(), 1);
+
+    // Recording stopped after a complete event.
+    let replay = Replay::load_data(&closed[..closed.len() - 3], &render_args()).unwrap();
+    assert_eq!(replay.count(), 1);
+
+    // Recording stopped in the middle of an event.
+    let partial = "[\n    {type: \"seed\", value: 7},\n    {type: \"upd";
+    let replay = Replay::load_data(partial, &render_args()).unwrap();
+    assert_eq!(replay.seed(), Some(7));
+    assert_eq!(replay.count(), 0);
+
+    // Recording stopped after the separator.
+    let replay = Replay::load_data("[\n    {type: \"seed\", value: 7},", &render_args()).unwrap();
+    assert_eq!(replay.count(), 0);
+
+    // Errors in a closed recording are reported.
+    assert!(Replay::load_data("[{type: \"update\"}]", &render_args()).is_err());
+}
+
+#[test]
+fn test_audio() {
+    let mut module = Module::new();
+    add_audio_functions::<RecordingAudio>(&mut module);
+    load_str("audio.dyon", Arc::new(r#"
+        fn main() {
+            jump := unwrap(load_sound("jump.wav"))
+            song := unwrap(load_music("song.ogg"))
+            play_sound(jump)
+            play_music__repeat(song, -1)
+            set_volume(0.5)
+            stop_music()
+            play_music__repeat(song, 2)
+        }
+    "#.into()), &mut module).unwrap();
+    let module = Arc::new(module);
+    let mut audio = RecordingAudio::new();
+    {
+        let audio_guard: CurrentGuard<RecordingAudio> = CurrentGuard::new(&mut audio);
+        Runtime::new().run(&module).unwrap();
+        drop(audio_guard);
+    }
+    let jump: Arc<String> = Arc::new("jump.wav".into());
+    let song: Arc<String> = Arc::new("song.ogg".into());
+    assert_eq!(audio.events, vec![
+        AudioEvent::PlaySound(jump),
+        AudioEvent::PlayMusic(song.clone(), Repeat::Forever),
+        AudioEvent::SetVolume(0.5),
+        AudioEvent::StopMusic,
+        AudioEvent::PlayMusic(song, Repeat::Times(2)),
+    ]);
+
+    // Playing a sound that is not loaded is an error.
+    let mut module = Module::new();
+    add_audio_functions::<RecordingAudio>(&mut module);
+    load_str("audio.dyon", Arc::new("fn main() { play_sound(0) }".into()), &mut module).unwrap();
+    let mut audio = RecordingAudio::new();
+    let audio_guard: CurrentGuard<RecordingAudio> = CurrentGuard::new(&mut audio);
+    assert!(Runtime::new().run(&Arc::new(module)).is_err());
+    drop(audio_guard);
+}
+
+/// Returns a texture with a red and a green tile of 1x1 pixels.
+fn red_green() -> Vec<Texture> {
+    let mut image = RgbaImage::from_pixel(2, 1, rgba([255, 0, 0, 255]));
+    image.put_pixel(1, 0, rgba([0, 255, 0, 255]));
+    vec![Texture(image)]
+}
+
+#[test]
+fn test_draw_tilemap() {
+    let (red, green, black) = (rgba([255, 0, 0, 255]), rgba([0, 255, 0, 255]), rgba([0, 0, 0, 255]));
+    let sheet = Spritesheet {texture: 0, tile_size: [1.0, 1.0]};
+    let map = Tilemap {sheet, width: 2, tiles: vec![1, 0, -1, 1]};
+    let draw = |map: Tilemap| list(vec![
+        text("tilemap__map_pos_color"),
+        object(map),
+        Variable::Vec4([1.0, 2.0, 0.0, 0.0]),
+        Variable::Vec4([1.0; 4]),
+    ]);
+    let image = render(vec![draw(map)], &mut red_green()).unwrap();
+    for (x, y, p) in image.enumerate_pixels() {
+        let expected = match (x, y) {
+            (1, 2) | (2, 3) => green,
+            (2, 2) => red,
+            _ => black,
+        };
+        assert_eq!(*p, expected, "At ({}, {})", x, y);
+    }
+
+    let map = Tilemap {sheet, width: 1, tiles: vec![2]};
+    let err = render(vec![draw(map)], &mut red_green()).unwrap_err();
+    assert_eq!(err, "Draw list item 0: Tile index 2 is outside range of spritesheet with 2 tiles");
+
+    // Indices are not truncated to a tile in range.
+    let map = Tilemap {sheet, width: 1, tiles: vec![1 << 32]};
+    let err = render(vec![draw(map)], &mut red_green()).unwrap_err();
+    assert_eq!(err, "Draw list item 0: Tile index 4294967296 is outside range of spritesheet");
+}
+
+#[test]
+fn test_draw_sprites() {
+    let (red, green, black) = (rgba([255, 0, 0, 255]), rgba([0, 255, 0, 255]), rgba([0, 0, 0, 255]));
+    let sheet = Spritesheet {texture: 0, tile_size: [1.0, 1.0]};
+    let batch = SpriteBatch {
+        sheet,
+        color: [1.0; 4],
+        sprites: vec![(0, [0.0, 0.0]), (1, [3.0, 3.0]), (1, [3.0, 0.0])],
+    };
+    let image = render(vec![
+        list(vec![text("sprites__batch"), object(batch)]),
+        list(vec![
+            text("sprite__sheet_index_pos_color"),
+            object(sheet),
+            Variable::f64(0.0),
+            Variable::Vec4([0.0, 3.0, 0.0, 0.0]),
+            Variable::Vec4([1.0; 4]),
+        ]),
+    ], &mut red_green()).unwrap();
+    for (x, y, p) in image.enumerate_pixels() {
+        let expected = match (x, y) {
+            (0, 0) | (0, 3) => red,
+            (3, 3) | (3, 0) => green,
+            _ => black,
+        };
+        assert_eq!(*p, expected, "At ({}, {})", x, y);
+    }
+
+    let batch = SpriteBatch {sheet, color: [1.0; 4], sprites: vec![(5, [0.0, 0.0])]};
+    let err = render(vec![list(vec![text("sprites__batch"), object(batch)])], &mut red_green())
+        .unwrap_err();
+    assert_eq!(err, "Draw list item 0: Tile index 5 is outside range of spritesheet with 2 tiles");
+}
+
+#[test]
+fn test_spritesheet_from_image() {
+    use dyon_interactive::ImageNames;
+    use dyon_interactive::sprite::{add_sprite_functions, with_variable};
+
+    let mut module = Module::new();
+    add_sprite_functions::<(), Texture>(&mut module);
+    load_str("sprite.dyon", Arc::new(r#"
+        fn main() {}
+        fn sheet() -> { return spritesheet__image_size("tiles.png", (1, 1)) }
+        fn missing() -> { return spritesheet__image_size("other.png", (1, 1)) }
+    "#.into()), &mut module).unwrap();
+    let module = Arc::new(module);
+
+    let mut factory = ();
+    let mut images = vec![RgbaImage::new(1, 1), red_green().remove(0).0];
+    let mut image_names = ImageNames(vec![Arc::new("blank".into()), Arc::new("tiles.png".into())]);
+    let mut textures: Vec<Texture> = vec![];
+    let factory_guard: CurrentGuard<()> = CurrentGuard::new(&mut factory);
+    let images_guard: CurrentGuard<Vec<RgbaImage>> = CurrentGuard::new(&mut images);
+    let image_names_guard: CurrentGuard<ImageNames> = CurrentGuard::new(&mut image_names);
+    let textures_guard: CurrentGuard<Vec<Texture>> = CurrentGuard::new(&mut textures);
+
+    let mut rt = Runtime::new();
+    let sheet: Variable = Call::new("sheet").run_ret(&mut rt, &module).unwrap();
+    let sheet = with_variable(&rt, &sheet, "Spritesheet", |x: &mut Spritesheet| *x).unwrap();
+    assert!(Call::new("missing").run_ret::<Variable>(&mut rt, &module).is_err());
+
+    drop(textures_guard);
+    drop(image_names_guard);
+    drop(images_guard);
+    drop(factory_guard);
+    assert_eq!(sheet.texture, 0);
+    assert_eq!(textures.len(), 1);
+    assert_eq!(*textures[0].0.get_pixel(1, 0), rgba([0, 255, 0, 255]));
+}
+
+#[test]
+fn test_gui() {
+    use dyon_interactive::{add_event_functions, add_gui, add_render, FontNames, ImageNames};
+    use dyon_interactive::record::add_replay_functions;
+    use dyon_interactive::software::add_functions;
+    use texture::TextureSettings;
+
+    let mut module = Module::new();
+    add_functions(&mut module);
+    add_event_functions(&mut module);
+    add_replay_functions(&mut module);
+    add_render(&mut module).unwrap();
+    add_gui(&mut module).unwrap();
+    load_str("test.dyon", Arc::new(r#"
+            fn main() {}
+
+            fn clicks() -> [[bool]] {
+                ~ gui := gui(font: 0, size: 16)
+                ~ draw_list := []
+                value := false
+                res := []
+                loop {
+                    if !next_event() {break}
+                    gui_event()
+                    if render() {
+                        clicked := gui_button(id: "button", text: "A")
+                        value = gui_checkbox(id: "checkbox", text: "B", value: value)
+                        gui_end()
+                        push(mut res, [clicked, value])
+                        clear(mut draw_list)
+                    }
+                }
+                return clone(res)
+            }
+        "#.into()), &mut module).unwrap();
+    let module = Arc::new(module);
+
+    // The button is at (0, 0) and the checkbox at (0, 28), both 24 pixels high.
+    let mut replay = Replay::load_data(r#"[
+        {type: "mouse_cursor", x: 2, y: 2},
+        {type: "press", mouse: 1},
+        {type: "render", ext_dt: 0},
+        {type: "release", mouse: 1},
+        {type: "render", ext_dt: 0},
+        {type: "mouse_cursor", x: 2, y: 30},
+        {type: "press", mouse: 1},
+        {type: "render", ext_dt: 0},
+        {type: "release", mouse: 1},
+        {type: "render", ext_dt: 0},
+        {type: "press", mouse: 1},
+        {type: "render", ext_dt: 0},
+        {type: "mouse_cursor", x: 200, y: 200},
+        {type: "release", mouse: 1},
+        {type: "render", ext_dt: 0}
+    ]"#, &render_args()).unwrap();
+
+    let fira_sans = include_bytes!("../assets/FiraSans-Regular.ttf");
+    let mut factory = ();
+    let mut glyphs = vec![GlyphCache::from_bytes(&fira_sans[..], (), TextureSettings::new()).unwrap()];
+    let mut font_names = FontNames(vec![Arc::new("FiraSans-Regular".into())]);
+    let mut images: Vec<RgbaImage> = vec![];
+    let mut image_names = ImageNames(vec![]);
+    let mut textures: Vec<Texture> = vec![];
+    let mut e: Option<Event> = None;
+    let factory_guard: CurrentGuard<()> = CurrentGuard::new(&mut factory);
+    let glyphs_guard: CurrentGuard<Vec<GlyphCache>> = CurrentGuard::new(&mut glyphs);
+    let font_names_guard: CurrentGuard<FontNames> = CurrentGuard::new(&mut font_names);
+    let images_guard: CurrentGuard<Vec<RgbaImage>> = CurrentGuard::new(&mut images);
+    let image_names_guard: CurrentGuard<ImageNames> = CurrentGuard::new(&mut image_names);
+    let textures_guard: CurrentGuard<Vec<Texture>> = CurrentGuard::new(&mut textures);
+    let replay_guard: CurrentGuard<Replay> = CurrentGuard::new(&mut replay);
+    let event_guard: CurrentGuard<Option<Event>> = CurrentGuard::new(&mut e);
+
+    let clicks: Result<Vec<Vec<bool>>, String> =
+        Call::new("clicks").run_ret(&mut Runtime::new(), &module);
+
+    drop(event_guard);
+    drop(replay_guard);
+    drop(textures_guard);
+    drop(image_names_guard);
+    drop(images_guard);
+    drop(font_names_guard);
+    drop(glyphs_guard);
+    drop(factory_guard);
+    assert_eq!(clicks.unwrap(), vec![
+        // The button fires on release.
+        vec![false, false],
+        vec![true, false],
+        // The checkbox toggles on release too.
+        vec![false, false],
+        vec![false, true],
+        // Releasing outside the checkbox cancels the toggle.
+        vec![false, true],
+        vec![false, true],
+    ]);
+}
+
+#[test]
+fn test_blend() {
+    use dyon_interactive::image_ops::BlendMode;
+
+    // Straight alpha does not darken the source over transparent pixels.
+    assert_eq!(BlendMode::Alpha.blend([1.0, 0.0, 0.0, 0.5], [0.0, 0.0, 1.0, 0.0]),
+               [1.0, 0.0, 0.0, 0.5]);
+    assert_eq!(BlendMode::Alpha.blend([1.0, 0.0, 0.0, 0.5], [0.0, 0.0, 1.0, 1.0]),
+               [0.5, 0.0, 0.5, 1.0]);
+    assert_eq!(BlendMode::Alpha.blend([1.0, 0.0, 0.0, 0.0], [0.0; 4]), [0.0; 4]);
+    // Blending with a transparent destination gives the source color.
+    assert_eq!(BlendMode::Multiply.blend([1.0, 0.5, 0.0, 1.0], [0.0; 4]), [1.0, 0.5, 0.0, 1.0]);
+    assert_eq!(BlendMode::Multiply.blend([1.0, 0.5, 0.0, 1.0], [0.5, 0.5, 0.5, 1.0]),
+               [0.5, 0.25, 0.0, 1.0]);
+    assert_eq!(BlendMode::Screen.blend([0.5, 0.0, 1.0, 1.0], [0.5, 0.5, 0.5, 1.0]),
+               [0.75, 0.5, 1.0, 1.0]);
+    assert_eq!(BlendMode::Add.blend([0.5, 0.75, 0.0, 1.0], [0.5, 0.5, 0.5, 1.0]),
+               [1.0, 1.0, 0.5, 1.0]);
+    assert_eq!(BlendMode::Replace.blend([0.5, 0.0, 0.0, 0.5], [1.0; 4]), [0.5, 0.0, 0.0, 0.5]);
+}
+
+/// Returns a 2x3 image where each pixel is unique.
+fn numbered() -> RgbaImage {
+    RgbaImage::from_fn(2, 3, |x, y| rgba([x as u8, y as u8, 0, 255]))
+}
+
+#[test]
+fn test_fill() {
+    use dyon_interactive::image_ops::fill;
+
+    let mut image = RgbaImage::from_pixel(4, 4, rgba([0, 0, 0, 255]));
+    fill(&mut image, [1.0, 2.0], [2.0, 10.0], [1.0, 0.0, 0.0, 1.0]);
+    for (x, y, p) in image.enumerate_pixels() {
+        let inside = x >= 1 && x < 3 && y >= 2;
+        let expected = if inside {rgba([255, 0, 0, 255])} else {rgba([0, 0, 0, 255])};
+        assert_eq!(*p, expected, "At ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn test_blit() {
+    use dyon_interactive::image_ops::{blit, blit__image_pos_from, BlendMode};
+
+    let src = numbered();
+    let mut dst = RgbaImage::from_pixel(4, 4, rgba([9, 9, 9, 255]));
+    blit(&mut dst, [3.0, -1.0], &src, [0.0, 0.0, 2.0, 3.0], BlendMode::Replace);
+    for (x, y, p) in dst.enumerate_pixels() {
+        let expected = if x == 3 && y < 2 {rgba([0, y as u8 + 1, 0, 255])} else {rgba([9, 9, 9, 255])};
+        assert_eq!(*p, expected, "At ({}, {})", x, y);
+    }
+
+    // Blitting an image onto itself reads from the unchanged source.
+    let mut images = vec![RgbaImage::new(1, 1), numbered()];
+    {
+        let images_guard: CurrentGuard<Vec<RgbaImage>> = CurrentGuard::new(&mut images);
+        let mut rt = Runtime::new();
+        rt.stack.push(Variable::f64(1.0));
+        rt.stack.push(Variable::Vec4([0.0, 1.0, 0.0, 0.0]));
+        rt.stack.push(Variable::f64(1.0));
+        blit__image_pos_from(&mut rt).unwrap();
+        rt.stack.push(Variable::f64(0.0));
+        rt.stack.push(Variable::Vec4([0.0; 4]));
+        rt.stack.push(Variable::f64(5.0));
+        assert!(blit__image_pos_from(&mut rt).is_err());
+        drop(images_guard);
+    }
+    let expected = RgbaImage::from_fn(2, 3, |x, y| rgba([x as u8, y.max(1) as u8 - 1, 0, 255]));
+    assert_eq!(images[1], expected);
+}
+
+#[test]
+fn test_resize() {
+    use dyon_interactive::image_ops::{resize, Filter};
+
+    let image = RgbaImage::from_fn(2, 1, |x, _| rgba([x as u8 * 255, 0, 0, 255]));
+    let nearest = resize(&image, 4, 2, Filter::Nearest);
+    assert_eq!(nearest.dimensions(), (4, 2));
+    for (x, y, p) in nearest.enumerate_pixels() {
+        let expected = if x < 2 {rgba([0, 0, 0, 255])} else {rgba([255, 0, 0, 255])};
+        assert_eq!(*p, expected, "At ({}, {})", x, y);
+    }
+    let bilinear = resize(&image, 4, 1, Filter::Bilinear);
+    let reds: Vec<u8> = bilinear.pixels().map(|p| p.data[0]).collect();
+    assert_eq!(reds, vec![0, 64, 191, 255]);
+    assert!(bilinear.pixels().all(|p| p.data[3] == 255));
+}
+
+#[test]
+fn test_convolve() {
+    use dyon_interactive::image_ops::convolve;
+
+    let image = numbered();
+    let identity = vec![vec![0.0, 0.0, 0.0], vec![0.0, 1.0, 0.0], vec![0.0, 0.0, 0.0]];
+    assert_eq!(convolve(&image, &identity).unwrap(), image);
+    // Pixels outside the image are clamped to the edge.
+    let up = vec![vec![1.0], vec![0.0], vec![0.0]];
+    let expected = RgbaImage::from_fn(2, 3, |x, y| rgba([x as u8, y.max(1) as u8 - 1, 0, 255]));
+    assert_eq!(convolve(&image, &up).unwrap(), expected);
+    let uniform = RgbaImage::from_pixel(3, 3, rgba([100, 50, 200, 255]));
+    let blur = vec![vec![1.0 / 9.0; 3]; 3];
+    assert_eq!(convolve(&uniform, &blur).unwrap(), uniform);
+    assert!(convolve(&image, &[vec![1.0, 1.0]]).is_err());
+    assert!(convolve(&image, &[vec![1.0], vec![1.0, 1.0, 1.0], vec![1.0]]).is_err());
+}
+
+#[test]
+fn test_rotations() {
+    use dyon_interactive::image_ops::{
+        flipped_horizontal, flipped_vertical, rotated180, rotated270, rotated90
+    };
+
+    let image = numbered();
+    let r90 = rotated90(&image);
+    assert_eq!(r90.dimensions(), (3, 2));
+    // The lower left corner moves to the upper left corner.
+    assert_eq!(*r90.get_pixel(0, 0), rgba([0, 2, 0, 255]));
+    assert_eq!(*r90.get_pixel(2, 0), rgba([0, 0, 0, 255]));
+    assert_eq!(*r90.get_pixel(2, 1), rgba([1, 0, 0, 255]));
+    assert_eq!(rotated270(&image), rotated90(&rotated180(&image)));
+    assert_eq!(rotated90(&r90), rotated180(&image));
+    assert_eq!(rotated180(&image), flipped_horizontal(&flipped_vertical(&image)));
+    assert_eq!(*flipped_horizontal(&image).get_pixel(0, 2), rgba([1, 2, 0, 255]));
+    assert_eq!(*flipped_vertical(&image).get_pixel(0, 0), rgba([0, 2, 0, 255]));
+}
@@ -208,6 +208,20 @@ fn infer_expr(
                 if res.is_some() { return res; }
             }
         }
+        Match(ref match_expr) => {
+            let res = infer_expr(&match_expr.expr, name, decls);
+            if res.is_some() { return res; }
+            for arm in &match_expr.arms {
+                // Check for binding of same name.
+                if arm.pattern.binds(name) { continue; }
+                if let Some(ref guard) = arm.guard {
+                    let res = infer_expr(guard, name, decls);
+                    if res.is_some() { return res; }
+                }
+                let res = infer_expr(&arm.expr, name, decls);
+                if res.is_some() { return res; }
+            }
+        }
         Compare(ref cmp_expr) => {
             let left = infer_expr(&cmp_expr.left, name, decls);
             if left.is_some() { return left; }
@@ -25,6 +25,12 @@ pub fn convert(
     module: &mut Module
 ) -> Result<(), ()> {
     let mut convert = Convert::new(data);
+    let start = module.functions.len();
+
+    // The strict pragma is used by the type checker.
+    if let Ok((range, _)) = convert.meta_bool("strict") {
+        convert.update(range);
+    }
 
     let namespace = if let Ok((range, val)) = Namespace::from_meta_data(convert, ignored) {
         convert.update(range);
@@ -45,6 +51,11 @@ pub fn convert(
         Function::from_meta_data(&namespace, &file, &source, "fn", convert, ignored) {
             convert.update(range);
             module.register(function);
+        } else if let Ok((range, val)) = Enum::from_meta_data(convert, ignored) {
+            convert.update(range);
+            for function in val.constructors(&namespace, &file, &source) {
+                module.register(function);
+            }
         } else if convert.remaining_data_len() > 0 {
             return Err(());
         } else {
@@ -54,6 +65,9 @@ pub fn convert(
     for (i, f) in module.functions.iter().enumerate() {
         f.resolve_locals(i, module, &use_lookup);
     }
+    if module.optimize {
+        ::optimize::optimize(module, start);
+    }
     Ok(())
 }
 
@@ -380,6 +394,7 @@ impl Function {
         convert.update(start_range);
 
         let mut name: Option<Arc<String>> = None;
+        let mut generics: Vec<Arc<String>> = vec![];
         let mut args: Vec<Arg> = vec![];
         let mut currents: Vec<Current> = vec![];
         let mut block: Option<Block> = None;
@@ -392,6 +407,9 @@ impl Function {
             } else if let Ok((range, val)) = convert.meta_string("name") {
                 convert.update(range);
                 name = Some(val);
+            } else if let Ok((range, val)) = convert.meta_string("generic") {
+                convert.update(range);
+                generics.push(val);
             } else if let Ok((range, val)) = Arg::from_meta_data(
                     convert, ignored) {
                 convert.update(range);
@@ -424,6 +442,12 @@ impl Function {
         }
 
         let mut name = name.ok_or(())?;
+        if !generics.is_empty() {
+            for arg in &mut args {
+                arg.ty = arg.ty.with_vars(&generics);
+            }
+            ret = ret.map(|ret| ret.with_vars(&generics));
+        }
         let block = match expr {
             None => block.ok_or(())?,
             Some(expr) => {
@@ -487,6 +511,176 @@ impl Function {
     }
 }
 
+/// Enum declaration, e.g. `enum Shape { Circle(radius: f64), Empty }`.
+///
+/// Each variant is a constructor function returning an object,
+/// where the `variant` field stores the name of the variant.
+#[derive(Debug, Clone)]
+pub struct Enum {
+    /// The name of the enum.
+    pub name: Arc<String>,
+    /// The variants of the enum.
+    pub variants: Vec<Variant>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Enum {
+    /// Creates enum from meta data.
+    pub fn from_meta_data(
+        mut convert: Convert,
+        ignored: &mut Vec<Range>
+    ) -> Result<(Range, Enum), ()> {
+        let start = convert;
+        let node = "enum";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut name: Option<Arc<String>> = None;
+        let mut variants: Vec<Variant> = vec![];
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, val)) = convert.meta_string("name") {
+                convert.update(range);
+                name = Some(val);
+            } else if let Ok((range, val)) = Variant::from_meta_data(
+                    convert, ignored) {
+                convert.update(range);
+                variants.push(val);
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        let name = name.ok_or(())?;
+        Ok((convert.subtract(start), Enum {
+            name,
+            variants,
+            source_range: convert.source(start).unwrap(),
+        }))
+    }
+
+    /// Returns the type of values constructed by the variants.
+    pub fn ty(&self) -> Type {
+        Type::AdHoc(self.name.clone(), Box::new(Type::object()))
+    }
+
+    /// Creates constructor functions, one for each variant.
+    pub fn constructors(
+        &self,
+        namespace: &Arc<Vec<Arc<String>>>,
+        file: &Arc<String>,
+        source: &Arc<String>,
+    ) -> Vec<Function> {
+        use std::sync::Mutex;
+        use std::sync::atomic::AtomicBool;
+
+        self.variants.iter().map(|variant| {
+            let mut key_values = vec![(
+                Arc::new(ENUM_KEY.into()),
+                Expression::Variable(Box::new((
+                    variant.source_range,
+                    Variable::Text(self.name.clone())
+                )))
+            ), (
+                Arc::new(VARIANT_KEY.into()),
+                Expression::Variable(Box::new((
+                    variant.source_range,
+                    Variable::Text(variant.name.clone())
+                )))
+            )];
+            for arg in &variant.args {
+                key_values.push((
+                    arg.name.clone(),
+                    Expression::Item(Box::new(
+                        Item::from_variable(arg.name.clone(), arg.source_range)
+                    ))
+                ));
+            }
+            let obj = Expression::Object(Box::new(Object {
+                key_values,
+                source_range: variant.source_range,
+            }));
+            Function {
+                namespace: namespace.clone(),
+                name: variant.name.clone(),
+                file: file.clone(),
+                source: source.clone(),
+                args: variant.args.clone(),
+                currents: vec![],
+                block: Block {
+                    expressions: vec![Expression::Return(Box::new(obj))],
+                    source_range: variant.source_range,
+                },
+                ret: self.ty(),
+                resolved: Arc::new(AtomicBool::new(false)),
+                source_range: variant.source_range,
+                senders: Arc::new((AtomicBool::new(false), Mutex::new(vec![]))),
+            }
+        }).collect()
+    }
+}
+
+/// The object key storing the name of the enum of a variant.
+pub const ENUM_KEY: &str = "enum";
+/// The object key storing the name of an enum variant.
+pub const VARIANT_KEY: &str = "variant";
+
+/// Enum variant, e.g. `Circle(radius: f64)`.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    /// The name of the variant.
+    pub name: Arc<String>,
+    /// The fields of the variant, declared like function arguments.
+    pub args: Vec<Arg>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Variant {
+    /// Creates variant from meta data.
+    pub fn from_meta_data(
+        mut convert: Convert,
+        ignored: &mut Vec<Range>
+    ) -> Result<(Range, Variant), ()> {
+        let start = convert;
+        let node = "variant";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut name: Option<Arc<String>> = None;
+        let mut args: Vec<Arg> = vec![];
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, val)) = convert.meta_string("name") {
+                convert.update(range);
+                name = Some(val);
+            } else if let Ok((range, val)) = Arg::from_meta_data(
+                    convert, ignored) {
+                convert.update(range);
+                args.push(val);
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        let name = name.ok_or(())?;
+        Ok((convert.subtract(start), Variant {
+            name,
+            args,
+            source_range: convert.source(start).unwrap(),
+        }))
+    }
+}
+
 /// Closure.
 #[derive(Debug, Clone)]
 pub struct Closure {
@@ -999,6 +1193,8 @@ pub enum Expression {
     LinkIn(Box<ForIn>),
     /// If-expression.
     If(Box<If>),
+    /// Match expression.
+    Match(Box<Match>),
     /// Compare expression.
     Compare(Box<Compare>),
     /// Unary operator expression.
@@ -1257,6 +1453,10 @@ impl Expression {
                     file, source, convert, ignored) {
                 convert.update(range);
                 result = Some(Expression::If(Box::new(val)));
+            } else if let Ok((range, val)) = Match::from_meta_data(
+                    file, source, convert, ignored) {
+                convert.update(range);
+                result = Some(Expression::Match(Box::new(val)));
             } else if let Ok((range, val)) = Compare::from_meta_data(
                     file, source, convert, ignored) {
                 convert.update(range);
@@ -1364,6 +1564,7 @@ impl Expression {
             LinkFor(ref for_n_expr) => for_n_expr.source_range,
             LinkIn(ref for_in_expr) => for_in_expr.source_range,
             If(ref if_expr) => if_expr.source_range,
+            Match(ref match_expr) => match_expr.source_range,
             Compare(ref comp) => comp.source_range,
             Norm(ref norm) => norm.source_range,
             UnOp(ref unop) => unop.source_range,
@@ -1465,6 +1666,8 @@ impl Expression {
                 for_in_expr.resolve_locals(relative, stack, closure_stack, module, use_lookup),
             If(ref if_expr) =>
                 if_expr.resolve_locals(relative, stack, closure_stack, module, use_lookup),
+            Match(ref match_expr) =>
+                match_expr.resolve_locals(relative, stack, closure_stack, module, use_lookup),
             Compare(ref comp) =>
                 comp.resolve_locals(relative, stack, closure_stack, module, use_lookup),
             Norm(ref norm) =>
@@ -3426,6 +3629,9 @@ impl Vec4UnLoop {
 }
 
 /// Swizzle expression.
+///
+/// The components are named `x`, `y`, `z` and `w`,
+/// so swizzles on `vecn` only reach the first four components.
 #[derive(Debug, Clone)]
 pub struct Swizzle {
     /// First component swizzle.
@@ -3436,7 +3642,7 @@ pub struct Swizzle {
     pub sw2: Option<usize>,
     /// Fourth component swizzle.
     pub sw3: Option<usize>,
-    /// 4D vector expression.
+    /// Vector expression, `vec4` or `vecn`.
     pub expr: Expression,
     /// The range in source.
     pub source_range: Range,
@@ -4166,6 +4372,318 @@ impl If {
     }
 }
 
+/// Match expression, e.g. `match x { some(y) => y, none() => 0 }`.
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// The value to match against.
+    pub expr: Expression,
+    /// Match arms, tried in order.
+    pub arms: Vec<Arm>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Match {
+    /// Creates match expression from meta data.
+    pub fn from_meta_data(
+        file: &Arc<String>,
+        source: &Arc<String>,
+        mut convert: Convert,
+        ignored: &mut Vec<Range>)
+    -> Result<(Range, Match), ()> {
+        let start = convert;
+        let node = "match";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut expr: Option<Expression> = None;
+        let mut arms: Vec<Arm> = vec![];
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, val)) = Expression::from_meta_data(
+                file, source, "subject", convert, ignored) {
+                convert.update(range);
+                expr = Some(val);
+            } else if let Ok((range, val)) = Arm::from_meta_data(
+                    file, source, convert, ignored) {
+                convert.update(range);
+                arms.push(val);
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        let expr = expr.ok_or(())?;
+        Ok((convert.subtract(start), Match {
+            expr,
+            arms,
+            source_range: convert.source(start).unwrap(),
+        }))
+    }
+
+    fn resolve_locals(
+        &self,
+        relative: usize,
+        stack: &mut Vec<Option<Arc<String>>>,
+        closure_stack: &mut Vec<usize>,
+        module: &Module,
+        use_lookup: &UseLookup,
+    ) {
+        let st = stack.len();
+        self.expr.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+        stack.truncate(st);
+        for arm in &self.arms {
+            arm.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+            stack.truncate(st);
+        }
+    }
+}
+
+/// Match arm, e.g. `some(x) if x > 0 => x`.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    /// The pattern to match.
+    pub pattern: Pattern,
+    /// Optional condition that must be true for the arm to be chosen.
+    pub guard: Option<Expression>,
+    /// The expression to evaluate when the arm is chosen.
+    pub expr: Expression,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Arm {
+    /// Creates match arm from meta data.
+    pub fn from_meta_data(
+        file: &Arc<String>,
+        source: &Arc<String>,
+        mut convert: Convert,
+        ignored: &mut Vec<Range>)
+    -> Result<(Range, Arm), ()> {
+        let start = convert;
+        let node = "arm";
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut pattern: Option<Pattern> = None;
+        let mut guard: Option<Expression> = None;
+        let mut expr: Option<Expression> = None;
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, val)) = Pattern::from_meta_data(
+                    "pattern", convert, ignored) {
+                convert.update(range);
+                pattern = Some(val);
+            } else if let Ok((range, val)) = Expression::from_meta_data(
+                file, source, "guard", convert, ignored) {
+                convert.update(range);
+                guard = Some(val);
+            } else if let Ok((range, val)) = Expression::from_meta_data(
+                file, source, "expr", convert, ignored) {
+                convert.update(range);
+                expr = Some(val);
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        let pattern = pattern.ok_or(())?;
+        let expr = expr.ok_or(())?;
+        Ok((convert.subtract(start), Arm {
+            pattern,
+            guard,
+            expr,
+            source_range: convert.source(start).unwrap(),
+        }))
+    }
+
+    fn resolve_locals(
+        &self,
+        relative: usize,
+        stack: &mut Vec<Option<Arc<String>>>,
+        closure_stack: &mut Vec<usize>,
+        module: &Module,
+        use_lookup: &UseLookup,
+    ) {
+        // Bindings are pushed in the same order as they are matched at runtime.
+        self.pattern.resolve_locals(relative, stack, module);
+        if let Some(ref guard) = self.guard {
+            let st = stack.len();
+            guard.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+            stack.truncate(st);
+        }
+        self.expr.resolve_locals(relative, stack, closure_stack, module, use_lookup);
+    }
+}
+
+/// Pattern in a match arm.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Matches anything, e.g. `_`.
+    Wildcard(Range),
+    /// Matches anything and binds the value to a local variable.
+    Bind(Arc<String>, Range),
+    /// Matches a text, number or bool literal.
+    Value(Variable, Range),
+    /// Matches an array with the same length, e.g. `[a, _, 3]`.
+    Array(Vec<Pattern>, Range),
+    /// Matches the components of a 4D vector, e.g. `(x, y, _)`.
+    Vec4(Vec<Pattern>, Range),
+    /// Matches a variant, e.g. `some(x)`, `err(msg)` or `Circle(r)`.
+    Variant(Box<VariantPattern>),
+}
+
+/// Variant pattern, e.g. `Circle(r)`.
+#[derive(Debug, Clone)]
+pub struct VariantPattern {
+    /// The name of the variant.
+    pub name: Arc<String>,
+    /// Patterns for the fields, in declared order.
+    pub args: Vec<Pattern>,
+    /// The constructor function, used to look up field names.
+    pub f_index: Cell<FnIndex>,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Pattern {
+    /// Creates pattern from meta data.
+    pub fn from_meta_data(
+        node: &str,
+        mut convert: Convert,
+        ignored: &mut Vec<Range>)
+    -> Result<(Range, Pattern), ()> {
+        let start = convert;
+        let start_range = convert.start_node(node)?;
+        convert.update(start_range);
+
+        let mut result: Option<Pattern> = None;
+        let mut variant: Option<Arc<String>> = None;
+        let mut array = false;
+        let mut items: Vec<Pattern> = vec![];
+        let mut vec4: Vec<Pattern> = vec![];
+        let mut args: Vec<Pattern> = vec![];
+        loop {
+            if let Ok(range) = convert.end_node(node) {
+                convert.update(range);
+                break;
+            } else if let Ok((range, _)) = convert.meta_bool("wildcard") {
+                convert.update(range);
+                result = Some(Pattern::Wildcard(convert.source(start).unwrap()));
+            } else if let Ok((range, val)) = convert.meta_string("bind") {
+                convert.update(range);
+                result = Some(Pattern::Bind(val, convert.source(start).unwrap()));
+            } else if let Ok((range, val)) = convert.meta_string("text") {
+                convert.update(range);
+                result = Some(Pattern::Value(Variable::Text(val),
+                    convert.source(start).unwrap()));
+            } else if let Ok((range, val)) = convert.meta_f64("num") {
+                convert.update(range);
+                result = Some(Pattern::Value(Variable::f64(val),
+                    convert.source(start).unwrap()));
+            } else if let Ok((range, val)) = convert.meta_bool("bool") {
+                convert.update(range);
+                result = Some(Pattern::Value(Variable::bool(val),
+                    convert.source(start).unwrap()));
+            } else if let Ok((range, val)) = convert.meta_string("variant") {
+                convert.update(range);
+                variant = Some(val);
+            } else if let Ok((range, _)) = convert.meta_bool("array") {
+                convert.update(range);
+                array = true;
+            } else if let Ok((range, val)) = Pattern::from_meta_data(
+                    "pat_arr", convert, ignored) {
+                convert.update(range);
+                items.push(val);
+            } else if let Ok((range, val)) = Pattern::from_meta_data(
+                    "pat_vec4", convert, ignored) {
+                convert.update(range);
+                vec4.push(val);
+            } else if let Ok((range, val)) = Pattern::from_meta_data(
+                    "pat_arg", convert, ignored) {
+                convert.update(range);
+                args.push(val);
+            } else {
+                let range = convert.ignore();
+                convert.update(range);
+                ignored.push(range);
+            }
+        }
+
+        let source_range = convert.source(start).unwrap();
+        if let Some(name) = variant {
+            result = Some(Pattern::Variant(Box::new(VariantPattern {
+                name,
+                args,
+                f_index: Cell::new(FnIndex::None),
+                source_range,
+            })));
+        } else if array {
+            result = Some(Pattern::Array(items, source_range));
+        } else if !vec4.is_empty() {
+            if vec4.len() < 2 || vec4.len() > 4 { return Err(()); }
+            result = Some(Pattern::Vec4(vec4, source_range));
+        }
+        let result = result.ok_or(())?;
+        Ok((convert.subtract(start), result))
+    }
+
+    /// Gets the range in source.
+    pub fn source_range(&self) -> Range {
+        match *self {
+            Pattern::Wildcard(range) => range,
+            Pattern::Bind(_, range) => range,
+            Pattern::Value(_, range) => range,
+            Pattern::Array(_, range) => range,
+            Pattern::Vec4(_, range) => range,
+            Pattern::Variant(ref variant) => variant.source_range,
+        }
+    }
+
+    /// Returns `true` if the pattern binds a variable with the name.
+    pub fn binds(&self, name: &str) -> bool {
+        match *self {
+            Pattern::Wildcard(_) | Pattern::Value(_, _) => false,
+            Pattern::Bind(ref bind, _) => &**bind == name,
+            Pattern::Array(ref items, _) | Pattern::Vec4(ref items, _) =>
+                items.iter().any(|p| p.binds(name)),
+            Pattern::Variant(ref variant) =>
+                variant.args.iter().any(|p| p.binds(name)),
+        }
+    }
+
+    fn resolve_locals(
+        &self,
+        relative: usize,
+        stack: &mut Vec<Option<Arc<String>>>,
+        module: &Module,
+    ) {
+        match *self {
+            Pattern::Wildcard(_) | Pattern::Value(_, _) => {}
+            Pattern::Bind(ref name, _) => stack.push(Some(name.clone())),
+            Pattern::Array(ref items, _) | Pattern::Vec4(ref items, _) => {
+                for item in items {
+                    item.resolve_locals(relative, stack, module);
+                }
+            }
+            Pattern::Variant(ref variant) => {
+                variant.f_index.set(module.find_function(&variant.name, relative));
+                for arg in &variant.args {
+                    arg.resolve_locals(relative, stack, module);
+                }
+            }
+        }
+    }
+}
+
 /// Compare expression.
 #[derive(Debug, Clone)]
 pub struct Compare {
@@ -19,6 +19,8 @@ use super::{
     If,
     Item,
     Link,
+    Match,
+    Arm,
     Object,
     Norm,
     Swizzle,
@@ -320,6 +322,26 @@ pub fn number(expr: &Expression, name: &Arc<String>, val: f64) -> Expression {
                 source_range: if_expr.source_range,
             }))
         }
+        E::Match(ref match_expr) => {
+            let mut new_arms: Vec<Arm> = vec![];
+            for arm in &match_expr.arms {
+                if arm.pattern.binds(name) {
+                    new_arms.push(arm.clone());
+                } else {
+                    new_arms.push(Arm {
+                        pattern: arm.pattern.clone(),
+                        guard: arm.guard.as_ref().map(|guard| number(guard, name, val)),
+                        expr: number(&arm.expr, name, val),
+                        source_range: arm.source_range,
+                    });
+                }
+            }
+            E::Match(Box::new(Match {
+                expr: number(&match_expr.expr, name, val),
+                arms: new_arms,
+                source_range: match_expr.source_range,
+            }))
+        }
         E::Compare(ref cmp_expr) => {
             E::Compare(Box::new(Compare {
                 op: cmp_expr.op,
@@ -0,0 +1,440 @@
+//! Binary format for data.
+//!
+//! A compact alternative to the Dyon data format that is faster to load,
+//! in particular for large arrays of numbers.
+//!
+//! The data starts with the header `dyon` followed by the version as a `u32`.
+//! All numbers are stored in little endian.
+//! Every value starts with a tag byte, followed by:
+//!
+//! - `f64`: 8 bytes
+//! - `vec4`: 4 x `f32`
+//! - `vecn`: length as `u64` and 8 bytes per component
+//! - `mat4`: 16 x `f32`, column by column
+//! - strings: length as `u64` and UTF-8 bytes, or an index to a previous string
+//! - arrays, links and objects: length as `u64` and items,
+//!   where object keys are strings
+//! - arrays of numbers: length as `u64` and 8 bytes per number
+//! - `some(x)`, `ok(x)`: the value `x`
+//! - `err(x)`: the value `x`, the trace length as `u64` and trace strings
+//!
+//! Secrets are not stored.
+//! Object keys are sorted, such that the same data gives the same bytes.
+//! Values nested deeper than `MAX_DEPTH` are rejected when reading,
+//! such that untrusted data can not overflow the stack.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use Variable;
+
+/// The current version of the binary format.
+pub const VERSION: u32 = 1;
+
+/// The maximum nesting depth of values when reading.
+pub const MAX_DEPTH: usize = 256;
+
+const HEADER: &[u8] = b"dyon";
+
+const NONE: u8 = 0;
+const SOME: u8 = 1;
+const F64: u8 = 2;
+const FALSE: u8 = 3;
+const TRUE: u8 = 4;
+const STR: u8 = 5;
+const STR_REF: u8 = 6;
+const VEC4: u8 = 7;
+const MAT4: u8 = 8;
+const ARRAY: u8 = 9;
+const F64_ARRAY: u8 = 10;
+const OBJECT: u8 = 11;
+const OK: u8 = 12;
+const ERR: u8 = 13;
+const LINK: u8 = 14;
+const VECN: u8 = 15;
+
+/// Converts variable to bytes.
+pub fn to_bytes(v: &Variable) -> Result<Vec<u8>, String> {
+    let mut res = vec![];
+    write(&mut res, v)?;
+    Ok(res)
+}
+
+/// Writes variable in binary format.
+pub fn write<W: io::Write>(w: &mut W, v: &Variable) -> Result<(), String> {
+    let mut writer = Writer {w, strings: HashMap::new()};
+    writer.bytes(HEADER)
+        .and_then(|_| writer.bytes(&u32_bytes(VERSION)))
+        .map_err(|err| format!("{}", err))?;
+    writer.variable(v).map_err(|err| match err {
+        WriteError::Io(err) => format!("{}", err),
+        WriteError::Unsupported(ty) => format!("Can not write `{}` in binary format", ty),
+    })
+}
+
+/// Converts bytes to variable.
+pub fn from_bytes(bytes: &[u8]) -> Result<Variable, String> {
+    if !bytes.starts_with(HEADER) {
+        return Err("Expected header `dyon` of binary format".into());
+    }
+    let mut reader = Reader {bytes, pos: HEADER.len(), strings: vec![], depth: 0};
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(format!("Unsupported version {} of binary format, expected {}",
+                           version, VERSION));
+    }
+    let v = reader.variable()?;
+    if reader.pos != bytes.len() {
+        return Err(format!("Unexpected data after end at byte {}", reader.pos));
+    }
+    Ok(v)
+}
+
+/// Loads data in binary format from a file.
+#[cfg(feature = "file")]
+pub fn load_file(file: &str) -> Result<Variable, String> {
+    use std::fs::File;
+    use std::io::Read;
+    use dyon_std::io::io_error;
+
+    let mut data_file = File::open(file).map_err(|err| io_error("open", file, &err))?;
+    let mut bytes = vec![];
+    data_file.read_to_end(&mut bytes).map_err(|err| io_error("read", file, &err))?;
+    from_bytes(&bytes)
+}
+
+/// Loads data in binary format from a file.
+#[cfg(not(feature = "file"))]
+pub fn load_file(_: &str) -> Result<Variable, String> {
+    Err(::dyon_std::FILE_SUPPORT_DISABLED.into())
+}
+
+/// Saves data in binary format to a file, replacing any existing file.
+#[cfg(feature = "file")]
+pub fn save_file(file: &str, v: &Variable) -> Result<(), String> {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use dyon_std::io::io_error;
+
+    let f = File::create(file).map_err(|err| io_error("create", file, &err))?;
+    let mut w = BufWriter::new(f);
+    write(&mut w, v)?;
+    w.flush().map_err(|err| io_error("write", file, &err))
+}
+
+/// Saves data in binary format to a file, replacing any existing file.
+#[cfg(not(feature = "file"))]
+pub fn save_file(_: &str, _: &Variable) -> Result<(), String> {
+    Err(::dyon_std::FILE_SUPPORT_DISABLED.into())
+}
+
+fn u32_bytes(val: u32) -> [u8; 4] {
+    let mut res = [0; 4];
+    for (i, b) in res.iter_mut().enumerate() {
+        *b = (val >> (8 * i)) as u8;
+    }
+    res
+}
+
+fn u64_bytes(val: u64) -> [u8; 8] {
+    let mut res = [0; 8];
+    for (i, b) in res.iter_mut().enumerate() {
+        *b = (val >> (8 * i)) as u8;
+    }
+    res
+}
+
+enum WriteError {
+    Io(io::Error),
+    Unsupported(Arc<String>),
+}
+
+impl From<io::Error> for WriteError {
+    fn from(err: io::Error) -> WriteError {WriteError::Io(err)}
+}
+
+struct Writer<'a, W: 'a> {
+    w: &'a mut W,
+    /// Index of strings written so far.
+    strings: HashMap<Arc<String>, u64>,
+}
+
+impl<'a, W: io::Write> Writer<'a, W> {
+    fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.w.write_all(bytes)
+    }
+
+    fn tag(&mut self, tag: u8) -> io::Result<()> {
+        self.bytes(&[tag])
+    }
+
+    fn u64(&mut self, val: u64) -> io::Result<()> {
+        self.bytes(&u64_bytes(val))
+    }
+
+    fn f32(&mut self, val: f32) -> io::Result<()> {
+        self.bytes(&u32_bytes(val.to_bits()))
+    }
+
+    fn f64(&mut self, val: f64) -> io::Result<()> {
+        self.u64(val.to_bits())
+    }
+
+    fn string(&mut self, s: &Arc<String>) -> io::Result<()> {
+        if let Some(&i) = self.strings.get(s) {
+            self.tag(STR_REF)?;
+            return self.u64(i);
+        }
+        let i = self.strings.len() as u64;
+        self.strings.insert(s.clone(), i);
+        self.tag(STR)?;
+        self.u64(s.len() as u64)?;
+        self.bytes(s.as_bytes())
+    }
+
+    fn variable(&mut self, v: &Variable) -> Result<(), WriteError> {
+        match *v {
+            Variable::F64(x, _) => {
+                self.tag(F64)?;
+                self.f64(x)?;
+            }
+            Variable::Bool(x, _) => self.tag(if x {TRUE} else {FALSE})?,
+            Variable::Text(ref t) => self.string(t)?,
+            Variable::Vec4(v) => {
+                self.tag(VEC4)?;
+                for &x in &v {self.f32(x)?}
+            }
+            Variable::VecN(ref v) => {
+                self.tag(VECN)?;
+                self.u64(v.len() as u64)?;
+                for &x in v.iter() {self.f64(x)?}
+            }
+            Variable::Mat4(ref m) => {
+                self.tag(MAT4)?;
+                for col in m.iter() {
+                    for &x in col {self.f32(x)?}
+                }
+            }
+            Variable::Array(ref arr) => {
+                let numbers = !arr.is_empty() && arr.iter().all(|x| {
+                    if let Variable::F64(_, _) = *x {true} else {false}
+                });
+                if numbers {
+                    self.tag(F64_ARRAY)?;
+                    self.u64(arr.len() as u64)?;
+                    for x in arr.iter() {
+                        if let Variable::F64(x, _) = *x {self.f64(x)?}
+                    }
+                } else {
+                    self.tag(ARRAY)?;
+                    self.u64(arr.len() as u64)?;
+                    for x in arr.iter() {self.variable(x)?}
+                }
+            }
+            Variable::Link(ref link) => {
+                self.tag(LINK)?;
+                let n: usize = link.slices.iter()
+                    .map(|s| (s.end - s.start) as usize).sum();
+                self.u64(n as u64)?;
+                for slice in &link.slices {
+                    for i in slice.start..slice.end {
+                        self.variable(&slice.block.var(i))?;
+                    }
+                }
+            }
+            Variable::Object(ref obj) => {
+                self.tag(OBJECT)?;
+                self.u64(obj.len() as u64)?;
+                let mut keys: Vec<&Arc<String>> = obj.keys().collect();
+                keys.sort();
+                for key in keys {
+                    self.string(key)?;
+                    self.variable(&obj[key])?;
+                }
+            }
+            Variable::Option(None) => self.tag(NONE)?,
+            Variable::Option(Some(ref x)) => {
+                self.tag(SOME)?;
+                self.variable(x)?;
+            }
+            Variable::Result(Ok(ref x)) => {
+                self.tag(OK)?;
+                self.variable(x)?;
+            }
+            Variable::Result(Err(ref err)) => {
+                self.tag(ERR)?;
+                self.variable(&err.message)?;
+                self.u64(err.trace.len() as u64)?;
+                for s in &err.trace {
+                    self.u64(s.len() as u64)?;
+                    self.bytes(s.as_bytes())?;
+                }
+            }
+            ref x => return Err(WriteError::Unsupported(x.typeof_var())),
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Strings read so far.
+    strings: Vec<Arc<String>>,
+    /// Nesting depth of the current value.
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if n > self.bytes.len() - self.pos {
+            return Err(format!("Unexpected end of data at byte {}", self.bytes.len()));
+        }
+        let res = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(self.take(4)?.iter().rev().fold(0, |acc, &b| (acc << 8) | u32::from(b)))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(self.take(8)?.iter().rev().fold(0, |acc, &b| (acc << 8) | u64::from(b)))
+    }
+
+    /// Reads a length, which can not be larger than the remaining bytes.
+    fn len(&mut self) -> Result<usize, String> {
+        let pos = self.pos;
+        let n = self.u64()?;
+        if n > (self.bytes.len() - self.pos) as u64 {
+            return Err(format!("Invalid length {} at byte {}", n, pos));
+        }
+        Ok(n as usize)
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    fn utf8(&mut self) -> Result<String, String> {
+        let pos = self.pos;
+        let n = self.len()?;
+        let bytes = self.take(n)?;
+        String::from_utf8(bytes.into())
+            .map_err(|_| format!("Invalid UTF-8 in string at byte {}", pos))
+    }
+
+    fn string(&mut self) -> Result<Arc<String>, String> {
+        let pos = self.pos;
+        match self.u8()? {
+            STR => {
+                let s = Arc::new(self.utf8()?);
+                self.strings.push(s.clone());
+                Ok(s)
+            }
+            STR_REF => {
+                let i = self.u64()?;
+                self.strings.get(i as usize).cloned()
+                    .ok_or_else(|| format!("Invalid string reference {} at byte {}", i, pos))
+            }
+            tag => Err(format!("Expected string, found tag {} at byte {}", tag, pos)),
+        }
+    }
+
+    fn variable(&mut self) -> Result<Variable, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("Values nested deeper than {} at byte {}", MAX_DEPTH, self.pos));
+        }
+        self.depth += 1;
+        let v = self.value();
+        self.depth -= 1;
+        v
+    }
+
+    fn value(&mut self) -> Result<Variable, String> {
+        use Error;
+
+        let pos = self.pos;
+        Ok(match self.u8()? {
+            NONE => Variable::Option(None),
+            SOME => Variable::Option(Some(Box::new(self.variable()?))),
+            F64 => Variable::f64(self.f64()?),
+            FALSE => Variable::bool(false),
+            TRUE => Variable::bool(true),
+            STR | STR_REF => {
+                self.pos = pos;
+                Variable::Text(self.string()?)
+            }
+            VEC4 => {
+                let mut v = [0.0; 4];
+                for x in &mut v {*x = self.f32()?}
+                Variable::Vec4(v)
+            }
+            VECN => {
+                let n = self.len()?;
+                let mut v = Vec::with_capacity(n);
+                for _ in 0..n {v.push(self.f64()?)}
+                Variable::VecN(Arc::new(v))
+            }
+            MAT4 => {
+                let mut m = [[0.0; 4]; 4];
+                for col in &mut m {
+                    for x in col {*x = self.f32()?}
+                }
+                Variable::Mat4(Box::new(m))
+            }
+            ARRAY => {
+                let n = self.len()?;
+                let mut arr = Vec::with_capacity(n);
+                for _ in 0..n {arr.push(self.variable()?)}
+                Variable::Array(Arc::new(arr))
+            }
+            F64_ARRAY => {
+                let n = self.len()?;
+                let mut arr = Vec::with_capacity(n);
+                for _ in 0..n {arr.push(Variable::f64(self.f64()?))}
+                Variable::Array(Arc::new(arr))
+            }
+            LINK => {
+                use Link;
+
+                let n = self.len()?;
+                let mut link = Link::new();
+                for _ in 0..n {
+                    let pos = self.pos;
+                    link.push(&self.variable()?)
+                        .map_err(|err| format!("{} at byte {}", err, pos))?;
+                }
+                Variable::Link(Box::new(link))
+            }
+            OBJECT => {
+                let n = self.len()?;
+                let mut obj = HashMap::with_capacity(n);
+                for _ in 0..n {
+                    let key = self.string()?;
+                    obj.insert(key, self.variable()?);
+                }
+                Variable::Object(Arc::new(obj))
+            }
+            OK => Variable::Result(Ok(Box::new(self.variable()?))),
+            ERR => {
+                let message = self.variable()?;
+                let n = self.len()?;
+                let mut trace = Vec::with_capacity(n);
+                for _ in 0..n {trace.push(self.utf8()?)}
+                Variable::Result(Err(Box::new(Error {message, trace})))
+            }
+            tag => return Err(format!("Invalid tag {} at byte {}", tag, pos)),
+        })
+    }
+}
@@ -0,0 +1,153 @@
+//! CSV import.
+//!
+//! Quoted fields are loaded as strings.
+//! Unquoted fields are loaded as numbers or booleans when they parse,
+//! otherwise as strings.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use range::Range;
+
+use super::data::{error, read_file};
+
+use Variable;
+
+/// Loads CSV from a file.
+///
+/// When `header` is `true`, the first row is used as keys
+/// and the other rows are loaded as objects.
+/// Otherwise, every row is loaded as an array.
+pub fn load_file(file: &str, header: bool) -> Result<Variable, String> {
+    load_data(&read_file(file)?, header)
+}
+
+/// Loads CSV from text.
+pub fn load_data(data: &str, header: bool) -> Result<Variable, String> {
+    let mut rows = rows(data)?.into_iter();
+    let res: Vec<Variable> = if header {
+        let keys: Vec<Arc<String>> = match rows.next() {
+            None => vec![],
+            Some((range, fields)) => {
+                let keys: Vec<Arc<String>> =
+                    fields.into_iter().map(|f| Arc::new(f.text)).collect();
+                for (i, key) in keys.iter().enumerate() {
+                    if keys[..i].contains(key) {
+                        return Err(error(range, &format!("Duplicate key `{}`", key), data));
+                    }
+                }
+                keys
+            }
+        };
+        let mut res = vec![];
+        for (range, fields) in rows {
+            if fields.len() != keys.len() {
+                return Err(error(range, &format!("Expected {} fields, found {}",
+                    keys.len(), fields.len()), data));
+            }
+            let obj: HashMap<Arc<String>, Variable> = keys.iter().cloned()
+                .zip(fields.into_iter().map(|f| f.into_variable()))
+                .collect();
+            res.push(Variable::Object(Arc::new(obj)));
+        }
+        res
+    } else {
+        rows.map(|(_, fields)| Variable::Array(Arc::new(
+            fields.into_iter().map(|f| f.into_variable()).collect()
+        ))).collect()
+    };
+    Ok(Variable::Array(Arc::new(res)))
+}
+
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+impl Field {
+    fn into_variable(self) -> Variable {
+        if !self.quoted {
+            let t = self.text.trim();
+            if let Some(x) = number(t) {
+                return Variable::f64(x);
+            }
+            match t {
+                "true" => return Variable::bool(true),
+                "false" => return Variable::bool(false),
+                _ => {}
+            }
+        }
+        Variable::Text(Arc::new(self.text))
+    }
+}
+
+/// Parses a finite number in decimal syntax, e.g. `-2.5e3`.
+///
+/// Other text that Rust parses as `f64`, such as `inf` or `NaN`, is kept as text.
+fn number(t: &str) -> Option<f64> {
+    if !t.bytes().any(|c| c.is_ascii_digit()) ||
+       !t.bytes().all(|c| c.is_ascii_digit() || b"+-.eE".contains(&c)) {
+        return None;
+    }
+    t.parse::<f64>().ok().filter(|x| x.is_finite())
+}
+
+/// Splits text into rows of fields, with the range of every row.
+fn rows(data: &str) -> Result<Vec<(Range, Vec<Field>)>, String> {
+    let mut res = vec![];
+    let mut chars = data.char_indices().peekable();
+    while chars.peek().is_some() {
+        let start = chars.peek().unwrap().0;
+        let mut fields = vec![];
+        let mut end_of_row = false;
+        while !end_of_row {
+            let mut field = Field {text: String::new(), quoted: false};
+            if let Some(&(quote, '"')) = chars.peek() {
+                chars.next();
+                field.quoted = true;
+                loop {
+                    match chars.next() {
+                        None => return Err(error(Range::new(quote, 1),
+                            "Expected closing `\"`", data)),
+                        Some((_, '"')) => {
+                            if let Some(&(_, '"')) = chars.peek() {
+                                chars.next();
+                                field.text.push('"');
+                            } else {
+                                break;
+                            }
+                        }
+                        Some((_, c)) => field.text.push(c),
+                    }
+                }
+            }
+            loop {
+                match chars.next() {
+                    None => {
+                        end_of_row = true;
+                        break;
+                    }
+                    Some((_, ',')) => break,
+                    Some((_, '\n')) => {
+                        end_of_row = true;
+                        break;
+                    }
+                    Some((_, '\r')) if chars.peek().map(|c| c.1) == Some('\n') => {}
+                    Some((i, c)) => {
+                        if field.quoted {
+                            return Err(error(Range::new(i, c.len_utf8()),
+                                "Expected `,` or new line after quoted field", data));
+                        }
+                        field.text.push(c);
+                    }
+                }
+            }
+            fields.push(field);
+        }
+        let end = chars.peek().map(|c| c.0).unwrap_or(data.len());
+        // Skip empty lines.
+        if fields.len() == 1 && !fields[0].quoted && fields[0].text.is_empty() {continue};
+        res.push((Range::new(start, end - start), fields));
+    }
+    Ok(res)
+}
@@ -1,6 +1,6 @@
 use std::fs::File;
 use std::io::Read;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use range::Range;
@@ -13,16 +13,21 @@ use Variable;
 type Strings = HashSet<Arc<String>>;
 
 /// Loads data from a file.
-#[cfg(feature = "file")]
 pub fn load_file(file: &str) -> Result<Variable, String> {
+    load_data(&read_file(file)?)
+}
+
+/// Reads text from a file.
+#[cfg(feature = "file")]
+pub fn read_file(file: &str) -> Result<String, String> {
     let mut data_file = File::open(file).map_err(|err| io_error("open", file, &err))?;
     let mut d = String::new();
     data_file.read_to_string(&mut d).map_err(|err| io_error("read", file, &err))?;
-    load_data(&d)
+    Ok(d)
 }
 
 #[cfg(not(feature = "file"))]
-pub fn load_file(_: &str) -> Result<Variable, String> {
+pub fn read_file(_: &str) -> Result<String, String> {
     Err(super::FILE_SUPPORT_DISABLED.into())
 }
 
@@ -30,8 +35,376 @@ pub fn load_file(_: &str) -> Result<Variable, String> {
 pub fn load_data(data: &str) -> Result<Variable, String> {
     let mut read = ReadToken::new(data, 0);
     let mut strings: Strings = HashSet::new();
+    let mut pos = Positions::disabled();
+    opt_w(&mut read);
+    expr(&mut read, &mut strings, &mut pos, data)
+}
+
+/// Minimum number of bytes to read at a time when streaming.
+const CHUNK: usize = 1 << 16;
+
+/// Reads the items of a top level array one at a time.
+///
+/// Text is read in chunks and only the current item is kept in memory.
+pub struct Items<R> {
+    reader: R,
+    buf: String,
+    /// Offset in the buffer where the next item starts.
+    ///
+    /// Text before the offset is removed when reading more text.
+    start: usize,
+    /// Bytes of an incomplete UTF-8 character at the end of the last chunk.
+    pending: Vec<u8>,
+    strings: Strings,
+    started: bool,
+    done: bool,
+    eof: bool,
+    index: usize,
+    line: usize,
+    /// Progress of the search for the end of the next item.
+    scan: Scan,
+}
+
+/// Where to resume the search for the end of the next item.
+struct Scan {
+    /// Offset in the buffer.
+    pos: usize,
+    state: ScanState,
+    depth: usize,
+    started: bool,
+}
+
+#[derive(Clone, Copy)]
+enum ScanState {
+    Code,
+    Text,
+    Escape,
+    LineComment,
+    BlockComment,
+    BlockCommentStar,
+}
+
+impl<R: Read> Items<R> {
+    /// Creates a new item reader.
+    pub fn new(reader: R) -> Items<R> {
+        Items {
+            reader,
+            buf: String::new(),
+            start: 0,
+            pending: vec![],
+            strings: HashSet::new(),
+            started: false,
+            done: false,
+            eof: false,
+            index: 0,
+            line: 1,
+            scan: Scan {pos: 0, state: ScanState::Code, depth: 0, started: false},
+        }
+    }
+
+    fn item(&mut self) -> Result<Option<Variable>, String> {
+        loop {
+            let eof = self.eof;
+            match self.try_item() {
+                Ok(Some((v, n))) => {
+                    self.started = true;
+                    self.line += self.buf[self.start..self.start + n].matches('\n').count();
+                    self.start += n;
+                    self.scan = Scan {pos: self.start, state: ScanState::Code, depth: 0, started: true};
+                    self.strings.clear();
+                    if v.is_some() {self.index += 1};
+                    return Ok(v);
+                }
+                Ok(None) if !eof => self.fill()?,
+                // Only read more text when the error might be caused by an incomplete item.
+                Err(_) if !eof && !self.item_end() => self.fill()?,
+                Ok(None) => return Err(format!(
+                    "In item {} starting at line {}:\nReached end of file",
+                    self.index, self.line)),
+                Err(err) => return Err(format!(
+                    "In item {} starting at line {}:\n{}",
+                    self.index, self.line, err)),
+            }
+        }
+    }
+
+    /// Tries to read an item from the buffer.
+    ///
+    /// Returns `None` when more text is needed.
+    /// An item is complete when followed by `,` or `]`,
+    /// since a number or comment might continue in the next chunk.
+    fn try_item(&mut self) -> Result<Option<(Option<Variable>, usize)>, String> {
+        let data: &str = &self.buf[self.start..];
+        let mut read = ReadToken::new(data, 0);
+        opt_w(&mut read);
+        if !self.started {
+            if let Some(range) = read.tag("[") {
+                read = read.consume(range.length);
+            } else if read.start().offset < data.len() {
+                return Err(error(read.start(), "Expected `[`", data));
+            } else {
+                return Ok(None);
+            }
+            opt_w(&mut read);
+        }
+        if let Some(range) = read.tag("]") {
+            read = read.consume(range.length);
+            return Ok(Some((None, read.start().offset)));
+        }
+        let mut pos = Positions::disabled();
+        let v = expr(&mut read, &mut self.strings, &mut pos, data)?;
+        opt_w(&mut read);
+        if let Some(range) = read.tag(",") {
+            read = read.consume(range.length);
+        } else if read.tag("]").is_none() {
+            if read.start().offset < data.len() {
+                return Err(error(read.start(), "Expected `,`", data));
+            }
+            return Ok(None);
+        }
+        Ok(Some((Some(v), read.start().offset)))
+    }
+
+    /// Returns `true` if the buffer contains the end of the next item.
+    ///
+    /// An item ends with `,` or a closing bracket outside nested brackets,
+    /// text and comments. Before the array is started, any other character than `[`
+    /// counts as the end, since it is an error.
+    ///
+    /// Resumes from where the last search stopped.
+    fn item_end(&mut self) -> bool {
+        use self::ScanState::*;
+
+        let bytes = self.buf.as_bytes();
+        let scan = &mut self.scan;
+        while let Some(&c) = bytes.get(scan.pos) {
+            match scan.state {
+                Text if c == b'\\' => scan.state = Escape,
+                Text if c == b'"' => scan.state = Code,
+                Text => {}
+                Escape => scan.state = Text,
+                LineComment => if c == b'\n' {scan.state = Code},
+                BlockComment => if c == b'*' {scan.state = BlockCommentStar},
+                BlockCommentStar => scan.state = match c {
+                    b'/' => Code,
+                    b'*' => BlockCommentStar,
+                    _ => BlockComment,
+                },
+                Code => match c {
+                    b'"' => scan.state = Text,
+                    b'/' => match bytes.get(scan.pos + 1) {
+                        // Wait for the next character.
+                        None => return false,
+                        Some(&b'/') => {
+                            scan.state = LineComment;
+                            scan.pos += 1;
+                        }
+                        Some(&b'*') => {
+                            scan.state = BlockComment;
+                            scan.pos += 1;
+                        }
+                        Some(_) if !scan.started => return true,
+                        Some(_) => {}
+                    },
+                    _ if c.is_ascii_whitespace() => {}
+                    b'[' if !scan.started => scan.started = true,
+                    _ if !scan.started => return true,
+                    b'(' | b'{' | b'[' => scan.depth += 1,
+                    b')' | b'}' | b']' if scan.depth == 0 => return true,
+                    b')' | b'}' | b']' => scan.depth -= 1,
+                    b',' if scan.depth == 0 => return true,
+                    _ => {}
+                }
+            }
+            scan.pos += 1;
+        }
+        false
+    }
+
+    /// Reads the next chunk, at least doubling the buffer.
+    fn fill(&mut self) -> Result<(), String> {
+        use std::io::ErrorKind;
+        use std::str::from_utf8;
+
+        // Remove the items that are read.
+        self.buf.drain(..self.start);
+        self.scan.pos -= self.start;
+        self.start = 0;
+
+        let start = self.pending.len();
+        self.pending.resize(start + self.buf.len().max(CHUNK), 0);
+        let n = loop {
+            match self.reader.read(&mut self.pending[start..]) {
+                Ok(n) => break n,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(format!("{}", err)),
+            }
+        };
+        self.pending.truncate(start + n);
+        if n == 0 {
+            self.eof = true;
+        }
+        let valid = match from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(ref err) if err.error_len().is_none() && !self.eof => err.valid_up_to(),
+            Err(_) => return Err("Invalid UTF-8".into()),
+        };
+        self.buf.push_str(from_utf8(&self.pending[..valid]).unwrap());
+        self.pending.drain(..valid);
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Items<R> {
+    type Item = Result<Variable, String>;
+
+    fn next(&mut self) -> Option<Result<Variable, String>> {
+        if self.done {return None};
+        match self.item() {
+            Ok(Some(v)) => Some(Ok(v)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Streams the items of a top level array in a file.
+#[cfg(feature = "file")]
+pub fn stream_file(file: &str) -> Result<Items<File>, String> {
+    Ok(Items::new(File::open(file).map_err(|err| io_error("open", file, &err))?))
+}
+
+#[cfg(not(feature = "file"))]
+pub fn stream_file(_: &str) -> Result<Items<File>, String> {
+    Err(super::FILE_SUPPORT_DISABLED.into())
+}
+
+/// Number of bytes to search from the end of file for the closing `]`.
+#[cfg(feature = "file")]
+const TAIL: u64 = 4096;
+
+/// Appends an item to a top level array in a file.
+///
+/// Creates the file if it does not exist.
+/// Only the end of the file is read and rewritten.
+#[cfg(feature = "file")]
+pub fn append_file<F>(file: &str, f: F) -> Result<(), String>
+    where F: FnOnce(&mut ::std::io::BufWriter<&mut File>) -> ::std::io::Result<()>
+{
+    use std::fs::OpenOptions;
+    use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+    let err = |err: io::Error| io_error("append to", file, &err);
+    let mut data_file = OpenOptions::new().read(true).write(true).create(true).open(file)
+        .map_err(|err| io_error("open", file, &err))?;
+    let len = data_file.seek(SeekFrom::End(0)).map_err(&err)?;
+    let tail_len = len.min(TAIL);
+    data_file.seek(SeekFrom::Start(len - tail_len)).map_err(&err)?;
+    let mut tail = vec![];
+    data_file.read_to_end(&mut tail).map_err(&err)?;
+    let mut chars = tail.iter().enumerate().rev().filter(|&(_, c)| !c.is_ascii_whitespace());
+    let (at, prefix) = match chars.next() {
+        None if len == tail_len => (0, "[\n    "),
+        Some((i, &b']')) => {
+            let at = len - tail_len + i as u64;
+            match chars.next() {
+                Some((_, &b'[')) => (at, "\n    "),
+                _ => (at, ",\n    "),
+            }
+        }
+        _ => return Err(format!("Expected `]` at end of file `{}`", file)),
+    };
+    data_file.seek(SeekFrom::Start(at)).map_err(&err)?;
+    {
+        let mut w = BufWriter::new(&mut data_file);
+        w.write_all(prefix.as_bytes()).map_err(&err)?;
+        f(&mut w).map_err(&err)?;
+        w.write_all(b"\n]\n").map_err(&err)?;
+        w.flush().map_err(&err)?;
+    }
+    let end = data_file.seek(SeekFrom::Current(0)).map_err(&err)?;
+    data_file.set_len(end).map_err(&err)?;
+    Ok(())
+}
+
+/// Loads data from text with the source ranges of values.
+///
+/// The ranges are stored by key path, e.g. `players[0].name`.
+/// The key path of the top level value is empty.
+/// Keys that are not identifiers are quoted, e.g. `players[0]."first name"`.
+pub fn load_data_ranges(data: &str) -> Result<(Variable, HashMap<String, Range>), String> {
+    let mut read = ReadToken::new(data, 0);
+    let mut strings: Strings = HashSet::new();
+    let mut pos = Positions {
+        path: String::new(),
+        ranges: Some(HashMap::new()),
+    };
     opt_w(&mut read);
-    expr(&mut read, &mut strings, data)
+    let v = expr(&mut read, &mut strings, &mut pos, data)?;
+    Ok((v, pos.ranges.unwrap()))
+}
+
+/// Keeps track of key path and source ranges of values while loading.
+struct Positions {
+    path: String,
+    ranges: Option<HashMap<String, Range>>,
+}
+
+impl Positions {
+    fn disabled() -> Positions {
+        Positions {path: String::new(), ranges: None}
+    }
+
+    fn insert(&mut self, range: Range) {
+        if let Some(ref mut ranges) = self.ranges {
+            ranges.insert(self.path.clone(), range);
+        }
+    }
+
+    /// Pushes key to path and returns the previous length.
+    fn push_key(&mut self, key: &str) -> usize {
+        let n = self.path.len();
+        if self.ranges.is_some() {
+            push_key(&mut self.path, key);
+        }
+        n
+    }
+
+    /// Pushes index to path and returns the previous length.
+    fn push_index(&mut self, i: usize) -> usize {
+        use std::fmt::Write;
+
+        let n = self.path.len();
+        if self.ranges.is_some() {
+            write!(&mut self.path, "[{}]", i).unwrap();
+        }
+        n
+    }
+
+    fn pop(&mut self, n: usize) {
+        self.path.truncate(n);
+    }
+}
+
+/// Pushes key to a key path.
+///
+/// Keys that are not identifiers are quoted,
+/// such that keys containing e.g. `.` or `[` do not collide with other paths.
+pub(crate) fn push_key(path: &mut String, key: &str) {
+    use std::fmt::Write;
+
+    if !path.is_empty() {path.push('.')};
+    if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        path.push_str(key);
+    } else {
+        write!(path, "{:?}", key).unwrap();
+    }
 }
 
 static NUMBER_SETTINGS: NumberSettings = NumberSettings {
@@ -43,23 +416,41 @@ const SEPS: &str = &"(){}[],.:;\n\"\\";
 fn expr(
     read: &mut ReadToken,
     strings: &mut Strings,
+    pos: &mut Positions,
+    data: &str
+) -> Result<Variable, String> {
+    let start = *read;
+    let v = value(read, strings, pos, data)?;
+    pos.insert(read.subtract(&start));
+    Ok(v)
+}
+
+fn value(
+    read: &mut ReadToken,
+    strings: &mut Strings,
+    pos: &mut Positions,
     data: &str
 ) -> Result<Variable, String> {
     if let Some(range) = read.tag("{") {
         // Object.
         *read = read.consume(range.length);
-        return object(read, strings, data);
+        return object(read, strings, pos, data);
     }
     if let Some(range) = read.tag("[") {
         // Array.
         *read = read.consume(range.length);
-        return array(read, strings, data);
+        return array(read, strings, pos, data);
     }
     if let Some(range) = read.tag("(") {
         // Vec4.
         *read = read.consume(range.length);
         return vec4(read, data);
     }
+    if let Some(range) = read.tag("vecn(") {
+        // VecN.
+        *read = read.consume(range.length);
+        return vecn(read, data);
+    }
     if let Some(range) = read.tag("#") {
         use read_color::rgb_maybe_a;
 
@@ -82,20 +473,14 @@ fn expr(
     if let Some(range) = read.tag("link") {
         // Link.
         *read = read.consume(range.length);
-        return link(read, strings, data);
+        return link(read, strings, pos, data);
     }
     // Text.
     if let Some(range) = read.string() {
         match read.parse_string(range.length) {
             Ok(s) => {
                 *read = read.consume(range.length);
-                return Ok(Variable::Text(
-                    if let Some(s) = strings.get(&s) {
-                        s.clone()
-                    } else {
-                        Arc::new(s)
-                    }
-                ));
+                return Ok(Variable::Text(intern(strings, s)));
             }
             Err(err_range) => {
                 let (range, err) = err_range.decouple();
@@ -130,7 +515,7 @@ fn expr(
     if let Some(range) = read.tag("some(") {
         *read = read.consume(range.length);
         opt_w(read);
-        let res = expr(read, strings, data)?;
+        let res = expr(read, strings, pos, data)?;
         opt_w(read);
         return if let Some(range) = read.tag(")") {
             *read = read.consume(range.length);
@@ -145,6 +530,7 @@ fn expr(
 fn object(
     read: &mut ReadToken,
     strings: &mut Strings,
+    pos: &mut Positions,
     data: &str
 ) -> Result<Variable, String> {
     use std::collections::HashMap;
@@ -167,12 +553,7 @@ fn object(
         if let Some(range) = read.string() {
             match read.parse_string(range.length) {
                 Ok(s) => {
-                    // Use reference to existing string to reduce memory.
-                    key = if let Some(s) = strings.get(&s) {
-                        s.clone()
-                    } else {
-                        Arc::new(s)
-                    };
+                    key = intern(strings, s);
                     *read = read.consume(range.length);
                 }
                 Err(err_range) => {
@@ -186,12 +567,7 @@ fn object(
                 return Err(error(range, "Expected key", data));
             } else {
                 let k = read.raw_string(range.length);
-                // Use reference to existing string to reduce memory.
-                key = if let Some(s) = strings.get(&k) {
-                    s.clone()
-                } else {
-                    Arc::new(k)
-                };
+                key = intern(strings, k);
                 *read = read.consume(range.length);
             };
         }
@@ -206,7 +582,10 @@ fn object(
 
         opt_w(read);
 
-        res.insert(key, expr(read, strings, data)?);
+        let n = pos.push_key(&key);
+        let v = expr(read, strings, pos, data)?;
+        pos.pop(n);
+        res.insert(key, v);
 
         was_comma = comma(read);
     }
@@ -216,6 +595,7 @@ fn object(
 fn array(
     read: &mut ReadToken,
     strings: &mut Strings,
+    pos: &mut Positions,
     data: &str
 ) -> Result<Variable, String> {
     use std::sync::Arc;
@@ -234,7 +614,10 @@ fn array(
             return Err(error(read.start(), "Expected `,`", data));
         }
 
-        res.push(expr(read, strings, data)?);
+        let n = pos.push_index(res.len());
+        let v = expr(read, strings, pos, data)?;
+        pos.pop(n);
+        res.push(v);
         was_comma = comma(read);
     }
     Ok(Variable::Array(Arc::new(res)))
@@ -243,6 +626,7 @@ fn array(
 fn link(
     read: &mut ReadToken,
     strings: &mut Strings,
+    pos: &mut Positions,
     data: &str
 ) -> Result<Variable, String> {
     use Link;
@@ -267,7 +651,7 @@ fn link(
             break;
         }
 
-        match link.push(&expr(read, strings, data)?) {
+        match link.push(&expr(read, strings, pos, data)?) {
             Ok(()) => {}
             Err(err) => return Err(err),
         };
@@ -328,6 +712,61 @@ fn vec4(read: &mut ReadToken, data: &str) -> Result<Variable, String> {
     Ok(Variable::Vec4([x as f32, y as f32, z as f32, w as f32]))
 }
 
+/// Reads the components of `vecn([..])`, after `vecn(`.
+fn vecn(read: &mut ReadToken, data: &str) -> Result<Variable, String> {
+    opt_w(read);
+    if let Some(range) = read.tag("[") {
+        *read = read.consume(range.length);
+    } else {
+        return Err(error(read.start(), "Expected `[`", data));
+    }
+    let mut res = vec![];
+    let mut was_comma = false;
+    loop {
+        opt_w(read);
+
+        if let Some(range) = read.tag("]") {
+            *read = read.consume(range.length);
+            break;
+        }
+
+        if !res.is_empty() && !was_comma {
+            return Err(error(read.start(), "Expected `,`", data));
+        }
+
+        if let Some(range) = read.number(&NUMBER_SETTINGS) {
+            match read.parse_number(&NUMBER_SETTINGS, range.length) {
+                Ok(x) => {
+                    *read = read.consume(range.length);
+                    res.push(x);
+                }
+                Err(err) => return Err(error(range, &format!("{}", err), data)),
+            }
+        } else {
+            return Err(error(read.start(), "Expected component", data));
+        }
+
+        was_comma = comma(read);
+    }
+    opt_w(read);
+    if let Some(range) = read.tag(")") {
+        *read = read.consume(range.length);
+    } else {
+        return Err(error(read.start(), "Expected `)`", data));
+    }
+    Ok(Variable::VecN(Arc::new(res)))
+}
+
+/// Uses reference to existing string to reduce memory.
+fn intern(strings: &mut Strings, s: String) -> Arc<String> {
+    if let Some(s) = strings.get(&s) {
+        return s.clone();
+    }
+    let s = Arc::new(s);
+    strings.insert(s.clone());
+    s
+}
+
 /// Reads optional whitespace including comments.
 fn opt_w(read: &mut ReadToken) {
     loop {
@@ -401,7 +840,7 @@ fn comma(read: &mut ReadToken) -> bool {
 }
 
 /// Generates error message using Piston-Meta's error handler.
-fn error(range: Range, msg: &str, data: &str) -> String {
+pub(crate) fn error(range: Range, msg: &str, data: &str) -> String {
     use piston_meta::ParseErrorHandler;
 
     let mut handler = ParseErrorHandler::new(data);
@@ -0,0 +1,270 @@
+//! JSON import and export.
+//!
+//! Values map onto `Variable` the same way as the Dyon data format:
+//! objects, arrays, strings, numbers and booleans map to themselves
+//! and `null` maps to `none()`.
+//! When exporting, `some(x)` is written as `x`, 4D vectors as arrays of 4 numbers
+//! and links as arrays of their items.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::Arc;
+
+use read_token::{NumberSettings, ReadToken};
+
+use super::data::{error, read_file};
+
+use Variable;
+
+type Strings = HashSet<Arc<String>>;
+
+/// Loads JSON from a file.
+pub fn load_file(file: &str) -> Result<Variable, String> {
+    load_data(&read_file(file)?)
+}
+
+/// Loads JSON from text.
+pub fn load_data(data: &str) -> Result<Variable, String> {
+    let mut read = ReadToken::new(data, 0);
+    let mut strings: Strings = HashSet::new();
+    w(&mut read);
+    let res = expr(&mut read, &mut strings, data)?;
+    w(&mut read);
+    if read.start().offset < data.len() {
+        return Err(error(read.start(), "Expected end of file", data));
+    }
+    Ok(res)
+}
+
+static NUMBER_SETTINGS: NumberSettings = NumberSettings {
+    allow_underscore: false,
+};
+
+fn expr(
+    read: &mut ReadToken,
+    strings: &mut Strings,
+    data: &str
+) -> Result<Variable, String> {
+    if let Some(range) = read.tag("{") {
+        *read = read.consume(range.length);
+        return object(read, strings, data);
+    }
+    if let Some(range) = read.tag("[") {
+        *read = read.consume(range.length);
+        return array(read, strings, data);
+    }
+    if read.string().is_some() {
+        return Ok(Variable::Text(text(read, strings, data)?));
+    }
+    if let Some(range) = read.number(&NUMBER_SETTINGS) {
+        match read.parse_number(&NUMBER_SETTINGS, range.length) {
+            Ok(val) => {
+                *read = read.consume(range.length);
+                return Ok(Variable::f64(val));
+            }
+            Err(err) => return Err(error(range, &format!("{}", err), data)),
+        }
+    }
+    if let Some(range) = read.tag("false") {
+        *read = read.consume(range.length);
+        return Ok(Variable::bool(false));
+    }
+    if let Some(range) = read.tag("true") {
+        *read = read.consume(range.length);
+        return Ok(Variable::bool(true));
+    }
+    if let Some(range) = read.tag("null") {
+        *read = read.consume(range.length);
+        return Ok(Variable::Option(None));
+    }
+    if read.start().offset >= data.len() {
+        Err(error(read.start(), "Reached end of file", data))
+    } else {
+        Err(error(read.start(), "Expected JSON value", data))
+    }
+}
+
+/// Reads a string, sharing memory with equal strings.
+fn text(
+    read: &mut ReadToken,
+    strings: &mut Strings,
+    data: &str
+) -> Result<Arc<String>, String> {
+    let range = read.string().unwrap();
+    match read.parse_string(range.length) {
+        Ok(s) => {
+            *read = read.consume(range.length);
+            Ok(if let Some(s) = strings.get(&s) {
+                s.clone()
+            } else {
+                let s = Arc::new(s);
+                strings.insert(s.clone());
+                s
+            })
+        }
+        Err(err_range) => {
+            let (range, err) = err_range.decouple();
+            Err(error(range, &format!("{}", err), data))
+        }
+    }
+}
+
+fn object(
+    read: &mut ReadToken,
+    strings: &mut Strings,
+    data: &str
+) -> Result<Variable, String> {
+    let mut res: HashMap<Arc<String>, Variable> = HashMap::new();
+    w(read);
+    if let Some(range) = read.tag("}") {
+        *read = read.consume(range.length);
+        return Ok(Variable::Object(Arc::new(res)));
+    }
+    loop {
+        w(read);
+        if read.string().is_none() {
+            return Err(error(read.start(), "Expected key", data));
+        }
+        let key = text(read, strings, data)?;
+        w(read);
+        if let Some(range) = read.tag(":") {
+            *read = read.consume(range.length);
+        } else {
+            return Err(error(read.start(), "Expected `:`", data));
+        }
+        w(read);
+        let v = expr(read, strings, data)?;
+        res.insert(key, v);
+        w(read);
+        if let Some(range) = read.tag(",") {
+            *read = read.consume(range.length);
+        } else if let Some(range) = read.tag("}") {
+            *read = read.consume(range.length);
+            break;
+        } else {
+            return Err(error(read.start(), "Expected `,` or `}`", data));
+        }
+    }
+    Ok(Variable::Object(Arc::new(res)))
+}
+
+fn array(
+    read: &mut ReadToken,
+    strings: &mut Strings,
+    data: &str
+) -> Result<Variable, String> {
+    let mut res = vec![];
+    w(read);
+    if let Some(range) = read.tag("]") {
+        *read = read.consume(range.length);
+        return Ok(Variable::Array(Arc::new(res)));
+    }
+    loop {
+        w(read);
+        res.push(expr(read, strings, data)?);
+        w(read);
+        if let Some(range) = read.tag(",") {
+            *read = read.consume(range.length);
+        } else if let Some(range) = read.tag("]") {
+            *read = read.consume(range.length);
+            break;
+        } else {
+            return Err(error(read.start(), "Expected `,` or `]`", data));
+        }
+    }
+    Ok(Variable::Array(Arc::new(res)))
+}
+
+/// Reads whitespace.
+fn w(read: &mut ReadToken) {
+    let range = read.whitespace();
+    *read = read.consume(range.length);
+}
+
+/// Writes variable as JSON.
+///
+/// Returns an error for values that have no JSON representation.
+pub fn write_json<W: io::Write>(w: &mut W, v: &Variable) -> Result<(), String> {
+    write(w, v).map_err(|err| match err {
+        WriteError::Io(err) => format!("{}", err),
+        WriteError::Unsupported(ty) => format!("Can not write `{}` as JSON", ty),
+    })
+}
+
+enum WriteError {
+    Io(io::Error),
+    Unsupported(Arc<String>),
+}
+
+impl From<io::Error> for WriteError {
+    fn from(err: io::Error) -> WriteError {WriteError::Io(err)}
+}
+
+fn write<W: io::Write>(w: &mut W, v: &Variable) -> Result<(), WriteError> {
+    use piston_meta::json::write_string;
+
+    match *v {
+        Variable::Text(ref t) => write_string(w, t)?,
+        Variable::F64(x, _) => {
+            if !x.is_finite() {
+                return Err(WriteError::Unsupported(Arc::new(format!("{}", x))));
+            }
+            write!(w, "{}", x)?;
+        }
+        Variable::Bool(x, _) => write!(w, "{}", x)?,
+        Variable::Vec4(v) => {
+            if let Some(x) = v.iter().find(|x| !x.is_finite()) {
+                return Err(WriteError::Unsupported(Arc::new(format!("{}", x))));
+            }
+            write!(w, "[{},{},{},{}]", v[0], v[1], v[2], v[3])?;
+        }
+        Variable::VecN(ref v) => {
+            write!(w, "[")?;
+            for (i, &x) in v.iter().enumerate() {
+                if !x.is_finite() {
+                    return Err(WriteError::Unsupported(Arc::new(format!("{}", x))));
+                }
+                if i > 0 {write!(w, ",")?};
+                write!(w, "{}", x)?;
+            }
+            write!(w, "]")?;
+        }
+        Variable::Option(None) => write!(w, "null")?,
+        Variable::Option(Some(ref x)) => write(w, x)?,
+        Variable::Array(ref arr) => {
+            write!(w, "[")?;
+            for (i, x) in arr.iter().enumerate() {
+                if i > 0 {write!(w, ",")?};
+                write(w, x)?;
+            }
+            write!(w, "]")?;
+        }
+        Variable::Link(ref link) => {
+            write!(w, "[")?;
+            let mut first = true;
+            for slice in &link.slices {
+                for i in slice.start..slice.end {
+                    if !first {write!(w, ",")?};
+                    first = false;
+                    write(w, &slice.block.var(i))?;
+                }
+            }
+            write!(w, "]")?;
+        }
+        Variable::Object(ref obj) => {
+            // Sort keys to make output deterministic.
+            let mut keys: Vec<&Arc<String>> = obj.keys().collect();
+            keys.sort();
+            write!(w, "{{")?;
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {write!(w, ",")?};
+                write_string(w, key)?;
+                write!(w, ":")?;
+                write(w, &obj[key])?;
+            }
+            write!(w, "}}")?;
+        }
+        ref x => return Err(WriteError::Unsupported(x.typeof_var())),
+    }
+    Ok(())
+}
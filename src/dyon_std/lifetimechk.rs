@@ -62,6 +62,7 @@ fn min_ref(v: &Variable, min: &mut Option<usize>) {
         Bool(_, _) => {}
         F64(_, _) => {}
         Vec4(_) => {}
+        VecN(_) => {}
         Mat4(_) => {}
         Text(_) => {}
         Link(_) => {}
@@ -261,3 +261,76 @@ pub fn json_from_meta_data(data: &[Variable]) -> Result<String, io::Error> {
     writeln!(w, "")?;
     Ok(String::from_utf8(w).unwrap())
 }
+
+/// Converts flat meta data into a tree.
+///
+/// Nodes become objects `{type: "node", name, offset, length, children}`
+/// and values become objects `{type, name, offset, length, value}`.
+pub fn meta_tree(data: &[Variable]) -> Result<Vec<Variable>, String> {
+    use std::collections::HashMap;
+
+    fn object(
+        ty: &Arc<String>,
+        name: &Arc<String>,
+        offset: f64,
+        length: f64,
+        val: (&str, Variable),
+    ) -> Variable {
+        let mut obj = HashMap::new();
+        obj.insert(Arc::new("type".into()), Variable::Text(ty.clone()));
+        obj.insert(Arc::new("name".into()), Variable::Text(name.clone()));
+        obj.insert(Arc::new("offset".into()), Variable::f64(offset));
+        obj.insert(Arc::new("length".into()), Variable::f64(length));
+        obj.insert(Arc::new(val.0.into()), val.1);
+        Variable::Object(Arc::new(obj))
+    }
+
+    let node: Arc<String> = Arc::new("node".into());
+    // Stores name, offset and children of open nodes.
+    let mut stack: Vec<(Arc<String>, f64, Vec<Variable>)> = vec![];
+    let mut res = vec![];
+    for (i, item) in data.iter().enumerate() {
+        let expected = || format!(
+            "Expected `[offset, length, type, name, value]` at index {}", i);
+        let arr = match *item {
+            Variable::Array(ref arr) if arr.len() >= 4 => arr,
+            _ => return Err(expected()),
+        };
+        let (offset, length, ty, name) = match (&arr[0], &arr[1], &arr[2], &arr[3]) {
+            (&Variable::F64(offset, _), &Variable::F64(length, _),
+             &Variable::Text(ref ty), &Variable::Text(ref name)) =>
+                (offset, length, ty, name),
+            _ => return Err(expected()),
+        };
+        let v = match &***ty {
+            "start" => {
+                stack.push((name.clone(), offset, vec![]));
+                continue;
+            }
+            "end" => {
+                let (start_name, start, children) = match stack.pop() {
+                    Some(x) => x,
+                    None => return Err(format!(
+                        "End of node `{}` without start at index {}", name, i)),
+                };
+                if start_name != *name {
+                    return Err(format!("Expected end of node `{}`, found `{}` at index {}",
+                                       start_name, name, i));
+                }
+                object(&node, name, start, offset + length - start,
+                       ("children", Variable::Array(Arc::new(children))))
+            }
+            "bool" | "str" | "f64" if arr.len() >= 5 =>
+                object(ty, name, offset, length, ("value", arr[4].clone())),
+            _ => return Err(expected()),
+        };
+        match stack.last_mut() {
+            Some(&mut (_, _, ref mut children)) => children.push(v),
+            None => res.push(v),
+        }
+    }
+    if let Some(&(ref name, _, _)) = stack.last() {
+        return Err(format!("Missing end of node `{}`", name));
+    }
+    Ok(res)
+}
@@ -0,0 +1,311 @@
+//! Prints meta data trees as text using Piston-Meta syntax rules.
+//!
+//! This is the reverse of parsing: Rules are matched against the tree
+//! generated by `meta::meta_tree`, emitting tags and values as text.
+//! Optional whitespace is only emitted where two words would run together,
+//! required whitespace becomes a single space and lines become new lines.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use piston_meta::{Rule as MetaRule, Syntax};
+
+use Variable;
+
+/// Maximum depth of rules, to stop rules that refer to themselves
+/// without printing anything.
+const MAX_DEPTH: usize = 1000;
+
+/// Prints meta data tree as text using syntax rules.
+///
+/// The start rule is the last rule.
+pub fn print_tree(syntax: &Syntax, tree: &[Variable]) -> Result<String, String> {
+    let rules: HashMap<Arc<String>, Rule> = syntax.names.iter().cloned()
+        .zip(syntax.rules.iter().map(convert))
+        .collect();
+    let start = match syntax.names.last() {
+        Some(start) => start,
+        None => return Err("Expected at least one rule in syntax".into()),
+    };
+    let mut printer = Printer {
+        rules: &rules,
+        out: String::new(),
+        space: false,
+        depth: 0,
+    };
+    let mut i = 0;
+    if printer.print(&rules[start], tree, &mut i) && i == tree.len() {
+        Ok(printer.out)
+    } else {
+        Err(format!("Could not print tree using rule `{}`, stopped at item {}", start, i))
+    }
+}
+
+/// Rules for printing.
+///
+/// Values without a name are not stored in the tree,
+/// so they are printed as `""`, `0` or nothing.
+enum Rule {
+    /// Tag text, whether it is a look-ahead that prints nothing,
+    /// and bool property with whether it is inverted.
+    Tag(Arc<String>, bool, Option<(Arc<String>, bool)>),
+    /// Whitespace, where `true` means required.
+    Whitespace(bool),
+    /// Text with double quotes.
+    Text(Option<Arc<String>>),
+    /// Number.
+    Number(Option<Arc<String>>),
+    /// Text until any of some characters.
+    Until(Option<Arc<String>>),
+    Sequence(Vec<Rule>),
+    Select(Vec<Rule>),
+    Optional(Box<Rule>),
+    /// Look-ahead that prints nothing.
+    Not,
+    /// Repeated rule, where `true` means it can be empty.
+    Repeat(Box<Rule>, bool),
+    Lines(Box<Rule>),
+    /// Rule separated by another rule, where `true` means it can be empty.
+    SeparateBy(Box<Rule>, Box<Rule>, bool),
+    /// Reference to rule by name, with node name.
+    Ref(Arc<String>, Option<Arc<String>>),
+}
+
+/// Converts a Piston-Meta rule into a rule for printing.
+fn convert(rule: &MetaRule) -> Rule {
+    match *rule {
+        MetaRule::Tag(ref tag) => Rule::Tag(tag.text.clone(), tag.not,
+            tag.property.clone().map(|name| (name, tag.inverted))),
+        MetaRule::Whitespace(ref w) => Rule::Whitespace(!w.optional),
+        MetaRule::Text(ref text) => Rule::Text(text.property.clone()),
+        MetaRule::Number(ref number) => Rule::Number(number.property.clone()),
+        MetaRule::UntilAny(ref until) => Rule::Until(until.property.clone()),
+        MetaRule::UntilAnyOrWhitespace(ref until) => Rule::Until(until.property.clone()),
+        MetaRule::Sequence(ref seq) => Rule::Sequence(seq.args.iter().map(convert).collect()),
+        MetaRule::Select(ref sel) => Rule::Select(sel.args.iter().map(convert).collect()),
+        MetaRule::Optional(ref opt) => Rule::Optional(Box::new(convert(&opt.rule))),
+        MetaRule::Not(_) => Rule::Not,
+        MetaRule::Repeat(ref rep) => Rule::Repeat(Box::new(convert(&rep.rule)), rep.optional),
+        MetaRule::Lines(ref lines) => Rule::Lines(Box::new(convert(&lines.rule))),
+        MetaRule::SeparateBy(ref sep) => Rule::SeparateBy(
+            Box::new(convert(&sep.rule)), Box::new(convert(&sep.by)), sep.optional),
+        MetaRule::Node(ref node) => Rule::Ref(node.name.clone(), node.property.clone()),
+    }
+}
+
+struct Printer<'a> {
+    rules: &'a HashMap<Arc<String>, Rule>,
+    out: String,
+    /// Whether optional whitespace was passed since last output.
+    space: bool,
+    depth: usize,
+}
+
+/// Returns field of child object.
+fn field<'b>(child: &'b Variable, key: &str) -> Option<&'b Variable> {
+    if let Variable::Object(ref obj) = *child {
+        obj.get(&key.to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if child has type and name.
+fn is(child: &Variable, ty: &str, name: &str) -> bool {
+    let text = |key: &str| match field(child, key) {
+        Some(&Variable::Text(ref t)) => Some(t.clone()),
+        _ => None,
+    };
+    text("type").map(|t| &**t == ty) == Some(true) &&
+    text("name").map(|t| &**t == name) == Some(true)
+}
+
+impl<'a> Printer<'a> {
+    fn emit(&mut self, s: &str) {
+        if s.is_empty() {return};
+        let word = |c: char| c.is_alphanumeric() || c == '_';
+        if self.space && self.out.chars().last().map(&word) == Some(true) &&
+           s.chars().next().map(&word) == Some(true) {
+            self.out.push(' ');
+        }
+        self.space = false;
+        self.out.push_str(s);
+    }
+
+    /// Returns value of child with type and name, moving to next child.
+    fn value<'b>(
+        &self,
+        ty: &str,
+        name: &Option<Arc<String>>,
+        children: &'b [Variable],
+        i: &mut usize
+    ) -> Option<&'b Variable> {
+        let name = match *name {
+            Some(ref name) => name,
+            None => return None,
+        };
+        let child = children.get(*i)?;
+        if !is(child, ty, name) {return None};
+        *i += 1;
+        field(child, "value")
+    }
+
+    /// Prints rule, returning `false` if the tree does not match.
+    ///
+    /// Output and position are not restored on failure,
+    /// see `try_print`.
+    fn print(&mut self, rule: &Rule, children: &[Variable], i: &mut usize) -> bool {
+        match *rule {
+            Rule::Tag(ref text, not, ref property) => {
+                if let Some((ref name, inverted)) = *property {
+                    match self.value("bool", &Some(name.clone()), children, i) {
+                        Some(&Variable::Bool(val, _)) if val != inverted => {}
+                        _ => return false,
+                    }
+                }
+                if !not {self.emit(text)};
+                true
+            }
+            Rule::Whitespace(required) => {
+                if required {
+                    if self.out.chars().last().map(|c| c.is_whitespace()) == Some(false) {
+                        self.out.push(' ');
+                    }
+                } else {
+                    self.space = true;
+                }
+                true
+            }
+            Rule::Text(ref name) => {
+                use piston_meta::json::write_string;
+
+                let s = match (name, self.value("str", name, children, i)) {
+                    (_, Some(&Variable::Text(ref t))) => t.clone(),
+                    (&None, _) => Arc::new(String::new()),
+                    _ => return false,
+                };
+                let mut buf: Vec<u8> = vec![];
+                write_string(&mut buf, &s).unwrap();
+                self.emit(&String::from_utf8(buf).unwrap());
+                true
+            }
+            Rule::Number(ref name) => {
+                let x = match (name, self.value("f64", name, children, i)) {
+                    (_, Some(&Variable::F64(x, _))) => x,
+                    (&None, _) => 0.0,
+                    _ => return false,
+                };
+                self.emit(&format!("{}", x));
+                true
+            }
+            Rule::Until(ref name) => {
+                match (name, self.value("str", name, children, i)) {
+                    (_, Some(&Variable::Text(ref t))) => {
+                        self.emit(t);
+                        true
+                    }
+                    (&None, _) => true,
+                    _ => false,
+                }
+            }
+            Rule::Sequence(ref rules) => {
+                rules.iter().all(|rule| self.print(rule, children, i))
+            }
+            Rule::Select(ref rules) => {
+                rules.iter().any(|rule| self.try_print(rule, children, i))
+            }
+            Rule::Optional(ref rule) => {
+                self.try_print(rule, children, i);
+                true
+            }
+            Rule::Not => true,
+            Rule::Repeat(ref rule, optional) => {
+                let mut count = 0;
+                loop {
+                    let start = *i;
+                    if !self.try_print(rule, children, i) {break};
+                    count += 1;
+                    if *i == start {break};
+                }
+                optional || count > 0
+            }
+            Rule::Lines(ref rule) => {
+                loop {
+                    let (start, len) = (*i, self.out.len());
+                    if !self.out.is_empty() {
+                        self.out.push('\n');
+                        self.space = false;
+                    }
+                    if !self.try_print(rule, children, i) || *i == start {
+                        *i = start;
+                        self.out.truncate(len);
+                        break;
+                    }
+                }
+                true
+            }
+            Rule::SeparateBy(ref rule, ref by, optional) => {
+                if !self.try_print(rule, children, i) {return optional};
+                loop {
+                    let (start, len, space) = (*i, self.out.len(), self.space);
+                    if !self.print(by, children, i) ||
+                       !self.print(rule, children, i) || *i == start
+                    {
+                        *i = start;
+                        self.out.truncate(len);
+                        self.space = space;
+                        break;
+                    }
+                }
+                true
+            }
+            Rule::Ref(ref name, ref node) => {
+                let rules = self.rules;
+                let rule = match rules.get(name) {
+                    Some(rule) => rule,
+                    None => return false,
+                };
+                if self.depth >= MAX_DEPTH {return false};
+                self.depth += 1;
+                let res = match *node {
+                    None => self.print(rule, children, i),
+                    Some(ref node) => {
+                        match children.get(*i) {
+                            Some(child) if is(child, "node", node) => {
+                                let empty = vec![];
+                                let node_children = match field(child, "children") {
+                                    Some(&Variable::Array(ref arr)) => &**arr,
+                                    _ => &empty,
+                                };
+                                let mut j = 0;
+                                if self.print(rule, node_children, &mut j) &&
+                                   j == node_children.len()
+                                {
+                                    *i += 1;
+                                    true
+                                } else {
+                                    false
+                                }
+                            }
+                            _ => false,
+                        }
+                    }
+                };
+                self.depth -= 1;
+                res
+            }
+        }
+    }
+
+    /// Prints rule, restoring output and position on failure.
+    fn try_print(&mut self, rule: &Rule, children: &[Variable], i: &mut usize) -> bool {
+        let (start, len, space) = (*i, self.out.len(), self.space);
+        if self.print(rule, children, i) {
+            true
+        } else {
+            *i = start;
+            self.out.truncate(len);
+            self.space = space;
+            false
+        }
+    }
+}
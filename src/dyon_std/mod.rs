@@ -2,9 +2,13 @@
 
 use *;
 
-mod io;
+pub(crate) mod io;
 mod meta;
-mod data;
+mod meta_print;
+pub(crate) mod data;
+mod json;
+mod csv;
+mod toml;
 mod lifetimechk;
 mod functions;
 
@@ -12,7 +16,7 @@ mod functions;
 const HTTP_SUPPORT_DISABLED: &'static str = "Http support is disabled";
 
 #[cfg(not(feature = "file"))]
-const FILE_SUPPORT_DISABLED: &'static str = "File support is disabled";
+pub(crate) const FILE_SUPPORT_DISABLED: &'static str = "File support is disabled";
 
 dyon_fn!{fn x(v: Vec4) -> f64 {f64::from(v.0[0])}}
 dyon_fn!{fn y(v: Vec4) -> f64 {f64::from(v.0[1])}}
@@ -135,11 +139,7 @@ pub(crate) fn why(
 ) -> Result<Option<Variable>, String> {
     let v = rt.stack.pop().expect(TINVOTS);
     let v = Variable::Array(Arc::new(match rt.resolve(&v) {
-        &Variable::Bool(true, Some(ref sec)) => {
-            let mut sec = (**sec).clone();
-            sec.reverse();
-            sec
-        }
+        &Variable::Bool(true, Some(ref sec)) => values(sec),
         &Variable::Bool(true, None) => {
             return Err(module.error(call.args[0].source_range(),
                 &format!("{}\nThis does not make sense, perhaps an array is empty?",
@@ -170,9 +170,7 @@ pub(crate) fn _where(
                     &format!("{}\nExpected number, found `NaN`",
                         rt.stack_trace()), rt))
             } else {
-                let mut sec = (**sec).clone();
-                sec.reverse();
-                sec
+                values(sec)
             }
         }
         &Variable::F64(_, None) => {
@@ -236,6 +234,105 @@ pub(crate) fn explain_where(
     Ok(Some(Variable::F64(val, Some(wh))))
 }
 
+/// Gets the values of the explanation of a secret, from the outermost step.
+fn values(sec: &[Variable]) -> Vec<Variable> {
+    use runtime::as_loop_step;
+
+    sec.iter().rev().map(|v| as_loop_step(v).map(|(_, val)| val).unwrap_or(v).clone()).collect()
+}
+
+/// Converts the explanation of a secret into objects, from the outermost step.
+///
+/// Loop steps are stored as `{name, index}` and explanations as `{explain}`.
+/// When an explanation is itself a secret, its derivation is stored in `why`.
+fn explanation(sec: &[Variable]) -> Vec<Variable> {
+    use std::collections::HashMap;
+    use runtime::as_loop_step;
+
+    let text = |s: &str| Arc::new(String::from(s));
+    sec.iter().rev().map(|v| {
+        let mut obj = HashMap::new();
+        if let Some((name, val)) = as_loop_step(v) {
+            obj.insert(text("name"), Variable::Text(name.clone()));
+            obj.insert(text("index"), val.clone());
+        } else {
+            obj.insert(text("explain"), v.clone());
+            match *v {
+                Variable::Bool(_, Some(ref sec)) | Variable::F64(_, Some(ref sec)) => {
+                    obj.insert(text("why"), Variable::Array(Arc::new(explanation(sec))));
+                }
+                _ => {}
+            }
+        }
+        Variable::Object(Arc::new(obj))
+    }).collect()
+}
+
+// TODO: Can't be rewritten as external function because it reports error on arguments.
+pub(crate) fn explain_all(
+    rt: &mut Runtime,
+    call: &ast::Call,
+    module: &Arc<Module>,
+) -> Result<Option<Variable>, String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let v = Variable::Array(Arc::new(match rt.resolve(&v) {
+        &Variable::Bool(_, Some(ref sec)) | &Variable::F64(_, Some(ref sec)) => explanation(sec),
+        &Variable::Bool(_, None) | &Variable::F64(_, None) => vec![],
+        x => return Err(module.error(call.args[0].source_range(),
+            &rt.expected(x, "sec[bool] or sec[f64]"), rt))
+    }));
+    Ok(Some(v))
+}
+
+pub(crate) fn specialize(
+    rt: &mut Runtime,
+    call: &ast::Call,
+    module: &Arc<Module>,
+) -> Result<Option<Variable>, String> {
+    let known = rt.stack.pop().expect(TINVOTS);
+    let f = rt.stack.pop().expect(TINVOTS);
+    let (f, env) = match rt.resolve(&f) {
+        &Variable::Closure(ref f, ref env) => (f.clone(), env.clone()),
+        x => return Err(module.error(call.args[0].source_range(),
+            &rt.expected(x, "closure"), rt))
+    };
+    let obj = match rt.resolve(&known) {
+        &Variable::Object(ref obj) => obj.clone(),
+        x => return Err(module.error(call.args[1].source_range(),
+            &rt.expected(x, "object"), rt))
+    };
+
+    let mut env_known = env.known.clone();
+    let mut spec_known = vec![];
+    for (key, val) in obj.iter() {
+        if env.known.iter().any(|k| &k.1 == key) {
+            return Err(module.error(call.args[1].source_range(),
+                &format!("{}\nArgument `{}` is already known", rt.stack_trace(), key), rt))
+        }
+        let i = match f.args.iter().position(|arg| &arg.name == key) {
+            None => return Err(module.error(call.args[1].source_range(),
+                &format!("{}\nClosure has no argument `{}`", rt.stack_trace(), key), rt)),
+            Some(i) => i,
+        };
+        // Find the stack slot, skipping slots of arguments that are already known.
+        let mut slot = i;
+        for k in &env.known {
+            if k.0 <= slot {slot += 1} else {break}
+        }
+        let val = rt.resolve(val).deep_clone(&rt.stack);
+        env_known.push((slot, key.clone(), val.clone()));
+        spec_known.push((key.clone(), val));
+    }
+    env_known.sort_by_key(|k| k.0);
+
+    let new_f = ::grab::specialize(rt, &f, spec_known, &env.module)?;
+    Ok(Some(Variable::Closure(Arc::new(new_f), Box::new(ClosureEnvironment {
+        module: env.module.clone(),
+        relative: env.relative,
+        known: env_known,
+    }))))
+}
+
 pub(crate) fn println(rt: &mut Runtime) -> Result<(), String> {
     use write::{print_variable, EscapeString};
 
@@ -360,6 +457,56 @@ pub(crate) fn random(rt: &mut Runtime) -> Result<(), String> {
     Ok(())
 }
 
+pub(crate) fn seed(rt: &mut Runtime) -> Result<(), String> {
+    let v: f64 = rt.pop()?;
+    if !(v >= 0.0 && v.fract() == 0.0 && v < 18446744073709551616.0) {
+        return Err(format!("Expected seed to be a non-negative integer, found `{}`", v));
+    }
+    rt.set_seed(v as u64);
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn random__range(rt: &mut Runtime) -> Result<(), String> {
+    use rand::Rng;
+
+    let b: f64 = rt.pop()?;
+    let a: f64 = rt.pop()?;
+    let v: f64 = rt.rng.gen();
+    rt.push(a + (b - a) * v);
+    Ok(())
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn random__normal(rt: &mut Runtime) -> Result<(), String> {
+    use rand::Rng;
+    use rand::distributions::StandardNormal;
+
+    let std: f64 = rt.pop()?;
+    let mean: f64 = rt.pop()?;
+    let v: f64 = rt.rng.sample(StandardNormal);
+    rt.push(mean + std * v);
+    Ok(())
+}
+
+pub(crate) fn choose(rt: &mut Runtime) -> Result<(), String> {
+    use rand::Rng;
+
+    let v = rt.stack.pop().expect(TINVOTS);
+    let arr = match rt.resolve(&v) {
+        &Variable::Array(ref arr) => arr.clone(),
+        x => return Err(rt.expected(x, "array"))
+    };
+    let v = if arr.is_empty() {
+        Variable::Option(None)
+    } else {
+        let i = rt.rng.gen_range(0, arr.len());
+        Variable::Option(Some(Box::new(rt.resolve(&arr[i]).deep_clone(&rt.stack))))
+    };
+    rt.stack.push(v);
+    Ok(())
+}
+
 dyon_fn!{fn tau() -> f64 {6.283_185_307_179_586}}
 
 // TODO: Can't be rewritten as external function because it reports error on arguments.
@@ -631,6 +778,35 @@ pub(crate) fn reverse(
     Ok(None)
 }
 
+// TODO: Can't be rewritten as external function because it reports error on arguments.
+pub(crate) fn shuffle(
+    rt: &mut Runtime,
+    call: &ast::Call,
+    module: &Arc<Module>,
+) -> Result<Option<Variable>, String> {
+    use rand::seq::SliceRandom;
+
+    let v = rt.stack.pop().expect(TINVOTS);
+    if let Variable::Ref(ind) = v {
+        let ok = if let Variable::Array(ref mut arr) = rt.stack[ind] {
+            Arc::make_mut(arr).shuffle(&mut rt.rng);
+            true
+        } else {
+            false
+        };
+        if !ok {
+            return Err(module.error(call.args[0].source_range(),
+                &format!("{}\nExpected reference to array",
+                    rt.stack_trace()), rt));
+        }
+    } else {
+        return Err(module.error(call.args[0].source_range(),
+            &format!("{}\nExpected reference to array",
+                rt.stack_trace()), rt));
+    }
+    Ok(None)
+}
+
 // TODO: Can't be rewritten as external function because it reports error on arguments.
 pub(crate) fn clear(
     rt: &mut Runtime,
@@ -749,7 +925,7 @@ pub(crate) fn _str(rt: &mut Runtime) -> Result<(), String> {
 
     let v = rt.stack.pop().expect(TINVOTS);
     let mut buf: Vec<u8> = vec![];
-    write_variable(&mut buf, rt, rt.resolve(&v), EscapeString::None, 0).unwrap();
+    write_variable(&mut buf, &rt.stack, rt.resolve(&v), EscapeString::None, 0).unwrap();
     rt.push(String::from_utf8(buf).unwrap());
     Ok(())
 }
@@ -759,7 +935,7 @@ pub(crate) fn json_string(rt: &mut Runtime) -> Result<(), String> {
 
     let v = rt.stack.pop().expect(TINVOTS);
     let mut buf: Vec<u8> = vec![];
-    write_variable(&mut buf, rt, rt.resolve(&v), EscapeString::Json, 0).unwrap();
+    write_variable(&mut buf, &rt.stack, rt.resolve(&v), EscapeString::Json, 0).unwrap();
     rt.stack.push(Variable::Text(Arc::new(String::from_utf8(buf).unwrap())));
     Ok(())
 }
@@ -823,6 +999,7 @@ pub(crate) fn _typeof(rt: &mut Runtime) -> Result<(), String> {
         Text(_) => TEXT_TYPE.clone(),
         F64(_, _) => F64_TYPE.clone(),
         Vec4(_) => VEC4_TYPE.clone(),
+        VecN(_) => VECN_TYPE.clone(),
         Mat4(_) => MAT4_TYPE.clone(),
         Return => RETURN_TYPE.clone(),
         Bool(_, _) => BOOL_TYPE.clone(),
@@ -1367,7 +1544,7 @@ pub(crate) fn unwrap(
             let mut w: Vec<u8> = vec![];
             w.extend_from_slice(rt.stack_trace().as_bytes());
             w.extend_from_slice("\n".as_bytes());
-            write_variable(&mut w, rt, &err.message,
+            write_variable(&mut w, &rt.stack, &err.message,
                            EscapeString::None, 0).unwrap();
             for t in &err.trace {
                 w.extend_from_slice("\n".as_bytes());
@@ -1425,6 +1602,331 @@ pub(crate) fn unwrap_err(
 
 dyon_fn!{fn dir__angle(val: f64) -> Vec4 {Vec4([val.cos() as f32, val.sin() as f32, 0.0, 0.0])}}
 
+dyon_fn!{fn dot(a: Vec4, b: Vec4) -> f64 {
+    f64::from(a.0[0] * b.0[0] + a.0[1] * b.0[1] + a.0[2] * b.0[2] + a.0[3] * b.0[3])
+}}
+dyon_fn!{fn normalize(v: Vec4) -> Vec4 {
+    let v = v.0;
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len == 0.0 {Vec4([0.0; 4])} else {Vec4([v[0] / len, v[1] / len, v[2] / len, 0.0])}
+}}
+dyon_fn!{fn reflect__dir_normal(dir: Vec4, normal: Vec4) -> Vec4 {
+    let (d, n) = (dir.0, normal.0);
+    let k = 2.0 * (d[0] * n[0] + d[1] * n[1] + d[2] * n[2]);
+    Vec4([d[0] - k * n[0], d[1] - k * n[1], d[2] - k * n[2], 0.0])
+}}
+dyon_fn!{fn rotate__vec_angle(v: Vec4, angle: f64) -> Vec4 {
+    let (sin, cos) = angle.sin_cos();
+    let (x, y) = (f64::from(v.0[0]), f64::from(v.0[1]));
+    Vec4([(x * cos - y * sin) as f32, (x * sin + y * cos) as f32, v.0[2], v.0[3]])
+}}
+
+pub(crate) fn vecn(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let v = match *rt.resolve(&v) {
+        Variable::VecN(ref v) => v.clone(),
+        Variable::Vec4(v) => Arc::new(v.iter().map(|&x| f64::from(x)).collect()),
+        Variable::Array(ref arr) => {
+            let mut res = Vec::with_capacity(arr.len());
+            for x in &**arr {
+                match *rt.resolve(x) {
+                    Variable::F64(x, _) => res.push(x),
+                    ref x => return Err(rt.expected(x, "f64"))
+                }
+            }
+            Arc::new(res)
+        }
+        ref x => return Err(rt.expected(x, "[f64], vec4 or vecn"))
+    };
+    rt.stack.push(Variable::VecN(v));
+    Ok(())
+}
+
+pub(crate) fn components(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let arr = match *rt.resolve(&v) {
+        Variable::VecN(ref v) => v.iter().map(|&x| Variable::f64(x)).collect(),
+        ref x => return Err(rt.expected(x, "vecn"))
+    };
+    rt.stack.push(Variable::Array(Arc::new(arr)));
+    Ok(())
+}
+
+pub(crate) fn dim(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let n = match *rt.resolve(&v) {
+        Variable::VecN(ref v) => v.len(),
+        ref x => return Err(rt.expected(x, "vecn"))
+    };
+    rt.push(n as f64);
+    Ok(())
+}
+
+pub(crate) fn sum(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let sum = match *rt.resolve(&v) {
+        Variable::VecN(ref v) => v.iter().sum::<f64>(),
+        ref x => return Err(rt.expected(x, "vecn"))
+    };
+    rt.push(sum);
+    Ok(())
+}
+
+pub(crate) fn s_vecn(rt: &mut Runtime) -> Result<(), String> {
+    let ind: f64 = rt.pop().expect(TINVOTS);
+    let ind = ind as usize;
+    let v = rt.stack.pop().expect(TINVOTS);
+    let x = match *rt.resolve(&v) {
+        Variable::VecN(ref v) => match v.get(ind) {
+            Some(&x) => x,
+            None => return Err(format!("Index out of bounds `{}`", ind))
+        },
+        ref x => return Err(rt.expected(x, "vecn"))
+    };
+    rt.push(x);
+    Ok(())
+}
+
+pub(crate) fn min_vecn(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let min = match *rt.resolve(&v) {
+        Variable::VecN(ref v) => {
+            let mut min: f64 = ::std::f64::NAN;
+            for &val in v.iter() {
+                if val < min || min.is_nan() { min = val }
+            }
+            min
+        }
+        ref x => return Err(rt.expected(x, "vecn"))
+    };
+    rt.push(min);
+    Ok(())
+}
+
+pub(crate) fn max_vecn(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let max = match *rt.resolve(&v) {
+        Variable::VecN(ref v) => {
+            let mut max: f64 = ::std::f64::NAN;
+            for &val in v.iter() {
+                if val > max || max.is_nan() { max = val }
+            }
+            max
+        }
+        ref x => return Err(rt.expected(x, "vecn"))
+    };
+    rt.push(max);
+    Ok(())
+}
+
+pub(crate) fn dot_vecn(rt: &mut Runtime) -> Result<(), String> {
+    let b = rt.stack.pop().expect(TINVOTS);
+    let a = rt.stack.pop().expect(TINVOTS);
+    let v = match (rt.resolve(&a), rt.resolve(&b)) {
+        (&Variable::VecN(ref a), &Variable::VecN(ref b)) => {
+            if a.len() != b.len() {
+                return Err(format!("Expected `vecn` of same length, found {} and {}",
+                                   a.len(), b.len()))
+            }
+            a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+        }
+        (a, b) => return Err(format!("Expected two `vecn`, found `{}` and `{}`",
+                                     a.typeof_var(), b.typeof_var()))
+    };
+    rt.push(v);
+    Ok(())
+}
+
+pub(crate) fn lerp__from_to_t(rt: &mut Runtime) -> Result<(), String> {
+    let t: f64 = rt.pop()?;
+    let to = rt.stack.pop().expect(TINVOTS);
+    let from = rt.stack.pop().expect(TINVOTS);
+    let v = match (rt.resolve(&from), rt.resolve(&to)) {
+        (&Variable::F64(a, _), &Variable::F64(b, _)) => Variable::f64(a + (b - a) * t),
+        (&Variable::Vec4(a), &Variable::Vec4(b)) => {
+            let t = t as f32;
+            Variable::Vec4([
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+                a[3] + (b[3] - a[3]) * t
+            ])
+        }
+        (&Variable::VecN(ref a), &Variable::VecN(ref b)) if a.len() == b.len() =>
+            Variable::VecN(Arc::new(a.iter().zip(b.iter())
+                .map(|(&a, &b)| a + (b - a) * t).collect())),
+        (a, b) => return Err(format!(
+            "Expected two `f64`, two `vec4` or two `vecn` of same length, \
+             found `{}` and `{}`", a.typeof_var(), b.typeof_var()))
+    };
+    rt.stack.push(v);
+    Ok(())
+}
+
+pub(crate) fn clamp__value_min_max(rt: &mut Runtime) -> Result<(), String> {
+    let max = rt.stack.pop().expect(TINVOTS);
+    let min = rt.stack.pop().expect(TINVOTS);
+    let value = rt.stack.pop().expect(TINVOTS);
+    let v = match (rt.resolve(&value), rt.resolve(&min), rt.resolve(&max)) {
+        (&Variable::F64(x, ref sec), &Variable::F64(min, _), &Variable::F64(max, _)) =>
+            Variable::F64(x.max(min).min(max), sec.clone()),
+        (&Variable::Vec4(x), &Variable::Vec4(min), &Variable::Vec4(max)) => Variable::Vec4([
+            x[0].max(min[0]).min(max[0]),
+            x[1].max(min[1]).min(max[1]),
+            x[2].max(min[2]).min(max[2]),
+            x[3].max(min[3]).min(max[3])
+        ]),
+        (&Variable::VecN(ref x), &Variable::VecN(ref min), &Variable::VecN(ref max))
+        if x.len() == min.len() && x.len() == max.len() =>
+            Variable::VecN(Arc::new(x.iter().zip(min.iter()).zip(max.iter())
+                .map(|((&x, &min), &max)| x.max(min).min(max)).collect())),
+        (x, _, _) => return Err(format!(
+            "Expected value, min and max to be all `f64`, all `vec4` \
+             or all `vecn` of same length, found `{}`",
+            x.typeof_var()))
+    };
+    rt.stack.push(v);
+    Ok(())
+}
+
+/// Converts quaternion `(x, y, z, w)` to `f64`.
+fn quat_f64(q: Vec4) -> [f64; 4] {
+    [f64::from(q.0[0]), f64::from(q.0[1]), f64::from(q.0[2]), f64::from(q.0[3])]
+}
+
+fn quat_vec4(q: [f64; 4]) -> Vec4 {
+    Vec4([q[0] as f32, q[1] as f32, q[2] as f32, q[3] as f32])
+}
+
+/// Returns the columns of rotation matrix from unit quaternion,
+/// using the same layout as `rot__axis_angle`.
+fn quat_columns(q: [f64; 4]) -> [[f64; 3]; 3] {
+    let [x, y, z, w] = q;
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+        [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+        [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+    ]
+}
+
+dyon_fn!{fn quat__axis_angle(axis: Vec4, angle: f64) -> Vec4 {
+    let a = [f64::from(axis.0[0]), f64::from(axis.0[1]), f64::from(axis.0[2])];
+    let len = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+    if len == 0.0 {return Vec4([0.0, 0.0, 0.0, 1.0])};
+    let (sin, cos) = (angle * 0.5).sin_cos();
+    let s = sin / len;
+    quat_vec4([a[0] * s, a[1] * s, a[2] * s, cos])
+}}
+dyon_fn!{fn quat_mul(a: Vec4, b: Vec4) -> Vec4 {
+    // Product `b * a`, such that the matrices multiply in the same order.
+    let ([ax, ay, az, aw], [bx, by, bz, bw]) = (quat_f64(b), quat_f64(a));
+    quat_vec4([
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+        aw * bw - ax * bx - ay * by - az * bz
+    ])
+}}
+dyon_fn!{fn quat_inv(q: Vec4) -> Vec4 {
+    let [x, y, z, w] = quat_f64(q);
+    let len2 = x * x + y * y + z * z + w * w;
+    if len2 == 0.0 {return Vec4([0.0, 0.0, 0.0, 1.0])};
+    quat_vec4([-x / len2, -y / len2, -z / len2, w / len2])
+}}
+dyon_fn!{fn rotate__quat_vec(quat: Vec4, v: Vec4) -> Vec4 {
+    let m = quat_columns(quat_f64(quat));
+    let v64 = [f64::from(v.0[0]), f64::from(v.0[1]), f64::from(v.0[2])];
+    let mut res = [0.0; 3];
+    for (col, &x) in m.iter().zip(v64.iter()) {
+        for i in 0..3 {
+            res[i] += col[i] * x;
+        }
+    }
+    Vec4([res[0] as f32, res[1] as f32, res[2] as f32, v.0[3]])
+}}
+dyon_fn!{fn slerp__from_to_t(from: Vec4, to: Vec4, t: f64) -> Vec4 {
+    let a = quat_f64(from);
+    let mut b = quat_f64(to);
+    let mut cos = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+    // Take the shortest path.
+    if cos < 0.0 {
+        b = [-b[0], -b[1], -b[2], -b[3]];
+        cos = -cos;
+    }
+    let (wa, wb) = if cos > 0.9995 {
+        // Nearly parallel, so interpolate linearly to avoid division by zero.
+        (1.0 - t, t)
+    } else {
+        let angle = cos.acos();
+        let sin = angle.sin();
+        (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+    };
+    let mut q = [0.0; 4];
+    for i in 0..4 {
+        q[i] = a[i] * wa + b[i] * wb;
+    }
+    let len = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    if len == 0.0 {return Vec4([0.0, 0.0, 0.0, 1.0])};
+    quat_vec4([q[0] / len, q[1] / len, q[2] / len, q[3] / len])
+}}
+dyon_fn!{fn quat_to_mat4(quat: Vec4) -> Mat4 {
+    let m = quat_columns(quat_f64(quat));
+    let col = |i: usize| [m[i][0] as f32, m[i][1] as f32, m[i][2] as f32, 0.0];
+    Mat4([col(0), col(1), col(2), [0.0, 0.0, 0.0, 1.0]])
+}}
+
+dyon_fn!{fn ray_aabb__pos_dir_min_max(pos: Vec4, dir: Vec4, min: Vec4, max: Vec4) -> Option<f64> {
+    let mut near = 0.0_f64;
+    let mut far = ::std::f64::INFINITY;
+    for i in 0..3 {
+        let (p, d) = (f64::from(pos.0[i]), f64::from(dir.0[i]));
+        let (lo, hi) = (f64::from(min.0[i]), f64::from(max.0[i]));
+        if d == 0.0 {
+            if p < lo || p > hi {return None};
+        } else {
+            let (t0, t1) = ((lo - p) / d, (hi - p) / d);
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+            if near > far {return None};
+        }
+    }
+    Some(near)
+}}
+dyon_fn!{fn ray_sphere__pos_dir_center_radius(
+    pos: Vec4,
+    dir: Vec4,
+    center: Vec4,
+    radius: f64
+) -> Option<f64> {
+    let mut a = 0.0;
+    let mut b = 0.0;
+    let mut c = -radius * radius;
+    for i in 0..3 {
+        let (p, d) = (f64::from(pos.0[i] - center.0[i]), f64::from(dir.0[i]));
+        a += d * d;
+        b += 2.0 * p * d;
+        c += p * p;
+    }
+    let disc = b * b - 4.0 * a * c;
+    if a == 0.0 || disc < 0.0 {return None};
+    let (t0, t1) = ((-b - disc.sqrt()) / (2.0 * a), (-b + disc.sqrt()) / (2.0 * a));
+    if t1 < 0.0 {None} else {Some(t0.max(0.0))}
+}}
+dyon_fn!{fn segment_segment__a_b_c_d(a: Vec4, b: Vec4, c: Vec4, d: Vec4) -> Option<Vec4> {
+    let cross = |u: [f64; 2], v: [f64; 2]| u[0] * v[1] - u[1] * v[0];
+    let p = |v: Vec4| [f64::from(v.0[0]), f64::from(v.0[1])];
+    let (a, b, c, d) = (p(a), p(b), p(c), p(d));
+    let r = [b[0] - a[0], b[1] - a[1]];
+    let s = [d[0] - c[0], d[1] - c[1]];
+    let ac = [c[0] - a[0], c[1] - a[1]];
+    let denom = cross(r, s);
+    if denom == 0.0 {return None};
+    let t = cross(ac, s) / denom;
+    let u = cross(ac, r) / denom;
+    if t < 0.0 || t > 1.0 || u < 0.0 || u > 1.0 {return None};
+    Some(Vec4([(a[0] + t * r[0]) as f32, (a[1] + t * r[1]) as f32, 0.0, 0.0]))
+}}
+
 dyon_fn!{fn load__meta_file(meta: Arc<String>, file: Arc<String>) -> Variable {
     let res = meta::load_meta_file(&**meta, &**file);
     Variable::Result(match res {
@@ -1502,6 +2004,39 @@ pub(crate) fn meta__syntax_in_string(
     })))
 }
 
+dyon_fn!{fn meta_tree__data(data: Vec<Variable>) -> Variable {
+    Variable::Result(match meta::meta_tree(&data) {
+        Ok(res) => Ok(Box::new(Variable::Array(Arc::new(res)))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(err)),
+            trace: vec![]
+        }))
+    })
+}}
+
+pub(crate) fn print_meta__syntax_tree(rt: &mut Runtime) -> Result<(), String> {
+    use piston_meta::Syntax;
+
+    let tree: Vec<Variable> = rt.pop()?;
+    let syntax_var = rt.stack.pop().expect(TINVOTS);
+    let syntax = match rt.resolve(&syntax_var) {
+        &Variable::RustObject(ref obj) => obj.clone(),
+        x => return Err(rt.expected(x, "Syntax"))
+    };
+    let res = match syntax.lock().unwrap().downcast_ref::<Arc<Syntax>>() {
+        Some(s) => meta_print::print_tree(s, &tree),
+        None => return Err(rt.expected(&syntax_var, "Syntax"))
+    };
+    rt.stack.push(Variable::Result(match res {
+        Ok(res) => Ok(Box::new(Variable::Text(Arc::new(res)))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(err)),
+            trace: vec![]
+        }))
+    }));
+    Ok(())
+}
+
 dyon_fn!{fn download__url_file(url: Arc<String>, file: Arc<String>) -> Variable {
     let res = meta::download_url_to_file(&**url, &**file);
     Variable::Result(match res {
@@ -1574,6 +2109,73 @@ pub(crate) fn load_string__file(_: &mut Runtime) -> Result<(), String> {
     Err(FILE_SUPPORT_DISABLED.into())
 }
 
+#[cfg(feature = "file")]
+pub(crate) fn write__link_file(rt: &mut Runtime) -> Result<(), String> {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use std::error::Error as StdError;
+
+    let file: Arc<String> = rt.pop()?;
+    let link = rt.stack.pop().expect(TINVOTS);
+    let res = match *rt.resolve(&link) {
+        Variable::Link(ref link) => {
+            match File::create(&**file) {
+                Ok(f) => {
+                    let mut w = BufWriter::new(f);
+                    match link.write_to(&mut w).and_then(|_| w.flush()) {
+                        Ok(()) => Ok(Box::new(Variable::Text(file.clone()))),
+                        Err(err) => Err(err)
+                    }
+                }
+                Err(err) => Err(err)
+            }
+        }
+        ref x => return Err(rt.expected(x, "link"))
+    };
+    let res = res.map_err(|err| Box::new(Error {
+        message: Variable::Text(Arc::new(format!(
+                    "Error when writing link to file `{}`:\n{}",
+                    file, err.description()))),
+        trace: vec![]
+    }));
+    rt.stack.push(Variable::Result(res));
+    Ok(())
+}
+
+#[cfg(not(feature = "file"))]
+pub(crate) fn write__link_file(_: &mut Runtime) -> Result<(), String> {
+    Err(FILE_SUPPORT_DISABLED.into())
+}
+
+#[cfg(feature = "file")]
+pub(crate) fn stream__file(rt: &mut Runtime) -> Result<(), String> {
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    // Returns the link itself, since unwrapping `ok(_)` would clone it without the writer.
+    let file: Arc<String> = rt.pop()?;
+    let f = File::create(&**file)
+        .map_err(|err| format!("Error when creating file `{}`:\n{}", file, err))?;
+    rt.stack.push(Variable::Link(Box::new(Link::with_writer(BufWriter::new(f)))));
+    Ok(())
+}
+
+#[cfg(not(feature = "file"))]
+pub(crate) fn stream__file(_: &mut Runtime) -> Result<(), String> {
+    Err(FILE_SUPPORT_DISABLED.into())
+}
+
+pub(crate) fn flush(rt: &mut Runtime) -> Result<(), String> {
+    let v = rt.stack.pop().expect(TINVOTS);
+    let ind = if let Variable::Ref(ind) = v { ind } else {
+        return Err("Expected reference to link".into())
+    };
+    if let Variable::Link(ref mut link) = rt.stack[ind] {
+        return link.flush().map_err(|err| format!("Could not write link:\n{}", err));
+    }
+    Err(rt.expected(&rt.stack[ind], "link"))
+}
+
 dyon_fn!{fn load_string__url(url: Arc<String>) -> Variable {
     Variable::Result(match meta::load_text_file_from_url(&**url) {
         Ok(s) => {
@@ -1653,6 +2255,212 @@ dyon_fn!{fn load_data__string(text: Arc<String>) -> Variable {
     Variable::Result(res)
 }}
 
+dyon_fn!{fn load_data__file_schema(file: Arc<String>, schema: Variable) -> Variable {
+    use Error;
+    use schema::Schema;
+
+    let res = match Schema::from_variable(&schema).and_then(|schema| schema.load_file(&file)) {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(format!(
+                        "Error loading data from file `{}`:\n{}",
+                        file, err))),
+            trace: vec![]
+        }))
+    };
+    Variable::Result(res)
+}}
+
+dyon_fn!{fn load_data__string_schema(text: Arc<String>, schema: Variable) -> Variable {
+    use Error;
+    use schema::Schema;
+
+    let res = match Schema::from_variable(&schema).and_then(|schema| schema.load_data(&text)) {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(format!(
+                        "Error loading data from string `{}`:\n{}",
+                        text, err))),
+            trace: vec![]
+        }))
+    };
+    Variable::Result(res)
+}}
+
+dyon_fn!{fn validate__data_schema(data: Variable, schema: Variable) -> Variable {
+    use Error;
+    use schema::{format_violations, Schema};
+
+    let res = Schema::from_variable(&schema).and_then(|schema| {
+        let violations = schema.validate(&data);
+        if violations.is_empty() {Ok(data)}
+        else {Err(format_violations("", &violations))}
+    });
+    Variable::Result(match res {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(err)),
+            trace: vec![]
+        }))
+    })
+}}
+
+/// Number of items that a data stream reads ahead.
+const STREAM_BUFFER: usize = 64;
+
+dyon_fn!{fn stream_data__file(file: Arc<String>) -> Variable {
+    use std::sync::mpsc::sync_channel;
+    use std::sync::Mutex;
+    use std::thread;
+
+    let (tx, rx) = sync_channel(STREAM_BUFFER);
+    thread::spawn(move || {
+        let err = |err: String| Variable::Result(Err(Box::new(Error {
+            message: Variable::Text(Arc::new(format!(
+                        "Error streaming data from file `{}`:\n{}",
+                        file, err))),
+            trace: vec![]
+        })));
+        let items = match data::stream_file(&file) {
+            Ok(items) => items,
+            Err(e) => {
+                let _ = tx.send(err(e));
+                return;
+            }
+        };
+        for item in items {
+            let v = match item {
+                Ok(v) => Variable::Result(Ok(Box::new(v))),
+                Err(e) => err(e),
+            };
+            // Stop reading when the receiver is dropped.
+            if tx.send(v).is_err() {break}
+        }
+    });
+    Variable::In(Arc::new(Mutex::new(rx)))
+}}
+
+#[cfg(feature = "file")]
+pub(crate) fn append__data_file(rt: &mut Runtime) -> Result<(), String> {
+    use write::{write_variable, EscapeString};
+
+    let file: Arc<String> = rt.pop()?;
+    let data = rt.stack.pop().expect(TINVOTS);
+    let res = {
+        let rt: &Runtime = rt;
+        data::append_file(&file, |w| {
+            write_variable(w, &rt.stack, rt.resolve(&data), EscapeString::Json, 1)
+        })
+    };
+    let res = match res {
+        Ok(()) => Ok(Box::new(Variable::Text(file.clone()))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(format!(
+                        "Error when appending data to file `{}`:\n{}",
+                        file, err))),
+            trace: vec![]
+        }))
+    };
+    rt.stack.push(Variable::Result(res));
+    Ok(())
+}
+
+#[cfg(not(feature = "file"))]
+pub(crate) fn append__data_file(_: &mut Runtime) -> Result<(), String> {
+    Err(FILE_SUPPORT_DISABLED.into())
+}
+
+/// Wraps the result of loading a format in a Dyon result.
+fn load_format(res: Result<Variable, String>, format: &str, source: &str) -> Variable {
+    Variable::Result(match res {
+        Ok(data) => Ok(Box::new(data)),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(format!(
+                        "Error loading {} from {}:\n{}",
+                        format, source, err))),
+            trace: vec![]
+        }))
+    })
+}
+
+dyon_fn!{fn load_binary__file(file: Arc<String>) -> Variable {
+    use binary;
+
+    load_format(binary::load_file(&file), "binary data", &format!("file `{}`", file))
+}}
+
+pub(crate) fn save_binary__data_file(rt: &mut Runtime) -> Result<(), String> {
+    use binary;
+
+    let file: Arc<String> = rt.pop()?;
+    let data = rt.stack.pop().expect(TINVOTS);
+    let res = match binary::save_file(&file, rt.resolve(&data)) {
+        Ok(()) => Ok(Box::new(Variable::Text(file.clone()))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(format!(
+                        "Error when saving binary data to file `{}`:\n{}",
+                        file, err))),
+            trace: vec![]
+        }))
+    };
+    rt.stack.push(Variable::Result(res));
+    Ok(())
+}
+
+dyon_fn!{fn load_json__file(file: Arc<String>) -> Variable {
+    load_format(json::load_file(&file), "JSON", &format!("file `{}`", file))
+}}
+
+dyon_fn!{fn load_json__string(text: Arc<String>) -> Variable {
+    load_format(json::load_data(&text), "JSON", &format!("string `{}`", text))
+}}
+
+#[cfg(feature = "file")]
+pub(crate) fn save_json__data_file(rt: &mut Runtime) -> Result<(), String> {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    let file: Arc<String> = rt.pop()?;
+    let data = rt.stack.pop().expect(TINVOTS);
+    let res = File::create(&**file).map_err(|err| err.to_string()).and_then(|f| {
+        let mut w = BufWriter::new(f);
+        json::write_json(&mut w, rt.resolve(&data))?;
+        w.flush().map_err(|err| err.to_string())
+    });
+    let res = match res {
+        Ok(()) => Ok(Box::new(Variable::Text(file.clone()))),
+        Err(err) => Err(Box::new(Error {
+            message: Variable::Text(Arc::new(format!(
+                        "Error when writing JSON to file `{}`:\n{}",
+                        file, err))),
+            trace: vec![]
+        }))
+    };
+    rt.stack.push(Variable::Result(res));
+    Ok(())
+}
+
+#[cfg(not(feature = "file"))]
+pub(crate) fn save_json__data_file(_: &mut Runtime) -> Result<(), String> {
+    Err(FILE_SUPPORT_DISABLED.into())
+}
+
+dyon_fn!{fn load_csv__file(file: Arc<String>) -> Variable {
+    load_format(csv::load_file(&file, true), "CSV", &format!("file `{}`", file))
+}}
+
+dyon_fn!{fn load_csv__file_header(file: Arc<String>, header: bool) -> Variable {
+    load_format(csv::load_file(&file, header), "CSV", &format!("file `{}`", file))
+}}
+
+dyon_fn!{fn load_toml__file(file: Arc<String>) -> Variable {
+    load_format(toml::load_file(&file), "TOML", &format!("file `{}`", file))
+}}
+
+dyon_fn!{fn load_toml__string(text: Arc<String>) -> Variable {
+    load_format(toml::load_data(&text), "TOML", &format!("string `{}`", text))
+}}
+
 pub(crate) fn args_os(rt: &mut Runtime) -> Result<(), String> {
     let mut arr: Vec<Variable> = vec![];
     for arg in ::std::env::args_os() {
@@ -1694,7 +2502,7 @@ pub(crate) fn save__data_file(
                         rt.stack_trace(), file, err.description()), rt))
         }
     };
-    let res = match write_variable(&mut f, rt, &data, EscapeString::Json, 0) {
+    let res = match write_variable(&mut f, &rt.stack, &data, EscapeString::Json, 0) {
         Ok(()) => Ok(Box::new(Variable::Text(file.clone()))),
         Err(err) => {
             Err(Box::new(::Error {
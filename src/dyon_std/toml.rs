@@ -0,0 +1,423 @@
+//! TOML import.
+//!
+//! Tables map to objects, arrays to arrays and strings, numbers and booleans
+//! to themselves.
+//! Integers are loaded as numbers and dates and times are loaded as strings.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use range::Range;
+
+use super::data::{error, read_file};
+
+use Variable;
+
+type Obj = HashMap<Arc<String>, Variable>;
+
+/// Loads TOML from a file.
+pub fn load_file(file: &str) -> Result<Variable, String> {
+    load_data(&read_file(file)?)
+}
+
+/// Loads TOML from text.
+pub fn load_data(data: &str) -> Result<Variable, String> {
+    let mut read = Reader {data, pos: 0};
+    let mut root: Obj = HashMap::new();
+    let mut current: Vec<Arc<String>> = vec![];
+    // Tables defined by `[table]` headers, since a table can only be defined once.
+    let mut defined: HashSet<Vec<Arc<String>>> = HashSet::new();
+    loop {
+        read.ws_newlines();
+        let start = read.pos;
+        if read.eof() {break};
+        if read.tag("[[") {
+            let path = read.keys()?;
+            read.ws();
+            if !read.tag("]]") {return Err(read.error("Expected `]]`"))};
+            let range = Range::new(start, read.pos - start);
+            let (last, parent) = path.split_last().unwrap();
+            let parent = table_mut(&mut root, parent, range, data)?;
+            let v = parent.entry(last.clone())
+                .or_insert_with(|| Variable::Array(Arc::new(vec![])));
+            match *v {
+                Variable::Array(ref mut arr) =>
+                    Arc::make_mut(arr).push(Variable::Object(Arc::new(HashMap::new()))),
+                _ => return Err(error(range,
+                    &format!("Key `{}` is not an array of tables", last), data)),
+            }
+            // Tables in the new table of the array can be defined again.
+            defined.retain(|p| !p.starts_with(&path));
+            current = path;
+        } else if read.tag("[") {
+            let path = read.keys()?;
+            read.ws();
+            if !read.tag("]") {return Err(read.error("Expected `]`"))};
+            let range = Range::new(start, read.pos - start);
+            if !defined.insert(path.clone()) {
+                let name: Vec<&str> = path.iter().map(|k| &***k).collect();
+                return Err(error(range, &format!("Duplicate table `{}`", name.join(".")), data));
+            }
+            table_mut(&mut root, &path, range, data)?;
+            current = path;
+        } else {
+            let path = read.keys()?;
+            read.ws();
+            if !read.tag("=") {return Err(read.error("Expected `=`"))};
+            read.ws();
+            let v = read.value()?;
+            let range = Range::new(start, read.pos - start);
+            let (last, parent) = path.split_last().unwrap();
+            let mut table_path = current.clone();
+            table_path.extend_from_slice(parent);
+            let table = table_mut(&mut root, &table_path, range, data)?;
+            if table.contains_key(last) {
+                return Err(error(range, &format!("Duplicate key `{}`", last), data));
+            }
+            table.insert(last.clone(), v);
+        }
+        read.ws();
+        read.comment();
+        if !read.eof() && !read.newline() {
+            return Err(read.error("Expected new line"));
+        }
+    }
+    Ok(Variable::Object(Arc::new(root)))
+}
+
+/// Gets table by key path, creating missing tables.
+///
+/// An array of tables refers to its last table.
+fn table_mut<'a>(
+    root: &'a mut Obj,
+    path: &[Arc<String>],
+    range: Range,
+    data: &str
+) -> Result<&'a mut Obj, String> {
+    let mut obj = root;
+    for key in path {
+        let v = obj.entry(key.clone())
+            .or_insert_with(|| Variable::Object(Arc::new(HashMap::new())));
+        obj = match *v {
+            Variable::Object(ref mut o) => Arc::make_mut(o),
+            Variable::Array(ref mut arr) => match Arc::make_mut(arr).last_mut() {
+                Some(&mut Variable::Object(ref mut o)) => Arc::make_mut(o),
+                _ => return Err(error(range, &format!("Key `{}` is not a table", key), data)),
+            },
+            _ => return Err(error(range, &format!("Key `{}` is not a table", key), data)),
+        };
+    }
+    Ok(obj)
+}
+
+struct Reader<'a> {
+    data: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {&self.data[self.pos..]}
+
+    fn eof(&self) -> bool {self.pos >= self.data.len()}
+
+    fn peek(&self) -> Option<char> {self.rest().chars().next()}
+
+    fn error(&self, msg: &str) -> String {
+        let len = self.peek().map(|c| c.len_utf8()).unwrap_or(0);
+        error(Range::new(self.pos, len), msg, self.data)
+    }
+
+    fn tag(&mut self, tag: &str) -> bool {
+        if self.rest().starts_with(tag) {
+            self.pos += tag.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reads spaces and tabs.
+    fn ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ' ' || c == '\t' {self.pos += 1} else {break}
+        }
+    }
+
+    fn comment(&mut self) {
+        if self.rest().starts_with('#') {
+            self.pos += self.rest().find('\n').unwrap_or(self.rest().len());
+        }
+    }
+
+    fn newline(&mut self) -> bool {
+        self.tag("\n") || self.tag("\r\n")
+    }
+
+    /// Reads whitespace, comments and new lines.
+    fn ws_newlines(&mut self) {
+        loop {
+            self.ws();
+            self.comment();
+            if !self.newline() {break}
+        }
+    }
+
+    /// Reads dotted key.
+    fn keys(&mut self) -> Result<Vec<Arc<String>>, String> {
+        let mut res = vec![];
+        loop {
+            self.ws();
+            res.push(Arc::new(self.key()?));
+            self.ws();
+            if !self.tag(".") {break}
+        }
+        Ok(res)
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let n = self.rest()
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(self.rest().len());
+                if n == 0 {return Err(self.error("Expected key"))};
+                let key = self.rest()[..n].into();
+                self.pos += n;
+                Ok(key)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Variable, String> {
+        if self.rest().starts_with("\"\"\"") {
+            Ok(Variable::Text(Arc::new(self.multi_line_basic_string()?)))
+        } else if self.rest().starts_with("'''") {
+            Ok(Variable::Text(Arc::new(self.multi_line_literal_string()?)))
+        } else if self.rest().starts_with('"') {
+            Ok(Variable::Text(Arc::new(self.basic_string()?)))
+        } else if self.rest().starts_with('\'') {
+            Ok(Variable::Text(Arc::new(self.literal_string()?)))
+        } else if self.tag("true") {
+            Ok(Variable::bool(true))
+        } else if self.tag("false") {
+            Ok(Variable::bool(false))
+        } else if self.tag("[") {
+            self.array()
+        } else if self.tag("{") {
+            self.inline_table()
+        } else {
+            self.number_or_date()
+        }
+    }
+
+    fn array(&mut self) -> Result<Variable, String> {
+        let mut res = vec![];
+        loop {
+            self.ws_newlines();
+            if self.tag("]") {break};
+            res.push(self.value()?);
+            self.ws_newlines();
+            if self.tag("]") {break};
+            if !self.tag(",") {return Err(self.error("Expected `,` or `]`"))};
+        }
+        Ok(Variable::Array(Arc::new(res)))
+    }
+
+    fn inline_table(&mut self) -> Result<Variable, String> {
+        let mut res: Obj = HashMap::new();
+        self.ws();
+        if self.tag("}") {return Ok(Variable::Object(Arc::new(res)))};
+        loop {
+            let start = self.pos;
+            let path = self.keys()?;
+            if !self.tag("=") {return Err(self.error("Expected `=`"))};
+            self.ws();
+            let v = self.value()?;
+            let range = Range::new(start, self.pos - start);
+            let (last, parent) = path.split_last().unwrap();
+            let table = table_mut(&mut res, parent, range, self.data)?;
+            if table.contains_key(last) {
+                return Err(error(range, &format!("Duplicate key `{}`", last), self.data));
+            }
+            table.insert(last.clone(), v);
+            self.ws();
+            if self.tag("}") {break};
+            if !self.tag(",") {return Err(self.error("Expected `,` or `}`"))};
+            self.ws();
+        }
+        Ok(Variable::Object(Arc::new(res)))
+    }
+
+    fn number_or_date(&mut self) -> Result<Variable, String> {
+        let start = self.pos;
+        let n = self.rest()
+            .find(|c: char| c == ',' || c == ']' || c == '}' || c == '#' ||
+                  c == '\n' || c == '\r' ||
+                  // Dates and times can contain a space before the time.
+                  (c == ' ' || c == '\t'))
+            .unwrap_or(self.rest().len());
+        let mut token: &str = &self.rest()[..n];
+        // Include time after date separated by space, e.g. `1979-05-27 07:32:00`.
+        if is_date(token) {
+            let after = &self.rest()[n..];
+            if after.starts_with(' ') && after[1..].chars().next().map(|c| c.is_ascii_digit())
+                == Some(true)
+            {
+                let m = after[1..].find(|c: char| c == ',' || c == ']' || c == '}' ||
+                    c == '#' || c.is_whitespace()).unwrap_or(after.len() - 1);
+                token = &self.rest()[..n + 1 + m];
+            }
+            self.pos += token.len();
+            return Ok(Variable::Text(Arc::new(token.into())));
+        }
+        if token.is_empty() {return Err(self.error("Expected value"))};
+        self.pos += token.len();
+        let range = Range::new(start, token.len());
+        let clean: String = token.chars().filter(|&c| c != '_').collect();
+        let (sign, digits) = if clean.starts_with('-') {
+            (-1.0, &clean[1..])
+        } else if clean.starts_with('+') {
+            (1.0, &clean[1..])
+        } else {
+            (1.0, &clean[..])
+        };
+        let radix = if digits.starts_with("0x") {16}
+            else if digits.starts_with("0o") {8}
+            else if digits.starts_with("0b") {2}
+            else {10};
+        let val = if radix != 10 {
+            i64::from_str_radix(&digits[2..], radix).ok().map(|x| sign * x as f64)
+        } else {
+            match digits {
+                "inf" => Some(sign * ::std::f64::INFINITY),
+                "nan" => Some(::std::f64::NAN),
+                _ => clean.parse::<f64>().ok(),
+            }
+        };
+        match val {
+            Some(x) => Ok(Variable::f64(x)),
+            None => Err(error(range, &format!("Could not parse `{}`", token), self.data)),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut res = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(error(Range::new(start, 1),
+                    "Expected closing `\"`", self.data)),
+                Some('"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('\\') => res.push(self.escape()?),
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    res.push(c);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn multi_line_basic_string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 3;
+        // A new line directly after the delimiter is trimmed.
+        self.newline();
+        let mut res = String::new();
+        loop {
+            if self.tag("\"\"\"") {break};
+            match self.peek() {
+                None => return Err(error(Range::new(start, 3),
+                    "Expected closing `\"\"\"`", self.data)),
+                Some('\\') => {
+                    // Line ending backslash trims whitespace and new lines.
+                    let rest = &self.rest()[1..];
+                    let trimmed = rest.trim_start_matches(|c: char| c == ' ' || c == '\t');
+                    if trimmed.starts_with('\n') || trimmed.starts_with("\r\n") {
+                        self.pos += 1 + rest.len() - trimmed.len();
+                        while let Some(c) = self.peek() {
+                            if c.is_whitespace() {self.pos += c.len_utf8()} else {break}
+                        }
+                    } else {
+                        res.push(self.escape()?);
+                    }
+                }
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    res.push(c);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 1;
+        match self.rest().find(|c: char| c == '\'' || c == '\n') {
+            Some(n) if self.rest()[n..].starts_with('\'') => {
+                let res = self.rest()[..n].into();
+                self.pos += n + 1;
+                Ok(res)
+            }
+            _ => Err(error(Range::new(start, 1), "Expected closing `'`", self.data)),
+        }
+    }
+
+    fn multi_line_literal_string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 3;
+        self.newline();
+        match self.rest().find("'''") {
+            Some(n) => {
+                let res = self.rest()[..n].into();
+                self.pos += n + 3;
+                Ok(res)
+            }
+            None => Err(error(Range::new(start, 3), "Expected closing `'''`", self.data)),
+        }
+    }
+
+    /// Reads escaped character in a basic string.
+    fn escape(&mut self) -> Result<char, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let c = match self.peek() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u) if u == 'u' || u == 'U' => {
+                let n = if u == 'u' {4} else {8};
+                let hex = self.rest().get(1..1 + n).unwrap_or("");
+                match u32::from_str_radix(hex, 16).ok().and_then(::std::char::from_u32) {
+                    Some(c) => {
+                        self.pos += n;
+                        c
+                    }
+                    None => return Err(error(Range::new(start, 2 + hex.len()),
+                        "Invalid unicode escape", self.data)),
+                }
+            }
+            _ => return Err(error(Range::new(start, 2), "Invalid escape", self.data)),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+}
+
+/// Returns `true` if token looks like a date or time, e.g. `1979-05-27` or `07:32:00`.
+fn is_date(token: &str) -> bool {
+    let b = token.as_bytes();
+    (b.len() >= 10 && b[..4].iter().all(|c| c.is_ascii_digit()) && b[4] == b'-') ||
+    (b.len() >= 8 && b[..2].iter().all(|c| c.is_ascii_digit()) && b[2] == b':')
+}
@@ -1,6 +1,9 @@
 /*
 Evaluate grab expressions and return closure where grab expressions
 are constants.
+
+The same pass is used to specialize closures,
+where known arguments are inlined and constant subexpressions are folded.
 */
 
 use std::sync::Arc;
@@ -18,8 +21,283 @@ pub enum Grabbed {
     ForN(ast::ForN),
 }
 
+/// Stores state when specializing a closure or optimizing a function.
+///
+/// When there are no known arguments, only constant folding is done.
+pub struct Specialize {
+    /// Known arguments with their values.
+    pub known: Vec<(Arc<String>, Variable)>,
+    /// Known arguments that are declared or mutated in the body.
+    ///
+    /// These are not inlined, but keep their value on the stack.
+    pub fixed: Vec<Arc<String>>,
+    /// Whether to collect fixed names only.
+    pub scan: bool,
+}
+
+impl Specialize {
+    fn fix(&mut self, name: &Arc<String>) {
+        if self.known.iter().any(|k| &k.0 == name) &&
+           !self.fixed.iter().any(|n| n == name) {
+            self.fixed.push(name.clone());
+        }
+    }
+
+    /// Returns the constant value of an item, if any.
+    fn value(&self, item: &ast::Item) -> Option<Variable> {
+        use ast::Expression as E;
+
+        if self.scan || item.current || item.try || !item.try_ids.is_empty() ||
+           self.fixed.iter().any(|n| n == &item.name) {return None};
+        let mut v = match self.known.iter().find(|k| k.0 == item.name) {
+            None => return None,
+            Some(&(_, ref v)) => v.clone(),
+        };
+        for id in &item.ids {
+            v = match (id, &v) {
+                (&ast::Id::String(_, ref key), &Variable::Object(ref obj)) => {
+                    match obj.get(key) {
+                        None => return None,
+                        Some(x) => x.clone(),
+                    }
+                }
+                (&ast::Id::F64(_, ind), &Variable::Array(ref arr)) => {
+                    match arr.get(ind as usize) {
+                        None => return None,
+                        Some(x) => x.clone(),
+                    }
+                }
+                (&ast::Id::Expression(E::Variable(ref range_var)), &Variable::Array(ref arr)) => {
+                    if let Variable::F64(ind, _) = range_var.1 {
+                        match arr.get(ind as usize) {
+                            None => return None,
+                            Some(x) => x.clone(),
+                        }
+                    } else {return None}
+                }
+                _ => return None,
+            };
+        }
+        Some(v)
+    }
+}
+
+/// Specializes a closure for known argument values.
+///
+/// Known arguments are inlined as constants,
+/// constant subexpressions are evaluated and dead branches are removed.
+/// Known arguments are removed from the signature of the closure,
+/// but keep their slots on the stack when called,
+/// such that the local offsets of the closure are unchanged.
+pub fn specialize(
+    rt: &mut Runtime,
+    closure: &ast::Closure,
+    known: Vec<(Arc<String>, Variable)>,
+    module: &Arc<Module>,
+) -> Result<ast::Closure, String> {
+    let args = closure.args.iter()
+        .filter(|arg| !known.iter().any(|k| k.0 == arg.name))
+        .cloned().collect();
+    let mut spec = Some(Specialize {known, fixed: vec![], scan: true});
+    // Collect known arguments that can not be inlined.
+    grab_expr(1, &mut spec, rt, &closure.expr, Side::Right, module)?;
+    if let Some(ref mut spec) = spec {spec.scan = false};
+    let expr = match grab_expr(1, &mut spec, rt, &closure.expr, Side::Right, module)? {
+        (Grabbed::Expression(x), Flow::Continue) => x,
+        _ => return Err(module.error(closure.expr.source_range(),
+                        &format!("{}\nExpected something",
+                            rt.stack_trace()), rt))
+    };
+    Ok(ast::Closure {
+        currents: closure.currents.clone(),
+        args,
+        source_range: closure.source_range,
+        ret: closure.ret.clone(),
+        file: closure.file.clone(),
+        source: closure.source.clone(),
+        expr,
+    })
+}
+
+fn fix(spec: &mut Option<Specialize>, name: &Arc<String>) {
+    if let Some(ref mut spec) = *spec {spec.fix(name)}
+}
+
+/// Fixes names bound by a pattern.
+fn fix_pattern(spec: &mut Option<Specialize>, pattern: &ast::Pattern) {
+    use ast::Pattern as P;
+
+    match *pattern {
+        P::Bind(ref name, _) => fix(spec, name),
+        P::Array(ref items, _) | P::Vec4(ref items, _) => {
+            for item in items {fix_pattern(spec, item)}
+        }
+        P::Variant(ref variant) => {
+            for arg in &variant.args {fix_pattern(spec, arg)}
+        }
+        P::Wildcard(_) | P::Value(_, _) => {}
+    }
+}
+
+/// Fixes names of items passed as mutable arguments.
+///
+/// Mutability information is stored in the function name, e.g. `push(mut,_)`.
+fn fix_mut_args(spec: &mut Option<Specialize>, call: &ast::Call) {
+    use ast::Expression as E;
+
+    if spec.is_none() {return};
+    let mutable = match (call.name.find('('), call.name.ends_with(')')) {
+        (Some(i), true) => &call.name[i + 1..call.name.len() - 1],
+        _ => return,
+    };
+    for (arg, m) in call.args.iter().zip(mutable.split(',')) {
+        if m == "mut" {
+            if let E::Item(ref item) = *arg {fix(spec, &item.name)}
+        }
+    }
+}
+
+/// Returns `true` if the direct children of an expression are constants.
+fn constant_children(expr: &ast::Expression) -> bool {
+    use ast::Expression as E;
+
+    fn c(expr: &ast::Expression) -> bool {
+        if let E::Variable(_) = *expr {true} else {false}
+    }
+
+    match *expr {
+        E::BinOp(ref binop_expr) => c(&binop_expr.left) && c(&binop_expr.right),
+        E::Compare(ref compare) => c(&compare.left) && c(&compare.right),
+        E::UnOp(ref unop) => c(&unop.expr),
+        E::Norm(ref norm) => c(&norm.expr),
+        E::Vec4(ref vec4) => vec4.args.iter().all(c),
+        E::Mat4(ref mat4) => mat4.args.iter().all(c),
+        E::Link(ref link) => link.items.iter().all(c),
+        E::Array(ref arr) => arr.items.iter().all(c),
+        E::Object(ref obj) => obj.key_values.iter().all(|kv| c(&kv.1)),
+        E::ArrayFill(ref arr_fill) => c(&arr_fill.fill) && c(&arr_fill.n),
+        _ => false,
+    }
+}
+
+/// Evaluates an expression with constant children to a constant.
+///
+/// When evaluation fails, the expression is kept,
+/// such that the error is reported when calling the closure.
+fn fold(
+    spec: &Option<Specialize>,
+    rt: &mut Runtime,
+    expr: ast::Expression,
+    module: &Arc<Module>,
+) -> ast::Expression {
+    match *spec {
+        Some(ref spec) if !spec.scan => {}
+        _ => return expr,
+    }
+    if !constant_children(&expr) {return expr};
+
+    let st = rt.stack.len();
+    let lc = rt.local_stack.len();
+    let cu = rt.current_stack.len();
+    let res = rt.expression(&expr, Side::Right, module);
+    rt.stack.truncate(st);
+    rt.local_stack.truncate(lc);
+    rt.current_stack.truncate(cu);
+    match res {
+        Ok((Some(v), Flow::Continue)) =>
+            ast::Expression::Variable(Box::new((expr.source_range(), v))),
+        _ => expr,
+    }
+}
+
+/// Removes branches of if-expression with constant conditions.
+fn fold_if(spec: &Option<Specialize>, if_expr: ast::If) -> ast::Expression {
+    use ast::Expression as E;
+
+    match *spec {
+        Some(ref spec) if !spec.scan => {}
+        _ => return E::If(Box::new(if_expr)),
+    }
+
+    let ast::If {
+        cond, true_block, else_if_conds, else_if_blocks, else_block, source_range
+    } = if_expr;
+    let mut conds = vec![];
+    let mut blocks = vec![];
+    let mut else_block = else_block;
+    let branches = Some((cond, true_block)).into_iter()
+        .chain(else_if_conds.into_iter().zip(else_if_blocks.into_iter()));
+    for (cond, block) in branches {
+        match cond {
+            E::Variable(ref range_var) if is_bool(&range_var.1, false) => continue,
+            E::Variable(ref range_var) if is_bool(&range_var.1, true) => {
+                else_block = Some(block);
+                break;
+            }
+            _ => {}
+        }
+        conds.push(cond);
+        blocks.push(block);
+    }
+
+    if conds.is_empty() {
+        return E::Block(Box::new(else_block.unwrap_or(ast::Block {
+            expressions: vec![],
+            source_range,
+        })));
+    }
+    let cond = conds.remove(0);
+    let true_block = blocks.remove(0);
+    E::If(Box::new(ast::If {
+        cond,
+        true_block,
+        else_if_conds: conds,
+        else_if_blocks: blocks,
+        else_block,
+        source_range,
+    }))
+}
+
+/// Expands swizzle of a constant 4D vector into its components.
+fn push_vec4_arg(
+    spec: &Option<Specialize>,
+    new_args: &mut Vec<ast::Expression>,
+    arg: ast::Expression,
+) {
+    use ast::Expression as E;
+
+    match *spec {
+        Some(ref spec) if !spec.scan => {}
+        _ => {
+            new_args.push(arg);
+            return;
+        }
+    }
+    if let E::Swizzle(ref swizzle) = arg {
+        if let E::Variable(ref range_var) = swizzle.expr {
+            if let Variable::Vec4(v) = range_var.1 {
+                let range = swizzle.source_range;
+                let sw = [Some(swizzle.sw0), Some(swizzle.sw1), swizzle.sw2, swizzle.sw3];
+                for &ind in sw.iter() {
+                    if let Some(ind) = ind {
+                        new_args.push(E::Variable(Box::new((range,
+                            Variable::f64(f64::from(v[ind]))))));
+                    }
+                }
+                return;
+            }
+        }
+    }
+    new_args.push(arg);
+}
+
+fn is_bool(v: &Variable, val: bool) -> bool {
+    if let Variable::Bool(b, _) = *v {b == val} else {false}
+}
+
 pub fn grab_expr(
     level: u16,
+    spec: &mut Option<Specialize>,
     rt: &mut Runtime,
     expr: &ast::Expression,
     side: Side,
@@ -29,7 +307,7 @@ pub fn grab_expr(
 
     match *expr {
         E::Grab(ref grab) => {
-            if grab.level == level {
+            if grab.level == level && spec.is_none() {
                 // Evaluate the expression and insert it into new AST as constant.
                 let v = match rt.expression(&grab.expr, side, module)? {
                     (Some(x), Flow::Continue) => x,
@@ -46,44 +324,49 @@ pub fn grab_expr(
         }
         E::Return(ref expr) => {
             Ok((Grabbed::Expression(E::Return(
-                Box::new(match grab_expr(level, rt, expr, side, module) {
+                Box::new(match grab_expr(level, spec, rt, expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 }))), Flow::Continue))
         }
         E::Try(ref expr) => {
             Ok((Grabbed::Expression(E::Try(
-                Box::new(match grab_expr(level, rt, expr, side, module) {
+                Box::new(match grab_expr(level, spec, rt, expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 }))), Flow::Continue))
         }
         E::BinOp(ref binop_expr) => {
-            Ok((Grabbed::Expression(E::BinOp(Box::new(ast::BinOpExpression {
+            let new_expr = E::BinOp(Box::new(ast::BinOpExpression {
                 op: binop_expr.op,
-                left: match grab_expr(level, rt, &binop_expr.left, side, module) {
+                left: match grab_expr(level, spec, rt, &binop_expr.left, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                right: match grab_expr(level, rt, &binop_expr.right, side, module) {
+                right: match grab_expr(level, spec, rt, &binop_expr.right, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
                 source_range: binop_expr.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::ReturnVoid(_) |
         E::Break(_) |
         E::Continue(_) |
         E::Variable(_) =>
             Ok((Grabbed::Expression(expr.clone()), Flow::Continue)),
+        E::Closure(_) if spec.is_some() => {
+            // Nested closures are left as-is, so their bodies are not folded.
+            Ok((Grabbed::Expression(expr.clone()), Flow::Continue))
+        }
         E::Closure(ref closure) => {
             Ok((Grabbed::Expression(E::Closure(Arc::new(ast::Closure {
                 file: closure.file.clone(),
                 source: closure.source.clone(),
                 args: closure.args.clone(),
                 currents: closure.currents.clone(),
-                expr: match grab_expr(level + 1, rt, &closure.expr, side, module) {
+                expr: match grab_expr(level + 1, &mut None, rt, &closure.expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -91,13 +374,17 @@ pub fn grab_expr(
                 source_range: closure.source_range,
             }))), Flow::Continue))
         }
-        E::Item(ref item) => match grab_item(level, rt, item, side, module) {
+        E::Item(ref item) => match grab_item(level, spec, rt, item, side, module) {
             Ok((Grabbed::Item(x), Flow::Continue)) => {
+                if let Some(v) = spec.as_ref().and_then(|spec| spec.value(&x)) {
+                    return Ok((Grabbed::Expression(E::Variable(Box::new((x.source_range, v)))),
+                        Flow::Continue));
+                }
                 Ok((Grabbed::Expression(E::Item(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::Block(ref block) => match grab_block(level, rt, block, side, module) {
+        E::Block(ref block) => match grab_block(level, spec, rt, block, side, module) {
             Ok((Grabbed::Block(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::Block(Box::new(x))), Flow::Continue))
             }
@@ -106,11 +393,20 @@ pub fn grab_expr(
         E::Assign(ref assign) => {
             Ok((Grabbed::Expression(E::Assign(Box::new(ast::Assign {
                 op: assign.op,
-                left: match grab_expr(level, rt, &assign.left, side, module) {
-                    Ok((Grabbed::Expression(x), Flow::Continue)) => x,
-                    x => return x,
+                left: if let E::Item(ref item) = assign.left {
+                    // The left side is declared or mutated, so it is never inlined.
+                    fix(spec, &item.name);
+                    match grab_item(level, spec, rt, item, side, module) {
+                        Ok((Grabbed::Item(x), Flow::Continue)) => E::Item(Box::new(x)),
+                        x => return x,
+                    }
+                } else {
+                    match grab_expr(level, spec, rt, &assign.left, side, module) {
+                        Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                        x => return x,
+                    }
                 },
-                right: match grab_expr(level, rt, &assign.right, side, module) {
+                right: match grab_expr(level, spec, rt, &assign.right, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -118,26 +414,27 @@ pub fn grab_expr(
             }))), Flow::Continue))
         },
         E::Compare(ref compare) => {
-            Ok((Grabbed::Expression(E::Compare(Box::new(ast::Compare {
+            let new_expr = E::Compare(Box::new(ast::Compare {
                 op: compare.op,
-                left: match grab_expr(level, rt, &compare.left, side, module) {
+                left: match grab_expr(level, spec, rt, &compare.left, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                right: match grab_expr(level, rt, &compare.right, side, module) {
+                right: match grab_expr(level, spec, rt, &compare.right, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
                 source_range: compare.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::If(ref if_expr) => {
-            Ok((Grabbed::Expression(E::If(Box::new(ast::If {
-                cond: match grab_expr(level, rt, &if_expr.cond, side, module) {
+            let new_if = ast::If {
+                cond: match grab_expr(level, spec, rt, &if_expr.cond, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                true_block: match grab_block(level, rt, &if_expr.true_block, side, module) {
+                true_block: match grab_block(level, spec, rt, &if_expr.true_block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -145,7 +442,7 @@ pub fn grab_expr(
                     let mut new_else_if_conds = vec![];
                     for else_if_cond in &if_expr.else_if_conds {
                         new_else_if_conds.push(
-                            match grab_expr(level, rt, else_if_cond, side, module) {
+                            match grab_expr(level, spec, rt, else_if_cond, side, module) {
                                 Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                                 x => return x,
                             });
@@ -156,7 +453,7 @@ pub fn grab_expr(
                     let mut new_else_if_blocks = vec![];
                     for else_if_block in &if_expr.else_if_blocks {
                         new_else_if_blocks.push(
-                                match grab_block(level, rt, else_if_block, side, module) {
+                                match grab_block(level, spec, rt, else_if_block, side, module) {
                                 Ok((Grabbed::Block(x), Flow::Continue)) => x,
                                 x => return x,
                             });
@@ -166,17 +463,52 @@ pub fn grab_expr(
                 else_block: match if_expr.else_block {
                     None => None,
                     Some(ref else_block) => {
-                        match grab_block(level, rt, &else_block, side, module) {
+                        match grab_block(level, spec, rt, &else_block, side, module) {
                             Ok((Grabbed::Block(x), Flow::Continue)) => Some(x),
                             x => return x,
                         }
                     }
                 },
                 source_range: if_expr.source_range,
+            };
+            Ok((Grabbed::Expression(fold_if(spec, new_if)), Flow::Continue))
+        },
+        E::Match(ref match_expr) => {
+            Ok((Grabbed::Expression(E::Match(Box::new(ast::Match {
+                expr: match grab_expr(level, spec, rt, &match_expr.expr, side, module) {
+                    Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                    x => return x,
+                },
+                arms: {
+                    let mut new_arms = vec![];
+                    for arm in &match_expr.arms {
+                        fix_pattern(spec, &arm.pattern);
+                        new_arms.push(ast::Arm {
+                            pattern: arm.pattern.clone(),
+                            guard: match arm.guard {
+                                None => None,
+                                Some(ref guard) => {
+                                    match grab_expr(level, spec, rt, guard, side, module) {
+                                        Ok((Grabbed::Expression(x), Flow::Continue)) => Some(x),
+                                        x => return x,
+                                    }
+                                }
+                            },
+                            expr: match grab_expr(level, spec, rt, &arm.expr, side, module) {
+                                Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                                x => return x,
+                            },
+                            source_range: arm.source_range,
+                        });
+                    }
+                    new_arms
+                },
+                source_range: match_expr.source_range,
             }))), Flow::Continue))
         },
         E::Go(ref go) => {
             let call = &go.call;
+            fix_mut_args(spec, call);
             Ok((Grabbed::Expression(E::Go(Box::new(ast::Go {
                 call: ast::Call {
                     alias: call.alias.clone(),
@@ -184,7 +516,7 @@ pub fn grab_expr(
                     args: {
                         let mut new_args = vec![];
                         for arg in &call.args {
-                            new_args.push(match grab_expr(level, rt, arg, side, module) {
+                            new_args.push(match grab_expr(level, spec, rt, arg, side, module) {
                                 Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                                 x => return x,
                             });
@@ -199,13 +531,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::Call(ref call) => {
+            fix_mut_args(spec, call);
             Ok((Grabbed::Expression(E::Call(Box::new(ast::Call {
                 alias: call.alias.clone(),
                 name: call.name.clone(),
                 args: {
                     let mut new_args = vec![];
                     for arg in &call.args {
-                        new_args.push(match grab_expr(level, rt, arg, side, module) {
+                        new_args.push(match grab_expr(level, spec, rt, arg, side, module) {
                             Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                             x => return x,
                         });
@@ -218,15 +551,19 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::CallClosure(ref call_closure) => {
+            // Mutability of closure arguments is not known, so items are not inlined.
+            for arg in &call_closure.args {
+                if let E::Item(ref item) = *arg {fix(spec, &item.name)}
+            }
             Ok((Grabbed::Expression(E::CallClosure(Box::new(ast::CallClosure {
-                item: match grab_item(level, rt, &call_closure.item, side, module) {
+                item: match grab_item(level, spec, rt, &call_closure.item, side, module) {
                     Ok((Grabbed::Item(x), Flow::Continue)) => x,
                     x => return x,
                 },
                 args: {
                     let mut new_args = vec![];
                     for arg in &call_closure.args {
-                        new_args.push(match grab_expr(level, rt, arg, side, module) {
+                        new_args.push(match grab_expr(level, spec, rt, arg, side, module) {
                             Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                             x => return x,
                         });
@@ -236,112 +573,116 @@ pub fn grab_expr(
                 source_range: call_closure.source_range,
             }))), Flow::Continue))
         }
-        E::ForN(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::ForN(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::ForN(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::Sum(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::Sum(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::Sum(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::Prod(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::Prod(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::Prod(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::Min(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::Min(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::Min(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::Max(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::Max(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::Max(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::Any(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::Any(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::Any(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::All(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::All(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::All(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::LinkFor(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::LinkFor(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::LinkFor(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::SumVec4(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::SumVec4(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::SumVec4(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::ProdVec4(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::ProdVec4(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::ProdVec4(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
-        E::Sift(ref for_n) => match grab_for_n(level, rt, for_n, side, module) {
+        E::Sift(ref for_n) => match grab_for_n(level, spec, rt, for_n, side, module) {
             Ok((Grabbed::ForN(x), Flow::Continue)) => {
                 Ok((Grabbed::Expression(E::Sift(Box::new(x))), Flow::Continue))
             }
             x => x,
         },
         E::UnOp(ref unop) => {
-            Ok((Grabbed::Expression(E::UnOp(Box::new(ast::UnOpExpression {
+            let new_expr = E::UnOp(Box::new(ast::UnOpExpression {
                 op: unop.op,
-                expr: match grab_expr(level, rt, &unop.expr, side, module) {
+                expr: match grab_expr(level, spec, rt, &unop.expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
                 source_range: unop.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::Norm(ref norm) => {
-            Ok((Grabbed::Expression(E::Norm(Box::new(ast::Norm {
-                expr: match grab_expr(level, rt, &norm.expr, side, module) {
+            let new_expr = E::Norm(Box::new(ast::Norm {
+                expr: match grab_expr(level, spec, rt, &norm.expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
                 source_range: norm.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::Vec4(ref vec4) => {
-            Ok((Grabbed::Expression(E::Vec4(Box::new(ast::Vec4 {
+            let new_expr = E::Vec4(Box::new(ast::Vec4 {
                 args: {
                     let mut new_args = vec![];
                     for arg in &vec4.args {
-                        new_args.push(match grab_expr(level, rt, arg, side, module) {
-                            Ok((Grabbed::Expression(x), Flow::Continue)) => x,
-                            x => return x,
-                        });
+                        push_vec4_arg(spec, &mut new_args,
+                            match grab_expr(level, spec, rt, arg, side, module) {
+                                Ok((Grabbed::Expression(x), Flow::Continue)) => x,
+                                x => return x,
+                            });
                     }
                     new_args
                 },
                 source_range: vec4.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::Mat4(ref mat4) => {
-            Ok((Grabbed::Expression(E::Mat4(Box::new(ast::Mat4 {
+            let new_expr = E::Mat4(Box::new(ast::Mat4 {
                 args: {
                     let mut new_args = vec![];
                     for arg in &mat4.args {
-                        new_args.push(match grab_expr(level, rt, arg, side, module) {
+                        new_args.push(match grab_expr(level, spec, rt, arg, side, module) {
                             Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                             x => return x,
                         });
@@ -349,14 +690,15 @@ pub fn grab_expr(
                     new_args
                 },
                 source_range: mat4.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::Link(ref link) => {
-            Ok((Grabbed::Expression(E::Link(Box::new(ast::Link {
+            let new_expr = E::Link(Box::new(ast::Link {
                 items: {
                     let mut new_items = vec![];
                     for item in &link.items {
-                        new_items.push(match grab_expr(level, rt, item, side, module) {
+                        new_items.push(match grab_expr(level, spec, rt, item, side, module) {
                             Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                             x => return x,
                         });
@@ -364,15 +706,16 @@ pub fn grab_expr(
                     new_items
                 },
                 source_range: link.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::Object(ref obj) => {
-            Ok((Grabbed::Expression(E::Object(Box::new(ast::Object {
+            let new_expr = E::Object(Box::new(ast::Object {
                 key_values: {
                     let mut new_key_values = vec![];
                     for key_value in &obj.key_values {
                         new_key_values.push((key_value.0.clone(),
-                        match grab_expr(level, rt, &key_value.1, side, module) {
+                        match grab_expr(level, spec, rt, &key_value.1, side, module) {
                             Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                             x => return x,
                         }));
@@ -380,14 +723,15 @@ pub fn grab_expr(
                     new_key_values
                 },
                 source_range: obj.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::Array(ref arr) => {
-            Ok((Grabbed::Expression(E::Array(Box::new(ast::Array {
+            let new_expr = E::Array(Box::new(ast::Array {
                 items: {
                     let mut new_items = vec![];
                     for item in &arr.items {
-                        new_items.push(match grab_expr(level, rt, item, side, module) {
+                        new_items.push(match grab_expr(level, spec, rt, item, side, module) {
                             Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                             x => return x,
                         });
@@ -395,36 +739,38 @@ pub fn grab_expr(
                     new_items
                 },
                 source_range: arr.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::ArrayFill(ref arr_fill) => {
-            Ok((Grabbed::Expression(E::ArrayFill(Box::new(ast::ArrayFill {
-                fill: match grab_expr(level, rt, &arr_fill.fill, side, module) {
+            let new_expr = E::ArrayFill(Box::new(ast::ArrayFill {
+                fill: match grab_expr(level, spec, rt, &arr_fill.fill, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                n: match grab_expr(level, rt, &arr_fill.n, side, module) {
+                n: match grab_expr(level, spec, rt, &arr_fill.n, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
                 source_range: arr_fill.source_range,
-            }))), Flow::Continue))
+            }));
+            Ok((Grabbed::Expression(fold(spec, rt, new_expr, module)), Flow::Continue))
         }
         E::For(ref for_expr) => {
             Ok((Grabbed::Expression(E::For(Box::new(ast::For {
-                init: match grab_expr(level, rt, &for_expr.init, side, module) {
+                init: match grab_expr(level, spec, rt, &for_expr.init, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                cond: match grab_expr(level, rt, &for_expr.cond, side, module) {
+                cond: match grab_expr(level, spec, rt, &for_expr.cond, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                step: match grab_expr(level, rt, &for_expr.step, side, module) {
+                step: match grab_expr(level, spec, rt, &for_expr.step, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -433,13 +779,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::ForIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::ForIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -448,13 +795,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::SumIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::SumIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -463,13 +811,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::ProdIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::ProdIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -478,13 +827,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::MinIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::MinIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -493,13 +843,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::MaxIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::MaxIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -508,13 +859,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::AnyIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::AnyIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -523,13 +875,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::AllIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::AllIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -538,13 +891,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::SiftIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::SiftIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -553,13 +907,14 @@ pub fn grab_expr(
             }))), Flow::Continue))
         }
         E::LinkIn(ref for_in_expr) => {
+            fix(spec, &for_in_expr.name);
             Ok((Grabbed::Expression(E::LinkIn(Box::new(ast::ForIn {
                 name: for_in_expr.name.clone(),
-                iter: match grab_expr(level, rt, &for_in_expr.iter, side, module) {
+                iter: match grab_expr(level, spec, rt, &for_in_expr.iter, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
-                block: match grab_block(level, rt, &for_in_expr.block, side, module) {
+                block: match grab_block(level, spec, rt, &for_in_expr.block, side, module) {
                     Ok((Grabbed::Block(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -573,7 +928,7 @@ pub fn grab_expr(
                 sw1: swizzle.sw1,
                 sw2: swizzle.sw2,
                 sw3: swizzle.sw3,
-                expr: match grab_expr(level, rt, &swizzle.expr, side, module) {
+                expr: match grab_expr(level, spec, rt, &swizzle.expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -582,7 +937,7 @@ pub fn grab_expr(
         }
         E::TryExpr(ref try_expr) => {
             Ok((Grabbed::Expression(E::TryExpr(Box::new(ast::TryExpr {
-                expr: match grab_expr(level, rt, &try_expr.expr, side, module) {
+                expr: match grab_expr(level, spec, rt, &try_expr.expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 },
@@ -595,8 +950,9 @@ pub fn grab_expr(
     }
 }
 
-fn grab_block(
+pub fn grab_block(
     level: u16,
+    spec: &mut Option<Specialize>,
     rt: &mut Runtime,
     block: &ast::Block,
     side: Side,
@@ -606,7 +962,7 @@ fn grab_block(
         expressions: {
             let mut new_expressions = vec![];
             for expr in &block.expressions {
-                new_expressions.push(match grab_expr(level, rt, expr, side, module) {
+                new_expressions.push(match grab_expr(level, spec, rt, expr, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => x,
                     x => return x,
                 });
@@ -619,6 +975,7 @@ fn grab_block(
 
 fn grab_item(
     level: u16,
+    spec: &mut Option<Specialize>,
     rt: &mut Runtime,
     item: &ast::Item,
     side: Side,
@@ -637,7 +994,7 @@ fn grab_item(
                     ast::Id::String(_, _) => id.clone(),
                     ast::Id::F64(_, _) => id.clone(),
                     ast::Id::Expression(ref expr) =>
-                        match grab_expr(level, rt, &expr, side, module) {
+                        match grab_expr(level, spec, rt, &expr, side, module) {
                             Ok((Grabbed::Expression(x), Flow::Continue)) =>
                                 ast::Id::Expression(x),
                             x => return x,
@@ -653,27 +1010,29 @@ fn grab_item(
 
 fn grab_for_n(
     level: u16,
+    spec: &mut Option<Specialize>,
     rt: &mut Runtime,
     for_n: &ast::ForN,
     side: Side,
     module: &Arc<Module>,
 ) -> Result<(Grabbed, Flow), String> {
+    fix(spec, &for_n.name);
     Ok((Grabbed::ForN(ast::ForN {
         name: for_n.name.clone(),
         start: match for_n.start {
             None => None,
             Some(ref start) => {
-                match grab_expr(level, rt, start, side, module) {
+                match grab_expr(level, spec, rt, start, side, module) {
                     Ok((Grabbed::Expression(x), Flow::Continue)) => Some(x),
                     x => return x,
                 }
             }
         },
-        end: match grab_expr(level, rt, &for_n.end, side, module) {
+        end: match grab_expr(level, spec, rt, &for_n.end, side, module) {
             Ok((Grabbed::Expression(x), Flow::Continue)) => x,
             x => return x,
         },
-        block: match grab_block(level, rt, &for_n.block, side, module) {
+        block: match grab_block(level, spec, rt, &for_n.block, side, module) {
             Ok((Grabbed::Block(x), Flow::Continue)) => x,
             x => return x,
         },
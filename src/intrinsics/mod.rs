@@ -51,6 +51,9 @@ const INSERT_REF: usize = 38;
 const REMOVE: usize = 39;
 const NEXT: usize = 40;
 const WAIT_NEXT: usize = 41;
+const EXPLAIN_ALL: usize = 42;
+const SPECIALIZE: usize = 43;
+const SHUFFLE: usize = 44;
 
 const TABLE: &[(usize, fn(
         &mut Runtime,
@@ -100,6 +103,9 @@ const TABLE: &[(usize, fn(
     (REMOVE, remove),
     (NEXT, next),
     (WAIT_NEXT, wait_next),
+    (EXPLAIN_ALL, explain_all),
+    (SPECIALIZE, specialize),
+    (SHUFFLE, shuffle),
 ];
 
 pub(crate) fn standard(f: &mut Prelude) {
@@ -137,21 +143,28 @@ pub(crate) fn standard(f: &mut Prelude) {
     sarg(f, "len", LEN, Type::array(), Type::F64);
     f.intrinsic(Arc::new("push_ref(mut,_)".into()), PUSH_REF, Dfn {
         lts: vec![Lt::Default, Lt::Arg(0)],
-        tys: vec![Type::array(), Type::Any],
+        tys: vec![
+            Type::Array(Box::new(Type::Var(Arc::new("T".into())))),
+            Type::Var(Arc::new("T".into()))
+        ],
         ret: Type::Void
     });
     f.intrinsic(Arc::new("push(mut,_)".into()), PUSH, Dfn {
         lts: vec![Lt::Default; 2],
-        tys: vec![Type::array(), Type::Any],
+        tys: vec![
+            Type::Array(Box::new(Type::Var(Arc::new("T".into())))),
+            Type::Var(Arc::new("T".into()))
+        ],
         ret: Type::Void
     });
     f.intrinsic(Arc::new("pop(mut)".into()), POP, Dfn {
         lts: vec![Lt::Return],
-        tys: vec![Type::array()],
-        ret: Type::Any
+        tys: vec![Type::Array(Box::new(Type::Var(Arc::new("T".into()))))],
+        ret: Type::Var(Arc::new("T".into()))
     });
     sarg(f, "reverse(mut)", REVERSE, Type::array(), Type::Void);
     sarg(f, "clear(mut)", CLEAR, Type::array(), Type::Void);
+    sarg(f, "shuffle(mut)", SHUFFLE, Type::array(), Type::Void);
     f.intrinsic(Arc::new("swap(mut,_,_)".into()), SWAP, Dfn {
         lts: vec![Lt::Default; 3],
         tys: vec![Type::array(), Type::F64, Type::F64],
@@ -224,18 +237,26 @@ pub(crate) fn standard(f: &mut Prelude) {
     });
     f.intrinsic(Arc::new("insert(mut,_,_)".into()), INSERT, Dfn {
         lts: vec![Lt::Default; 3],
-        tys: vec![Type::array(), Type::F64, Type::Any],
+        tys: vec![
+            Type::Array(Box::new(Type::Var(Arc::new("T".into())))),
+            Type::F64,
+            Type::Var(Arc::new("T".into()))
+        ],
         ret: Type::Void
     });
     f.intrinsic(Arc::new("insert_ref(mut,_,_)".into()), INSERT_REF, Dfn {
         lts: vec![Lt::Default, Lt::Default, Lt::Arg(0)],
-        tys: vec![Type::array(), Type::F64, Type::Any],
+        tys: vec![
+            Type::Array(Box::new(Type::Var(Arc::new("T".into())))),
+            Type::F64,
+            Type::Var(Arc::new("T".into()))
+        ],
         ret: Type::Void
     });
     f.intrinsic(Arc::new("remove(mut,_)".into()), REMOVE, Dfn {
         lts: vec![Lt::Return, Lt::Default],
-        tys: vec![Type::array(), Type::F64],
-        ret: Type::Any
+        tys: vec![Type::Array(Box::new(Type::Var(Arc::new("T".into())))), Type::F64],
+        ret: Type::Var(Arc::new("T".into()))
     });
     f.intrinsic(Arc::new("next".into()), NEXT, Dfn {
         lts: vec![Lt::Default],
@@ -247,6 +268,12 @@ pub(crate) fn standard(f: &mut Prelude) {
         tys: vec![Type::in_ty()],
         ret: Type::Any
     });
+    sarg(f, "explain_all", EXPLAIN_ALL, Type::Any, Type::Array(Box::new(Type::Object)));
+    f.intrinsic(Arc::new("specialize".into()), SPECIALIZE, Dfn {
+        lts: vec![Lt::Default; 2],
+        tys: vec![Type::Any, Type::Object],
+        ret: Type::Any
+    });
 }
 
 pub(crate) fn call_standard(
@@ -11,6 +11,7 @@
 #![deny(missing_docs)]
 extern crate piston_meta;
 extern crate rand;
+extern crate rand_hc;
 extern crate range;
 extern crate read_color;
 extern crate read_token;
@@ -34,6 +35,8 @@ mod lifetime;
 mod intrinsics;
 mod prelude;
 pub mod embed;
+pub mod schema;
+pub mod binary;
 mod ty;
 mod link;
 pub mod macros;
@@ -42,6 +45,7 @@ mod mat4;
 mod write;
 
 mod grab;
+mod optimize;
 mod dyon_std;
 
 pub use runtime::Runtime;
@@ -51,6 +55,11 @@ pub use link::Link;
 pub use vec4::Vec4;
 pub use mat4::Mat4;
 
+/// Loading and streaming of data in the Dyon data format.
+pub mod data {
+    pub use dyon_std::data::{load_data, load_file, stream_file, Items};
+}
+
 /// A common error message when there is no value on the stack.
 pub const TINVOTS: &str = "There is no value on the stack";
 
@@ -71,6 +80,72 @@ pub struct Error {
     pub trace: Vec<String>,
 }
 
+/// Stores a non-fatal warning reported when loading a source.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    /// The range in source.
+    pub range: Range,
+    /// The warning message.
+    pub message: String,
+}
+
+/// Describes the signature of a loaded function.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    /// The namespace of the function.
+    pub namespace: Arc<Vec<Arc<String>>>,
+    /// The name of the function.
+    pub name: Arc<String>,
+    /// The file which the function was loaded from.
+    pub file: Arc<String>,
+    /// Function arguments, with lifetimes and types.
+    pub args: Vec<ast::Arg>,
+    /// Current object references.
+    pub currents: Vec<ast::Current>,
+    /// The return type, refined by the type checker.
+    pub ret: Type,
+    /// The range in source.
+    pub source_range: Range,
+}
+
+impl Signature {
+    fn new(f: &ast::Function) -> Signature {
+        Signature {
+            namespace: f.namespace.clone(),
+            name: f.name.clone(),
+            file: f.file.clone(),
+            args: f.args.clone(),
+            currents: f.currents.clone(),
+            ret: f.ret.clone(),
+            source_range: f.source_range,
+        }
+    }
+}
+
+/// Stores the inferred type of an expression, local variable or argument.
+#[derive(Debug, Clone)]
+pub struct TypeInfo {
+    /// The file which the source was loaded from.
+    pub file: Arc<String>,
+    /// The range in source.
+    pub range: Range,
+    /// The name of the local variable or argument declared by the range.
+    pub local: Option<Arc<String>>,
+    /// The inferred type.
+    pub ty: Type,
+}
+
+/// Decides how warnings are handled when loading a source with `load` or `load_str`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Warnings {
+    /// Warnings are ignored.
+    Ignore,
+    /// Warnings are printed to standard error.
+    Print,
+    /// Warnings are reported as an error.
+    Error,
+}
+
 /// Stores a thread handle.
 #[derive(Clone)]
 pub struct Thread {
@@ -135,6 +210,18 @@ pub struct ClosureEnvironment {
     pub module: Arc<Module>,
     /// Relative index, used to resolve function indices.
     pub relative: usize,
+    /// Known arguments of specialized closure with their slots and names, sorted by slot.
+    ///
+    /// These are removed from the arguments of the closure,
+    /// and inserted into their slots on the stack when calling the closure.
+    pub(crate) known: Vec<(usize, Arc<String>, Variable)>,
+}
+
+impl ClosureEnvironment {
+    /// Creates a new closure environment without known arguments.
+    pub fn new(module: Arc<Module>, relative: usize) -> ClosureEnvironment {
+        ClosureEnvironment {module, relative, known: vec![]}
+    }
 }
 
 impl fmt::Debug for ClosureEnvironment {
@@ -151,11 +238,17 @@ pub enum Variable {
     /// Return handle.
     Return,
     /// Boolean.
+    ///
+    /// The secret stores the derived information, from the innermost loop and outwards.
     Bool(bool, Option<Box<Vec<Variable>>>),
     /// F64.
+    ///
+    /// The secret stores the derived information, from the innermost loop and outwards.
     F64(f64, Option<Box<Vec<Variable>>>),
     /// 4D vector.
     Vec4([f32; 4]),
+    /// Vector of `f64` with any length.
+    VecN(Arc<Vec<f64>>),
     /// 4D matrix.
     Mat4(Box<[[f32; 4]; 4]>),
     /// Text.
@@ -209,6 +302,7 @@ impl Variable {
             Text(_) => TEXT_TYPE.clone(),
             F64(_, _) => F64_TYPE.clone(),
             Vec4(_) => VEC4_TYPE.clone(),
+            VecN(_) => VECN_TYPE.clone(),
             Mat4(_) => MAT4_TYPE.clone(),
             Return => RETURN_TYPE.clone(),
             Bool(_, _) => BOOL_TYPE.clone(),
@@ -232,6 +326,7 @@ impl Variable {
         match *self {
             F64(_, _) => self.clone(),
             Vec4(_) => self.clone(),
+            VecN(_) => self.clone(),
             Mat4(_) => self.clone(),
             Return => self.clone(),
             Bool(_, _) => self.clone(),
@@ -277,6 +372,7 @@ impl PartialEq for Variable {
             (&Variable::Bool(a, _), &Variable::Bool(b, _)) => a == b,
             (&Variable::F64(a, _), &Variable::F64(b, _)) => a == b,
             (&Variable::Text(ref a), &Variable::Text(ref b)) => a == b,
+            (&Variable::VecN(ref a), &Variable::VecN(ref b)) => a == b,
             (&Variable::Object(ref a), &Variable::Object(ref b)) => a == b,
             (&Variable::Array(ref a), &Variable::Array(ref b)) => a == b,
             (&Variable::Ref(_), _) => false,
@@ -343,6 +439,11 @@ pub struct Module {
     ext_prelude: Vec<FnExternal>,
     intrinsics: Arc<HashMap<Arc<String>, usize>>,
     register_namespace: Arc<Vec<Arc<String>>>,
+    strict: bool,
+    optimize: bool,
+    warnings: Warnings,
+    store_type_info: bool,
+    type_info: Vec<TypeInfo>,
 }
 
 impl Default for Module {
@@ -402,6 +503,13 @@ impl Module {
         m.add_str("ceil", ceil, Dfn::nl(vec![F64], F64));
         m.add_str("sleep", sleep, Dfn::nl(vec![F64], Void));
         m.add_str("random", random, Dfn::nl(vec![], F64));
+        m.add_str("random__range", random__range, Dfn::nl(vec![F64; 2], F64));
+        m.add_str("random__normal", random__normal, Dfn::nl(vec![F64; 2], F64));
+        m.add_str("seed", seed, Dfn::nl(vec![F64], Void));
+        m.add_str("choose", choose, Dfn::nl(
+            vec![Array(Box::new(Var(Arc::new("T".into()))))],
+            Option(Box::new(Var(Arc::new("T".into()))))
+        ));
         m.add_str("tau", tau, Dfn::nl(vec![], F64));
         m.add_str("read_line", read_line, Dfn::nl(vec![], Text));
         m.add_str("read_number", read_number, Dfn::nl(vec![Text], F64));
@@ -422,6 +530,38 @@ impl Module {
         m.add_str("ok", ok, Dfn::nl(vec![Any], Type::result()));
         m.add_str("err", err, Dfn::nl(vec![Any], Type::result()));
         m.add_str("dir__angle", dir__angle, Dfn::nl(vec![F64], Vec4));
+        m.add_str("dot", dot, Dfn::nl(vec![Vec4; 2], F64));
+        m.add_str("normalize", normalize, Dfn::nl(vec![Vec4], Vec4));
+        m.add_str("reflect__dir_normal", reflect__dir_normal, Dfn::nl(vec![Vec4; 2], Vec4));
+        m.add_str("rotate__vec_angle", rotate__vec_angle, Dfn::nl(vec![Vec4, F64], Vec4));
+        m.add_str("vecn", vecn, Dfn::nl(vec![Any], VecN));
+        m.add_str("components", components, Dfn::nl(vec![VecN], Array(Box::new(F64))));
+        m.add_str("dim", dim, Dfn::nl(vec![VecN], F64));
+        m.add_str("sum", sum, Dfn::nl(vec![VecN], F64));
+        m.add_str("s_vecn", s_vecn, Dfn::nl(vec![VecN, F64], F64));
+        m.add_str("min_vecn", min_vecn, Dfn::nl(vec![VecN], F64));
+        m.add_str("max_vecn", max_vecn, Dfn::nl(vec![VecN], F64));
+        m.add_str("dot_vecn", dot_vecn, Dfn::nl(vec![VecN; 2], F64));
+        m.add_str("lerp__from_to_t", lerp__from_to_t, Dfn::nl(
+            vec![Var(Arc::new("T".into())), Var(Arc::new("T".into())), F64],
+            Var(Arc::new("T".into()))
+        ));
+        m.add_str("clamp__value_min_max", clamp__value_min_max, Dfn::nl(
+            vec![Var(Arc::new("T".into())); 3],
+            Var(Arc::new("T".into()))
+        ));
+        m.add_str("quat__axis_angle", quat__axis_angle, Dfn::nl(vec![Vec4, F64], Vec4));
+        m.add_str("quat_mul", quat_mul, Dfn::nl(vec![Vec4; 2], Vec4));
+        m.add_str("quat_inv", quat_inv, Dfn::nl(vec![Vec4], Vec4));
+        m.add_str("rotate__quat_vec", rotate__quat_vec, Dfn::nl(vec![Vec4; 2], Vec4));
+        m.add_str("slerp__from_to_t", slerp__from_to_t, Dfn::nl(vec![Vec4, Vec4, F64], Vec4));
+        m.add_str("quat_to_mat4", quat_to_mat4, Dfn::nl(vec![Vec4], Mat4));
+        m.add_str("ray_aabb__pos_dir_min_max", ray_aabb__pos_dir_min_max,
+                  Dfn::nl(vec![Vec4; 4], Option(Box::new(F64))));
+        m.add_str("ray_sphere__pos_dir_center_radius", ray_sphere__pos_dir_center_radius,
+                  Dfn::nl(vec![Vec4, Vec4, Vec4, F64], Option(Box::new(F64))));
+        m.add_str("segment_segment__a_b_c_d", segment_segment__a_b_c_d,
+                  Dfn::nl(vec![Vec4; 4], Option(Box::new(Vec4))));
         m.add_str("load__meta_file", load__meta_file, Dfn::nl(vec![Type::Text; 2],
             Type::Result(Box::new(Type::Array(Box::new(Type::array()))))
         ));
@@ -430,10 +570,20 @@ impl Module {
         ));
         m.add_str("syntax__in_string", syntax__in_string,
                   Dfn::nl(vec![Type::Text; 2], Type::Result(Box::new(Type::Any))));
+        m.add_str("meta_tree__data", meta_tree__data,
+                  Dfn::nl(vec![Type::Array(Box::new(Type::array()))],
+                          Type::Result(Box::new(Type::Array(Box::new(Type::Object))))));
+        m.add_str("print_meta__syntax_tree", print_meta__syntax_tree,
+                  Dfn::nl(vec![Type::Any, Type::Array(Box::new(Type::Object))],
+                          Type::Result(Box::new(Type::Text))));
         m.add_str("download__url_file", download__url_file,
                   Dfn::nl(vec![Type::Text; 2], Type::Result(Box::new(Type::Text))));
         m.add_str("save__string_file", save__string_file,
                   Dfn::nl(vec![Type::Text; 2], Type::Result(Box::new(Type::Text))));
+        m.add_str("write__link_file", write__link_file,
+                  Dfn::nl(vec![Type::Link, Type::Text], Type::Result(Box::new(Type::Text))));
+        m.add_str("stream__file", stream__file, Dfn::nl(vec![Type::Text], Type::Link));
+        m.add_str("flush(mut)", flush, Dfn::nl(vec![Type::Link], Type::Void));
         m.add_str("load_string__file", load_string__file,
                   Dfn::nl(vec![Text], Type::Result(Box::new(Type::Text))));
         m.add_str("load_string__url", load_string__url,
@@ -444,6 +594,34 @@ impl Module {
                   Dfn::nl(vec![Text], Type::Result(Box::new(Type::Any))));
         m.add_str("load_data__string", load_data__string,
                   Dfn::nl(vec![Text], Type::Result(Box::new(Type::Any))));
+        m.add_str("load_data__file_schema", load_data__file_schema,
+                  Dfn::nl(vec![Text, Any], Type::Result(Box::new(Type::Any))));
+        m.add_str("load_data__string_schema", load_data__string_schema,
+                  Dfn::nl(vec![Text, Any], Type::Result(Box::new(Type::Any))));
+        m.add_str("validate__data_schema", validate__data_schema,
+                  Dfn::nl(vec![Any, Any], Type::Result(Box::new(Type::Any))));
+        m.add_str("stream_data__file", stream_data__file,
+                  Dfn::nl(vec![Text], Type::In(Box::new(Type::Result(Box::new(Type::Any))))));
+        m.add_str("append__data_file", append__data_file,
+                  Dfn::nl(vec![Any, Text], Type::Result(Box::new(Type::Text))));
+        m.add_str("load_binary__file", load_binary__file,
+                  Dfn::nl(vec![Text], Type::Result(Box::new(Type::Any))));
+        m.add_str("save_binary__data_file", save_binary__data_file,
+                  Dfn::nl(vec![Any, Text], Type::Result(Box::new(Type::Text))));
+        m.add_str("load_json__file", load_json__file,
+                  Dfn::nl(vec![Text], Type::Result(Box::new(Type::Any))));
+        m.add_str("load_json__string", load_json__string,
+                  Dfn::nl(vec![Text], Type::Result(Box::new(Type::Any))));
+        m.add_str("save_json__data_file", save_json__data_file,
+                  Dfn::nl(vec![Any, Text], Type::Result(Box::new(Type::Text))));
+        m.add_str("load_csv__file", load_csv__file,
+                  Dfn::nl(vec![Text], Type::Result(Box::new(Type::array()))));
+        m.add_str("load_csv__file_header", load_csv__file_header,
+                  Dfn::nl(vec![Text, Bool], Type::Result(Box::new(Type::array()))));
+        m.add_str("load_toml__file", load_toml__file,
+                  Dfn::nl(vec![Text], Type::Result(Box::new(Type::object()))));
+        m.add_str("load_toml__string", load_toml__string,
+                  Dfn::nl(vec![Text], Type::Result(Box::new(Type::object()))));
         m.add_str("args_os", args_os, Dfn::nl(vec![], Type::Array(Box::new(Type::Text))));
         m.add_str("now", now, Dfn::nl(vec![], F64));
         m.add_str("is_nan", is_nan, Dfn::nl(vec![F64], Bool));
@@ -457,6 +635,11 @@ impl Module {
             ext_prelude: vec![],
             intrinsics,
             register_namespace: Arc::new(vec![]),
+            strict: false,
+            optimize: false,
+            warnings: Warnings::Ignore,
+            store_type_info: false,
+            type_info: vec![],
         }
     }
 
@@ -473,6 +656,88 @@ impl Module {
         self.register_namespace = Arc::new(vec![]);
     }
 
+    /// Sets whether following loaded sources are type checked in strict mode.
+    ///
+    /// Strict mode requires type annotations on arguments and return values,
+    /// and warns about values inferred as `any` where some other type is expected.
+    /// Use `Module::set_warnings` to print these warnings or treat them as errors.
+    /// A single source can enable strict mode with the `#strict` pragma.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Returns `true` if following loaded sources are type checked in strict mode.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Sets whether following loaded sources are optimized.
+    ///
+    /// The optimizer folds constant expressions and removes `if` branches
+    /// with constant conditions. This is disabled by default.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Returns `true` if following loaded sources are optimized.
+    pub fn is_optimize(&self) -> bool {
+        self.optimize
+    }
+
+    /// Sets how warnings are handled for following loaded sources.
+    pub fn set_warnings(&mut self, warnings: Warnings) {
+        self.warnings = warnings;
+    }
+
+    /// Returns how warnings are handled for following loaded sources.
+    pub fn warnings(&self) -> Warnings {
+        self.warnings
+    }
+
+    /// Sets whether the inferred types of following loaded sources are stored.
+    ///
+    /// The types are returned by `type_info` and `type_at`.
+    /// This is disabled by default.
+    pub fn set_type_info(&mut self, store_type_info: bool) {
+        self.store_type_info = store_type_info;
+    }
+
+    /// Returns `true` if the inferred types of following loaded sources are stored.
+    pub fn is_type_info(&self) -> bool {
+        self.store_type_info
+    }
+
+    /// Returns the signatures of loaded functions, in the order they were loaded.
+    pub fn signatures<'a>(&'a self) -> impl Iterator<Item = Signature> + 'a {
+        self.functions.iter().map(Signature::new)
+    }
+
+    /// Returns the signature of a loaded function.
+    pub fn signature(&self, name: &str) -> Option<Signature> {
+        self.functions.iter().rev().find(|f| &**f.name == name).map(Signature::new)
+    }
+
+    /// Returns the inferred types of expressions, local variables and arguments
+    /// in loaded sources, when enabled with `set_type_info`.
+    pub fn type_info(&self) -> &[TypeInfo] {
+        &self.type_info
+    }
+
+    /// Returns the inferred type of the innermost expression at an offset in a file.
+    pub fn type_at(&self, file: &str, offset: usize) -> Option<&TypeInfo> {
+        let mut found: Option<&TypeInfo> = None;
+        for info in &self.type_info {
+            if &**info.file != file { continue; }
+            if offset < info.range.offset ||
+               offset >= info.range.offset + info.range.length { continue; }
+            // Nested expressions come after their parents.
+            if found.map(|f| info.range.length <= f.range.length) != Some(false) {
+                found = Some(info);
+            }
+        }
+        found
+    }
+
     fn register(&mut self, function: ast::Function) {
         self.functions.push(function);
     }
@@ -628,6 +893,15 @@ impl Call {
 
 /// Loads source from file.
 pub fn load(source: &str, module: &mut Module) -> Result<(), String> {
+    load_str(source, read_source(source)?, module)
+}
+
+/// Loads source from file and returns non-fatal warnings.
+pub fn load_warnings(source: &str, module: &mut Module) -> Result<Vec<Warning>, String> {
+    load_str_warnings(source, read_source(source)?, module)
+}
+
+fn read_source(source: &str) -> Result<Arc<String>, String> {
     use std::fs::File;
     use std::io::Read;
 
@@ -635,7 +909,7 @@ pub fn load(source: &str, module: &mut Module) -> Result<(), String> {
         format!("Could not open `{}`, {}", source, err))?;
     let mut data = Arc::new(String::new());
     data_file.read_to_string(Arc::make_mut(&mut data)).unwrap();
-    load_str(source, data, module)
+    Ok(data)
 }
 
 /// Loads a source from string.
@@ -643,7 +917,43 @@ pub fn load(source: &str, module: &mut Module) -> Result<(), String> {
 /// - source - The name of source file
 /// - d - The data of source file
 /// - module - The module to load the source
+///
+/// Warnings are handled as set by `Module::set_warnings`.
 pub fn load_str(source: &str, d: Arc<String>, module: &mut Module) -> Result<(), String> {
+    let warnings = load_str_warnings(source, d.clone(), module)?;
+    if warnings.is_empty() { return Ok(()); }
+    match module.warnings {
+        Warnings::Ignore => {}
+        Warnings::Print => eprintln!("{}", format_warnings(source, &d, &warnings)),
+        Warnings::Error => return Err(format_warnings(source, &d, &warnings)),
+    }
+    Ok(())
+}
+
+/// Formats warnings with the lines in source they refer to.
+pub fn format_warnings(source: &str, d: &str, warnings: &[Warning]) -> String {
+    use std::io::Write;
+    use piston_meta::ParseErrorHandler;
+
+    let mut buf: Vec<u8> = vec![];
+    writeln!(&mut buf, "In `{}`:\n", source).unwrap();
+    for warning in warnings {
+        ParseErrorHandler::new(d)
+            .write_msg(&mut buf, warning.range, &format!("Warning: {}", warning.message))
+            .unwrap();
+    }
+    String::from_utf8(buf).unwrap()
+}
+
+/// Loads a source from string and returns non-fatal warnings.
+///
+/// Unlike `load_str`, the warnings are returned to the caller
+/// instead of being handled as set by `Module::set_warnings`.
+pub fn load_str_warnings(
+    source: &str,
+    d: Arc<String>,
+    module: &mut Module
+) -> Result<Vec<Warning>, String> {
     use std::thread;
     use piston_meta::{parse_errstr, syntax_errstr, Syntax};
 
@@ -663,11 +973,17 @@ pub fn load_str(source: &str, d: Arc<String>, module: &mut Module) -> Result<(),
 
     let check_data = data.clone();
     let prelude = Arc::new(Prelude::from_module(module));
+    let strict = module.strict;
+    let store_type_info = module.store_type_info;
 
     // Do lifetime checking in parallel directly on meta data.
     let handle = thread::spawn(move || {
         let check_data = check_data;
-        lifetime::check(&check_data, &prelude)
+        let mut warnings = vec![];
+        let mut types = vec![];
+        lifetime::check(&check_data, &prelude, strict, &mut warnings,
+                        if store_type_info {Some(&mut types)} else {None})
+            .map(|refined_rets| (refined_rets, warnings, types))
     });
 
     // Convert to AST.
@@ -675,14 +991,15 @@ pub fn load_str(source: &str, d: Arc<String>, module: &mut Module) -> Result<(),
     let conv_res = ast::convert(Arc::new(source.into()), d.clone(), &data, &mut ignored, module);
 
     // Check that lifetime checking succeeded.
-    match handle.join().unwrap() {
-        Ok(refined_rets) => {
+    let (warnings, types) = match handle.join().unwrap() {
+        Ok((refined_rets, warnings, types)) => {
             for (name, ty) in &refined_rets {
                 if let FnIndex::Loaded(f_index) = module.find_function(name, 0) {
                     let f = &mut module.functions[f_index as usize];
                     f.ret = ty.clone();
                 }
             }
+            (warnings, types)
         }
         Err(err_msg) => {
             use std::io::Write;
@@ -697,9 +1014,19 @@ pub fn load_str(source: &str, d: Arc<String>, module: &mut Module) -> Result<(),
                 .unwrap();
             return Err(String::from_utf8(buf).unwrap())
         }
-    }
-
-    check_ignored_meta_data(conv_res, source, &d, &data, &ignored)
+    };
+
+    check_ignored_meta_data(conv_res, source, &d, &data, &ignored)?;
+    // Replace the types of a reloaded source.
+    module.type_info.retain(|info| &**info.file != source);
+    let file: Arc<String> = Arc::new(source.into());
+    module.type_info.extend(types.into_iter().map(|(range, local, ty)| TypeInfo {
+        file: file.clone(), range, local, ty
+    }));
+    Ok(warnings.into_iter().map(|w| {
+        let (range, message) = w.decouple();
+        Warning {range, message}
+    }).collect())
 }
 
 /// Loads a source from meta data.
@@ -819,6 +1146,17 @@ mod tests {
         run(source).unwrap_or_else(|err| panic!("{}", err));
     }
 
+    fn run_bench_optimize(source: &str) {
+        use std::sync::Arc;
+        use super::{load, runtime, Module};
+
+        let mut module = Module::new();
+        module.set_optimize(true);
+        load(source, &mut module).unwrap_or_else(|err| panic!("{}", err));
+        let mut runtime = runtime::Runtime::new();
+        runtime.run(&Arc::new(module)).unwrap_or_else(|err| panic!("{}", err));
+    }
+
     #[bench]
     fn bench_add(b: &mut Bencher) {
         b.iter(|| run_bench("source/bench/add.dyon"));
@@ -914,6 +1252,16 @@ mod tests {
         b.iter(|| run_bench("source/bench/push_link_go.dyon"));
     }
 
+    #[bench]
+    fn bench_push_link_vec4(b: &mut Bencher) {
+        b.iter(|| run_bench("source/bench/push_link_vec4.dyon"));
+    }
+
+    #[bench]
+    fn bench_push_link_var(b: &mut Bencher) {
+        b.iter(|| run_bench("source/bench/push_link_var.dyon"));
+    }
+
     #[bench]
     fn bench_push_str(b: &mut Bencher) {
         b.iter(|| run_bench("source/bench/push_str.dyon"));
@@ -923,4 +1271,19 @@ mod tests {
     fn bench_push_in(b: &mut Bencher) {
         b.iter(|| run_bench("source/bench/push_in.dyon"));
     }
+
+    #[bench]
+    fn bench_const_fold(b: &mut Bencher) {
+        b.iter(|| run_bench("source/bench/const_fold.dyon"));
+    }
+
+    #[bench]
+    fn bench_const_fold_optimize(b: &mut Bencher) {
+        b.iter(|| run_bench_optimize("source/bench/const_fold.dyon"));
+    }
+
+    #[bench]
+    fn bench_n_body_optimize(b: &mut Bencher) {
+        b.iter(|| run_bench_optimize("source/bench/n_body.dyon"));
+    }
 }
@@ -0,0 +1,331 @@
+use std::fmt;
+use std::sync::Arc;
+use std::collections::HashMap;
+use range::Range;
+use super::piston_meta::MetaData;
+use super::piston_meta::bootstrap::Convert;
+use super::node::Node;
+use super::kind::Kind;
+use ast::Pattern;
+use Variable;
+
+/// The variants of an enum, with name and number of fields.
+type Family = Vec<(Arc<String>, usize)>;
+
+/// A constructor that a pattern can match against.
+#[derive(Clone, PartialEq)]
+enum Ctor {
+    /// Variant with enum name and variant name.
+    Variant(Arc<String>, Arc<String>),
+    Bool(bool),
+    Text(Arc<String>),
+    F64(f64),
+    Array(usize),
+    Vec4,
+}
+
+/// A simplified pattern used to check matches.
+#[derive(Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+impl fmt::Display for Pat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_list(f: &mut fmt::Formatter, args: &[Pat]) -> fmt::Result {
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 { write!(f, ", ")?; }
+                write!(f, "{}", arg)?;
+            }
+            Ok(())
+        }
+
+        match *self {
+            Pat::Wild => write!(f, "_"),
+            Pat::Ctor(Ctor::Variant(_, ref name), ref args) => {
+                write!(f, "{}(", name)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            Pat::Ctor(Ctor::Bool(val), _) => write!(f, "{}", val),
+            Pat::Ctor(Ctor::Text(ref val), _) => write!(f, "{:?}", val),
+            Pat::Ctor(Ctor::F64(val), _) => write!(f, "{}", val),
+            Pat::Ctor(Ctor::Array(_), ref args) => {
+                write!(f, "[")?;
+                write_list(f, args)?;
+                write!(f, "]")
+            }
+            Pat::Ctor(Ctor::Vec4, ref args) => {
+                write!(f, "(")?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+struct Checker {
+    /// The variants of each enum, by enum name.
+    families: HashMap<Arc<String>, Family>,
+    /// The enum of each variant, by variant name.
+    enums: HashMap<Arc<String>, Arc<String>>,
+}
+
+impl Checker {
+    fn new(nodes: &[Node]) -> Checker {
+        let mut checker = Checker { families: HashMap::new(), enums: HashMap::new() };
+        let builtin = |names: &[(&str, usize)]| -> Family {
+            names.iter().map(|&(name, n)| (Arc::new(name.into()), n)).collect()
+        };
+        checker.add(Arc::new("opt[any]".into()), builtin(&[("some", 1), ("none", 0)]));
+        checker.add(Arc::new("res[any]".into()), builtin(&[("ok", 1), ("err", 1)]));
+        for node in nodes {
+            if node.kind != Kind::Enum { continue; }
+            let enum_name = node.name().expect("Expected name").clone();
+            checker.add(enum_name, node.children.iter()
+                .filter(|&&ch| nodes[ch].kind == Kind::Variant)
+                .filter_map(|&ch| nodes[ch].name().map(|name| (name.clone(),
+                    nodes[ch].children.iter()
+                        .filter(|&&arg| nodes[arg].kind == Kind::Arg).count())))
+                .collect());
+        }
+        checker
+    }
+
+    fn add(&mut self, enum_name: Arc<String>, family: Family) {
+        for &(ref name, _) in &family {
+            self.enums.insert(name.clone(), enum_name.clone());
+        }
+        self.families.insert(enum_name, family);
+    }
+
+    fn family(&self, enum_name: &Arc<String>) -> &Family {
+        &self.families[enum_name]
+    }
+
+    /// Converts pattern from AST.
+    fn pat(&self, pattern: &Pattern) -> Result<Pat, Range<String>> {
+        Ok(match *pattern {
+            Pattern::Wildcard(_) | Pattern::Bind(_, _) => Pat::Wild,
+            Pattern::Value(ref val, range) => Pat::Ctor(match *val {
+                Variable::Bool(val, _) => Ctor::Bool(val),
+                Variable::Text(ref val) => Ctor::Text(val.clone()),
+                Variable::F64(val, _) => Ctor::F64(val),
+                _ => return Err(range.wrap("Unsupported pattern".into()))
+            }, vec![]),
+            Pattern::Array(ref items, _) => {
+                let items = items.iter().map(|p| self.pat(p))
+                    .collect::<Result<Vec<Pat>, _>>()?;
+                Pat::Ctor(Ctor::Array(items.len()), items)
+            }
+            Pattern::Vec4(ref items, _) => {
+                let mut items = items.iter().map(|p| self.pat(p))
+                    .collect::<Result<Vec<Pat>, _>>()?;
+                // Missing components are ignored.
+                while items.len() < 4 { items.push(Pat::Wild); }
+                Pat::Ctor(Ctor::Vec4, items)
+            }
+            Pattern::Variant(ref variant) => {
+                let enum_name = match self.enums.get(&variant.name) {
+                    None => return Err(variant.source_range.wrap(
+                        format!("Could not find variant `{}`", variant.name))),
+                    Some(enum_name) => enum_name.clone()
+                };
+                let arity = self.family(&enum_name).iter()
+                    .find(|v| v.0 == variant.name).map(|v| v.1).unwrap_or(0);
+                if arity != variant.args.len() {
+                    return Err(variant.source_range.wrap(
+                        format!("Variant `{}` has {} field(s), found {}",
+                            variant.name, arity, variant.args.len())));
+                }
+                let args = variant.args.iter().map(|p| self.pat(p))
+                    .collect::<Result<Vec<Pat>, _>>()?;
+                Pat::Ctor(Ctor::Variant(enum_name, variant.name.clone()), args)
+            }
+        })
+    }
+
+    fn arity(&self, ctor: &Ctor) -> usize {
+        match *ctor {
+            Ctor::Variant(ref enum_name, ref name) => self.family(enum_name).iter()
+                .find(|v| &v.0 == name).map(|v| v.1).unwrap_or(0),
+            Ctor::Bool(_) | Ctor::Text(_) | Ctor::F64(_) => 0,
+            Ctor::Array(n) => n,
+            Ctor::Vec4 => 4,
+        }
+    }
+
+    /// Returns all constructors of the type, or `None` if there are infinitely many.
+    fn all_ctors(&self, ctor: &Ctor) -> Option<Vec<Ctor>> {
+        match *ctor {
+            Ctor::Variant(ref enum_name, _) => Some(self.family(enum_name).iter()
+                .map(|v| Ctor::Variant(enum_name.clone(), v.0.clone())).collect()),
+            Ctor::Bool(_) => Some(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+            Ctor::Vec4 => Some(vec![Ctor::Vec4]),
+            Ctor::Text(_) | Ctor::F64(_) | Ctor::Array(_) => None,
+        }
+    }
+
+    /// Returns the constructors in the first column that are not covered,
+    /// or `None` if the constructors in the first column cover all values.
+    fn uncovered(&self, heads: &[Ctor]) -> Option<Option<Ctor>> {
+        let all = match heads.first() {
+            None => return Some(None),
+            Some(head) => match self.all_ctors(head) {
+                None => return Some(None),
+                Some(all) => all
+            }
+        };
+        for ctor in all {
+            if !heads.contains(&ctor) { return Some(Some(ctor)); }
+        }
+        None
+    }
+
+    fn specialize(&self, rows: &[Vec<Pat>], ctor: &Ctor) -> Vec<Vec<Pat>> {
+        let arity = self.arity(ctor);
+        rows.iter().filter_map(|row| specialize_row(row, ctor, arity)).collect()
+    }
+
+    /// Returns `true` if the pattern matches some value not matched by the rows.
+    fn useful(&self, rows: &[Vec<Pat>], q: &[Pat]) -> bool {
+        if q.is_empty() { return rows.is_empty(); }
+        match q[0] {
+            Pat::Ctor(ref ctor, _) => {
+                let q = specialize_row(q, ctor, self.arity(ctor)).unwrap();
+                self.useful(&self.specialize(rows, ctor), &q)
+            }
+            Pat::Wild => {
+                let heads = head_ctors(rows);
+                if self.uncovered(&heads).is_none() {
+                    heads.iter().any(|ctor| {
+                        let q = specialize_row(q, ctor, self.arity(ctor)).unwrap();
+                        self.useful(&self.specialize(rows, ctor), &q)
+                    })
+                } else {
+                    self.useful(&default_rows(rows), &q[1..])
+                }
+            }
+        }
+    }
+
+    /// Returns an example of `n` patterns that are not matched by the rows.
+    fn missing(&self, rows: &[Vec<Pat>], n: usize) -> Option<Vec<Pat>> {
+        if n == 0 {
+            return if rows.is_empty() { Some(vec![]) } else { None };
+        }
+        let heads = head_ctors(rows);
+        match self.uncovered(&heads) {
+            None => {
+                for ctor in &heads {
+                    let arity = self.arity(ctor);
+                    if let Some(mut witness) = self.missing(
+                        &self.specialize(rows, ctor), arity + n - 1
+                    ) {
+                        let rest = witness.split_off(arity);
+                        let mut res = vec![Pat::Ctor(ctor.clone(), witness)];
+                        res.extend(rest);
+                        return Some(res);
+                    }
+                }
+                None
+            }
+            Some(ctor) => {
+                let witness = self.missing(&default_rows(rows), n - 1)?;
+                let mut res = vec![match ctor {
+                    None => Pat::Wild,
+                    Some(ctor) => {
+                        let arity = self.arity(&ctor);
+                        Pat::Ctor(ctor, vec![Pat::Wild; arity])
+                    }
+                }];
+                res.extend(witness);
+                Some(res)
+            }
+        }
+    }
+}
+
+fn specialize_row(row: &[Pat], ctor: &Ctor, arity: usize) -> Option<Vec<Pat>> {
+    let mut res = match row[0] {
+        Pat::Wild => vec![Pat::Wild; arity],
+        Pat::Ctor(ref c, ref args) if c == ctor => args.clone(),
+        Pat::Ctor(_, _) => return None
+    };
+    res.extend_from_slice(&row[1..]);
+    Some(res)
+}
+
+fn default_rows(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter().filter_map(|row| match row[0] {
+        Pat::Wild => Some(row[1..].to_vec()),
+        Pat::Ctor(_, _) => None
+    }).collect()
+}
+
+fn head_ctors(rows: &[Vec<Pat>]) -> Vec<Ctor> {
+    let mut res: Vec<Ctor> = vec![];
+    for row in rows {
+        if let Pat::Ctor(ref ctor, _) = row[0] {
+            if !res.contains(ctor) { res.push(ctor.clone()); }
+        }
+    }
+    res
+}
+
+/// Converts the patterns of match arms, by index of the arm node.
+pub fn patterns(
+    nodes: &[Node],
+    data: &[Range<MetaData>]
+) -> Result<HashMap<usize, Pattern>, Range<String>> {
+    let mut res = HashMap::new();
+    for (arm, node) in nodes.iter().enumerate() {
+        if node.kind != Kind::Arm { continue; }
+        let p = match node.find_child_by_kind(nodes, Kind::Pattern) {
+            None => continue,
+            Some(p) => p
+        };
+        let convert = Convert::new(&data[nodes[p].start..nodes[p].end]);
+        match Pattern::from_meta_data("pattern", convert, &mut vec![]) {
+            Ok((_, pattern)) => {res.insert(arm, pattern);}
+            Err(()) => return Err(nodes[p].source.wrap("Invalid pattern".into()))
+        }
+    }
+    Ok(res)
+}
+
+/// Checks that match expressions cover all values.
+///
+/// Arms that can not match any value are reported as warnings.
+pub fn check(
+    nodes: &[Node],
+    patterns: &HashMap<usize, Pattern>,
+    warnings: &mut Vec<Range<String>>
+) -> Result<(), Range<String>> {
+    let checker = Checker::new(nodes);
+    for node in nodes {
+        if node.kind != Kind::Match { continue; }
+        let mut rows: Vec<Vec<Pat>> = vec![];
+        for &arm in &node.children {
+            let pattern = match patterns.get(&arm) {
+                None => continue,
+                Some(pattern) => pattern
+            };
+            let row = vec![checker.pat(pattern)?];
+            if !checker.useful(&rows, &row) {
+                warnings.push(nodes[arm].source.wrap("Unreachable match arm".into()));
+                continue;
+            }
+            // Arms with guards might not match, so they do not cover values.
+            if nodes[arm].find_child_by_kind(nodes, Kind::Guard).is_none() {
+                rows.push(row);
+            }
+        }
+        if let Some(witness) = checker.missing(&rows, 1) {
+            return Err(node.source.wrap(
+                format!("Non-exhaustive match, `{}` is not covered", witness[0])));
+        }
+    }
+    Ok(())
+}
@@ -102,6 +102,13 @@ pub enum Kind {
     Grab,
     TryExpr,
     In,
+    Enum,
+    Variant,
+    Match,
+    Subject,
+    Arm,
+    Guard,
+    Pattern,
 }
 
 impl Kind {
@@ -210,6 +217,16 @@ impl Kind {
             "grab" => Kind::Grab,
             "try_expr" => Kind::TryExpr,
             "in" => Kind::In,
+            "enum" => Kind::Enum,
+            "variant" => Kind::Variant,
+            "match" => Kind::Match,
+            "subject" => Kind::Subject,
+            "arm" => Kind::Arm,
+            "guard" => Kind::Guard,
+            "pattern" => Kind::Pattern,
+            "pat_arr" => Kind::Pattern,
+            "pat_vec4" => Kind::Pattern,
+            "pat_arg" => Kind::Pattern,
             _ => return None
         })
     }
@@ -10,7 +10,7 @@ use self::node::{convert_meta_data, Node};
 use self::lt::{arg_lifetime, compare_lifetimes, Lifetime};
 
 use prelude::{Lt, Prelude};
-use ast::{AssignOp, UseLookup};
+use ast::{AssignOp, UseLookup, ENUM_KEY, VARIANT_KEY};
 
 use Type;
 
@@ -18,15 +18,30 @@ mod kind;
 mod node;
 mod lt;
 mod typecheck;
+mod exhaustive;
+mod strict;
+mod warnings;
 
 /// Checks lifetime constraints and does type checking.
 /// Returns refined return types of functions to put in AST.
+///
+/// In strict mode, arguments and return values must be annotated with types,
+/// and values inferred as `any` used where some other type is expected
+/// are pushed to `warnings`. Strict mode is also enabled by the `#strict` pragma.
+///
+/// Non-fatal warnings are pushed to `warnings` when checking succeeds.
+/// The inferred types of expressions are pushed to `types` if any,
+/// with the name of the local variable or argument declared by the range.
 pub fn check(
     data: &[Range<MetaData>],
-    prelude: &Prelude
+    prelude: &Prelude,
+    strict: bool,
+    warnings: &mut Vec<Range<String>>,
+    types: Option<&mut Vec<(Range, Option<Arc<String>>, Type)>>
 ) -> Result<HashMap<Arc<String>, Type>, Range<String>> {
     let mut nodes: Vec<Node> = vec![];
     convert_meta_data(&mut nodes, data)?;
+    let strict = strict || strict::pragma(data);
 
     // Add mutability information to function names.
     for i in 0..nodes.len() {
@@ -68,9 +83,40 @@ pub fn check(
         }
     }
 
+    // Check enum variants and set their type.
+    for i in 0..nodes.len() {
+        if nodes[i].kind != Kind::Variant { continue; }
+        let name = nodes[i].name().expect("Expected name").clone();
+        match &**name {
+            "some" | "none" | "ok" | "err" => {
+                return Err(nodes[i].source.wrap(
+                    format!("Variant name `{}` is reserved", name)));
+            }
+            _ => {}
+        }
+        for &ch in &nodes[i].children {
+            if nodes[ch].kind != Kind::Arg { continue; }
+            if nodes[ch].mutable {
+                return Err(nodes[ch].source.wrap(
+                    "Variant fields can not be mutable".to_string()));
+            }
+            if let Some(name) = nodes[ch].name() {
+                if &***name == ENUM_KEY || &***name == VARIANT_KEY {
+                    return Err(nodes[ch].source.wrap(
+                        format!("Field name `{}` is reserved", name)));
+                }
+            }
+        }
+        let parent = nodes[i].parent.expect("Expected parent");
+        let enum_name = nodes[parent].name().expect("Expected name").clone();
+        nodes[i].ty = Some(Type::AdHoc(enum_name, Box::new(Type::object())));
+    }
+
     // Collect indices to function nodes.
+    // Enum variants are constructor functions.
     let functions: Vec<usize> = nodes.iter().enumerate()
-        .filter(|&(_, n)| n.kind == Kind::Fn).map(|(i, _)| i).collect();
+        .filter(|&(_, n)| n.kind == Kind::Fn || n.kind == Kind::Variant)
+        .map(|(i, _)| i).collect();
 
     // Stores functions arguments with same index as `functions`.
     let mut function_args = Vec::with_capacity(functions.len());
@@ -206,7 +252,8 @@ pub fn check(
         'search: loop {
             if nodes[parent].kind.is_decl_loop() ||
                nodes[parent].kind.is_decl_un_loop() ||
-               nodes[parent].kind.is_in_loop() {
+               nodes[parent].kind.is_in_loop() ||
+               nodes[parent].kind == Kind::Arm {
                 let my_name = nodes[i].name().unwrap();
                 for name in &nodes[parent].names {
                     if name == my_name {
@@ -826,7 +873,20 @@ pub fn check(
         }
     }
 
-    typecheck::run(&mut nodes, prelude, &use_lookup)?;
+    let patterns = exhaustive::patterns(&nodes, data)?;
+
+    if strict {
+        strict::annotations(&nodes, data)?;
+    }
+
+    typecheck::run(&mut nodes, prelude, &use_lookup, &patterns)?;
+    exhaustive::check(&nodes, &patterns, warnings)?;
+
+    if strict {
+        strict::flows(&nodes, prelude, &use_lookup, warnings);
+    }
+
+    warnings::check(&nodes, warnings);
 
     // Copy refined return types to use in AST.
     let mut refined_rets: HashMap<Arc<String>, Type> = HashMap::new();
@@ -836,6 +896,33 @@ pub fn check(
         }
     }
 
+    // Collect inferred types of expressions, local variables and arguments.
+    if let Some(types) = types {
+        for n in &nodes {
+            match n.kind {
+                Kind::Fn | Kind::Enum | Kind::Variant => continue,
+                _ => {}
+            }
+            let ty = match n.ty {
+                None | Some(Type::Unreachable) => continue,
+                Some(ref ty) => ty
+            };
+            let local = match n.kind {
+                Kind::Arg => n.name().cloned(),
+                Kind::Item if n.declaration.is_none() && !n.item_ids() => {
+                    n.parent.and_then(|left| nodes[left].parent)
+                        .and_then(|assign| if nodes[assign].op == Some(AssignOp::Assign) {
+                            n.name().cloned()
+                        } else {
+                            None
+                        })
+                }
+                _ => None
+            };
+            types.push((n.source, local, ty.clone()));
+        }
+    }
+
     Ok(refined_rets)
 }
 
@@ -116,6 +116,9 @@ impl Node {
                     return arg_lifetime(declaration, &arg, nodes, arg_names);
                 } else if arg.kind == Kind::Current {
                     return Some(Lifetime::Current(declaration));
+                } else if arg.kind == Kind::Arm {
+                    // Values bound by a pattern are copied out of the matched value.
+                    return None;
                 } else {
                     return Some(Lifetime::Local(declaration));
                 }
@@ -232,6 +235,13 @@ impl Node {
                 (_, Kind::Exp) => {}
                 (_, Kind::Block) => {}
                 (_, Kind::If) => {}
+                (_, Kind::Match) => {}
+                (_, Kind::Arm) => {}
+                (_, Kind::Subject) | (_, Kind::Guard) | (_, Kind::Pattern) => {
+                    // The result of a match arm does not depend on the lifetime
+                    // of the matched value, since bound values are copied.
+                    continue
+                }
                 (_, Kind::TrueBlock) => {}
                 (_, Kind::ElseIfBlock) => {}
                 (_, Kind::ElseBlock) => {}
@@ -292,6 +302,8 @@ pub fn convert_meta_data(
     let mut parents: Vec<usize> = vec![];
     let ignored = &mut vec![];
     let mut skip: Option<usize> = None;
+    // Type variables declared by the current function.
+    let mut generics: Vec<Arc<String>> = vec![];
     for (i, d) in data.iter().enumerate() {
         if let Some(j) = skip {
             if j > i { continue; }
@@ -303,12 +315,14 @@ pub fn convert_meta_data(
                     None => return Err(d.range().wrap(format!("Unknown kind `{}`", kind_name)))
                 };
 
+                if kind == Kind::Fn { generics.clear(); }
+
                 // Parse type information and put it in parent node.
                 if kind == Kind::Type || kind == Kind::RetType {
                     let convert = Convert::new(&data[i..]);
                     if let Ok((range, val)) = Type::from_meta_data(kind_name, convert, ignored) {
                         let parent = *parents.last().unwrap();
-                        nodes[parent].ty = Some(val);
+                        nodes[parent].ty = Some(val.with_vars(&generics));
                         skip = Some(range.next_offset() + i);
                         continue;
                     }
@@ -322,7 +336,6 @@ pub fn convert_meta_data(
                     Kind::In => Some(Type::In(Box::new(Type::array()))),
                     Kind::Object => Some(Type::object()),
                     Kind::Sift | Kind::SiftIn => Some(Type::array()),
-                    Kind::Sum | Kind::SumIn | Kind::Prod | Kind::ProdIn => Some(Type::F64),
                     Kind::Norm => Some(Type::F64),
                     Kind::Swizzle => Some(Type::F64),
                     Kind::Link | Kind::LinkFor => Some(Type::Link),
@@ -377,6 +390,15 @@ pub fn convert_meta_data(
                         let i = *parents.last().unwrap();
                         nodes[i].names.push(val.clone());
                     }
+                    "generic" => generics.push(val.clone()),
+                    "bind" => {
+                        // Put bound variables in the match arm,
+                        // which is used as declaration.
+                        if let Some(&i) = parents.iter().rev()
+                            .find(|&&i| nodes[i].kind == Kind::Arm) {
+                            nodes[i].names.push(val.clone());
+                        }
+                    }
                     "word" => {
                         // Put words together to name.
                         let i = *parents.last().unwrap();
@@ -0,0 +1,142 @@
+use range::Range;
+use super::piston_meta::MetaData;
+use super::node::Node;
+use super::kind::Kind;
+use ast::UseLookup;
+use Prelude;
+use Type;
+
+/// Returns `true` if the source uses the `#strict` pragma.
+pub fn pragma(data: &[Range<MetaData>]) -> bool {
+    data.iter().any(|d| match d.data {
+        MetaData::Bool(ref n, val) => val && &***n == "strict",
+        _ => false
+    })
+}
+
+/// Checks that arguments and return values are annotated with types.
+///
+/// This runs before type checking, since type propagation
+/// overwrites the type information of nodes.
+pub fn annotations(nodes: &[Node], data: &[Range<MetaData>]) -> Result<(), Range<String>> {
+    for node in nodes {
+        match node.kind {
+            Kind::Fn | Kind::Closure | Kind::Variant => {}
+            _ => continue
+        }
+        for &ch in &node.children {
+            if nodes[ch].kind != Kind::Arg { continue; }
+            if nodes[ch].ty.is_none() {
+                return Err(nodes[ch].source.wrap(
+                    format!("Strict mode:\nArgument `{}` requires a type",
+                        nodes[ch].name().expect("Expected name"))));
+            }
+        }
+        if node.kind == Kind::Fn && returns_without_type(node, data) {
+            return Err(node.source.wrap(
+                format!("Strict mode:\nFunction `{}` requires a return type",
+                    node.name().expect("Expected name"))));
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if a function uses `->` without a return type.
+fn returns_without_type(node: &Node, data: &[Range<MetaData>]) -> bool {
+    let mut returns = false;
+    for d in &data[node.start..node.end] {
+        match d.data {
+            MetaData::Bool(ref n, val) if &***n == "returns" => returns = val,
+            MetaData::StartNode(ref n) if &***n == "ret_type" => return false,
+            // The return type comes before the body.
+            MetaData::StartNode(ref n) if &***n == "block" || &***n == "expr" => break,
+            _ => {}
+        }
+    }
+    returns
+}
+
+/// Warns about values inferred as `any` that flow into typed positions.
+///
+/// This runs after type checking.
+pub fn flows(
+    nodes: &[Node],
+    prelude: &Prelude,
+    use_lookup: &UseLookup,
+    warnings: &mut Vec<Range<String>>
+) {
+    for (i, node) in nodes.iter().enumerate() {
+        match node.kind {
+            Kind::Fn => {
+                // Return types of mathematical functions are inferred.
+                if node.find_child_by_kind(nodes, Kind::Expr).is_some() &&
+                   node.ty == Some(Type::Any) {
+                    warnings.push(node.source.wrap(
+                        format!("Strict mode: Function `{}` returns `any`",
+                            node.name().expect("Expected name"))));
+                }
+            }
+            Kind::Call => {
+                let tys: Vec<Type> = if let Some(decl) = node.declaration {
+                    nodes[decl].children.iter()
+                        .filter(|&&arg| nodes[arg].kind == Kind::Arg)
+                        .map(|&arg| nodes[arg].ty.clone().unwrap_or(Type::Any))
+                        .collect()
+                } else if let Some(ref alias) = node.alias {
+                    use ast::FnAlias;
+
+                    // External functions are treated as loaded in prelude.
+                    if let Some(&FnAlias::Loaded(f)) = use_lookup.aliases.get(alias)
+                    .and_then(|map| map.get(node.name().unwrap())) {
+                        prelude.list[f].tys.clone()
+                    } else {
+                        continue
+                    }
+                } else if let Some(&f) = prelude.functions.get(node.name().unwrap()) {
+                    prelude.list[f].tys.clone()
+                } else {
+                    continue
+                };
+                let args: Vec<usize> = node.children.iter()
+                    .filter(|&&ch| nodes[ch].kind == Kind::CallArg)
+                    .map(|&ch| ch).collect();
+                // Swizzled arguments do not map one-to-one to declared arguments.
+                if args.iter().any(|&arg| nodes[arg]
+                    .find_child_by_kind(nodes, Kind::Swizzle).is_some()) {
+                    continue;
+                }
+                for (&arg, ty) in args.iter().zip(tys.iter()) {
+                    warnings.extend(any_flow(&nodes[arg], ty));
+                }
+            }
+            Kind::Return => {
+                // Find function and check against return type.
+                let mut p = i;
+                while let Some(parent) = nodes[p].parent {
+                    p = parent;
+                    if nodes[p].kind == Kind::Closure { break; }
+                    if nodes[p].kind == Kind::Fn {
+                        if let Some(ref ret) = nodes[p].ty {
+                            if let Some(&ch) = node.children.first() {
+                                warnings.extend(any_flow(&nodes[ch], ret));
+                            }
+                        }
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn any_flow(node: &Node, expected: &Type) -> Option<Range<String>> {
+    match *expected {
+        Type::Any | Type::Var(_) => None,
+        _ if node.ty == Some(Type::Any) => {
+            Some(node.source.wrap(format!("Strict mode: Expected `{}`, found `any`",
+                expected.description())))
+        }
+        _ => None
+    }
+}
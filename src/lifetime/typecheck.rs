@@ -1,9 +1,11 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use range::Range;
 use super::node::Node;
 use super::kind::Kind;
 use Prelude;
 use Type;
-use ast::UseLookup;
+use ast::{Pattern, UseLookup};
 
 /// Runs type checking.
 ///
@@ -45,7 +47,18 @@ use ast::UseLookup;
 /// The type propagation step uses this assumption without checking the whole `if` expression.
 /// After type propagation, all blocks in the `if` expression should have some type information,
 /// but no further propagation is necessary, so it only need to check for consistency.
-pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) -> Result<(), Range<String>> {
+pub fn run(
+    nodes: &mut Vec<Node>,
+    prelude: &Prelude,
+    use_lookup: &UseLookup,
+    patterns: &HashMap<usize, Pattern>
+) -> Result<(), Range<String>> {
+    // Enum variants by name, used to find the types of fields bound by patterns.
+    let variants: HashMap<Arc<String>, usize> = nodes.iter().enumerate()
+        .filter(|&(_, n)| n.kind == Kind::Variant)
+        .filter_map(|(i, n)| n.name().map(|name| (name.clone(), i)))
+        .collect();
+
     // Type propagation.
     let mut changed;
     loop {
@@ -178,20 +191,8 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                     this_ty = expr_type;
                 }
                 Kind::Call => {
-                    if let Some(decl) = nodes[i].declaration {
-                        if let Some(ref ty) = nodes[decl].ty {
-                            this_ty = Some(ty.clone());
-                        }
-                    } else if let Some(ref alias) = nodes[i].alias {
-                        use ast::FnAlias;
-
-                        // External functions are treated as loaded in prelude.
-                        if let Some(&FnAlias::Loaded(f)) = use_lookup.aliases.get(alias)
-                        .and_then(|map| map.get(nodes[i].name().unwrap())) {
-                            this_ty = Some(prelude.list[f].ret.clone());
-                        }
-                    } else if let Some(&f) = prelude.functions.get(nodes[i].name().unwrap()) {
-                        this_ty = Some(prelude.list[f].ret.clone());
+                    if let Some((tys, ret)) = call_signature(i, nodes, prelude, use_lookup) {
+                        this_ty = generic_ret(i, nodes, &tys, &ret)?;
                     }
                 }
                 Kind::CallClosure => {
@@ -256,6 +257,16 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                                 this_ty = Some(nodes[i].inner_type(nodes[decl].ty.as_ref()
                                     .unwrap_or(&Type::Any)));
                             }
+                            Kind::Arm => {
+                                // Values bound by patterns get their type from the subject.
+                                let name = nodes[i].name().unwrap().clone();
+                                let ty = match bound_type(decl, &name, nodes, patterns,
+                                                          &variants)? {
+                                    None => continue 'node,
+                                    Some(ty) => ty
+                                };
+                                this_ty = Some(nodes[i].inner_type(&ty));
+                            }
                             _ => {
                                 if let Some(ref ty) = nodes[decl].ty {
                                     this_ty = Some(nodes[i].inner_type(ty));
@@ -282,7 +293,8 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                 }
                 Kind::Return | Kind::Val | Kind::Expr | Kind::Cond |
                 Kind::Exp | Kind::Base | Kind::Left | Kind::Right |
-                Kind::ElseIfCond | Kind::UnOp | Kind::Grab
+                Kind::ElseIfCond | Kind::UnOp | Kind::Grab |
+                Kind::Subject | Kind::Guard
                  => {
                      // TODO: Report error for expected unary operator.
                     if nodes[i].children.is_empty() { continue 'node; }
@@ -427,7 +439,12 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                             this_ty = Some(Type::Secret(Box::new(Type::Bool)));
                         }
                         Some(_) => {
-                            this_ty = Some(Type::Bool);
+                            // The secret of the right argument is used when the left has none.
+                            let right = nodes[i].find_child_by_kind(nodes, Kind::Right);
+                            this_ty = match right.and_then(|right| nodes[right].ty.as_ref()) {
+                                Some(&Type::Secret(_)) => Some(Type::Secret(Box::new(Type::Bool))),
+                                _ => Some(Type::Bool)
+                            };
                         }
                         _ => {}
                     }
@@ -443,6 +460,19 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                         }
                     }
                 }
+                Kind::Sum | Kind::SumIn | Kind::Prod | Kind::ProdIn => {
+                    // Secrets in the body are kept.
+                    let ch = if let Some(ch) = nodes[i].find_child_by_kind(nodes, Kind::Block) {
+                        ch
+                    } else {
+                        continue 'node;
+                    };
+                    this_ty = match nodes[ch].ty {
+                        None => continue 'node,
+                        Some(Type::Secret(_)) => Some(Type::Secret(Box::new(Type::F64))),
+                        Some(_) => Some(Type::F64)
+                    };
+                }
                 Kind::Sift => {
                     // Infer type from body.
                     let ch = if let Some(ch) = nodes[i].find_child_by_kind(nodes, Kind::Block) {
@@ -482,6 +512,26 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
 
                     this_ty = Some(true_type);
                 }
+                Kind::Arm => {
+                    let ch = match nodes[i].find_child_by_kind(nodes, Kind::Expr) {
+                        None => continue 'node,
+                        Some(ch) => ch
+                    };
+                    if nodes[ch].item_ids() { continue 'node; }
+                    if let Some(ref ty) = nodes[ch].ty {
+                        this_ty = Some(nodes[i].inner_type(ty));
+                    }
+                }
+                Kind::Match => {
+                    // The type of match is inferred from the first arm.
+                    let arm = match nodes[i].find_child_by_kind(nodes, Kind::Arm) {
+                        None => continue 'node,
+                        Some(arm) => arm
+                    };
+                    if let Some(ref ty) = nodes[arm].ty {
+                        this_ty = Some(nodes[i].inner_type(ty));
+                    }
+                }
                 Kind::Arg => {
                     this_ty = Some(Type::Any);
                 }
@@ -523,7 +573,20 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                 changed = true;
             }
         }
-        if !changed { break; }
+        if !changed {
+            // Sums and products are numbers when the body type is unknown.
+            for node in nodes.iter_mut() {
+                if node.ty.is_some() { continue; }
+                match node.kind {
+                    Kind::Sum | Kind::SumIn | Kind::Prod | Kind::ProdIn => {
+                        node.ty = Some(Type::F64);
+                        changed = true;
+                    }
+                    _ => {}
+                }
+            }
+            if !changed { break; }
+        }
     }
 
     // After type propagation.
@@ -579,9 +642,18 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                     }
                 }
             }
+            Kind::Call => {
+                // Check type variables when all argument types are known.
+                if let Some((tys, _)) = call_signature(i, nodes, prelude, use_lookup) {
+                    bind_args(i, nodes, &tys, &mut HashMap::new())?;
+                }
+            }
             Kind::If => {
                 check_if(i, nodes)?
             }
+            Kind::Match => {
+                check_match(i, nodes, patterns, &variants)?
+            }
             Kind::Assign => {
                 use ast::AssignOp;
 
@@ -640,9 +712,10 @@ pub fn run(nodes: &mut Vec<Node>, prelude: &Prelude, use_lookup: &UseLookup) ->
                 if let Some(ch) = nodes[i].find_child_by_kind(nodes, Kind::Expr) {
                     let expr_type = nodes[ch].ty.as_ref().map(|ty| nodes[ch].inner_type(&ty));
                     if let Some(ref ty) = expr_type {
-                        if !ty.goes_with(&Type::Vec4) {
+                        if !ty.goes_with(&Type::Vec4) && !ty.goes_with(&Type::VecN) {
                             return Err(nodes[ch].source.wrap(
-                                format!("Type mismatch (#1200):\nExpected `vec4`, found `{}`",
+                                format!("Type mismatch (#1200):\nExpected `vec4` or `vecn`, \
+                                         found `{}`",
                                     expr_type.as_ref().unwrap().description())));
                         }
                     }
@@ -709,6 +782,193 @@ fn check_fn(
     Ok(())
 }
 
+/// Returns the declared argument types and return type of a call.
+fn call_signature(
+    call: usize,
+    nodes: &[Node],
+    prelude: &Prelude,
+    use_lookup: &UseLookup
+) -> Option<(Vec<Type>, Type)> {
+    use ast::FnAlias;
+
+    if let Some(decl) = nodes[call].declaration {
+        let ret = match nodes[decl].ty {
+            None => return None,
+            Some(ref ty) => ty.clone()
+        };
+        let tys = nodes[decl].children.iter()
+            .filter(|&&arg| nodes[arg].kind == Kind::Arg)
+            .map(|&arg| nodes[arg].ty.clone().unwrap_or(Type::Any))
+            .collect();
+        Some((tys, ret))
+    } else if let Some(ref alias) = nodes[call].alias {
+        // External functions are treated as loaded in prelude.
+        if let Some(&FnAlias::Loaded(f)) = use_lookup.aliases.get(alias)
+        .and_then(|map| map.get(nodes[call].name().unwrap())) {
+            let f = &prelude.list[f];
+            Some((f.tys.clone(), f.ret.clone()))
+        } else {
+            None
+        }
+    } else if let Some(&f) = prelude.functions.get(nodes[call].name().unwrap()) {
+        let f = &prelude.list[f];
+        Some((f.tys.clone(), f.ret.clone()))
+    } else {
+        None
+    }
+}
+
+/// Infers the return type of a call by binding type variables
+/// to the types of the call arguments.
+///
+/// Returns `None` when the return type depends on call arguments
+/// that have no type information yet.
+fn generic_ret(
+    call: usize,
+    nodes: &[Node],
+    tys: &[Type],
+    ret: &Type
+) -> Result<Option<Type>, Range<String>> {
+    if !ret.has_vars() {
+        bind_args(call, nodes, tys, &mut HashMap::new())?;
+        return Ok(Some(ret.clone()));
+    }
+
+    let mut bindings = HashMap::new();
+    if !bind_args(call, nodes, tys, &mut bindings)? { return Ok(None); }
+    Ok(Some(ret.substitute(&bindings)))
+}
+
+/// Binds type variables in the declared argument types of a call.
+///
+/// Returns an error when a type variable is bound to conflicting types.
+/// Returns `false` when some arguments with type variables have no type information yet.
+fn bind_args(
+    call: usize,
+    nodes: &[Node],
+    tys: &[Type],
+    bindings: &mut HashMap<Arc<String>, Type>
+) -> Result<bool, Range<String>> {
+    if !tys.iter().any(|ty| ty.has_vars()) { return Ok(true); }
+
+    let args: Vec<usize> = nodes[call].children.iter()
+        .filter(|&&ch| nodes[ch].kind == Kind::CallArg)
+        .map(|&ch| ch).collect();
+    // Swizzled arguments do not map one-to-one to declared arguments.
+    if args.iter().any(|&arg| nodes[arg].find_child_by_kind(nodes, Kind::Swizzle).is_some()) {
+        return Ok(true);
+    }
+    let mut complete = true;
+    for (&arg, decl_ty) in args.iter().zip(tys.iter()) {
+        if !decl_ty.has_vars() { continue; }
+        let ty = match nodes[arg].ty {
+            None => {
+                complete = false;
+                continue;
+            }
+            Some(ref ty) => ty
+        };
+        if !decl_ty.unify(ty, bindings) {
+            return Err(nodes[arg].source.wrap(
+                format!("Type mismatch (#2000):\nExpected `{}`, found `{}`",
+                    decl_ty.substitute(bindings).description(), ty.description())));
+        }
+    }
+    Ok(complete)
+}
+
+/// Returns the type of a value bound by a pattern in a match arm,
+/// or `None` if the type of the match subject is not known yet.
+fn bound_type(
+    arm: usize,
+    name: &Arc<String>,
+    nodes: &[Node],
+    patterns: &HashMap<usize, Pattern>,
+    variants: &HashMap<Arc<String>, usize>
+) -> Result<Option<Type>, Range<String>> {
+    let pattern = match patterns.get(&arm) {
+        None => return Ok(Some(Type::Any)),
+        Some(pattern) => pattern
+    };
+    let subject = nodes[arm].parent
+        .and_then(|n| nodes[n].find_child_by_kind(nodes, Kind::Subject));
+    let ty = match subject.and_then(|ch| nodes[ch].ty.as_ref()) {
+        None => return Ok(None),
+        Some(ty) => ty
+    };
+    let mut binds = vec![];
+    pattern_binds(pattern, ty, nodes, variants, &mut binds)?;
+    Ok(Some(binds.into_iter().find(|b| &b.0 == name).map(|b| b.1).unwrap_or(Type::Any)))
+}
+
+/// Collects the types of values bound by a pattern matching a value of some type.
+///
+/// Returns an error when a variant pattern can not match a value of the type.
+fn pattern_binds(
+    pattern: &Pattern,
+    ty: &Type,
+    nodes: &[Node],
+    variants: &HashMap<Arc<String>, usize>,
+    binds: &mut Vec<(Arc<String>, Type)>
+) -> Result<(), Range<String>> {
+    match *pattern {
+        Pattern::Wildcard(_) | Pattern::Value(_, _) => {}
+        Pattern::Bind(ref name, _) => binds.push((name.clone(), ty.clone())),
+        Pattern::Array(ref items, _) => {
+            let item_ty = match *ty {
+                Type::Array(ref item_ty) => (**item_ty).clone(),
+                _ => Type::Any
+            };
+            for item in items {
+                pattern_binds(item, &item_ty, nodes, variants, binds)?;
+            }
+        }
+        Pattern::Vec4(ref items, _) => {
+            for item in items {
+                pattern_binds(item, &Type::F64, nodes, variants, binds)?;
+            }
+        }
+        Pattern::Variant(ref variant) => {
+            let (expected, fields) = match &**variant.name {
+                "some" | "none" => {
+                    let inner = match *ty {
+                        Type::Option(ref inner) => (**inner).clone(),
+                        _ => Type::Any
+                    };
+                    (Type::Option(Box::new(Type::Any)), vec![inner])
+                }
+                "ok" | "err" => {
+                    let inner = match *ty {
+                        Type::Result(ref inner) if &**variant.name == "ok" => (**inner).clone(),
+                        _ => Type::Any
+                    };
+                    (Type::Result(Box::new(Type::Any)), vec![inner])
+                }
+                _ => match variants.get(&variant.name) {
+                    // Unknown variants are reported when checking exhaustiveness.
+                    None => return Ok(()),
+                    Some(&v) => (
+                        nodes[v].ty.clone().unwrap_or(Type::Any),
+                        nodes[v].children.iter()
+                            .filter(|&&arg| nodes[arg].kind == Kind::Arg)
+                            .map(|&arg| nodes[arg].ty.clone().unwrap_or(Type::Any))
+                            .collect()
+                    )
+                }
+            };
+            if !expected.goes_with(ty) {
+                return Err(variant.source_range.wrap(
+                    format!("Type mismatch (#2100):\nVariant `{}` can not match `{}`",
+                        variant.name, ty.description())));
+            }
+            for (arg, field_ty) in variant.args.iter().zip(fields.iter()) {
+                pattern_binds(arg, field_ty, nodes, variants, binds)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn check_if(n: usize, nodes: &[Node]) -> Result<(), Range<String>> {
     if let Some(ch) = nodes[n].find_child_by_kind(nodes, Kind::Cond) {
         if let Some(ref cond_ty) = nodes[ch].ty {
@@ -758,3 +1018,47 @@ fn check_if(n: usize, nodes: &[Node]) -> Result<(), Range<String>> {
 
     Ok(())
 }
+
+fn check_match(
+    n: usize,
+    nodes: &[Node],
+    patterns: &HashMap<usize, Pattern>,
+    variants: &HashMap<Arc<String>, usize>
+) -> Result<(), Range<String>> {
+    let subject_ty = nodes[n].find_child_by_kind(nodes, Kind::Subject)
+        .and_then(|ch| nodes[ch].ty.as_ref());
+    for &arm in &nodes[n].children {
+        if nodes[arm].kind != Kind::Arm { continue; }
+        if let (Some(ty), Some(pattern)) = (subject_ty, patterns.get(&arm)) {
+            pattern_binds(pattern, ty, nodes, variants, &mut vec![])?;
+        }
+        if let Some(ch) = nodes[arm].find_child_by_kind(nodes, Kind::Guard) {
+            if let Some(ref guard_ty) = nodes[ch].ty {
+                if !Type::Bool.goes_with(guard_ty) {
+                    return Err(nodes[ch].source.wrap(
+                        format!("Type mismatch (#1800):\nExpected `{}`, found `{}`",
+                            Type::Bool.description(), guard_ty.description())));
+                }
+            }
+        }
+    }
+
+    // The type of matches are inferred from the first arm.
+    let first_type = match nodes[n].ty {
+        None => return Ok(()),
+        Some(ref ty) => ty
+    };
+
+    for &arm in &nodes[n].children {
+        if nodes[arm].kind != Kind::Arm { continue; }
+        if let Some(ref arm_type) = nodes[arm].ty {
+            if !arm_type.goes_with(&first_type) {
+                return Err(nodes[arm].source.wrap(
+                    format!("Type mismatch (#1900):\nExpected `{}`, found `{}`",
+                        first_type.description(), arm_type.description())));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use std::sync::Arc;
+use range::Range;
+use super::node::Node;
+use super::kind::Kind;
+use ast::AssignOp;
+
+/// Collects non-fatal warnings, sorted by position in source.
+///
+/// This runs after declarations are linked and type checking succeeded.
+pub fn check(nodes: &[Node], warnings: &mut Vec<Range<String>>) {
+    let read = reads(nodes);
+    unused_locals(nodes, &read, warnings);
+    unused_args(nodes, &read, warnings);
+    unreachable(nodes, warnings);
+    unused_uses(nodes, warnings);
+    warnings.sort_by_key(|w| w.offset);
+}
+
+/// Returns `true` if a name is marked as intentionally unused, e.g. `_x`.
+fn ignored_name(name: &str) -> bool {
+    name.starts_with('_')
+}
+
+/// Marks the declarations that are read by some item.
+///
+/// Items that are overwritten with `=` do not count as being read.
+fn reads(nodes: &[Node]) -> Vec<bool> {
+    let mut read = vec![false; nodes.len()];
+    for n in nodes {
+        if n.kind != Kind::Item { continue; }
+        let decl = match n.declaration {
+            None => continue,
+            Some(decl) => decl
+        };
+        let overwritten = !n.item_ids() && match n.parent {
+            Some(left) if nodes[left].kind == Kind::Left => {
+                let assign = nodes[left].parent.expect("Expected parent");
+                nodes[assign].op == Some(AssignOp::Set)
+            }
+            _ => false
+        };
+        if !overwritten { read[decl] = true; }
+    }
+    read
+}
+
+fn unused_locals(nodes: &[Node], read: &[bool], warnings: &mut Vec<Range<String>>) {
+    for (i, n) in nodes.iter().enumerate() {
+        if n.op != Some(AssignOp::Assign) || n.children.is_empty() { continue; }
+        let left = n.children[0];
+        if nodes[left].children.is_empty() { continue; }
+        let item = nodes[left].children[0];
+        // Ignore assignments to objects or arrays.
+        if nodes[item].item_ids() { continue; }
+        let name = match nodes[item].name() {
+            None => continue,
+            Some(name) => name
+        };
+        if &**name == "_" {
+            warnings.push(n.source.wrap("Result is ignored with `_ :=`".into()));
+            continue;
+        }
+        if shadows(nodes, i, name) {
+            warnings.push(nodes[item].source.wrap(
+                format!("`{}` shadows a previous declaration", name)));
+        }
+        if !ignored_name(name) && !read[item] {
+            warnings.push(nodes[item].source.wrap(
+                format!("Unused variable `{}`", name)));
+        }
+    }
+}
+
+fn unused_args(nodes: &[Node], read: &[bool], warnings: &mut Vec<Range<String>>) {
+    for n in nodes {
+        if n.kind != Kind::Fn && n.kind != Kind::Closure { continue; }
+        for &arg in &n.children {
+            if nodes[arg].kind != Kind::Arg { continue; }
+            let name = match nodes[arg].name() {
+                None => continue,
+                Some(name) => name
+            };
+            if !ignored_name(name) && !read[arg] {
+                warnings.push(nodes[arg].source.wrap(
+                    format!("Unused argument `{}`", name)));
+            }
+        }
+    }
+}
+
+/// Returns `true` if a local declaration has the same name as a visible declaration.
+fn shadows(nodes: &[Node], assign: usize, name: &Arc<String>) -> bool {
+    let mut child = assign;
+    let mut parent = match nodes[assign].parent {
+        None => return false,
+        Some(parent) => parent
+    };
+    loop {
+        if nodes[parent].kind.is_decl_loop() ||
+           nodes[parent].kind.is_decl_un_loop() ||
+           nodes[parent].kind.is_in_loop() ||
+           nodes[parent].kind == Kind::Arm {
+            if nodes[parent].names.contains(name) { return true; }
+        }
+
+        let me = nodes[parent].children.binary_search(&child)
+            .expect("Expected parent to contain child");
+        for &j in nodes[parent].children[..me].iter().rev() {
+            if nodes[j].children.is_empty() { continue; }
+            // Assign is inside an expression.
+            let j = nodes[j].children[0];
+            if nodes[j].op != Some(AssignOp::Assign) { continue; }
+            let left = nodes[j].children[0];
+            let item = nodes[left].children[0];
+            if !nodes[item].item_ids() && nodes[item].name() == Some(name) {
+                return true;
+            }
+        }
+
+        match nodes[parent].kind {
+            Kind::Fn | Kind::Closure => {
+                return nodes[parent].children.iter().any(|&j| {
+                    (nodes[j].kind == Kind::Arg || nodes[j].kind == Kind::Current) &&
+                    nodes[j].name() == Some(name)
+                });
+            }
+            _ => {}
+        }
+        child = parent;
+        parent = match nodes[parent].parent {
+            None => return false,
+            Some(parent) => parent
+        };
+    }
+}
+
+/// Returns `true` if a statement in a block leaves the block.
+fn leaves_block(nodes: &[Node], statement: &Node) -> bool {
+    if statement.kind == Kind::ReturnVoid { return true; }
+    match statement.children.first() {
+        Some(&ch) => match nodes[ch].kind {
+            Kind::Return | Kind::Break | Kind::Continue => true,
+            _ => false
+        },
+        None => false
+    }
+}
+
+fn unreachable(nodes: &[Node], warnings: &mut Vec<Range<String>>) {
+    for n in nodes {
+        match n.kind {
+            Kind::Block | Kind::TrueBlock | Kind::ElseIfBlock | Kind::ElseBlock => {}
+            _ => continue
+        }
+        let mut it = n.children.iter();
+        while let Some(&ch) = it.next() {
+            if leaves_block(nodes, &nodes[ch]) {
+                if let Some(&next) = it.next() {
+                    warnings.push(nodes[next].source.wrap("Unreachable statement".into()));
+                }
+                break;
+            }
+        }
+    }
+}
+
+fn unused_uses(nodes: &[Node], warnings: &mut Vec<Range<String>>) {
+    for n in nodes {
+        if n.kind != Kind::Use { continue; }
+        let alias = match n.alias {
+            None => continue,
+            Some(ref alias) => alias
+        };
+        let used = nodes.iter().any(|call| {
+            call.kind == Kind::Call && call.alias.as_ref() == Some(alias)
+        });
+        if !used {
+            warnings.push(n.source.wrap(format!("Unused import `{}`", alias)));
+        }
+    }
+}
@@ -1,38 +1,61 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::fmt;
+use std::io;
 
 use Variable;
 
 // Do not change this without updating the algorithms!
-const BLOCK_SIZE: usize = 124;
+const BLOCK_SIZE: usize = 120;
+// The number of type tags stored in each `u64`.
+const TAGS: usize = 16;
 
 const EMPTY: u64 = 0x0;
 const BOOL: u64 = 0x1;
 const F64: u64 = 0x2;
 const STR: u64 = 0x3;
+const VEC4: u64 = 0x4;
+// Other values are stored indirectly.
+const VAR: u64 = 0x5;
 
 /// Stores link memory in chunks of 1024 bytes.
+///
+/// Each item uses one `u64` slot and a 4 bit type tag.
+/// Text is stored as `Arc<String>`, 4D vectors as `Box<[f32; 4]>`
+/// and other values as `Box<Variable>`.
 pub struct Block {
     data: [u64; BLOCK_SIZE],
-    tys: [u64; 4]
+    tys: [u64; 8]
 }
 
 impl Block {
     fn new() -> Block {
         Block {
             data: [0; BLOCK_SIZE],
-            tys: [0; 4],
+            tys: [0; 8],
         }
     }
 
+    fn ty(&self, k: usize) -> u64 {
+        let i = k / TAGS;
+        let j = k - i * TAGS;
+        self.tys[i] >> (j * 4) & 0xf
+    }
+
+    fn set_ty(&mut self, k: usize, ty: u64) {
+        let i = k / TAGS;
+        let j = k - i * TAGS;
+        // Reset bits.
+        self.tys[i] &= !(0xf << (j * 4));
+        // Sets new bits.
+        self.tys[i] |= ty << (j * 4);
+    }
+
     pub(crate) fn var(&self, ind: u8) -> Variable {
         use std::mem::transmute;
 
         let k = ind as usize;
         assert!(k < BLOCK_SIZE);
-        let i = k / 32;
-        let j = k - i * 32;
-        match self.tys[i] >> (j * 2) & 0x3 {
+        match self.ty(k) {
             EMPTY => panic!("Reading beyond end"),
             BOOL => Variable::bool(self.data[k] != 0),
             F64 => {
@@ -43,6 +66,12 @@ impl Block {
                     transmute::<&u64, &Arc<String>>(&self.data[k])
                 }.clone())
             }
+            VEC4 => {
+                Variable::Vec4(unsafe { *(self.data[k] as usize as *const [f32; 4]) })
+            }
+            VAR => {
+                unsafe { (*(self.data[k] as usize as *const Variable)).clone() }
+            }
             _ => panic!("Invalid type"),
         }
     }
@@ -53,32 +82,45 @@ impl Block {
         let k = pos;
         assert!(k < BLOCK_SIZE);
 
-        let i = k / 32;
-        let j = k - i * 32;
+        // Release memory of previous value.
+        self.release(k);
         match *var {
             Variable::Bool(val, _) => {
-                // Reset bits.
-                self.tys[i] &= !(0x3 << (j * 2));
-                // Sets new bits.
-                self.tys[i] |= BOOL << (j * 2);
+                self.set_ty(k, BOOL);
                 self.data[k] = val as u64;
             }
             Variable::F64(val, _) => {
-                // Reset bits.
-                self.tys[i] &= !(0x3 << (j * 2));
-                // Sets new bits.
-                self.tys[i] |= F64 << (j * 2);
-                self.data[k] = unsafe { transmute::<f64, u64>(val) };
+                self.set_ty(k, F64);
+                self.data[k] = val.to_bits();
             }
             Variable::Text(ref s) => {
-                // Reset bits.
-                self.tys[i] &= !(0x3 << (j * 2));
-                // Sets new bits.
-                self.tys[i] |= STR << (j * 2);
+                self.set_ty(k, STR);
                 self.data[k] = unsafe { transmute::<Arc<String>, usize>(s.clone()) as u64 };
             }
-            _ => panic!("Expected `str`, `f64`, `bool`")
+            Variable::Vec4(val) => {
+                self.set_ty(k, VEC4);
+                self.data[k] = Box::into_raw(Box::new(val)) as usize as u64;
+            }
+            ref x => {
+                self.set_ty(k, VAR);
+                self.data[k] = Box::into_raw(Box::new(x.clone())) as usize as u64;
+            }
+        }
+    }
+
+    /// Drops value stored indirectly at slot and marks it as empty.
+    fn release(&mut self, k: usize) {
+        use std::mem::transmute;
+
+        unsafe {
+            match self.ty(k) {
+                STR => drop(transmute::<usize, Arc<String>>(self.data[k] as usize)),
+                VEC4 => drop(Box::from_raw(self.data[k] as usize as *mut [f32; 4])),
+                VAR => drop(Box::from_raw(self.data[k] as usize as *mut Variable)),
+                _ => {}
+            }
         }
+        self.set_ty(k, EMPTY);
     }
 }
 
@@ -88,9 +130,7 @@ impl Clone for Block {
 
         let mut data = self.data;
         for k in 0..BLOCK_SIZE {
-            let i = k / 32;
-            let j = k - i * 32;
-            match self.tys[i] >> (j * 2) & 0x3 {
+            match self.ty(k) {
                 EMPTY => break,
                 STR => {
                     // Arc<String>
@@ -101,6 +141,16 @@ impl Clone for Block {
                             ).clone()) as u64;
                     }
                 }
+                VEC4 => {
+                    // Box<[f32; 4]>
+                    let val = unsafe { *(self.data[k] as usize as *const [f32; 4]) };
+                    data[k] = Box::into_raw(Box::new(val)) as usize as u64;
+                }
+                VAR => {
+                    // Box<Variable>
+                    let val = unsafe { (*(self.data[k] as usize as *const Variable)).clone() };
+                    data[k] = Box::into_raw(Box::new(val)) as usize as u64;
+                }
                 _ => {}
             }
         }
@@ -110,21 +160,9 @@ impl Clone for Block {
 
 impl Drop for Block {
     fn drop(&mut self) {
-        use std::mem::transmute;
-
         for k in 0..BLOCK_SIZE {
-            let i = k / 32;
-            let j = k - i * 32;
-            match self.tys[i] >> (j * 2) & 0x3 {
-                EMPTY => break,
-                STR => {
-                    // Arc<String>
-                    unsafe {
-                        drop(transmute::<usize, Arc<String>>(self.data[k] as usize))
-                    }
-                }
-                _ => {}
-            }
+            if self.ty(k) == EMPTY { break; }
+            self.release(k);
         }
     }
 }
@@ -152,10 +190,34 @@ impl Slice {
     }
 }
 
+/// A writer that receives full blocks of a streaming link.
+#[derive(Clone)]
+pub(crate) struct Sink(Arc<Mutex<Box<dyn io::Write + Send>>>);
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sink")
+    }
+}
+
 /// Stores a link structure.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Link {
     pub(crate) slices: Vec<Slice>,
+    pub(crate) sink: Option<Sink>,
+}
+
+impl Clone for Link {
+    /// Clones the items of the link.
+    ///
+    /// The clone is not backed by a writer,
+    /// such that full blocks are only written once.
+    fn clone(&self) -> Link {
+        Link {
+            slices: self.slices.clone(),
+            sink: None,
+        }
+    }
 }
 
 impl Default for Link {
@@ -166,10 +228,58 @@ impl Link {
     /// Creates a new link.
     pub fn new() -> Link {
         Link {
-            slices: vec![]
+            slices: vec![],
+            sink: None,
+        }
+    }
+
+    /// Creates a new streaming link that is backed by a writer.
+    ///
+    /// Whenever a block is full, the items are written to the writer
+    /// and removed from the link, such that large outputs do not need
+    /// to be held in memory. Call `flush` to write the remaining items.
+    ///
+    /// Only items that are not written yet are visible to e.g. `head` and `tail`.
+    /// Clones of a streaming link are not backed by the writer.
+    ///
+    /// Scripts create streaming links with `stream__file` and call `flush(mut link)`
+    /// when done. Items that are not flushed are lost when the link is dropped.
+    pub fn with_writer<W: io::Write + Send + 'static>(w: W) -> Link {
+        Link {
+            slices: vec![],
+            sink: Some(Sink(Arc::new(Mutex::new(Box::new(w))))),
         }
     }
 
+    /// Returns `true` if the link is backed by a writer.
+    pub fn is_streaming(&self) -> bool { self.sink.is_some() }
+
+    /// Writes the items of the link, in the same format as `str(link)`.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        use write::{write_variable, EscapeString};
+
+        for slice in &self.slices {
+            for i in slice.start..slice.end {
+                // Links contain no references, so there is no stack to resolve against.
+                write_variable(w, &[], &slice.block.var(i), EscapeString::None, 0)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining items of a streaming link to its writer.
+    ///
+    /// Does nothing if the link is not backed by a writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(sink) = self.sink.clone() {
+            let mut w = sink.0.lock().unwrap();
+            self.write_to(&mut *w)?;
+            w.flush()?;
+            self.slices.clear();
+        }
+        Ok(())
+    }
+
     /// Gets the first item of the link.
     pub fn head(&self) -> Option<Box<Variable>> {
         if self.slices.is_empty() { None }
@@ -202,7 +312,7 @@ impl Link {
         else {
             let first = &self.slices[0];
             let mut l = Link::new();
-            // No danger of overflow since `BLOCK_SIZE = 124`.
+            // No danger of overflow since `BLOCK_SIZE = 120`.
             if first.start + 1 < first.end {
                 l.slices.push(first.clone());
                 l.slices[0].start += 1;
@@ -223,7 +333,7 @@ impl Link {
             for slice in self.slices.iter().take(self.slices.len() - 1) {
                 l.slices.push(slice.clone())
             }
-            // No danger of overflow since `BLOCK_SIZE = 124`.
+            // No danger of overflow since `BLOCK_SIZE = 120`.
             if last.start + 1 < last.end {
                 l.slices.push(Slice {
                     block: last.block.clone(),
@@ -239,21 +349,39 @@ impl Link {
     pub fn is_empty(&self) -> bool { self.slices.len() == 0 }
 
     /// Adds another link.
+    ///
+    /// The result is not backed by a writer.
     pub fn add(&self, other: &Link) -> Link {
         let mut slices = Vec::with_capacity(self.slices.len() + other.slices.len());
         slices.extend_from_slice(&self.slices);
         slices.extend_from_slice(&other.slices);
-        Link {slices}
+        Link {slices, sink: None}
+    }
+
+    /// Appends the items of another link.
+    ///
+    /// Unlike `add`, this keeps the writer of a streaming link.
+    pub fn append(&mut self, other: &Link) -> Result<(), String> {
+        for slice in &other.slices {
+            for i in slice.start..slice.end {
+                self.push(&slice.block.var(i))?
+            }
+        }
+        Ok(())
     }
 
     /// Pushes a variable to the link.
+    ///
+    /// Links are flattened, such that the items are pushed one by one.
+    /// References must be resolved before pushing.
     pub fn push(&mut self, v: &Variable) -> Result<(), String> {
         use crate::Variable::*;
 
         match *v {
-            Bool(_, _) |
-            F64(_, _) |
-            Text(_) => {
+            Ref(_) | UnsafeRef(_) | Return =>
+                Err(format!("Can not push `{}` to link", v.typeof_var())),
+            Link(ref link) => self.append(link),
+            _ => {
                 if !self.slices.is_empty() {
                     let last = self.slices.last_mut().unwrap();
                     if (last.end as usize) < BLOCK_SIZE {
@@ -263,21 +391,21 @@ impl Link {
                     }
                 }
 
+                // Write full blocks of streaming link.
+                if let Some(sink) = self.sink.clone() {
+                    let mut w = sink.0.lock().unwrap();
+                    if let Err(err) = self.write_to(&mut *w) {
+                        return Err(format!("Could not write link:\n{}", err));
+                    }
+                    self.slices.clear();
+                }
+
                 self.slices.push(Slice::new());
                 let last = self.slices.last_mut().unwrap();
                 Arc::make_mut(&mut last.block).push(v, 0);
                 last.end = 1;
                 Ok(())
             }
-            Link(ref link) => {
-                for slice in &link.slices {
-                    for i in slice.start..slice.end {
-                        self.push(&slice.block.var(i))?
-                    }
-                }
-                Ok(())
-            }
-            _ => Err("Expected `bool`, `f64` or `str`".into())
         }
     }
 }
@@ -0,0 +1,31 @@
+/*
+Optimizes loaded functions by folding constant expressions.
+
+This runs after converting meta data to AST and resolving locals.
+The same pass as for `grab` expressions is used, with no known arguments,
+such that constant subexpressions are evaluated once at load time.
+Calls are never evaluated, since they might have side effects.
+Folded expressions keep their source range for error messages.
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use grab::{self, Grabbed, Specialize};
+use runtime::{Flow, Runtime, Side};
+use Module;
+
+/// Optimizes functions loaded from index `start`.
+pub fn optimize(module: &mut Module, start: usize) {
+    let mut rt = Runtime::new();
+    // Constant expressions do not look up functions, so an empty module is used.
+    let empty = Arc::new(Module::new_intrinsics(Arc::new(HashMap::new())));
+    for f in &mut module.functions[start..] {
+        let mut spec = Some(Specialize {known: vec![], fixed: vec![], scan: false});
+        if let Ok((Grabbed::Block(block), Flow::Continue)) =
+            grab::grab_block(0, &mut spec, &mut rt, &f.block, Side::Right, &empty)
+        {
+            f.block = block;
+        }
+    }
+}
@@ -129,6 +129,7 @@ impl Runtime {
         let iter_val = iter_val!(iter, self, for_in_expr, module);
 
         let mut sum = 0.0;
+        let mut sec = None;
 
         // Initialize counter.
         self.local_stack.push((for_in_expr.name.clone(), self.stack.len()));
@@ -141,7 +142,14 @@ impl Runtime {
             match self.block(&for_in_expr.block, module)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => sum += val,
+                        &Variable::F64(val, ref val_sec) => {
+                            sum += val;
+                            // Keep the explanation of the first secret.
+                            if sec.is_none() && val_sec.is_some() {
+                                sec = explain_loop(val_sec, &for_in_expr.name,
+                                                   self.stack[st - 1].clone());
+                            }
+                        }
                         x => return Err(module.error(for_in_expr.block.source_range,
                                 &self.expected(x, "number"), self))
                     };
@@ -158,7 +166,7 @@ impl Runtime {
         };
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(sum)), flow))
+        Ok((Some(Variable::F64(sum, sec)), flow))
     }
 
     pub(crate) fn prod_in_expr(
@@ -175,6 +183,7 @@ impl Runtime {
         let iter_val = iter_val!(iter, self, for_in_expr, module);
 
         let mut prod = 1.0;
+        let mut sec = None;
 
         // Initialize counter.
         self.local_stack.push((for_in_expr.name.clone(), self.stack.len()));
@@ -187,7 +196,14 @@ impl Runtime {
             match self.block(&for_in_expr.block, module)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => prod *= val,
+                        &Variable::F64(val, ref val_sec) => {
+                            prod *= val;
+                            // Keep the explanation of the first secret.
+                            if sec.is_none() && val_sec.is_some() {
+                                sec = explain_loop(val_sec, &for_in_expr.name,
+                                                   self.stack[st - 1].clone());
+                            }
+                        }
                         x => return Err(module.error(for_in_expr.block.source_range,
                                 &self.expected(x, "number"), self))
                     };
@@ -204,7 +220,7 @@ impl Runtime {
         };
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(prod)), flow))
+        Ok((Some(Variable::F64(prod, sec)), flow))
     }
 
     pub(crate) fn min_in_expr(
@@ -235,16 +251,7 @@ impl Runtime {
                         &Variable::F64(val, ref val_sec) => {
                             if min.is_nan() || min > val {
                                 min = val;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![self.stack[st - 1].clone()]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(self.stack[st - 1].clone());
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_in_expr.name, self.stack[st - 1].clone());
                             }
                         },
                         x => return Err(module.error(for_in_expr.block.source_range,
@@ -297,16 +304,7 @@ impl Runtime {
                         &Variable::F64(val, ref val_sec) => {
                             if max.is_nan() || max < val {
                                 max = val;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![self.stack[st - 1].clone()]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(self.stack[st - 1].clone());
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_in_expr.name, self.stack[st - 1].clone());
                             }
                         },
                         x => return Err(module.error(for_in_expr.block.source_range,
@@ -360,16 +358,7 @@ impl Runtime {
                         &Variable::Bool(val, ref val_sec) => {
                             if val {
                                 any = true;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![self.stack[st - 1].clone()]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(self.stack[st - 1].clone());
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_in_expr.name, self.stack[st - 1].clone());
                                 break;
                             }
                         },
@@ -424,16 +413,7 @@ impl Runtime {
                         &Variable::Bool(val, ref val_sec) => {
                             if !val {
                                 all = false;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![self.stack[st - 1].clone()]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(self.stack[st - 1].clone());
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_in_expr.name, self.stack[st - 1].clone());
                                 break;
                             }
                         },
@@ -616,7 +596,10 @@ impl Runtime {
         let mut flow = Flow::Continue;
         loop {
             match self.block(&for_in_expr.block, module)? {
-                (Some(x), Flow::Continue) => res.push(x),
+                (Some(x), Flow::Continue) => {
+                    let x = explain_loop_item(x, &for_in_expr.name, &self.stack[st - 1]);
+                    res.push(x)
+                }
                 (x, Flow::Return) => { return Ok((x, Flow::Return)); }
                 (None, Flow::Continue) => {
                     return Err(module.error(for_in_expr.block.source_range,
@@ -150,6 +150,7 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
         let mut sum = 0.0;
+        let mut sec = None;
 
         let start = start!(self, for_n_expr, module);
         let end = end!(self, for_n_expr, module);
@@ -162,11 +163,17 @@ impl Runtime {
         let lc = self.local_stack.len();
         let mut flow = Flow::Continue;
         loop {
-            cond!(self, for_n_expr, st, end, module);
+            let ind = cond!(self, for_n_expr, st, end, module);
             match self.block(&for_n_expr.block, module)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => sum += val,
+                        &Variable::F64(val, ref val_sec) => {
+                            sum += val;
+                            // Keep the explanation of the first secret.
+                            if sec.is_none() && val_sec.is_some() {
+                                sec = explain_loop(val_sec, &for_n_expr.name, Variable::f64(ind));
+                            }
+                        }
                         x => return Err(module.error(for_n_expr.block.source_range,
                                 &self.expected(x, "number"), self))
                     };
@@ -185,7 +192,7 @@ impl Runtime {
         };
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(sum)), flow))
+        Ok((Some(Variable::F64(sum, sec)), flow))
     }
 
     pub(crate) fn prod_n_expr(
@@ -196,6 +203,7 @@ impl Runtime {
         let prev_st = self.stack.len();
         let prev_lc = self.local_stack.len();
         let mut prod = 1.0;
+        let mut sec = None;
 
         let start = start!(self, for_n_expr, module);
         let end = end!(self, for_n_expr, module);
@@ -208,11 +216,17 @@ impl Runtime {
         let lc = self.local_stack.len();
         let mut flow = Flow::Continue;
         loop {
-            cond!(self, for_n_expr, st, end, module);
+            let ind = cond!(self, for_n_expr, st, end, module);
             match self.block(&for_n_expr.block, module)? {
                 (Some(x), Flow::Continue) => {
                     match self.resolve(&x) {
-                        &Variable::F64(val, _) => prod *= val,
+                        &Variable::F64(val, ref val_sec) => {
+                            prod *= val;
+                            // Keep the explanation of the first secret.
+                            if sec.is_none() && val_sec.is_some() {
+                                sec = explain_loop(val_sec, &for_n_expr.name, Variable::f64(ind));
+                            }
+                        }
                         x => return Err(module.error(for_n_expr.block.source_range,
                                 &self.expected(x, "number"), self))
                     };
@@ -231,7 +245,7 @@ impl Runtime {
         };
         self.stack.truncate(prev_st);
         self.local_stack.truncate(prev_lc);
-        Ok((Some(Variable::f64(prod)), flow))
+        Ok((Some(Variable::F64(prod, sec)), flow))
     }
 
     pub(crate) fn min_n_expr(
@@ -261,16 +275,7 @@ impl Runtime {
                         &Variable::F64(val, ref val_sec) => {
                             if min.is_nan() || min > val {
                                 min = val;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![Variable::f64(ind)]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(Variable::f64(ind));
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_n_expr.name, Variable::f64(ind));
                             }
                         },
                         x => return Err(module.error(for_n_expr.block.source_range,
@@ -322,16 +327,7 @@ impl Runtime {
                         &Variable::F64(val, ref val_sec) => {
                             if max.is_nan() || max < val {
                                 max = val;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![Variable::f64(ind)]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(Variable::f64(ind));
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_n_expr.name, Variable::f64(ind));
                             }
                         },
                         x => return Err(module.error(for_n_expr.block.source_range,
@@ -383,16 +379,7 @@ impl Runtime {
                         &Variable::Bool(val, ref val_sec) => {
                             if val {
                                 any = true;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![Variable::f64(ind)]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(Variable::f64(ind));
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_n_expr.name, Variable::f64(ind));
                                 break;
                             }
                         },
@@ -445,16 +432,7 @@ impl Runtime {
                         &Variable::Bool(val, ref val_sec) => {
                             if !val {
                                 all = false;
-                                sec = match *val_sec {
-                                    None => {
-                                        Some(Box::new(vec![Variable::f64(ind)]))
-                                    }
-                                    Some(ref arr) => {
-                                        let mut arr = arr.clone();
-                                        arr.push(Variable::f64(ind));
-                                        Some(arr)
-                                    }
-                                };
+                                sec = explain_loop(val_sec, &for_n_expr.name, Variable::f64(ind));
                                 break;
                             }
                         },
@@ -611,9 +589,11 @@ impl Runtime {
         let lc = self.local_stack.len();
         let mut flow = Flow::Continue;
         loop {
-            cond!(self, for_n_expr, st, end, module);
+            let ind = cond!(self, for_n_expr, st, end, module);
             match self.block(&for_n_expr.block, module)? {
-                (Some(x), Flow::Continue) => res.push(x),
+                (Some(x), Flow::Continue) => {
+                    res.push(explain_loop_item(x, &for_n_expr.name, &Variable::f64(ind)))
+                }
                 (x, Flow::Return) => { return Ok((x, Flow::Return)); }
                 (None, Flow::Continue) => {
                     return Err(module.error(for_n_expr.block.source_range,
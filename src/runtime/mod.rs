@@ -12,12 +12,71 @@ use embed;
 use FnIndex;
 use Module;
 use Variable;
+use Type;
 use UnsafeRef;
 use TINVOTS;
 
 mod for_n;
 mod for_in;
 
+/// Creates a loop step in the explanation of a secret.
+///
+/// Loop steps are stored as `[return, name, value]`.
+/// Scripts can not create `return` values, such that loop steps
+/// are never confused with values added by `explain_why` or `explain_where`.
+pub(crate) fn loop_step(name: &Arc<String>, val: Variable) -> Variable {
+    Variable::Array(Arc::new(vec![Variable::Return, Variable::Text(name.clone()), val]))
+}
+
+/// Gets the loop name and value of a loop step, or `None` if it is an explanation.
+pub(crate) fn as_loop_step(v: &Variable) -> Option<(&Arc<String>, &Variable)> {
+    if let Variable::Array(ref arr) = *v {
+        if arr.len() == 3 {
+            if let (&Variable::Return, &Variable::Text(ref name)) = (&arr[0], &arr[1]) {
+                return Some((name, &arr[2]));
+            }
+        }
+    }
+    None
+}
+
+/// Adds a loop step to the explanation of a secret.
+pub(crate) fn explain_loop(
+    sec: &Option<Box<Vec<Variable>>>,
+    name: &Arc<String>,
+    val: Variable
+) -> Option<Box<Vec<Variable>>> {
+    let mut arr = match *sec {
+        None => Box::new(vec![]),
+        Some(ref arr) => arr.clone()
+    };
+    arr.push(loop_step(name, val));
+    Some(arr)
+}
+
+/// Adds a loop step to the explanation of an item, if the item is a secret.
+pub(crate) fn explain_loop_item(v: Variable, name: &Arc<String>, val: &Variable) -> Variable {
+    match v {
+        Variable::Bool(x, Some(mut sec)) => {
+            sec.push(loop_step(name, val.clone()));
+            Variable::Bool(x, Some(sec))
+        }
+        Variable::F64(x, Some(mut sec)) => {
+            sec.push(loop_step(name, val.clone()));
+            Variable::F64(x, Some(sec))
+        }
+        x => x
+    }
+}
+
+/// Picks the secret of the left argument, or the right one if the left has none.
+fn either_secret(
+    a: &Option<Box<Vec<Variable>>>,
+    b: &Option<Box<Vec<Variable>>>
+) -> Option<Box<Vec<Variable>>> {
+    if a.is_some() { a.clone() } else { b.clone() }
+}
+
 /// Which side an expression is evaluated.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Side {
@@ -59,6 +118,7 @@ lazy_static! {
     pub(crate) static ref TEXT_TYPE: Arc<String> = Arc::new("string".into());
     pub(crate) static ref F64_TYPE: Arc<String> = Arc::new("number".into());
     pub(crate) static ref VEC4_TYPE: Arc<String> = Arc::new("vec4".into());
+    pub(crate) static ref VECN_TYPE: Arc<String> = Arc::new("vecn".into());
     pub(crate) static ref MAT4_TYPE: Arc<String> = Arc::new("mat4".into());
     pub(crate) static ref RETURN_TYPE: Arc<String> = Arc::new("return".into());
     pub(crate) static ref BOOL_TYPE: Arc<String> = Arc::new("boolean".into());
@@ -73,6 +133,8 @@ lazy_static! {
     pub(crate) static ref THREAD_TYPE: Arc<String> = Arc::new("thread".into());
     pub(crate) static ref CLOSURE_TYPE: Arc<String> = Arc::new("closure".into());
     pub(crate) static ref IN_TYPE: Arc<String> = Arc::new("in".into());
+    pub(crate) static ref ENUM_KEY: Arc<String> = Arc::new(ast::ENUM_KEY.into());
+    pub(crate) static ref VARIANT_KEY: Arc<String> = Arc::new(ast::VARIANT_KEY.into());
 }
 
 /// Stores data needed for running a Dyon program.
@@ -89,7 +151,7 @@ pub struct Runtime {
     /// until it finds the last current variable with the name.
     pub current_stack: Vec<(Arc<String>, usize)>,
     ret: Arc<String>,
-    pub(crate) rng: rand::rngs::StdRng,
+    pub(crate) rng: rand_hc::Hc128Rng,
 }
 
 impl Default for Runtime {
@@ -303,8 +365,26 @@ impl Runtime {
             local_stack: vec![],
             current_stack: vec![],
             ret: Arc::new("return".into()),
-            rng: rand::rngs::StdRng::from_entropy(),
+            rng: rand_hc::Hc128Rng::from_entropy(),
+        }
+    }
+
+    /// Sets seed of random number generator.
+    ///
+    /// The generator is HC-128, keyed with the little endian bytes of the seed
+    /// and a zero IV, such that the same seed gives the same sequence of random numbers
+    /// across runs and platforms for this version of Dyon.
+    /// The sequence might change with a new version of Dyon,
+    /// since the conversions to numbers, ranges, shuffling and normal distribution
+    /// are done by the `rand` crate and might change between its releases.
+    pub fn set_seed(&mut self, seed: u64) {
+        use rand::SeedableRng;
+
+        let mut key = [0; 32];
+        for (i, b) in key.iter_mut().take(8).enumerate() {
+            *b = (seed >> (8 * i)) as u8;
         }
+        self.rng = rand_hc::Hc128Rng::from_seed(key);
     }
 
     /// Pops variable from stack.
@@ -512,6 +592,7 @@ impl Runtime {
             LinkFor(ref for_n_expr) => self.link_for_n_expr(for_n_expr, module),
             LinkIn(ref for_in_expr) => self.link_for_in_expr(for_in_expr, module),
             If(ref if_expr) => self.if_expr(if_expr, module),
+            Match(ref match_expr) => self.match_expr(match_expr, module),
             Compare(ref compare) => self.compare(compare, module),
             Variable(ref range_var) => Ok((Some(range_var.1.clone()), Flow::Continue)),
             Try(ref expr) => self.try(expr, side, module),
@@ -595,7 +676,9 @@ impl Runtime {
         // Create closure.
         let relative = self.call_stack.last().map(|c| c.index).unwrap_or(0);
         // Evaluate `grab` expressions and generate new AST.
-        let new_expr = match grab::grab_expr(1, self, &closure.expr, Side::Right, module)? {
+        let new_expr = match grab::grab_expr(
+            1, &mut None, self, &closure.expr, Side::Right, module
+        )? {
             (Grabbed::Expression(x), Flow::Continue) => x,
             (Grabbed::Variable(x), Flow::Return) => { return Ok((x, Flow::Return)); }
             _ => return Err(module.error(closure.expr.source_range(),
@@ -611,10 +694,7 @@ impl Runtime {
             file: closure.file.clone(),
             source: closure.source.clone(),
             expr: new_expr,
-        }), Box::new(ClosureEnvironment {
-            module: module.clone(),
-            relative
-        }))), Flow::Continue))
+        }), Box::new(ClosureEnvironment::new(module.clone(), relative))), Flow::Continue))
     }
 
     fn try_msg(v: &Variable) -> Option<Result<Box<Variable>, Box<::Error>>> {
@@ -842,7 +922,9 @@ impl Runtime {
                 local_len: 0,
                 current_len: 0,
             }],
-            rng: self.rng.clone(),
+            // Each thread gets its own stream, derived from the current one.
+            rng: rand::SeedableRng::from_rng(&mut self.rng)
+                .expect("Could not derive random number generator"),
             ret: self.ret.clone(),
         };
         let new_module = module.clone();
@@ -911,6 +993,11 @@ impl Runtime {
                                 self.stack_trace()), self))
             };
         }
+        // Insert known arguments of specialized closure into their slots.
+        // This is done after evaluating the arguments to keep their stack offsets.
+        for &(i, _, ref v) in &env.known {
+            self.stack.insert(st + i, v.clone());
+        }
 
         // Look for variable in current stack.
         if !f.currents.is_empty() {
@@ -937,9 +1024,18 @@ impl Runtime {
         if f.returns() {
             self.local_stack.push((self.ret.clone(), st - 1));
         }
-        for (i, arg) in f.args.iter().enumerate() {
+        let mut args = f.args.iter();
+        let mut known = env.known.iter().peekable();
+        for i in 0..f.args.len() + env.known.len() {
+            let name = match known.peek() {
+                Some(&&(slot, ref name, _)) if slot == i => {
+                    known.next();
+                    name.clone()
+                }
+                _ => args.next().expect("Expected argument").name.clone()
+            };
             // Do not resolve locals to keep fixed length from end of stack.
-            self.local_stack.push((arg.name.clone(), st + i));
+            self.local_stack.push((name, st + i));
         }
         let (x, flow) = self.expression(&f.expr, Side::Right, &env.module)?;
         match flow {
@@ -1308,17 +1404,30 @@ impl Runtime {
                                 self.stack_trace()), self))
         };
         let v = match self.resolve(&v) {
-            &Variable::Vec4(v) => v,
+            &Variable::Vec4(v) => [f64::from(v[0]), f64::from(v[1]),
+                                   f64::from(v[2]), f64::from(v[3])],
+            &Variable::VecN(ref v) => {
+                let max = [sw.sw2, sw.sw3].iter().filter_map(|&ind| ind)
+                    .fold(sw.sw0.max(sw.sw1), |a, b| a.max(b));
+                if max >= v.len() {
+                    return Err(module.error(sw.source_range,
+                        &format!("{}\nSwizzle component {} is out of bounds for `vecn` \
+                                  with length {}", self.stack_trace(), max, v.len()), self))
+                }
+                let mut res = [0.0; 4];
+                for (r, &x) in res.iter_mut().zip(v.iter()) {*r = x}
+                res
+            }
             x => return Err(module.error(sw.source_range,
-                    &self.expected(x, "vec4"), self))
+                    &self.expected(x, "vec4 or vecn"), self))
         };
-        self.stack.push(Variable::f64(f64::from(v[sw.sw0])));
-        self.stack.push(Variable::f64(f64::from(v[sw.sw1])));
+        self.stack.push(Variable::f64(v[sw.sw0]));
+        self.stack.push(Variable::f64(v[sw.sw1]));
         if let Some(ind) = sw.sw2 {
-            self.stack.push(Variable::f64(f64::from(v[ind])));
+            self.stack.push(Variable::f64(v[ind]));
         }
         if let Some(ind) = sw.sw3 {
-            self.stack.push(Variable::f64(f64::from(v[ind])));
+            self.stack.push(Variable::f64(v[ind]));
         }
         Ok(Flow::Continue)
     }
@@ -1514,6 +1623,19 @@ impl Runtime {
                                                 self.stack_trace()), self))
                                 }
                             }
+                            Variable::VecN(ref mut n) => {
+                                match vecn_assign_op(op) {
+                                    Some(op) => {
+                                        for x in Arc::make_mut(n).iter_mut() {
+                                            *x = vecn_op(op, *x, b);
+                                        }
+                                    }
+                                    None => return Err(module.error(
+                                            left.source_range(),
+                                            &format!("{}\nExpected assigning to a number",
+                                                self.stack_trace()), self))
+                                }
+                            }
                             Variable::Return => {
                                 if let Set = op {
                                     *r.0 = Variable::F64(b, sec.clone())
@@ -1573,6 +1695,17 @@ impl Runtime {
                                             self.stack_trace()), self))
                                 }
                             }
+                            Variable::Link(ref mut n) => {
+                                if let Add = op {
+                                    n.push(&Variable::Vec4(b))?;
+                                } else {
+                                    return Err(module.error(
+                                        left.source_range(),
+                                        &format!("{}\nCan not use this assignment \
+                                        operator with `link` and `vec4`",
+                                            self.stack_trace()), self));
+                                }
+                            }
                             _ => return Err(module.error(
                                     left.source_range(),
                                     &format!("{}\nExpected assigning to a vec4",
@@ -1580,6 +1713,53 @@ impl Runtime {
                         };
                     }
                 }
+                Variable::VecN(ref b) => {
+                    unsafe {
+                        match *r.0 {
+                            Variable::VecN(ref mut n) => {
+                                if let Set = op {
+                                    *n = b.clone();
+                                } else if let Some(op) = vecn_assign_op(op) {
+                                    if n.len() != b.len() {
+                                        return Err(module.error(
+                                            left.source_range(),
+                                            &format!("{}\nExpected `vecn` of same length, \
+                                            found {} and {}",
+                                                self.stack_trace(), n.len(), b.len()), self));
+                                    }
+                                    for (x, &b) in Arc::make_mut(n).iter_mut().zip(b.iter()) {
+                                        *x = vecn_op(op, *x, b);
+                                    }
+                                }
+                            }
+                            Variable::Return => {
+                                if let Set = op {
+                                    *r.0 = Variable::VecN(b.clone())
+                                } else {
+                                    return Err(module.error(
+                                        left.source_range(),
+                                        &format!("{}\nReturn has no value",
+                                            self.stack_trace()), self))
+                                }
+                            }
+                            Variable::Link(ref mut n) => {
+                                if let Add = op {
+                                    n.push(&Variable::VecN(b.clone()))?;
+                                } else {
+                                    return Err(module.error(
+                                        left.source_range(),
+                                        &format!("{}\nCan not use this assignment \
+                                        operator with `link` and `vecn`",
+                                            self.stack_trace()), self));
+                                }
+                            }
+                            _ => return Err(module.error(
+                                    left.source_range(),
+                                    &format!("{}\nExpected assigning to a vecn",
+                                        self.stack_trace()), self))
+                        };
+                    }
+                }
                 Variable::Mat4(ref b) => {
                     unsafe {
                         match *r.0 {
@@ -1725,6 +1905,17 @@ impl Runtime {
                                             self.stack_trace()), self))
                                 }
                             }
+                            Variable::Link(ref mut n) => {
+                                if let Add = op {
+                                    n.push(&Variable::Object(b.clone()))?;
+                                } else {
+                                    return Err(module.error(
+                                        left.source_range(),
+                                        &format!("{}\nCan not use this assignment \
+                                        operator with `link` and `object`",
+                                            self.stack_trace()), self));
+                                }
+                            }
                             _ => return Err(module.error(
                                 left.source_range(),
                                 &format!("{}\nExpected assigning to object",
@@ -1752,6 +1943,17 @@ impl Runtime {
                                             self.stack_trace()), self))
                                 }
                             }
+                            Variable::Link(ref mut n) => {
+                                if let Add = op {
+                                    n.push(&Variable::Array(b.clone()))?;
+                                } else {
+                                    return Err(module.error(
+                                        left.source_range(),
+                                        &format!("{}\nCan not use this assignment \
+                                        operator with `link` and `array`",
+                                            self.stack_trace()), self));
+                                }
+                            }
                             _ => return Err(module.error(
                                 left.source_range(),
                                 &format!("{}\nExpected assigning to array",
@@ -1765,8 +1967,13 @@ impl Runtime {
                             Variable::Link(ref mut n) => {
                                 match op {
                                     Set => *n = b.clone(),
-                                    Add => **n = n.add(b),
-                                    Sub => **n = b.add(n),
+                                    // Append in place such that streaming links keep their writer.
+                                    Add => n.append(b)?,
+                                    Sub => {
+                                        let sink = n.sink.clone();
+                                        **n = b.add(n);
+                                        n.sink = sink;
+                                    }
                                     _ => unimplemented!()
                                 }
                             }
@@ -2265,7 +2472,7 @@ impl Runtime {
             use ast::CompareOp::*;
 
             match (rt.resolve(&b), rt.resolve(&a)) {
-                (&Variable::F64(b, _), &Variable::F64(a, ref sec)) => {
+                (&Variable::F64(b, ref b_sec), &Variable::F64(a, ref sec)) => {
                     Ok(Variable::Bool(match compare.op {
                         Less => a < b,
                         LessOrEqual => a <= b,
@@ -2273,7 +2480,7 @@ impl Runtime {
                         GreaterOrEqual => a >= b,
                         Equal => a == b,
                         NotEqual => a != b
-                    }, sec.clone()))
+                    }, either_secret(sec, b_sec)))
                 }
                 (&Variable::Text(ref b), &Variable::Text(ref a)) => {
                     Ok(Variable::bool(match compare.op {
@@ -2285,7 +2492,7 @@ impl Runtime {
                         NotEqual => a != b
                     }))
                 }
-                (&Variable::Bool(b, _), &Variable::Bool(a, ref sec)) => {
+                (&Variable::Bool(b, ref b_sec), &Variable::Bool(a, ref sec)) => {
                     Ok(Variable::Bool(match compare.op {
                         Equal => a == b,
                         NotEqual => a != b,
@@ -2293,7 +2500,7 @@ impl Runtime {
                             &format!("{}\n`{}` can not be used with bools",
                                 rt.stack_trace(),
                                 x.symbol()), rt))
-                    }, sec.clone()))
+                    }, either_secret(sec, b_sec)))
                 }
                 (&Variable::Vec4(ref b), &Variable::Vec4(ref a)) => {
                     Ok(Variable::bool(match compare.op {
@@ -2305,6 +2512,16 @@ impl Runtime {
                                 x.symbol()), rt))
                     }))
                 }
+                (&Variable::VecN(ref b), &Variable::VecN(ref a)) => {
+                    Ok(Variable::bool(match compare.op {
+                        Equal => a == b,
+                        NotEqual => a != b,
+                        x => return Err(module.error(compare.source_range,
+                            &format!("{}\n`{}` can not be used with vecns",
+                                rt.stack_trace(),
+                                x.symbol()), rt))
+                    }))
+                }
                 (&Variable::Object(ref b), &Variable::Object(ref a)) => {
                     Ok(Variable::bool(match compare.op {
                         Equal => {
@@ -2463,6 +2680,174 @@ impl Runtime {
             Ok((None, Flow::Continue))
         }
     }
+    fn match_expr(
+        &mut self,
+        match_expr: &ast::Match,
+        module: &Arc<Module>
+    ) -> Result<(Option<Variable>, Flow), String> {
+        let val = match self.expression(&match_expr.expr, Side::Right, module)? {
+            (Some(x), Flow::Continue) => x,
+            (x, Flow::Return) => { return Ok((x, Flow::Return)); }
+            _ => return Err(module.error(match_expr.expr.source_range(),
+                &format!("{}\nExpected something to match",
+                    self.stack_trace()), self))
+        };
+        let val = self.resolve(&val).clone();
+        let st = self.stack.len();
+        let lc = self.local_stack.len();
+        for arm in &match_expr.arms {
+            if self.match_pattern(&arm.pattern, &val, module)? {
+                let chosen = if let Some(ref guard) = arm.guard {
+                    let cond = match self.expression(guard, Side::Right, module)? {
+                        (Some(x), Flow::Continue) => x,
+                        (x, Flow::Return) => {
+                            self.stack.truncate(st);
+                            self.local_stack.truncate(lc);
+                            return Ok((x, Flow::Return));
+                        }
+                        _ => return Err(module.error(guard.source_range(),
+                            &format!("{}\nExpected bool from match guard",
+                                self.stack_trace()), self))
+                    };
+                    match *self.resolve(&cond) {
+                        Variable::Bool(val, _) => val,
+                        _ => return Err(module.error(guard.source_range(),
+                            &format!("{}\nExpected bool from match guard",
+                                self.stack_trace()), self))
+                    }
+                } else {
+                    true
+                };
+                if chosen {
+                    let (x, flow) = self.expression(&arm.expr, Side::Right, module)?;
+                    // Bound variables are removed from the stack,
+                    // so a reference to one of them must be replaced by its value.
+                    let x = match x {
+                        Some(Variable::Ref(ind)) if ind >= st => Some(self.stack[ind].clone()),
+                        x => x
+                    };
+                    self.stack.truncate(st);
+                    self.local_stack.truncate(lc);
+                    return Ok((x, flow));
+                }
+            }
+            self.stack.truncate(st);
+            self.local_stack.truncate(lc);
+        }
+        Err(module.error(match_expr.source_range,
+            &format!("{}\nNo match arm for value of type `{}`",
+                self.stack_trace(), val.typeof_var()), self))
+    }
+    /// Matches a value against a pattern.
+    ///
+    /// Bound variables are pushed on the stack in the order they appear.
+    /// Returns `false` when the pattern does not match,
+    /// in which case the caller is responsible for truncating the stack.
+    fn match_pattern(
+        &mut self,
+        pattern: &ast::Pattern,
+        val: &Variable,
+        module: &Arc<Module>
+    ) -> Result<bool, String> {
+        use ast::Pattern as P;
+
+        let val = match *val {
+            Variable::Ref(ind) => self.stack[ind].clone(),
+            ref x => x.clone()
+        };
+        Ok(match *pattern {
+            P::Wildcard(_) => true,
+            P::Bind(ref name, _) => {
+                self.local_stack.push((name.clone(), self.stack.len()));
+                self.stack.push(val);
+                true
+            }
+            P::Value(ref v, _) => match (v, &val) {
+                (&Variable::Text(ref a), &Variable::Text(ref b)) => a == b,
+                (&Variable::F64(a, _), &Variable::F64(b, _)) => a == b,
+                (&Variable::Bool(a, _), &Variable::Bool(b, _)) => a == b,
+                _ => false
+            },
+            P::Array(ref items, _) => {
+                let arr = match val {
+                    Variable::Array(ref arr) if arr.len() == items.len() => arr.clone(),
+                    _ => return Ok(false)
+                };
+                for (item, v) in items.iter().zip(arr.iter()) {
+                    if !self.match_pattern(item, v, module)? { return Ok(false) }
+                }
+                true
+            }
+            P::Vec4(ref items, _) => {
+                let v = match val {
+                    Variable::Vec4(v) => v,
+                    _ => return Ok(false)
+                };
+                for (item, &x) in items.iter().zip(v.iter()) {
+                    let matched = match *item {
+                        // Compare literals with the precision of the components.
+                        P::Value(Variable::F64(a, _), _) => a as f32 == x,
+                        _ => self.match_pattern(item, &Variable::f64(f64::from(x)), module)?
+                    };
+                    if !matched { return Ok(false) }
+                }
+                true
+            }
+            P::Variant(ref variant) => {
+                let inner = match (&**variant.name, &val) {
+                    ("some", &Variable::Option(Some(ref v))) => Some((**v).clone()),
+                    ("ok", &Variable::Result(Ok(ref v))) => Some((**v).clone()),
+                    ("err", &Variable::Result(Err(ref err))) => Some(err.message.clone()),
+                    ("none", &Variable::Option(None)) => None,
+                    ("some", _) | ("ok", _) | ("err", _) | ("none", _) => return Ok(false),
+                    (_, &Variable::Object(_)) => return self.match_variant(variant, &val, module),
+                    _ => return Ok(false)
+                };
+                match (inner, variant.args.get(0)) {
+                    (Some(ref inner), Some(arg)) => self.match_pattern(arg, inner, module)?,
+                    _ => true
+                }
+            }
+        })
+    }
+    /// Matches an object created by an enum variant constructor.
+    fn match_variant(
+        &mut self,
+        variant: &ast::VariantPattern,
+        val: &Variable,
+        module: &Arc<Module>
+    ) -> Result<bool, String> {
+        let obj = match *val {
+            Variable::Object(ref obj) => obj.clone(),
+            _ => return Ok(false)
+        };
+        match obj.get(&*VARIANT_KEY) {
+            Some(&Variable::Text(ref name)) if name == &variant.name => {}
+            _ => return Ok(false)
+        }
+        let new_index = match variant.f_index.get() {
+            FnIndex::Loaded(f_index) => {
+                let relative = self.call_stack.last().map(|c| c.index).unwrap_or(0);
+                (f_index + relative as isize) as usize
+            }
+            _ => return Err(module.error(variant.source_range,
+                &format!("{}\nCould not find variant `{}`",
+                    self.stack_trace(), variant.name), self))
+        };
+        let f = &module.functions[new_index];
+        // Check that the object was created by the same enum, not just a variant with that name.
+        match (obj.get(&*ENUM_KEY), &f.ret) {
+            (Some(&Variable::Text(ref a)), &Type::AdHoc(ref b, _)) if a == b => {}
+            _ => return Ok(false)
+        }
+        for (arg, pattern) in f.args.iter().zip(variant.args.iter()) {
+            match obj.get(&arg.name) {
+                Some(v) => if !self.match_pattern(pattern, v, module)? { return Ok(false) },
+                None => return Ok(false)
+            }
+        }
+        Ok(true)
+    }
     fn for_expr(
         &mut self,
         for_expr: &ast::For,
@@ -2656,8 +3041,11 @@ impl Runtime {
             Variable::Vec4(b) => {
                 Variable::f64(f64::from(b[0] * b[0] + b[1] * b[1] + b[2] * b[2]).sqrt())
             }
+            Variable::VecN(ref b) => {
+                Variable::f64(b.iter().map(|x| x * x).sum::<f64>().sqrt())
+            }
             ref x => return Err(module.error(norm.source_range,
-                &self.expected(x, "vec4"), self))
+                &self.expected(x, "vec4 or vecn"), self))
         };
         Ok((Some(v), Flow::Continue))
     }
@@ -2699,6 +3087,14 @@ impl Runtime {
                                              self.stack_trace()), self))
                 })
             }
+            Variable::VecN(ref v) => {
+                Variable::VecN(match unop.op {
+                    ast::UnOp::Neg => Arc::new(v.iter().map(|x| -x).collect()),
+                    _ => return Err(module.error(unop.source_range,
+                                    &format!("{}\nUnknown vecn unary operator",
+                                             self.stack_trace()), self))
+                })
+            }
             Variable::Mat4(ref m) => {
                 match unop.op {
                     ast::UnOp::Neg => Variable::Mat4(Box::new([
@@ -2713,7 +3109,7 @@ impl Runtime {
                 }
             }
             _ => return Err(module.error(unop.source_range,
-                &format!("{}\nInvalid type for unary operator, expected bool, f64, vec4 or mat4", self.stack_trace()), self))
+                &format!("{}\nInvalid type for unary operator, expected bool, f64, vec4, vecn or mat4", self.stack_trace()), self))
         };
         Ok((Some(v), Flow::Continue))
     }
@@ -2756,7 +3152,7 @@ impl Runtime {
                     self.stack_trace()), self))
         };
         let v = match (self.resolve(&left), self.resolve(&right)) {
-            (&Variable::F64(a, ref sec), &Variable::F64(b, _)) => {
+            (&Variable::F64(a, ref sec), &Variable::F64(b, ref b_sec)) => {
                 Variable::F64(match binop.op {
                     Add => a + b,
                     Sub => a - b,
@@ -2768,7 +3164,7 @@ impl Runtime {
                         &format!("{}\nUnknown number operator `{:?}`",
                             self.stack_trace(),
                             binop.op.symbol()), self))
-                }, sec.clone())
+                }, either_secret(sec, b_sec))
             }
             (&Variable::Vec4(a), &Variable::Vec4(b)) => {
                 match binop.op {
@@ -2830,6 +3226,42 @@ impl Runtime {
                             binop.op.symbol_bool()), self)),
                 }
             }
+            (&Variable::VecN(ref a), &Variable::VecN(ref b)) => {
+                if a.len() != b.len() {
+                    return Err(module.error(binop.source_range,
+                        &format!("{}\nExpected `vecn` of same length, found {} and {}",
+                            self.stack_trace(), a.len(), b.len()), self))
+                }
+                match binop.op {
+                    Dot => Variable::f64(a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()),
+                    Cross | AndAlso | OrElse => return Err(module.error(binop.source_range,
+                        &format!("{}\nUnknown operator `{:?}` for `vecn` and `vecn`",
+                            self.stack_trace(),
+                            binop.op.symbol_bool()), self)),
+                    op => Variable::VecN(Arc::new(a.iter().zip(b.iter())
+                        .map(|(&a, &b)| vecn_op(op, a, b)).collect())),
+                }
+            }
+            (&Variable::VecN(ref a), &Variable::F64(b, _)) => {
+                match binop.op {
+                    Dot => Variable::f64(a.iter().map(|a| a * b).sum()),
+                    Cross | AndAlso | OrElse => return Err(module.error(binop.source_range,
+                        &format!("{}\nUnknown operator `{:?}` for `vecn` and `f64`",
+                            self.stack_trace(),
+                            binop.op.symbol_bool()), self)),
+                    op => Variable::VecN(Arc::new(a.iter().map(|&a| vecn_op(op, a, b)).collect())),
+                }
+            }
+            (&Variable::F64(a, _), &Variable::VecN(ref b)) => {
+                match binop.op {
+                    Dot => Variable::f64(b.iter().map(|b| a * b).sum()),
+                    Cross | AndAlso | OrElse => return Err(module.error(binop.source_range,
+                        &format!("{}\nUnknown operator `{:?}` for `f64` and `vecn`",
+                            self.stack_trace(),
+                            binop.op.symbol_bool()), self)),
+                    op => Variable::VecN(Arc::new(b.iter().map(|&b| vecn_op(op, a, b)).collect())),
+                }
+            }
             (&Variable::Mat4(ref a), &Variable::Mat4(ref b)) => {
                 use vecmath::{mat4_add, mat4_sub, col_mat4_mul};
 
@@ -2908,7 +3340,7 @@ impl Runtime {
                             binop.op.symbol_bool()), self)),
                 }
             }
-            (&Variable::Bool(a, ref sec), &Variable::Bool(b, _)) => {
+            (&Variable::Bool(a, ref sec), &Variable::Bool(b, ref b_sec)) => {
                 Variable::Bool(match binop.op {
                     Add | OrElse => a || b,
                     // Boolean subtraction with lazy precedence.
@@ -2919,7 +3351,7 @@ impl Runtime {
                         &format!("{}\nUnknown boolean operator `{:?}`",
                             self.stack_trace(),
                             binop.op.symbol_bool()), self))
-                }, sec.clone())
+                }, either_secret(sec, b_sec))
             }
             (&Variable::Text(ref a), &Variable::Text(ref b)) => {
                 match binop.op {
@@ -2950,7 +3382,7 @@ impl Runtime {
             }
             _ => return Err(module.error(binop.source_range, &format!(
                 "{}\nInvalid type for binary operator `{:?}`, \
-                expected numbers, vec4s, bools or strings",
+                expected numbers, vec4s, vecns, bools or strings",
                 self.stack_trace(),
                 binop.op.symbol()), self))
         };
@@ -2962,6 +3394,36 @@ impl Runtime {
     }
 }
 
+/// Returns the elementwise binary operator of an assignment operator for `vecn`.
+fn vecn_assign_op(op: ast::AssignOp) -> Option<ast::BinOp> {
+    use ast::AssignOp::*;
+
+    match op {
+        Add => Some(ast::BinOp::Add),
+        Sub => Some(ast::BinOp::Sub),
+        Mul => Some(ast::BinOp::Mul),
+        Div => Some(ast::BinOp::Div),
+        Rem => Some(ast::BinOp::Rem),
+        Pow => Some(ast::BinOp::Pow),
+        Assign | Set => None,
+    }
+}
+
+/// Applies an elementwise binary operator to components of `vecn`.
+fn vecn_op(op: ast::BinOp, a: f64, b: f64) -> f64 {
+    use ast::BinOp::*;
+
+    match op {
+        Add => a + b,
+        Sub => a - b,
+        Mul => a * b,
+        Div => a / b,
+        Rem => a % b,
+        Pow => a.powf(b),
+        Dot | Cross | AndAlso | OrElse => unreachable!(),
+    }
+}
+
 fn stack_trace(call_stack: &[Call]) -> String {
     let mut s = String::new();
     for call in call_stack.iter() {
@@ -0,0 +1,345 @@
+//! Schema validation for the Dyon data format.
+//!
+//! A schema is itself written in the Dyon data format:
+//!
+//! - `"any"` matches any value
+//! - `"bool"`, `"f64"`, `"str"`, `"vec4"`, `"vecn"` and `"link"` match values of that type
+//! - `"[]"`, `"{}"` and `"opt"` match any array, object or option
+//! - `[s]` matches an array where every item matches `s`
+//! - `{key: s, ...}` matches an object with the listed keys,
+//!   where keys ending with `?` are optional and other keys are reported as unknown
+//! - `some(s)` matches `none()` or `some(x)` where `x` matches `s`
+//!
+//! For example:
+//!
+//! ```text
+//! {
+//!     name: "str",
+//!     pos: "vec4",
+//!     "speed?": "f64",
+//!     items: [{name: "str", count: "f64"}],
+//! }
+//! ```
+//!
+//! All violations are reported, with the key path of the value,
+//! e.g. `items[2].count`, and the range in source when loaded from text.
+//! Keys that are not identifiers are quoted in the key path, e.g. `items[2]."first name"`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use range::Range;
+
+use dyon_std::data;
+use Variable;
+
+/// Describes the expected structure of data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    /// Matches any value.
+    Any,
+    /// Matches a boolean.
+    Bool,
+    /// Matches a number.
+    F64,
+    /// Matches a string.
+    Str,
+    /// Matches a 4D vector.
+    Vec4,
+    /// Matches a vector of any dimension.
+    VecN,
+    /// Matches a link.
+    Link,
+    /// Matches an array, with an optional schema for every item.
+    Array(Option<Box<Schema>>),
+    /// Matches an object, with an optional list of fields.
+    Object(Option<Vec<Field>>),
+    /// Matches an option, with an optional schema for the inner value.
+    Option(Option<Box<Schema>>),
+}
+
+/// Describes a field of an object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    /// The name of the field.
+    pub name: Arc<String>,
+    /// Whether the field can be missing.
+    pub optional: bool,
+    /// The schema of the field value.
+    pub schema: Schema,
+}
+
+/// Stores a value that does not match the schema.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// The key path of the value, e.g. `items[2].count`.
+    /// This is empty for the top level value.
+    /// Keys that are not identifiers are quoted, e.g. `items[2]."first name"`.
+    pub path: String,
+    /// The violation message.
+    pub message: String,
+    /// The range in source, when loaded from text.
+    pub range: Option<Range>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "In `{}`: {}", self.path, self.message)
+        }
+    }
+}
+
+impl Schema {
+    /// Creates schema from a variable in the Dyon data format.
+    pub fn from_variable(v: &Variable) -> Result<Schema, String> {
+        let mut path = String::new();
+        Schema::from_variable_path(v, &mut path)
+    }
+
+    /// Creates schema from text in the Dyon data format.
+    pub fn from_data(text: &str) -> Result<Schema, String> {
+        Schema::from_variable(&data::load_data(text)?)
+    }
+
+    fn from_variable_path(v: &Variable, path: &mut String) -> Result<Schema, String> {
+        fn err(path: &str, msg: String) -> Result<Schema, String> {
+            if path.is_empty() {
+                Err(format!("Invalid schema: {}", msg))
+            } else {
+                Err(format!("Invalid schema in `{}`: {}", path, msg))
+            }
+        }
+
+        Ok(match *v {
+            Variable::Text(ref t) => match t.as_str() {
+                "any" => Schema::Any,
+                "bool" => Schema::Bool,
+                "f64" => Schema::F64,
+                "str" => Schema::Str,
+                "vec4" => Schema::Vec4,
+                "vecn" => Schema::VecN,
+                "link" => Schema::Link,
+                "[]" => Schema::Array(None),
+                "{}" => Schema::Object(None),
+                "opt" => Schema::Option(None),
+                x => return err(path, format!("Unknown type `{}`", x)),
+            },
+            Variable::Array(ref arr) => match arr.len() {
+                0 => Schema::Array(None),
+                1 => {
+                    let n = path.len();
+                    path.push_str("[0]");
+                    let item = Schema::from_variable_path(&arr[0], path)?;
+                    path.truncate(n);
+                    Schema::Array(Some(Box::new(item)))
+                }
+                _ => return err(path, "Expected array with one item".into()),
+            },
+            Variable::Object(ref obj) => {
+                if obj.is_empty() {
+                    Schema::Object(None)
+                } else {
+                    let mut fields = vec![];
+                    for (key, val) in obj.iter() {
+                        let n = push_key(path, key);
+                        let schema = Schema::from_variable_path(val, path)?;
+                        path.truncate(n);
+                        let optional = key.ends_with('?');
+                        fields.push(Field {
+                            name: if optional {
+                                Arc::new(key[..key.len() - 1].into())
+                            } else {
+                                key.clone()
+                            },
+                            optional,
+                            schema,
+                        });
+                    }
+                    fields.sort_by(|a, b| a.name.cmp(&b.name));
+                    Schema::Object(Some(fields))
+                }
+            }
+            Variable::Option(Some(ref inner)) => {
+                Schema::Option(Some(Box::new(Schema::from_variable_path(inner, path)?)))
+            }
+            ref x => return err(path, format!("Unexpected `{}`", x.typeof_var())),
+        })
+    }
+
+    /// Validates data and returns all violations.
+    pub fn validate(&self, data: &Variable) -> Vec<Violation> {
+        let mut violations = vec![];
+        let mut path = String::new();
+        self.check(data, &mut path, None, &mut violations);
+        violations
+    }
+
+    /// Loads data from text and returns all violations,
+    /// with ranges in source.
+    ///
+    /// Returns `Err` if the text can not be loaded.
+    pub fn validate_str(&self, text: &str) -> Result<(Variable, Vec<Violation>), String> {
+        let (data, ranges) = data::load_data_ranges(text)?;
+        let mut violations = vec![];
+        let mut path = String::new();
+        self.check(&data, &mut path, Some(&ranges), &mut violations);
+        Ok((data, violations))
+    }
+
+    /// Loads data from text and validates it.
+    ///
+    /// Returns `Err` with all violations formatted with the lines in source.
+    pub fn load_data(&self, text: &str) -> Result<Variable, String> {
+        let (data, violations) = self.validate_str(text)?;
+        if violations.is_empty() {
+            Ok(data)
+        } else {
+            Err(format_violations(text, &violations))
+        }
+    }
+
+    /// Loads data from file and validates it.
+    pub fn load_file(&self, file: &str) -> Result<Variable, String> {
+        self.load_data(&data::read_file(file)?)
+    }
+
+    fn check(
+        &self,
+        v: &Variable,
+        path: &mut String,
+        ranges: Option<&HashMap<String, Range>>,
+        violations: &mut Vec<Violation>
+    ) {
+        let ok = match (self, v) {
+            (&Schema::Any, _) |
+            (&Schema::Bool, &Variable::Bool(_, _)) |
+            (&Schema::F64, &Variable::F64(_, _)) |
+            (&Schema::Str, &Variable::Text(_)) |
+            (&Schema::Vec4, &Variable::Vec4(_)) |
+            (&Schema::VecN, &Variable::VecN(_)) |
+            (&Schema::Link, &Variable::Link(_)) |
+            (&Schema::Array(None), &Variable::Array(_)) |
+            (&Schema::Object(None), &Variable::Object(_)) |
+            (&Schema::Option(None), &Variable::Option(_)) |
+            (&Schema::Option(Some(_)), &Variable::Option(None)) => true,
+            (&Schema::Array(Some(ref item)), &Variable::Array(ref arr)) => {
+                for (i, x) in arr.iter().enumerate() {
+                    let n = path.len();
+                    path.push_str(&format!("[{}]", i));
+                    item.check(x, path, ranges, violations);
+                    path.truncate(n);
+                }
+                true
+            }
+            (&Schema::Object(Some(ref fields)), &Variable::Object(ref obj)) => {
+                for field in fields {
+                    match obj.get(&field.name) {
+                        Some(x) => {
+                            let n = push_key(path, &field.name);
+                            field.schema.check(x, path, ranges, violations);
+                            path.truncate(n);
+                        }
+                        None if field.optional => {}
+                        None => {
+                            // The missing key has no range, so use the range of the object.
+                            let range = ranges.and_then(|r| r.get(path.as_str()).cloned());
+                            let n = push_key(path, &field.name);
+                            violations.push(Violation {
+                                path: path.clone(),
+                                message: format!("Missing key `{}`", field.name),
+                                range,
+                            });
+                            path.truncate(n);
+                        }
+                    }
+                }
+                let mut unknown: Vec<&Arc<String>> = obj.keys()
+                    .filter(|key| !fields.iter().any(|f| &f.name == *key))
+                    .collect();
+                unknown.sort();
+                for key in unknown {
+                    let n = push_key(path, key);
+                    violations.push(Violation {
+                        path: path.clone(),
+                        message: format!("Unknown key `{}`", key),
+                        range: ranges.and_then(|r| r.get(path.as_str()).cloned()),
+                    });
+                    path.truncate(n);
+                }
+                true
+            }
+            (&Schema::Option(Some(ref inner)), &Variable::Option(Some(ref x))) => {
+                inner.check(x, path, ranges, violations);
+                true
+            }
+            _ => false,
+        };
+        if !ok {
+            violations.push(Violation {
+                path: path.clone(),
+                message: format!("Expected `{}`, found `{}`", self.name(), type_name(v)),
+                range: ranges.and_then(|r| r.get(path.as_str()).cloned()),
+            });
+        }
+    }
+
+    /// Returns the name of the type matched by the schema.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Schema::Any => "any",
+            Schema::Bool => "bool",
+            Schema::F64 => "f64",
+            Schema::Str => "str",
+            Schema::Vec4 => "vec4",
+            Schema::VecN => "vecn",
+            Schema::Link => "link",
+            Schema::Array(_) => "[]",
+            Schema::Object(_) => "{}",
+            Schema::Option(_) => "opt",
+        }
+    }
+}
+
+fn push_key(path: &mut String, key: &str) -> usize {
+    let n = path.len();
+    data::push_key(path, key);
+    n
+}
+
+fn type_name(v: &Variable) -> &'static str {
+    match *v {
+        Variable::Bool(_, _) => "bool",
+        Variable::F64(_, _) => "f64",
+        Variable::Text(_) => "str",
+        Variable::Vec4(_) => "vec4",
+        Variable::VecN(_) => "vecn",
+        Variable::Link(_) => "link",
+        Variable::Array(_) => "[]",
+        Variable::Object(_) => "{}",
+        Variable::Option(_) => "opt",
+        _ => "any",
+    }
+}
+
+/// Formats violations with the lines in source they refer to.
+pub fn format_violations(text: &str, violations: &[Violation]) -> String {
+    use std::io::Write;
+    use piston_meta::ParseErrorHandler;
+
+    let mut buf: Vec<u8> = vec![];
+    for violation in violations {
+        match violation.range {
+            Some(range) => {
+                ParseErrorHandler::new(text)
+                    .write_msg(&mut buf, range, &format!("{}", violation))
+                    .unwrap();
+            }
+            None => writeln!(&mut buf, "{}", violation).unwrap(),
+        }
+    }
+    String::from_utf8(buf).unwrap()
+}
@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::collections::HashMap;
 
 use piston_meta::bootstrap::Convert;
 use range::Range;
@@ -20,6 +21,8 @@ pub enum Type {
     F64,
     /// 4D vector type.
     Vec4,
+    /// Vector type of `f64` with any length.
+    VecN,
     /// 4D matrix type.
     Mat4,
     /// String/text type.
@@ -44,6 +47,10 @@ pub enum Type {
     AdHoc(Arc<String>, Box<Type>),
     /// Closure type.
     Closure(Box<Dfn>),
+    /// Type variable, e.g. `T` in `fn first<T>(xs: [T]) -> opt[T]`.
+    ///
+    /// Type variables are bound by unifying the argument types of a call.
+    Var(Arc<String>),
 }
 
 impl Type {
@@ -58,6 +65,7 @@ impl Type {
             Bool => "bool".into(),
             F64 => "f64".into(),
             Vec4 => "vec4".into(),
+            VecN => "vecn".into(),
             Mat4 => "mat4".into(),
             Text => "str".into(),
             Link => "link".into(),
@@ -135,6 +143,7 @@ impl Type {
                 s.push_str(&closure.ret.description());
                 s
             }
+            Var(ref name) => (**name).clone(),
         }
     }
 
@@ -165,6 +174,10 @@ impl Type {
     pub fn goes_with(&self, other: &Type) -> bool {
         use self::Type::*;
 
+        // Type variables are checked by unification.
+        if let Var(_) = *other {
+            return *self != Void;
+        }
         // Invert the order because of complex ad-hoc logic.
         if let AdHoc(_, _) = *other {
             if let AdHoc(_, _) = *self {}
@@ -184,6 +197,7 @@ impl Type {
             &Unreachable => true,
             _ if *other == Unreachable => true,
             &Any => *other != Void,
+            &Var(_) => *other != Void,
             // Void only goes with void.
             &Void => *other == Void,
             &Array(ref arr) => {
@@ -299,13 +313,15 @@ impl Type {
             if **a == Type::F64 && **b == Type::F64 =>
                 Some(Secret(Box::new(F64))),
             (&Secret(ref a), &F64) if **a == Type::F64 => Some(Secret(Box::new(F64))),
-            (&F64, &Secret(ref b)) if **b == Type::F64 => Some(F64),
+            (&F64, &Secret(ref b)) if **b == Type::F64 => Some(Secret(Box::new(F64))),
             (&Text, &Text) => Some(Text),
             (&Vec4, &F64) => Some(Vec4),
             (&F64, &Vec4) => Some(Vec4),
             (&Vec4, &Vec4) => Some(Vec4),
-            (&Any, x) if x != &Type::Void => Some(Any),
-            (x, &Any) if x != &Type::Void => Some(Any),
+            (&VecN, &F64) | (&F64, &VecN) => Some(VecN),
+            (&VecN, &VecN) => Some(VecN),
+            (&Any, x) | (&Var(_), x) if x != &Type::Void => Some(Any),
+            (x, &Any) | (x, &Var(_)) if x != &Type::Void => Some(Any),
             _ => None
         }
     }
@@ -347,7 +363,7 @@ impl Type {
             if **a == Type::F64 && **b == Type::F64 =>
                 Some(Secret(Box::new(F64))),
             (&Secret(ref a), &F64) if **a == Type::F64 => Some(Secret(Box::new(F64))),
-            (&F64, &Secret(ref b)) if **b == Type::F64 => Some(F64),
+            (&F64, &Secret(ref b)) if **b == Type::F64 => Some(Secret(Box::new(F64))),
             (&Vec4, &F64) => Some(Vec4),
             (&F64, &Vec4) => Some(Vec4),
             (&Vec4, &Vec4) => {
@@ -357,8 +373,16 @@ impl Type {
                     Some(Vec4)
                 }
             }
-            (&Any, x) if x != &Type::Void => Some(Any),
-            (x, &Any) if x != &Type::Void => Some(Any),
+            (&VecN, &F64) | (&F64, &VecN) => Some(VecN),
+            (&VecN, &VecN) => {
+                match binop {
+                    BinOp::Dot => Some(F64),
+                    BinOp::Cross => None,
+                    _ => Some(VecN)
+                }
+            }
+            (&Any, x) | (&Var(_), x) if x != &Type::Void => Some(Any),
+            (x, &Any) | (x, &Var(_)) if x != &Type::Void => Some(Any),
             _ => None
         }
     }
@@ -381,15 +405,117 @@ impl Type {
             if **a == Type::F64 && **b == Type::F64 =>
                 Some(Secret(Box::new(F64))),
             (&Secret(ref a), &F64) if **a == Type::F64 => Some(Secret(Box::new(F64))),
-            (&F64, &Secret(ref b)) if **b == Type::F64 => Some(F64),
+            (&F64, &Secret(ref b)) if **b == Type::F64 => Some(Secret(Box::new(F64))),
             (&Vec4, &F64) | (&F64, &Vec4) => Some(Vec4),
             (&Vec4, &Vec4) => Some(Vec4),
-            (&Any, x) if x != &Type::Void => Some(Any),
-            (x, &Any) if x != &Type::Void => Some(Any),
+            (&VecN, &F64) | (&F64, &VecN) => Some(VecN),
+            (&VecN, &VecN) => Some(VecN),
+            (&Any, x) | (&Var(_), x) if x != &Type::Void => Some(Any),
+            (x, &Any) | (x, &Var(_)) if x != &Type::Void => Some(Any),
             _ => None
         }
     }
 
+    /// Returns `true` if the type contains type variables.
+    pub fn has_vars(&self) -> bool {
+        use self::Type::*;
+
+        match *self {
+            Var(_) => true,
+            Array(ref ty) | Option(ref ty) | Result(ref ty) | Secret(ref ty) |
+            Thread(ref ty) | In(ref ty) | AdHoc(_, ref ty) => ty.has_vars(),
+            Closure(ref cl) => cl.ret.has_vars() || cl.tys.iter().any(|ty| ty.has_vars()),
+            _ => false
+        }
+    }
+
+    /// Binds type variables in this type to the matching parts of another type.
+    ///
+    /// Returns `false` when a type variable is bound to conflicting types,
+    /// or when the other type does not go with the non-variable parts of this type.
+    pub fn unify(&self, other: &Type, bindings: &mut HashMap<Arc<String>, Type>) -> bool {
+        use self::Type::*;
+
+        match (self, other) {
+            // Nothing is learned about a type variable from `any`.
+            (_, &Any) | (_, &Unreachable) => true,
+            (&Var(ref name), _) => {
+                if let Some(ty) = bindings.get(name) {
+                    return ty.goes_with(other) || other.goes_with(ty);
+                }
+                bindings.insert(name.clone(), other.clone());
+                true
+            }
+            (&Array(ref a), &Array(ref b)) |
+            (&Option(ref a), &Option(ref b)) |
+            (&Result(ref a), &Result(ref b)) |
+            (&Secret(ref a), &Secret(ref b)) |
+            (&Thread(ref a), &Thread(ref b)) |
+            (&In(ref a), &In(ref b)) => a.unify(b, bindings),
+            (&AdHoc(ref name, ref a), &AdHoc(ref other_name, ref b))
+            if name == other_name => a.unify(b, bindings),
+            (&Closure(ref a), &Closure(ref b)) => {
+                a.tys.iter().zip(b.tys.iter()).all(|(a, b)| a.unify(b, bindings)) &&
+                a.ret.unify(&b.ret, bindings)
+            }
+            _ => other.goes_with(self)
+        }
+    }
+
+    /// Turns ad-hoc types named like declared type variables into type variables.
+    ///
+    /// The parser reads `T` in `fn first<T>(xs: [T]) -> opt[T]` as an ad-hoc type,
+    /// since the name is only known to be a type variable from the function declaration.
+    pub fn with_vars(&self, vars: &[Arc<String>]) -> Type {
+        use self::Type::*;
+
+        match *self {
+            AdHoc(ref name, ref ty) => {
+                if **ty == Object && vars.contains(name) {
+                    Var(name.clone())
+                } else {
+                    AdHoc(name.clone(), Box::new(ty.with_vars(vars)))
+                }
+            }
+            Array(ref ty) => Array(Box::new(ty.with_vars(vars))),
+            Option(ref ty) => Option(Box::new(ty.with_vars(vars))),
+            Result(ref ty) => Result(Box::new(ty.with_vars(vars))),
+            Secret(ref ty) => Secret(Box::new(ty.with_vars(vars))),
+            Thread(ref ty) => Thread(Box::new(ty.with_vars(vars))),
+            In(ref ty) => In(Box::new(ty.with_vars(vars))),
+            Closure(ref cl) => Closure(Box::new(Dfn {
+                lts: cl.lts.clone(),
+                tys: cl.tys.iter().map(|ty| ty.with_vars(vars)).collect(),
+                ret: cl.ret.with_vars(vars),
+            })),
+            ref x => x.clone()
+        }
+    }
+
+    /// Replaces type variables with their bound types.
+    ///
+    /// Unbound type variables are replaced with `any`.
+    pub fn substitute(&self, bindings: &HashMap<Arc<String>, Type>) -> Type {
+        use self::Type::*;
+
+        match *self {
+            Var(ref name) => bindings.get(name).cloned().unwrap_or(Any),
+            Array(ref ty) => Array(Box::new(ty.substitute(bindings))),
+            Option(ref ty) => Option(Box::new(ty.substitute(bindings))),
+            Result(ref ty) => Result(Box::new(ty.substitute(bindings))),
+            Secret(ref ty) => Secret(Box::new(ty.substitute(bindings))),
+            Thread(ref ty) => Thread(Box::new(ty.substitute(bindings))),
+            In(ref ty) => In(Box::new(ty.substitute(bindings))),
+            AdHoc(ref name, ref ty) => AdHoc(name.clone(), Box::new(ty.substitute(bindings))),
+            Closure(ref cl) => Closure(Box::new(Dfn {
+                lts: cl.lts.clone(),
+                tys: cl.tys.iter().map(|ty| ty.substitute(bindings)).collect(),
+                ret: cl.ret.substitute(bindings),
+            })),
+            ref x => x.clone()
+        }
+    }
+
     /// Converts meta data into a type.
     pub fn from_meta_data(node: &str, mut convert: Convert, ignored: &mut Vec<Range>)
     -> Result<(Range, Type), ()> {
@@ -423,6 +549,9 @@ impl Type {
             } else if let Ok((range, _)) = convert.meta_bool("vec4") {
                 convert.update(range);
                 ty = Some(Type::Vec4);
+            } else if let Ok((range, _)) = convert.meta_bool("vecn") {
+                convert.update(range);
+                ty = Some(Type::VecN);
             } else if let Ok((range, _)) = convert.meta_bool("mat4") {
                 convert.update(range);
                 ty = Some(Type::Mat4);
@@ -10,9 +10,10 @@ pub(crate) enum EscapeString {
     None
 }
 
+/// Writes a variable, resolving references on `stack`.
 pub(crate) fn write_variable<W>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     v: &Variable,
     escape_string: EscapeString,
     tabs: u32,
@@ -46,6 +47,16 @@ pub(crate) fn write_variable<W>(
                 write!(w, ")")?;
             }
         }
+        Variable::VecN(ref v) => {
+            write!(w, "vecn([")?;
+            for (i, x) in v.iter().enumerate() {
+                if i > 0 {
+                    write!(w, ", ")?;
+                }
+                write!(w, "{}", x)?;
+            }
+            write!(w, "])")?;
+        }
         Variable::Mat4(ref m) => {
             write!(w, "mat4 {{{},{},{},{}; {},{},{},{}; {},{},{},{}; {},{},{},{}}}",
                 m[0][0], m[1][0], m[2][0], m[3][0],
@@ -58,7 +69,7 @@ pub(crate) fn write_variable<W>(
             write!(w, "{}", x)?;
         }
         Variable::Ref(ind) => {
-            write_variable(w, rt, &rt.stack[ind], escape_string, tabs)?;
+            write_variable(w, stack, &stack[ind], escape_string, tabs)?;
         }
         Variable::Link(ref link) => {
             match escape_string {
@@ -68,7 +79,7 @@ pub(crate) fn write_variable<W>(
                     for slice in &link.slices {
                         for i in slice.start..slice.end {
                             let v = slice.block.var(i);
-                            write_variable(w, rt, &v, EscapeString::Json, tabs)?;
+                            write_variable(w, stack, &v, EscapeString::Json, tabs)?;
                             write!(w, " ")?;
                         }
                     }
@@ -78,7 +89,7 @@ pub(crate) fn write_variable<W>(
                     for slice in &link.slices {
                         for i in slice.start..slice.end {
                             let v = slice.block.var(i);
-                            write_variable(w, rt, &v, EscapeString::None, tabs)?;
+                            write_variable(w, stack, &v, EscapeString::None, tabs)?;
                         }
                     }
                 }
@@ -94,7 +105,7 @@ pub(crate) fn write_variable<W>(
                     json::write_string(w, &k)?;
                     write!(w, ": ")?;
                 }
-                write_variable(w, rt, v, EscapeString::Json, tabs)?;
+                write_variable(w, stack, v, EscapeString::Json, tabs)?;
                 if i + 1 < n {
                     write!(w, ", ")?;
                 }
@@ -105,7 +116,7 @@ pub(crate) fn write_variable<W>(
             write!(w, "[")?;
             let n = arr.len();
             for (i, v) in arr.iter().enumerate() {
-                write_variable(w, rt, v, EscapeString::Json, tabs)?;
+                write_variable(w, stack, v, EscapeString::Json, tabs)?;
                 if i + 1 < n {
                     write!(w, ", ")?;
                 }
@@ -119,7 +130,7 @@ pub(crate) fn write_variable<W>(
                 }
                 Some(ref v) => {
                     write!(w, "some(")?;
-                    write_variable(w, rt, v, EscapeString::Json, tabs)?;
+                    write_variable(w, stack, v, EscapeString::Json, tabs)?;
                     write!(w, ")")?;
                 }
             }
@@ -128,12 +139,12 @@ pub(crate) fn write_variable<W>(
             match *res {
                 Err(ref err) => {
                     write!(w, "err(")?;
-                    write_variable(w, rt, &err.message, EscapeString::Json, tabs)?;
+                    write_variable(w, stack, &err.message, EscapeString::Json, tabs)?;
                     write!(w, ")")?;
                 }
                 Ok(ref ok) => {
                     write!(w, "ok(")?;
-                    write_variable(w, rt, ok, EscapeString::Json, tabs)?;
+                    write_variable(w, stack, ok, EscapeString::Json, tabs)?;
                     write!(w, ")")?;
                 }
             }
@@ -142,7 +153,7 @@ pub(crate) fn write_variable<W>(
         Variable::Return => write!(w, "_return")?,
         Variable::UnsafeRef(_) => write!(w, "_unsafe_ref")?,
         Variable::RustObject(_) => write!(w, "_rust_object")?,
-        Variable::Closure(ref closure, _) => write_closure(w, rt, closure, tabs)?,
+        Variable::Closure(ref closure, _) => write_closure(w, stack, closure, tabs)?,
         Variable::In(_) => write!(w, "_in")?,
         // ref x => panic!("Could not print out `{:?}`", x)
     }
@@ -150,7 +161,7 @@ pub(crate) fn write_variable<W>(
 }
 
 pub(crate) fn print_variable(rt: &Runtime, v: &Variable, escape_string: EscapeString) {
-    write_variable(&mut io::stdout(), rt, v, escape_string, 0).unwrap();
+    write_variable(&mut io::stdout(), &rt.stack, v, escape_string, 0).unwrap();
 }
 
 fn write_tabs<W: io::Write>(w: &mut W, tabs: u32) -> Result<(), io::Error> {
@@ -162,7 +173,7 @@ fn write_tabs<W: io::Write>(w: &mut W, tabs: u32) -> Result<(), io::Error> {
 
 fn write_closure<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     closure: &ast::Closure,
     tabs: u32
 ) -> Result<(), io::Error> {
@@ -174,7 +185,7 @@ fn write_closure<W: io::Write>(
         }
     }
     write!(w, ") = ")?;
-    write_expr(w, rt, &closure.expr, tabs)?;
+    write_expr(w, stack, &closure.expr, tabs)?;
     Ok(())
 }
 
@@ -187,25 +198,25 @@ fn write_arg<W: io::Write>(
 
 fn write_expr<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     expr: &ast::Expression,
     tabs: u32,
 ) -> Result<(), io::Error> {
     use ast::Expression as E;
 
     match *expr {
-        E::BinOp(ref binop) => write_binop(w, rt, binop, tabs)?,
-        E::Item(ref item) => write_item(w, rt, item, tabs)?,
+        E::BinOp(ref binop) => write_binop(w, stack, binop, tabs)?,
+        E::Item(ref item) => write_item(w, stack, item, tabs)?,
         E::Variable(ref range_var) =>
-            write_variable(w, rt, &range_var.1, EscapeString::Json, tabs)?,
-        E::Link(ref link) => write_link(w, rt, link, tabs)?,
-        E::Object(ref obj) => write_obj(w, rt, obj, tabs)?,
-        E::Array(ref arr) => write_arr(w, rt, arr, tabs)?,
-        E::ArrayFill(ref arr_fill) => write_arr_fill(w, rt, arr_fill, tabs)?,
-        E::Call(ref call) => write_call(w, rt, call, tabs)?,
+            write_variable(w, stack, &range_var.1, EscapeString::Json, tabs)?,
+        E::Link(ref link) => write_link(w, stack, link, tabs)?,
+        E::Object(ref obj) => write_obj(w, stack, obj, tabs)?,
+        E::Array(ref arr) => write_arr(w, stack, arr, tabs)?,
+        E::ArrayFill(ref arr_fill) => write_arr_fill(w, stack, arr_fill, tabs)?,
+        E::Call(ref call) => write_call(w, stack, call, tabs)?,
         E::Return(ref expr) => {
             write!(w, "return ")?;
-            write_expr(w, rt, expr, tabs)?;
+            write_expr(w, stack, expr, tabs)?;
         }
         E::ReturnVoid(_) => write!(w, "return")?,
         E::Break(ref br) => {
@@ -222,108 +233,109 @@ fn write_expr<W: io::Write>(
                 write!(w, "continue")?;
             }
         }
-        E::Block(ref b) => write_block(w, rt, b, tabs)?,
+        E::Block(ref b) => write_block(w, stack, b, tabs)?,
         E::Go(ref go) => {
             write!(w, "go ")?;
-            write_call(w, rt, &go.call, tabs)?;
+            write_call(w, stack, &go.call, tabs)?;
         }
-        E::Assign(ref assign) => write_assign(w, rt, assign, tabs)?,
-        E::Vec4(ref vec4) => write_vec4(w, rt, vec4, tabs)?,
-        E::Mat4(ref mat4) => write_mat4(w, rt, mat4, tabs)?,
-        E::For(ref f) => write_for(w, rt, f, tabs)?,
-        E::Compare(ref comp) => write_compare(w, rt, comp, tabs)?,
+        E::Assign(ref assign) => write_assign(w, stack, assign, tabs)?,
+        E::Vec4(ref vec4) => write_vec4(w, stack, vec4, tabs)?,
+        E::Mat4(ref mat4) => write_mat4(w, stack, mat4, tabs)?,
+        E::For(ref f) => write_for(w, stack, f, tabs)?,
+        E::Compare(ref comp) => write_compare(w, stack, comp, tabs)?,
         E::ForN(ref for_n) => {
             write!(w, "for ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::ForIn(ref for_in) => {
             write!(w, "for ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::Sum(ref for_n) => {
             write!(w, "sum ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::SumIn(ref for_in) => {
             write!(w, "sum ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::SumVec4(ref for_n) => {
             write!(w, "sum_vec4 ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::Prod(ref for_n) => {
             write!(w, "prod ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::ProdIn(ref for_in) => {
             write!(w, "prod ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::ProdVec4(ref for_n) => {
             write!(w, "prod_vec4 ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::Min(ref for_n) => {
             write!(w, "min ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::MinIn(ref for_in) => {
             write!(w, "min ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::Max(ref for_n) => {
             write!(w, "max ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::MaxIn(ref for_in) => {
             write!(w, "max ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::Sift(ref for_n) => {
             write!(w, "sift ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::SiftIn(ref for_in) => {
             write!(w, "sift ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::Any(ref for_n) => {
             write!(w, "any ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::AnyIn(ref for_in) => {
             write!(w, "any ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::All(ref for_n) => {
             write!(w, "all ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::AllIn(ref for_in) => {
             write!(w, "all ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
         E::LinkFor(ref for_n) => {
             write!(w, "link ")?;
-            write_for_n(w, rt, for_n, tabs)?;
+            write_for_n(w, stack, for_n, tabs)?;
         }
         E::LinkIn(ref for_in) => {
             write!(w, "link ")?;
-            write_for_in(w, rt, for_in, tabs)?;
+            write_for_in(w, stack, for_in, tabs)?;
         }
-        E::If(ref if_expr) => write_if(w, rt, if_expr, tabs)?,
-        E::Norm(ref norm) => write_norm(w, rt, norm, tabs)?,
-        E::UnOp(ref unop) => write_unop(w, rt, unop, tabs)?,
+        E::If(ref if_expr) => write_if(w, stack, if_expr, tabs)?,
+        E::Match(ref match_expr) => write_match(w, stack, match_expr, tabs)?,
+        E::Norm(ref norm) => write_norm(w, stack, norm, tabs)?,
+        E::UnOp(ref unop) => write_unop(w, stack, unop, tabs)?,
         E::Try(ref expr) => {
-            write_expr(w, rt, expr, tabs)?;
+            write_expr(w, stack, expr, tabs)?;
             write!(w, "?")?;
         }
-        E::Swizzle(ref swizzle) => write_swizzle(w, rt, swizzle, tabs)?,
-        E::Closure(ref closure) => write_closure(w, rt, closure, tabs)?,
-        E::Grab(ref grab) =>write_grab(w, rt, grab, tabs)?,
-        E::TryExpr(ref try_expr) => write_try_expr(w, rt, try_expr, tabs)?,
-        E::CallClosure(ref call) => write_call_closure(w, rt, call, tabs)?,
+        E::Swizzle(ref swizzle) => write_swizzle(w, stack, swizzle, tabs)?,
+        E::Closure(ref closure) => write_closure(w, stack, closure, tabs)?,
+        E::Grab(ref grab) =>write_grab(w, stack, grab, tabs)?,
+        E::TryExpr(ref try_expr) => write_try_expr(w, stack, try_expr, tabs)?,
+        E::CallClosure(ref call) => write_call_closure(w, stack, call, tabs)?,
         E::In(ref in_expr) => {
             write!(w, "in {}", in_expr.name)?;
         }
@@ -334,7 +346,7 @@ fn write_expr<W: io::Write>(
 
 fn write_block<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     block: &ast::Block,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -344,14 +356,14 @@ fn write_block<W: io::Write>(
         }
         1 => {
             write!(w, "{{ ")?;
-            write_expr(w, rt, &block.expressions[0], tabs + 1)?;
+            write_expr(w, stack, &block.expressions[0], tabs + 1)?;
             write!(w, " }}")?;
         }
         _ => {
             writeln!(w, "{{")?;
             for expr in &block.expressions {
                 write_tabs(w, tabs + 1)?;
-                write_expr(w, rt, expr, tabs + 1)?;
+                write_expr(w, stack, expr, tabs + 1)?;
                 writeln!(w, "")?;
             }
             write_tabs(w, tabs)?;
@@ -381,7 +393,7 @@ fn binop_needs_parens(op: ast::BinOp, expr: &ast::Expression, right: bool) -> bo
 
 fn write_binop<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     binop: &ast::BinOpExpression,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -391,7 +403,7 @@ fn write_binop<W: io::Write>(
     if left_needs_parens {
         write!(w, "(")?;
     }
-    write_expr(w, rt, &binop.left, tabs)?;
+    write_expr(w, stack, &binop.left, tabs)?;
     if left_needs_parens {
         write!(w, ")")?;
     }
@@ -399,7 +411,7 @@ fn write_binop<W: io::Write>(
     if right_needs_parens {
         write!(w, "(")?;
     }
-    write_expr(w, rt, &binop.right, tabs)?;
+    write_expr(w, stack, &binop.right, tabs)?;
     if right_needs_parens {
         write!(w, ")")?;
     }
@@ -408,19 +420,19 @@ fn write_binop<W: io::Write>(
 
 fn write_norm<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     norm: &ast::Norm,
     tabs: u32
 ) -> Result<(), io::Error> {
     write!(w, "|")?;
-    write_expr(w, rt, &norm.expr, tabs)?;
+    write_expr(w, stack, &norm.expr, tabs)?;
     write!(w, "|")?;
     Ok(())
 }
 
 fn write_unop<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     unop: &ast::UnOpExpression,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -429,11 +441,11 @@ fn write_unop<W: io::Write>(
     match unop.op {
         Not => {
             write!(w, "!")?;
-            write_expr(w, rt, &unop.expr, tabs)?;
+            write_expr(w, stack, &unop.expr, tabs)?;
         }
         Neg => {
             write!(w, "-")?;
-            write_expr(w, rt, &unop.expr, tabs)?;
+            write_expr(w, stack, &unop.expr, tabs)?;
         }
     }
     Ok(())
@@ -441,7 +453,7 @@ fn write_unop<W: io::Write>(
 
 fn write_item<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     item: &ast::Item,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -457,7 +469,7 @@ fn write_item<W: io::Write>(
             Id::F64(_, ind) => write!(w, "[{}]", ind)?,
             Id::Expression(ref expr) => {
                 write!(w, "[")?;
-                write_expr(w, rt, expr, tabs)?;
+                write_expr(w, stack, expr, tabs)?;
                 write!(w, "]")?;
             }
         }
@@ -470,13 +482,13 @@ fn write_item<W: io::Write>(
 
 fn write_link<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     link: &ast::Link,
     tabs: u32,
 ) -> Result<(), io::Error> {
     write!(w, "link {{ ")?;
     for item in &link.items {
-        write_expr(w, rt, item, tabs)?;
+        write_expr(w, stack, item, tabs)?;
         write!(w, " ")?;
     }
     write!(w, "}}")?;
@@ -485,7 +497,7 @@ fn write_link<W: io::Write>(
 
 fn write_obj<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     obj: &ast::Object,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -497,7 +509,7 @@ fn write_obj<W: io::Write>(
             json::write_string(w, &key_value.0)?;
             write!(w, ": ")?;
         }
-        write_expr(w, rt, &key_value.1, tabs + 1)?;
+        write_expr(w, stack, &key_value.1, tabs + 1)?;
         if i + 1 < obj.key_values.len() {
             write!(w, ", ")?;
         }
@@ -508,13 +520,13 @@ fn write_obj<W: io::Write>(
 
 fn write_call<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     call: &ast::Call,
     tabs: u32,
 ) -> Result<(), io::Error> {
     write!(w, "{}(", call.name)?;
     for (i, arg) in call.args.iter().enumerate() {
-        write_expr(w, rt, arg, tabs)?;
+        write_expr(w, stack, arg, tabs)?;
         if i + 1 < call.args.len() {
             write!(w, ", ")?;
         }
@@ -525,15 +537,15 @@ fn write_call<W: io::Write>(
 
 fn write_call_closure<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     call: &ast::CallClosure,
     tabs: u32,
 ) -> Result<(), io::Error> {
     write!(w, "\\")?;
-    write_item(w, rt, &call.item, tabs)?;
+    write_item(w, stack, &call.item, tabs)?;
     write!(w, "(")?;
     for (i, arg) in call.args.iter().enumerate() {
-        write_expr(w, rt, arg, tabs + 1)?;
+        write_expr(w, stack, arg, tabs + 1)?;
         if i + 1 < call.args.len() {
             write!(w, ", ")?;
         }
@@ -544,13 +556,13 @@ fn write_call_closure<W: io::Write>(
 
 fn write_arr<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     arr: &ast::Array,
     tabs: u32,
 ) -> Result<(), io::Error> {
     write!(w, "[")?;
     for (i, item) in arr.items.iter().enumerate() {
-        write_expr(w, rt, item, tabs + 1)?;
+        write_expr(w, stack, item, tabs + 1)?;
         if i + 1 < arr.items.len() {
             write!(w, ", ")?;
         }
@@ -561,33 +573,33 @@ fn write_arr<W: io::Write>(
 
 fn write_arr_fill<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     arr_fill: &ast::ArrayFill,
     tabs: u32,
 ) -> Result<(), io::Error> {
     write!(w, "[")?;
-    write_expr(w, rt, &arr_fill.fill, tabs + 1)?;
+    write_expr(w, stack, &arr_fill.fill, tabs + 1)?;
     write!(w, ", ")?;
-    write_expr(w, rt, &arr_fill.n, tabs + 1)?;
+    write_expr(w, stack, &arr_fill.n, tabs + 1)?;
     write!(w, "]")?;
     Ok(())
 }
 
 fn write_assign<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     assign: &ast::Assign,
     tabs: u32,
 ) -> Result<(), io::Error> {
-    write_expr(w, rt, &assign.left, tabs)?;
+    write_expr(w, stack, &assign.left, tabs)?;
     write!(w, " {} ", assign.op.symbol())?;
-    write_expr(w, rt, &assign.right, tabs)?;
+    write_expr(w, stack, &assign.right, tabs)?;
     Ok(())
 }
 
 fn write_vec4<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     vec4: &ast::Vec4,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -605,7 +617,7 @@ fn write_vec4<W: io::Write>(
     }
     write!(w, "(")?;
     for (i, expr) in vec4.args[0..n].iter().enumerate() {
-        write_expr(w, rt, expr, tabs)?;
+        write_expr(w, stack, expr, tabs)?;
         if i + 1 < n {
             write!(w, ", ")?;
         }
@@ -619,14 +631,14 @@ fn write_vec4<W: io::Write>(
 
 fn write_mat4<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     mat4: &ast::Mat4,
     tabs: u32,
 ) -> Result<(), io::Error> {
     let n = mat4.args.len();
     write!(w, "mat4 {{")?;
     for (i, expr) in mat4.args[0..n].iter().enumerate() {
-        write_expr(w, rt, expr, tabs)?;
+        write_expr(w, stack, expr, tabs)?;
         if i + 1 < n {
             write!(w, "; ")?;
         }
@@ -640,7 +652,7 @@ fn write_mat4<W: io::Write>(
 
 fn write_swizzle<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     swizzle: &ast::Swizzle,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -662,13 +674,13 @@ fn write_swizzle<W: io::Write>(
         write!(w, "{}", comp(sw3))?;
     }
     write!(w, " ")?;
-    write_expr(w, rt, &swizzle.expr, tabs)?;
+    write_expr(w, stack, &swizzle.expr, tabs)?;
     Ok(())
 }
 
 fn write_for<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     f: &ast::For,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -680,7 +692,7 @@ fn write_for<W: io::Write>(
                         if let ast::Expression::Block(ref b) = f.step {
                             if b.expressions.is_empty() {
                                 write!(w, "loop ")?;
-                                write_block(w, rt, &f.block, tabs + 1)?;
+                                write_block(w, stack, &f.block, tabs + 1)?;
                                 return Ok(());
                             }
                         }
@@ -691,13 +703,13 @@ fn write_for<W: io::Write>(
     }
 
     write!(w, "for ")?;
-    write_expr(w, rt, &f.init, tabs)?;
+    write_expr(w, stack, &f.init, tabs)?;
     write!(w, "; ")?;
-    write_expr(w, rt, &f.cond, tabs)?;
+    write_expr(w, stack, &f.cond, tabs)?;
     write!(w, "; ")?;
-    write_expr(w, rt, &f.step, tabs)?;
+    write_expr(w, stack, &f.step, tabs)?;
     write!(w, " ")?;
-    write_block(w, rt, &f.block, tabs + 1)?;
+    write_block(w, stack, &f.block, tabs + 1)?;
     Ok(())
 }
 
@@ -712,7 +724,7 @@ fn compare_needs_parent(expr: &ast::Expression) -> bool {
 
 fn write_compare<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     comp: &ast::Compare,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -722,7 +734,7 @@ fn write_compare<W: io::Write>(
     if left_needs_parens {
         write!(w, "(")?;
     }
-    write_expr(w, rt, &comp.left, tabs)?;
+    write_expr(w, stack, &comp.left, tabs)?;
     if left_needs_parens {
         write!(w, ")")?;
     }
@@ -730,7 +742,7 @@ fn write_compare<W: io::Write>(
     if right_needs_parens {
         write!(w, "(")?;
     }
-    write_expr(w, rt, &comp.right, tabs)?;
+    write_expr(w, stack, &comp.right, tabs)?;
     if right_needs_parens {
         write!(w, ")")?;
     }
@@ -739,65 +751,139 @@ fn write_compare<W: io::Write>(
 
 fn write_for_n<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     for_n: &ast::ForN,
     tabs: u32
 ) -> Result<(), io::Error> {
     write!(w, "{} ", for_n.name)?;
     if let Some(ref start) = for_n.start {
         write!(w, "[")?;
-        write_expr(w, rt, start, tabs)?;
+        write_expr(w, stack, start, tabs)?;
         write!(w, ", ")?;
-        write_expr(w, rt, &for_n.end, tabs)?;
+        write_expr(w, stack, &for_n.end, tabs)?;
         write!(w, ") ")?;
     } else {
-        write_expr(w, rt, &for_n.end, tabs)?;
+        write_expr(w, stack, &for_n.end, tabs)?;
         write!(w, " ")?;
     }
-    write_block(w, rt, &for_n.block, tabs + 1)?;
+    write_block(w, stack, &for_n.block, tabs + 1)?;
     Ok(())
 }
 
 fn write_for_in<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     for_in: &ast::ForIn,
     tabs: u32
 ) -> Result<(), io::Error> {
     write!(w, "{} in ", for_in.name)?;
-    write_expr(w, rt, &for_in.iter, tabs)?;
+    write_expr(w, stack, &for_in.iter, tabs)?;
     write!(w, " ")?;
-    write_block(w, rt, &for_in.block, tabs + 1)?;
+    write_block(w, stack, &for_in.block, tabs + 1)?;
     Ok(())
 }
 
 fn write_if<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     if_expr: &ast::If,
     tabs: u32,
 ) -> Result<(), io::Error> {
     write!(w, "if ")?;
-    write_expr(w, rt, &if_expr.cond, tabs)?;
+    write_expr(w, stack, &if_expr.cond, tabs)?;
     write!(w, " ")?;
-    write_block(w, rt, &if_expr.true_block, tabs)?;
+    write_block(w, stack, &if_expr.true_block, tabs)?;
     for (else_if_cond, else_if_block) in if_expr.else_if_conds.iter()
         .zip(if_expr.else_if_blocks.iter()) {
         write!(w, " else if ")?;
-        write_expr(w, rt, else_if_cond, tabs)?;
+        write_expr(w, stack, else_if_cond, tabs)?;
         write!(w, " ")?;
-        write_block(w, rt, else_if_block, tabs)?;
+        write_block(w, stack, else_if_block, tabs)?;
     }
     if let Some(ref else_block) = if_expr.else_block {
         write!(w, " else ")?;
-        write_block(w, rt, else_block, tabs)?;
+        write_block(w, stack, else_block, tabs)?;
+    }
+    Ok(())
+}
+
+fn write_match<W: io::Write>(
+    w: &mut W,
+    stack: &[Variable],
+    match_expr: &ast::Match,
+    tabs: u32,
+) -> Result<(), io::Error> {
+    write!(w, "match ")?;
+    write_expr(w, stack, &match_expr.expr, tabs)?;
+    if match_expr.arms.is_empty() {
+        write!(w, " {{}}")?;
+        return Ok(());
+    }
+    writeln!(w, " {{")?;
+    for arm in &match_expr.arms {
+        write_tabs(w, tabs + 1)?;
+        write_pattern(w, &arm.pattern)?;
+        if let Some(ref guard) = arm.guard {
+            write!(w, " if ")?;
+            write_expr(w, stack, guard, tabs + 1)?;
+        }
+        write!(w, " => ")?;
+        write_expr(w, stack, &arm.expr, tabs + 1)?;
+        writeln!(w, ",")?;
+    }
+    write_tabs(w, tabs)?;
+    write!(w, "}}")?;
+    Ok(())
+}
+
+fn write_pattern<W: io::Write>(
+    w: &mut W,
+    pattern: &ast::Pattern,
+) -> Result<(), io::Error> {
+    use ast::Pattern as P;
+
+    match *pattern {
+        P::Wildcard(_) => write!(w, "_")?,
+        P::Bind(ref name, _) => write!(w, "{}", name)?,
+        P::Value(Variable::Text(ref t), _) => json::write_string(w, t)?,
+        P::Value(Variable::F64(x, _), _) => write!(w, "{}", x)?,
+        P::Value(Variable::Bool(x, _), _) => write!(w, "{}", x)?,
+        P::Value(_, _) => write!(w, "_")?,
+        P::Array(ref items, _) => {
+            write!(w, "[")?;
+            write_patterns(w, items)?;
+            write!(w, "]")?;
+        }
+        P::Vec4(ref items, _) => {
+            write!(w, "(")?;
+            write_patterns(w, items)?;
+            write!(w, ")")?;
+        }
+        P::Variant(ref variant) => {
+            write!(w, "{}(", variant.name)?;
+            write_patterns(w, &variant.args)?;
+            write!(w, ")")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_patterns<W: io::Write>(
+    w: &mut W,
+    patterns: &[ast::Pattern],
+) -> Result<(), io::Error> {
+    for (i, pattern) in patterns.iter().enumerate() {
+        write_pattern(w, pattern)?;
+        if i + 1 < patterns.len() {
+            write!(w, ", ")?;
+        }
     }
     Ok(())
 }
 
 fn write_grab<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     grab: &ast::Grab,
     tabs: u32,
 ) -> Result<(), io::Error> {
@@ -806,19 +892,19 @@ fn write_grab<W: io::Write>(
     } else {
         write!(w, "(grab ")?;
     }
-    write_expr(w, rt, &grab.expr, tabs)?;
+    write_expr(w, stack, &grab.expr, tabs)?;
     write!(w, ")")?;
     Ok(())
 }
 
 fn write_try_expr<W: io::Write>(
     w: &mut W,
-    rt: &Runtime,
+    stack: &[Variable],
     try_expr: &ast::TryExpr,
     tabs: u32,
 ) -> Result<(), io::Error> {
     write!(w, "(try ")?;
-    write_expr(w, rt, &try_expr.expr, tabs)?;
+    write_expr(w, stack, &try_expr.expr, tabs)?;
     write!(w, ")")?;
     Ok(())
 }
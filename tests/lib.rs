@@ -1,6 +1,7 @@
 extern crate piston_meta;
 extern crate dyon;
 
+use std::sync::Arc;
 use dyon::*;
 
 pub fn test_src(source: &str) {
@@ -22,6 +23,39 @@ pub fn test_fail_src(source: &str) {
     };
 }
 
+/// Checks the warnings of a source, as pairs of message and warned source text.
+pub fn test_warn_src(source: &str, warnings: &[(&str, &str)]) {
+    let mut module = Module::new();
+    let list = load_warnings(source, &mut module).unwrap_or_else(|err| {
+        panic!("In `{}`:\n{}", source, err);
+    });
+    let text = std::fs::read_to_string(source).unwrap();
+    let found: Vec<(&str, &str)> = list.iter()
+        .map(|w| (&*w.data, &text[w.offset..w.offset + w.length]))
+        .collect();
+    assert_eq!(found, warnings, "In `{}`", source);
+}
+
+/// Loads source and returns the module with a new runtime.
+pub fn load_src(source: &str) -> (Arc<Module>, Runtime) {
+    let mut module = Module::new();
+    load(source, &mut module).unwrap_or_else(|err| {
+        panic!("In `{}`:\n{}", source, err);
+    });
+    (Arc::new(module), Runtime::new())
+}
+
+/// Runs functions returning `bool` in source and checks that they return `true`.
+pub fn run_src_fns(source: &str, names: &[&str]) {
+    let (module, mut rt) = load_src(source);
+    for name in names {
+        let ok: bool = Call::new(name).run_ret(&mut rt, &module).unwrap_or_else(|err| {
+            panic!("In `{}`, `{}`:\n{}", source, name, err);
+        });
+        assert!(ok, "In `{}`: `{}` failed", source, name);
+    }
+}
+
 pub fn debug_src(source: &str) {
     let mut module = Module::new();
     load(source, &mut module).unwrap_or_else(|err| {
@@ -89,11 +123,13 @@ fn test_syntax() {
     test_src("source/syntax/accessor.dyon");
     test_src("source/syntax/sum.dyon");
     test_src("source/syntax/link_for.dyon");
+    test_src("source/syntax/link_values.dyon");
     test_src("source/syntax/min_max.dyon");
     test_src("source/syntax/vec4.dyon");
     test_src("source/syntax/vec4_2.dyon");
     test_src("source/syntax/vec4_un_loop.dyon");
     test_src("source/syntax/vec4_un_loop_2.dyon");
+    test_src("source/syntax/match.dyon");
     test_src("source/syntax/swizzle.dyon");
     test_src("source/syntax/color.dyon");
     test_src("source/syntax/parens.dyon");
@@ -213,6 +249,7 @@ fn test_typechk() {
     test_src("source/typechk/secret_8.dyon");
     test_src("source/typechk/secret_9.dyon");
     test_fail_src("source/typechk/secret_10.dyon");
+    test_src("source/typechk/secret_11.dyon");
     test_src("source/typechk/dot.dyon");
     test_src("source/typechk/in.dyon");
     test_fail_src("source/typechk/in_2.dyon");
@@ -220,6 +257,37 @@ fn test_typechk() {
     test_fail_src("source/typechk/mat4_1.dyon");
     test_src("source/typechk/mat4_2.dyon");
     test_src("source/typechk/ind_arr.dyon");
+    test_fail_src("source/typechk/match_non_exhaustive.dyon");
+    test_fail_src("source/typechk/match_enum.dyon");
+    test_fail_src("source/typechk/match_bind.dyon");
+    test_fail_src("source/typechk/match_guard.dyon");
+    test_src("source/typechk/generic.dyon");
+    test_fail_src("source/typechk/generic_2.dyon");
+    test_fail_src("source/typechk/generic_3.dyon");
+    test_src("source/typechk/generic_4.dyon");
+    test_src("source/typechk/generic_5.dyon");
+    test_fail_src("source/typechk/generic_6.dyon");
+    test_fail_src("source/typechk/vecn_min.dyon");
+    test_src("source/typechk/strict.dyon");
+    test_fail_src("source/typechk/strict_2.dyon");
+    test_fail_src("source/typechk/strict_3.dyon");
+    test_warn_src("source/typechk/strict_4.dyon",
+                  &[("Strict mode: Expected `f64`, found `any`", "unwrap(some(2))")]);
+}
+
+#[test]
+fn test_strict_warnings() {
+    let source = "source/typechk/strict_4.dyon";
+    let mut module = Module::new();
+    module.set_warnings(Warnings::Error);
+    let err = load(source, &mut module).expect_err("Expected warnings as errors");
+    assert!(err.contains("Strict mode: Expected `f64`, found `any`"), "{}", err);
+}
+
+#[test]
+fn test_match() {
+    run_src_fns("source/syntax/match.dyon",
+                &["shapes", "options", "results", "arrays", "vectors", "strings", "objects"]);
 }
 
 #[test]
@@ -237,3 +305,320 @@ fn test_error() {
     test_src("source/error/unwrap_err.dyon");
     test_src("source/error/option.dyon");
 }
+
+#[test]
+fn test_warnings() {
+    test_warn_src("source/warnings/none.dyon", &[]);
+    test_warn_src("source/warnings/unused.dyon", &[
+        ("Unused variable `x`", "x"),
+        ("Unused argument `b`", "b"),
+        ("Unused argument `b`", "b"),
+    ]);
+    test_warn_src("source/warnings/unreachable.dyon",
+                  &[("Unreachable statement", "println(\"never\")")]);
+    test_warn_src("source/warnings/shadow.dyon",
+                  &[("`x` shadows a previous declaration", "x")]);
+    test_warn_src("source/warnings/ignored.dyon",
+                  &[("Result is ignored with `_ :=`", "_ := foo()")]);
+    test_warn_src("source/warnings/match.dyon", &[("Unreachable match arm", "_ => 2")]);
+}
+
+#[test]
+fn test_type_info() {
+    let source = "source/typechk/query.dyon";
+    let mut module = Module::new();
+    load(source, &mut module).unwrap_or_else(|err| {
+        panic!("In `{}`:\n{}", source, err);
+    });
+    let add = module.signature("add").expect("Expected signature");
+    assert_eq!(add.args.len(), 2);
+    assert_eq!(add.ret, Type::F64);
+    // Types are only stored when enabled.
+    assert!(module.type_info().is_empty());
+
+    module.set_type_info(true);
+    for _ in 0..2 {
+        load(source, &mut module).unwrap();
+    }
+    // Reloading a source replaces its types.
+    let xs: Vec<&TypeInfo> = module.type_info().iter()
+        .filter(|info| info.local.as_ref().map(|n| &***n) == Some("x"))
+        .collect();
+    assert_eq!(xs.len(), 1);
+    assert_eq!(xs[0].ty, Type::F64);
+    let at = module.type_at(source, xs[0].range.offset).expect("Expected type");
+    assert_eq!(at.ty, Type::F64);
+}
+
+#[test]
+fn test_link_write_to() {
+    use std::io;
+    use std::sync::Mutex;
+
+    let mut link = Link::new();
+    link.push(&Variable::Text(Arc::new("x = ".into()))).unwrap();
+    link.push(&Variable::f64(2.0)).unwrap();
+    link.push(&Variable::Vec4([1.0, 2.0, 0.0, 0.0])).unwrap();
+    let mut buf: Vec<u8> = vec![];
+    link.write_to(&mut buf).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "x = 2(1, 2)");
+
+    #[derive(Clone)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    let shared = Shared(Arc::new(Mutex::new(vec![])));
+    let mut link = Link::with_writer(shared.clone());
+    for _ in 0..1000 {
+        link.push(&Variable::Text(Arc::new("a".into()))).unwrap();
+    }
+    // Full blocks are written while pushing.
+    assert!(shared.0.lock().unwrap().len() > 0);
+    // Clones do not write the blocks they share with the original.
+    let mut copy = link.clone();
+    assert!(!copy.is_streaming());
+    for _ in 0..1000 {
+        copy.push(&Variable::Text(Arc::new("b".into()))).unwrap();
+    }
+    assert!(!copy.tail().is_streaming());
+    link.flush().unwrap();
+    assert!(link.is_empty());
+    assert_eq!(shared.0.lock().unwrap().len(), 1000);
+}
+
+#[test]
+fn test_link_stream() {
+    use std::fs;
+
+    let file = std::env::temp_dir().join("dyon_test_link_stream.txt");
+    let file = file.to_str().unwrap().to_string();
+    let (module, mut rt) = load_src("source/syntax/link_stream.dyon");
+    // Only full blocks are written when the link is not flushed.
+    Call::new("stream").arg(file.clone()).arg(false).run(&mut rt, &module).unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "ab".repeat(480));
+    Call::new("stream").arg(file.clone()).arg(true).run(&mut rt, &module).unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "ab".repeat(500));
+    fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn test_explain_all() {
+    let (module, mut rt) = load_src("source/syntax/explain_all.dyon");
+    let tree: String = Call::new("tree").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(tree, "i2j1found");
+    let sum_where: String = Call::new("sum_where").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(sum_where, "0first");
+    run_src_fns("source/syntax/explain_all.dyon", &["why_steps", "where_steps"]);
+}
+
+#[test]
+fn test_schema() {
+    use dyon::schema::Schema;
+
+    let schema = Schema::from_data(r#"{
+        name: "str",
+        "speed?": "f64",
+        items: [{count: "f64"}],
+    }"#).unwrap();
+    assert!(schema.load_data(r#"{name: "hero", items: [{count: 2}]}"#).is_ok());
+    let (_, violations) = schema.validate_str(r#"{
+        nmae: "hero",
+        speed: "fast",
+        items: [{count: 2}, {count: true}],
+    }"#).unwrap();
+    let paths: Vec<&str> = violations.iter().map(|v| &*v.path).collect();
+    assert_eq!(paths, vec!["items[1].count", "name", "speed", "nmae"]);
+    assert_eq!(violations[1].message, "Missing key `name`");
+    assert_eq!(violations[3].message, "Unknown key `nmae`");
+    assert!(violations.iter().all(|v| v.range.is_some()));
+
+    // Keys that look like paths do not share ranges with other values.
+    let schema = Schema::from_data(r#"{a: {b: "f64"}, "a.b": "f64"}"#).unwrap();
+    let text = r#"{a: {b: "x"}, "a.b": 2}"#;
+    let (_, violations) = schema.validate_str(text).unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].path, "a.b");
+    let range = violations[0].range.as_ref().unwrap();
+    assert_eq!(&text[range.offset..range.offset + range.length], r#""x""#);
+    let (_, violations) = schema.validate_str(r#"{a: {b: 1}, "a.b": "x"}"#).unwrap();
+    assert_eq!(violations[0].path, r#""a.b""#);
+
+    run_src_fns("source/syntax/schema.dyon", &["valid", "typo", "from_string"]);
+}
+
+#[test]
+fn test_const_fold() {
+    let source = "source/syntax/const_fold.dyon";
+    for &optimize in &[true, false] {
+        let mut module = Module::new();
+        module.set_optimize(optimize);
+        load(source, &mut module).unwrap_or_else(|err| {
+            panic!("In `{}`:\n{}", source, err);
+        });
+        let module = Arc::new(module);
+        let mut rt = Runtime::new();
+        let arithmetic: f64 = Call::new("arithmetic").run_ret(&mut rt, &module).unwrap();
+        assert_eq!(arithmetic, 105.0);
+        let vec4_unloop: f64 = Call::new("vec4_unloop").run_ret(&mut rt, &module).unwrap();
+        assert_eq!(vec4_unloop, 7531.0);
+        let swizzle: f64 = Call::new("swizzle").run_ret(&mut rt, &module).unwrap();
+        assert_eq!(swizzle, 6512.0);
+        let branches: f64 = Call::new("branches").run_ret(&mut rt, &module).unwrap();
+        assert_eq!(branches, 22.0);
+        let side_effects: f64 = Call::new("side_effects").run_ret(&mut rt, &module).unwrap();
+        assert_eq!(side_effects, 3.0);
+    }
+}
+
+#[test]
+fn test_specialize() {
+    let (module, mut rt) = load_src("source/syntax/specialize.dyon");
+    let linear: f64 = Call::new("linear").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(linear, 7.0);
+    let folded: String = Call::new("folded").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(folded, "\\(y: f64) = 6 + y");
+    let dead_branch: f64 = Call::new("dead_branch").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(dead_branch, 4.0);
+    let shadowed: f64 = Call::new("shadowed").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(shadowed, 4.0);
+    let twice: f64 = Call::new("twice").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(twice, 4.0);
+}
+
+#[test]
+fn test_formats() {
+    let (module, mut rt) = load_src("source/syntax/formats.dyon");
+    let json: String = Call::new("json").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(json, "hero-300truenone()b\n\u{e6}");
+    let json_string: bool = Call::new("json_string").run_ret(&mut rt, &module).unwrap();
+    assert!(json_string);
+    let json_save: String = Call::new("json_save").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(json_save, r#"{"a":"x","b":[1,2]}"#);
+    let csv: String = Call::new("csv").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(csv, "pear, green4.5true");
+    let csv_no_header: f64 = Call::new("csv_no_header").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(csv_no_header, 6.0);
+    run_src_fns("source/syntax/formats.dyon", &["json_save_inf", "csv_numbers", "csv_duplicate"]);
+    let toml: String = Call::new("toml").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(toml, "Game1024falseshield1979-05-27T07:32:00Zsecond16");
+    let toml_string: bool = Call::new("toml_string").run_ret(&mut rt, &module).unwrap();
+    assert!(toml_string);
+}
+
+#[test]
+fn test_stream_data() {
+    let (module, mut rt) = load_src("source/syntax/stream.dyon");
+    let sum: f64 = Call::new("append_stream").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(sum, 45.0);
+    let items: String = Call::new("stream_error").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(items, "ok err");
+}
+
+#[test]
+fn test_random() {
+    let source = "source/syntax/random.dyon";
+    run_src_fns(source,
+                &["same_seed", "different_seed", "range", "shuffled", "chosen", "threads"]);
+    let (module, mut rt) = load_src(source);
+
+    // Seeding from the host gives the same numbers as seeding from a script.
+    rt.set_seed(7);
+    let a: f64 = Call::new("thread_value").run_ret(&mut rt, &module).unwrap();
+    let numbers: Vec<f64> = Call::new("numbers").arg(7.0).run_ret(&mut rt, &module).unwrap();
+    assert_eq!(a, numbers[0]);
+
+    // The sequence of a seed is pinned for this version of Dyon.
+    // Update these numbers if an upgrade of `rand` changes the sequence.
+    assert_eq!(numbers[0], 0.8174608854713135);
+    assert_eq!(numbers[1], 2.4177764097559518);
+
+    for &n in &[-1.0, 0.5, ::std::f64::NAN] {
+        let res: Result<Vec<f64>, String> = Call::new("numbers").arg(n).run_ret(&mut rt, &module);
+        assert!(res.is_err(), "Seed {}", n);
+    }
+}
+
+#[test]
+fn test_geometry() {
+    run_src_fns("source/syntax/geometry.dyon",
+                &["vectors", "lerp_clamp", "quaternions", "intersections"]);
+}
+
+#[test]
+fn test_vecn() {
+    run_src_fns("source/syntax/vecn.dyon",
+                &["arithmetic", "reductions", "swizzles", "conversions", "assign", "precision",
+                  "save_load"]);
+}
+
+#[test]
+fn test_binary() {
+    use dyon::binary::{from_bytes, to_bytes, MAX_DEPTH};
+    use dyon::data::{load_data, load_file};
+
+    let files = [
+        "data/array.dyon", "data/bool_false.dyon", "data/bool_true.dyon", "data/color.dyon",
+        "data/link.dyon", "data/number.dyon", "data/obj.dyon", "data/obj_str.dyon",
+        "data/text.dyon", "data/vec4_2.dyon", "data/vec4_3.dyon", "data/vec4_4.dyon",
+        "data/opt.dyon", "data/opt_2.dyon",
+    ];
+    let (module, mut rt) = load_src("source/syntax/binary.dyon");
+    let same = |rt: &mut Runtime, a: &Variable, b: &Variable| -> bool {
+        Call::new("same").arg(a.clone()).arg(b.clone()).run_ret(rt, &module).unwrap()
+    };
+    for file in &files {
+        let data = load_file(file).unwrap();
+        let bytes = to_bytes(&data).unwrap();
+        let loaded = from_bytes(&bytes).unwrap_or_else(|err| panic!("In `{}`:\n{}", file, err));
+        assert_eq!(to_bytes(&loaded).unwrap(), bytes, "In `{}`", file);
+        // The binary format loads the same data as the text format.
+        assert!(same(&mut rt, &loaded, &data), "In `{}`", file);
+    }
+
+    // Arrays of numbers use 8 bytes per number after header, tag and length.
+    let numbers = load_data("[1, 2, 3]").unwrap();
+    assert_eq!(to_bytes(&numbers).unwrap().len(), 8 + 1 + 8 + 3 * 8);
+    // Repeated strings are stored once.
+    let one = to_bytes(&load_data(r#"["hello"]"#).unwrap()).unwrap();
+    let two = to_bytes(&load_data(r#"["hello", "hello"]"#).unwrap()).unwrap();
+    assert_eq!(two.len() - one.len(), 1 + 8);
+
+    let bytes = to_bytes(&numbers).unwrap();
+    assert!(from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(from_bytes(b"[1, 2, 3]").is_err());
+    let mut wrong_version = bytes.clone();
+    wrong_version[4] = 2;
+    assert!(from_bytes(&wrong_version).is_err());
+
+    // Deeply nested values are rejected instead of overflowing the stack.
+    let nested = |depth: usize| -> Vec<u8> {
+        let mut bytes = to_bytes(&Variable::Option(None)).unwrap();
+        let none = bytes.pop().unwrap();
+        // Tag of `some(_)`.
+        bytes.extend(::std::iter::repeat(1).take(depth - 1));
+        bytes.push(none);
+        bytes
+    };
+    assert!(from_bytes(&nested(MAX_DEPTH)).is_ok());
+    let err = from_bytes(&nested(MAX_DEPTH + 1)).unwrap_err();
+    assert!(err.starts_with("Values nested deeper than"), "{}", err);
+    assert!(from_bytes(&nested(1_000_000)).is_err());
+
+    run_src_fns("source/syntax/binary.dyon", &["round_trip", "wrong_format"]);
+}
+
+#[test]
+fn test_meta_tree() {
+    let (module, mut rt) = load_src("source/syntax/meta_tree.dyon");
+    let text: String = Call::new("print_tree").run_ret(&mut rt, &module).unwrap();
+    assert_eq!(text, r#"[1,"a",[true,false],{x:2,y:[]}]"#);
+    let range: [f32; 4] = Call::new("tree_range").run_vec4(&mut rt, &module).unwrap();
+    assert_eq!(range, [2.0, 38.0, 4.0, 0.0]);
+    run_src_fns("source/syntax/meta_tree.dyon", &["round_trip", "unbalanced", "unnamed"]);
+}